@@ -1,7 +1,12 @@
 use std::env;
+use std::fs;
+use std::path::Path;
 
 const BOOT_ASM: &str = "src/boot.S";
 const LD_SCRIPT_DIR: &str = "ld_scripts";
+/// Environment variable naming the raw 32 byte Ed25519 public key that
+/// `verified_boot` builds trust, see `tools/signkernel.sh --pubkey`
+const PUBKEY_ENV: &str = "BOOT_PUBKEY";
 
 enum Platform {
     Bbb,
@@ -32,14 +37,6 @@ impl Platform {
         println!("cargo:rustc-link-arg=-T{}/{}", manifest_dir, ld_script);
     }
 
-    fn set_kernel_entry_addr(&self) {
-        println!("cargo:rerun-if-env-changed=KERNEL_ENTRY");
-        println!(
-            "cargo:rustc-env=KERNEL_ENTRY={}",
-            std::env::var("KERNEL_ENTRY").unwrap_or("0xA0000000".into())
-        );
-    }
-
     fn set_features(&self) {
         match self {
             Platform::Bbb => {
@@ -73,18 +70,58 @@ fn create_asm_entry_1() {
     println!("cargo:rustc-link-lib=static=boot");
 }
 
+/// Embed the verified boot public key as `BOOT_PUBLIC_KEY`
+fn embed_public_key() {
+    println!("cargo:rerun-if-env-changed={}", PUBKEY_ENV);
+    let path = env::var(PUBKEY_ENV).unwrap_or_else(|_| {
+        panic!(
+            "The 'verified_boot' feature needs {} set to a public key file.",
+            PUBKEY_ENV
+        )
+    });
+    println!("cargo:rerun-if-changed={}", path);
+
+    let key = fs::read(&path).unwrap_or_else(|e| panic!("Cannot read {}: {}", path, e));
+    if key.len() != 32 {
+        panic!(
+            "{} is {} bytes, expected a raw 32 byte Ed25519 public key.",
+            path,
+            key.len()
+        );
+    }
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("boot_pubkey.rs");
+    fs::write(
+        out,
+        format!(
+            "/// Ed25519 key kernel images must be signed with\n\
+             const BOOT_PUBLIC_KEY: [u8; 32] = {:?};\n",
+            key
+        ),
+    )
+    .unwrap();
+}
+
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed={}", BOOT_ASM);
 
+    // host builds only run the library's unit tests, there is nothing to link
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("none") {
+        return;
+    }
+
     let platform = Platform::from_env();
     platform.set_features();
     platform.set_ld_script();
-    platform.set_kernel_entry_addr();
 
     // set linking flags
     // println!("cargo:rustc-link-arg=-nostartfiles");
     println!("cargo:rustc-link-arg=-nostdlib");
 
+    if env::var("CARGO_FEATURE_VERIFIED_BOOT").is_ok() {
+        embed_public_key();
+    }
+
     create_asm_entry_1();
 }
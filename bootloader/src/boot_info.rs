@@ -0,0 +1,685 @@
+//! Boot information handed from the bootloader to the kernel.
+//!
+//! The structure is a [`BootInfoHeader`] followed by a list of tags. Every tag
+//! starts with a [`TagHeader`] and is padded to 8 bytes, the list ends with a
+//! [`tag::END`] tag. Readers skip tags they don't know about, so new tags can
+//! be added without bumping [`BOOT_INFO_VERSION`].
+use core::mem::size_of;
+
+use crate::slot::SlotState;
+
+pub const BOOT_INFO_MAGIC: u32 = 0xB007_1AF0;
+pub const BOOT_INFO_VERSION: u32 = 1;
+
+const TAG_ALIGN: usize = 8;
+const HEADER_SIZE: usize = size_of::<BootInfoHeader>();
+const TAG_HEADER_SIZE: usize = size_of::<TagHeader>();
+const REGION_SIZE: usize = size_of::<MemoryRegion>();
+const STAGE_SIZE: usize = STAGE_NAME_SIZE + 8;
+/// Longest [`BootStage`] name, longer ones are cut short
+pub const STAGE_NAME_SIZE: usize = 16;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BootInfoHeader {
+    pub magic: u32,
+    pub version: u32,
+    /// Size of the header plus every tag, in bytes
+    pub total_size: u32,
+    pub reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TagHeader {
+    pub tag: u32,
+    /// Size of the tag including this header, excluding padding
+    pub size: u32,
+}
+
+/// Tag type numbers
+pub mod tag {
+    pub const END: u32 = 0;
+    pub const MEMORY_MAP: u32 = 1;
+    pub const BOARD_NAME: u32 = 2;
+    pub const COMMAND_LINE: u32 = 3;
+    /// A/B slot state, in the on-disk format of [`crate::slot`]
+    pub const BOOT_SLOT: u32 = 4;
+    /// Bootloader stage timestamps, a list of [`super::BootStage`]s
+    pub const BOOT_TIMES: u32 = 5;
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryKind {
+    /// Physical DRAM, other regions are carved out of it
+    Ram = 1,
+    Bootloader = 2,
+    /// The bootloader's L1 translation table, still live when the kernel starts
+    PageTables = 3,
+    Kernel = 4,
+    Initrd = 5,
+    /// This structure
+    BootInfo = 6,
+    Reserved = 7,
+    /// Where to leave a [`PanicRecord`](crate::panic_record::PanicRecord)
+    /// for the next boot
+    PanicRecord = 8,
+}
+
+impl MemoryKind {
+    pub fn from_u32(kind: u32) -> Option<Self> {
+        Some(match kind {
+            1 => Self::Ram,
+            2 => Self::Bootloader,
+            3 => Self::PageTables,
+            4 => Self::Kernel,
+            5 => Self::Initrd,
+            6 => Self::BootInfo,
+            7 => Self::Reserved,
+            8 => Self::PanicRecord,
+            _ => return None,
+        })
+    }
+}
+
+/// One entry of the memory map. The [`MemoryKind::Ram`] entries describe all
+/// of DRAM, every other entry marks a range inside it as in use. RAM not
+/// covered by another entry is free.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryRegion {
+    pub base: u32,
+    pub size: u32,
+    kind: u32,
+}
+
+impl MemoryRegion {
+    pub const fn new(base: u32, size: u32, kind: MemoryKind) -> Self {
+        Self {
+            base,
+            size,
+            kind: kind as u32,
+        }
+    }
+
+    /// `None` for kinds added by a newer bootloader
+    pub fn kind(&self) -> Option<MemoryKind> {
+        MemoryKind::from_u32(self.kind)
+    }
+
+    pub fn end(&self) -> u32 {
+        self.base.saturating_add(self.size)
+    }
+
+    fn to_bytes(self) -> [u8; REGION_SIZE] {
+        let mut out = [0; REGION_SIZE];
+        out[0..4].copy_from_slice(&self.base.to_le_bytes());
+        out[4..8].copy_from_slice(&self.size.to_le_bytes());
+        out[8..12].copy_from_slice(&self.kind.to_le_bytes());
+        out
+    }
+}
+
+/// A named checkpoint in the bootloader and when it was reached. Encoded as
+/// the NUL padded name followed by the time as a little endian `u64`.
+#[derive(Debug, Clone, Copy)]
+pub struct BootStage {
+    name: [u8; STAGE_NAME_SIZE],
+    /// Microseconds since the bootloader started its clock
+    pub time_us: u64,
+}
+
+impl BootStage {
+    pub const fn new(name: &str, time_us: u64) -> Self {
+        let mut bytes = [0; STAGE_NAME_SIZE];
+        // cut on a character boundary
+        let mut len = if name.len() < STAGE_NAME_SIZE {
+            name.len()
+        } else {
+            STAGE_NAME_SIZE
+        };
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        let (prefix, _) = bytes.split_at_mut(len);
+        prefix.copy_from_slice(name.as_bytes().split_at(len).0);
+        Self {
+            name: bytes,
+            time_us,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(STAGE_NAME_SIZE);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+
+    fn to_bytes(self) -> [u8; STAGE_SIZE] {
+        let mut out = [0; STAGE_SIZE];
+        out[..STAGE_NAME_SIZE].copy_from_slice(&self.name);
+        out[STAGE_NAME_SIZE..].copy_from_slice(&self.time_us.to_le_bytes());
+        out
+    }
+
+    fn from_bytes(bytes: &[u8; STAGE_SIZE]) -> Self {
+        let (name, time) = bytes.split_at(STAGE_NAME_SIZE);
+        Self {
+            name: name.try_into().unwrap(),
+            time_us: u64::from_le_bytes(time.try_into().unwrap()),
+        }
+    }
+}
+
+/// The stages in a [`tag::BOOT_TIMES`] tag, in the order they were reached
+#[derive(Debug, Clone, Copy)]
+pub struct BootTimes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> BootTimes<'a> {
+    pub fn iter(&self) -> impl Iterator<Item = BootStage> + 'a {
+        self.data
+            .as_chunks::<STAGE_SIZE>()
+            .0
+            .iter()
+            .map(BootStage::from_bytes)
+    }
+}
+
+#[derive(Debug)]
+pub enum BootInfoError {
+    BadMagic(u32),
+    UnsupportedVersion(u32),
+    /// Buffer shorter than `total_size`, or a tag running past the end
+    Truncated,
+    /// Structure not 8-byte aligned
+    Misaligned,
+    /// Malformed tag at the given byte offset
+    BadTag {
+        offset: usize,
+    },
+    /// Builder ran out of room
+    NoSpace,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Tag<'a> {
+    MemoryMap(&'a [MemoryRegion]),
+    BoardName(&'a str),
+    CommandLine(&'a str),
+    BootSlot(SlotState),
+    BootTimes(BootTimes<'a>),
+    /// A tag this version of the parser doesn't know
+    Unknown {
+        tag: u32,
+        data: &'a [u8],
+    },
+}
+
+const fn align_up(value: usize) -> usize {
+    (value + TAG_ALIGN - 1) & !(TAG_ALIGN - 1)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset.checked_add(4)?)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Decode the tag at `offset`, returning it and the offset of the next one
+fn decode_tag(data: &[u8], offset: usize) -> Result<(u32, &[u8], usize), BootInfoError> {
+    let bad = || BootInfoError::BadTag { offset };
+    let tag = read_u32(data, offset).ok_or(BootInfoError::Truncated)?;
+    // the tag header was in bounds, so these offsets can't overflow
+    let size = read_u32(data, offset + 4).ok_or(BootInfoError::Truncated)? as usize;
+    if size < TAG_HEADER_SIZE {
+        return Err(bad());
+    }
+    // but the size comes from the blob and can take them anywhere
+    let end = offset.checked_add(size).ok_or_else(bad)?;
+    let next = end.checked_next_multiple_of(TAG_ALIGN).ok_or_else(bad)?;
+    let payload = data
+        .get(offset + TAG_HEADER_SIZE..end)
+        .ok_or(BootInfoError::Truncated)?;
+    Ok((tag, payload, next))
+}
+
+fn typed_tag(tag: u32, data: &[u8]) -> Option<Tag<'_>> {
+    Some(match tag {
+        tag::MEMORY_MAP => {
+            if !data.len().is_multiple_of(REGION_SIZE)
+                || !(data.as_ptr() as usize).is_multiple_of(4)
+            {
+                return None;
+            }
+            // SAFETY: length and alignment checked above, MemoryRegion is
+            // plain u32s so any bit pattern is valid
+            Tag::MemoryMap(unsafe {
+                core::slice::from_raw_parts(
+                    data.as_ptr() as *const MemoryRegion,
+                    data.len() / REGION_SIZE,
+                )
+            })
+        }
+        tag::BOARD_NAME => Tag::BoardName(core::str::from_utf8(data).ok()?),
+        tag::COMMAND_LINE => Tag::CommandLine(core::str::from_utf8(data).ok()?),
+        tag::BOOT_SLOT => Tag::BootSlot(SlotState::parse(data)?),
+        tag::BOOT_TIMES if data.len().is_multiple_of(STAGE_SIZE) => {
+            Tag::BootTimes(BootTimes { data })
+        }
+        tag::BOOT_TIMES => return None,
+        _ => Tag::Unknown { tag, data },
+    })
+}
+
+/// A validated boot information structure
+#[derive(Clone, Copy)]
+pub struct BootInfo<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> BootInfo<'a> {
+    /// Validate the header and every tag in `data`
+    pub fn parse(data: &'a [u8]) -> Result<Self, BootInfoError> {
+        if !(data.as_ptr() as usize).is_multiple_of(TAG_ALIGN) {
+            return Err(BootInfoError::Misaligned);
+        }
+        let magic = read_u32(data, 0).ok_or(BootInfoError::Truncated)?;
+        if magic != BOOT_INFO_MAGIC {
+            return Err(BootInfoError::BadMagic(magic));
+        }
+        let version = read_u32(data, 4).ok_or(BootInfoError::Truncated)?;
+        if version != BOOT_INFO_VERSION {
+            return Err(BootInfoError::UnsupportedVersion(version));
+        }
+        let total_size = read_u32(data, 8).ok_or(BootInfoError::Truncated)? as usize;
+        let data = data.get(..total_size).ok_or(BootInfoError::Truncated)?;
+
+        let mut offset = align_up(HEADER_SIZE);
+        loop {
+            let (tag, payload, next) = decode_tag(data, offset)?;
+            if tag == tag::END {
+                break;
+            }
+            if typed_tag(tag, payload).is_none() {
+                return Err(BootInfoError::BadTag { offset });
+            }
+            offset = next;
+        }
+
+        Ok(Self { data })
+    }
+
+    /// Parse the structure the bootloader left at `ptr`
+    ///
+    /// # Safety
+    /// `ptr` must point to readable memory holding at least a header, and
+    /// `total_size` bytes if the magic matches. The memory must stay valid
+    /// and unmodified for as long as the result is used.
+    pub unsafe fn from_ptr(ptr: *const BootInfoHeader) -> Result<BootInfo<'static>, BootInfoError> {
+        if ptr.is_null() || !(ptr as usize).is_multiple_of(TAG_ALIGN) {
+            return Err(BootInfoError::Misaligned);
+        }
+        let header = unsafe { ptr.read() };
+        if header.magic != BOOT_INFO_MAGIC {
+            return Err(BootInfoError::BadMagic(header.magic));
+        }
+        let data =
+            unsafe { core::slice::from_raw_parts(ptr as *const u8, header.total_size as usize) };
+        BootInfo::parse(data)
+    }
+
+    pub fn header(&self) -> BootInfoHeader {
+        BootInfoHeader {
+            magic: BOOT_INFO_MAGIC,
+            version: BOOT_INFO_VERSION,
+            total_size: self.data.len() as u32,
+            reserved: 0,
+        }
+    }
+
+    pub fn tags(&self) -> TagIter<'a> {
+        TagIter {
+            data: self.data,
+            offset: align_up(HEADER_SIZE),
+        }
+    }
+
+    pub fn memory_map(&self) -> &'a [MemoryRegion] {
+        self.tags()
+            .find_map(|tag| match tag {
+                Tag::MemoryMap(regions) => Some(regions),
+                _ => None,
+            })
+            .unwrap_or(&[])
+    }
+
+    /// Physical location of the initrd, the memory map entry of kind
+    /// [`MemoryKind::Initrd`]
+    pub fn initrd(&self) -> Option<&'a MemoryRegion> {
+        self.memory_map()
+            .iter()
+            .find(|region| region.kind() == Some(MemoryKind::Initrd))
+    }
+
+    pub fn board_name(&self) -> Option<&'a str> {
+        self.tags().find_map(|tag| match tag {
+            Tag::BoardName(name) => Some(name),
+            _ => None,
+        })
+    }
+
+    pub fn command_line(&self) -> Option<&'a str> {
+        self.tags().find_map(|tag| match tag {
+            Tag::CommandLine(cmdline) => Some(cmdline),
+            _ => None,
+        })
+    }
+
+    /// `None` when the kernel wasn't booted from an A/B slot
+    pub fn boot_slot(&self) -> Option<SlotState> {
+        self.tags().find_map(|tag| match tag {
+            Tag::BootSlot(state) => Some(state),
+            _ => None,
+        })
+    }
+
+    pub fn boot_times(&self) -> Option<BootTimes<'a>> {
+        self.tags().find_map(|tag| match tag {
+            Tag::BootTimes(times) => Some(times),
+            _ => None,
+        })
+    }
+}
+
+impl core::fmt::Debug for BootInfo<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.tags()).finish()
+    }
+}
+
+pub struct TagIter<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for TagIter<'a> {
+    type Item = Tag<'a>;
+
+    fn next(&mut self) -> Option<Tag<'a>> {
+        // already validated by BootInfo::parse, stop quietly on anything odd
+        let (tag, payload, next) = decode_tag(self.data, self.offset).ok()?;
+        if tag == tag::END {
+            return None;
+        }
+        self.offset = next;
+        typed_tag(tag, payload)
+    }
+}
+
+/// Writes a boot information structure into a caller supplied buffer
+pub struct BootInfoBuilder<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> BootInfoBuilder<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Result<Self, BootInfoError> {
+        if !(buf.as_ptr() as usize).is_multiple_of(TAG_ALIGN) {
+            return Err(BootInfoError::Misaligned);
+        }
+        if buf.len() < align_up(HEADER_SIZE) + TAG_HEADER_SIZE {
+            return Err(BootInfoError::NoSpace);
+        }
+        Ok(Self {
+            buf,
+            len: align_up(HEADER_SIZE),
+        })
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        self.buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Append a tag built from several payload pieces
+    fn add_tag_parts(&mut self, tag: u32, parts: &[&[u8]]) -> Result<(), BootInfoError> {
+        let payload_len: usize = parts.iter().map(|p| p.len()).sum();
+        let size = TAG_HEADER_SIZE + payload_len;
+        // always leave room for the END tag
+        if align_up(self.len + size) + TAG_HEADER_SIZE > self.buf.len() {
+            return Err(BootInfoError::NoSpace);
+        }
+
+        self.write_u32(self.len, tag);
+        self.write_u32(self.len + 4, size as u32);
+        let mut offset = self.len + TAG_HEADER_SIZE;
+        for part in parts {
+            self.buf[offset..offset + part.len()].copy_from_slice(part);
+            offset += part.len();
+        }
+        let end = align_up(offset);
+        self.buf[offset..end].fill(0);
+        self.len = end;
+        Ok(())
+    }
+
+    pub fn add_tag(&mut self, tag: u32, payload: &[u8]) -> Result<(), BootInfoError> {
+        self.add_tag_parts(tag, &[payload])
+    }
+
+    /// Append a tag holding `count` fixed size records
+    fn add_records<const N: usize>(
+        &mut self,
+        tag: u32,
+        count: usize,
+        records: impl Iterator<Item = [u8; N]>,
+    ) -> Result<(), BootInfoError> {
+        let size = TAG_HEADER_SIZE + count * N;
+        if align_up(self.len + size) + TAG_HEADER_SIZE > self.buf.len() {
+            return Err(BootInfoError::NoSpace);
+        }
+        self.add_tag_parts(tag, &[])?;
+        // grow the empty tag in place, one record at a time
+        let tag_start = self.len - TAG_HEADER_SIZE;
+        let mut offset = self.len;
+        for record in records.take(count) {
+            self.buf[offset..offset + N].copy_from_slice(&record);
+            offset += N;
+        }
+        self.write_u32(tag_start + 4, (offset - tag_start) as u32);
+        self.len = align_up(offset);
+        Ok(())
+    }
+
+    pub fn add_memory_map(&mut self, regions: &[MemoryRegion]) -> Result<(), BootInfoError> {
+        self.add_records(
+            tag::MEMORY_MAP,
+            regions.len(),
+            regions.iter().map(|region| region.to_bytes()),
+        )
+    }
+
+    pub fn add_board_name(&mut self, name: &str) -> Result<(), BootInfoError> {
+        self.add_tag(tag::BOARD_NAME, name.as_bytes())
+    }
+
+    pub fn add_command_line(&mut self, cmdline: &str) -> Result<(), BootInfoError> {
+        self.add_tag(tag::COMMAND_LINE, cmdline.as_bytes())
+    }
+
+    pub fn add_boot_slot(&mut self, state: &SlotState) -> Result<(), BootInfoError> {
+        self.add_tag(tag::BOOT_SLOT, &state.to_bytes())
+    }
+
+    pub fn add_boot_times(&mut self, stages: &[BootStage]) -> Result<(), BootInfoError> {
+        self.add_records(
+            tag::BOOT_TIMES,
+            stages.len(),
+            stages.iter().map(|stage| stage.to_bytes()),
+        )
+    }
+
+    /// Terminate the tag list and write the header, returning the total size
+    pub fn finish(mut self) -> usize {
+        self.write_u32(self.len, tag::END);
+        self.write_u32(self.len + 4, TAG_HEADER_SIZE as u32);
+        self.len += TAG_HEADER_SIZE;
+
+        let total_size = self.len as u32;
+        self.write_u32(0, BOOT_INFO_MAGIC);
+        self.write_u32(4, BOOT_INFO_VERSION);
+        self.write_u32(8, total_size);
+        self.write_u32(12, 0);
+        self.len
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use crate::slot::Slot;
+
+    #[repr(C, align(8))]
+    struct Buf([u8; 256]);
+
+    const REGIONS: [MemoryRegion; 3] = [
+        MemoryRegion::new(0x4000_0000, 0x4000_0000, MemoryKind::Ram),
+        MemoryRegion::new(0x4100_0000, 0x0010_0000, MemoryKind::Kernel),
+        MemoryRegion::new(0x4800_0000, 0x0080_0000, MemoryKind::Initrd),
+    ];
+
+    /// A structure with one of every tag, returning its total size
+    fn build(buf: &mut Buf) -> usize {
+        let mut builder = BootInfoBuilder::new(&mut buf.0).unwrap();
+        builder.add_memory_map(&REGIONS).unwrap();
+        builder.add_board_name("qemu-cubieboard").unwrap();
+        builder.add_command_line("console=ttyS0").unwrap();
+        builder.add_boot_slot(&SlotState::new(Slot::B)).unwrap();
+        builder
+            .add_boot_times(&[BootStage::new("start", 0), BootStage::new("kernel", 1234)])
+            .unwrap();
+        builder.add_tag(0x100, &[1, 2, 3]).unwrap();
+        builder.finish()
+    }
+
+    fn write_u32(buf: &mut Buf, offset: usize, value: u32) {
+        buf.0[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buf = Buf([0xAA; 256]);
+        let size = build(&mut buf);
+        let info = BootInfo::parse(&buf.0[..size]).unwrap();
+
+        assert_eq!(info.header().total_size as usize, size);
+        let map = info.memory_map();
+        assert_eq!(map.len(), REGIONS.len());
+        for (got, want) in map.iter().zip(&REGIONS) {
+            assert_eq!(
+                (got.base, got.size, got.kind()),
+                (want.base, want.size, want.kind())
+            );
+        }
+        assert_eq!(info.initrd().unwrap().base, 0x4800_0000);
+        assert_eq!(info.board_name(), Some("qemu-cubieboard"));
+        assert_eq!(info.command_line(), Some("console=ttyS0"));
+        assert_eq!(info.boot_slot(), Some(SlotState::new(Slot::B)));
+
+        let mut times = info.boot_times().unwrap().iter();
+        let stage = times.next().unwrap();
+        assert_eq!((stage.name(), stage.time_us), ("start", 0));
+        let stage = times.next().unwrap();
+        assert_eq!((stage.name(), stage.time_us), ("kernel", 1234));
+        assert!(times.next().is_none());
+
+        assert!(info.tags().any(|tag| matches!(
+            tag,
+            Tag::Unknown {
+                tag: 0x100,
+                data: [1, 2, 3]
+            }
+        )));
+        assert_eq!(info.tags().count(), 6);
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        let mut buf = Buf([0; 256]);
+        let size = build(&mut buf);
+
+        assert!(matches!(
+            BootInfo::parse(&buf.0[..size - 1]),
+            Err(BootInfoError::Truncated)
+        ));
+        assert!(matches!(
+            BootInfo::parse(&buf.0[1..size]),
+            Err(BootInfoError::Misaligned)
+        ));
+        write_u32(&mut buf, 4, BOOT_INFO_VERSION + 1);
+        assert!(matches!(
+            BootInfo::parse(&buf.0[..size]),
+            Err(BootInfoError::UnsupportedVersion(_))
+        ));
+        write_u32(&mut buf, 0, 0);
+        assert!(matches!(
+            BootInfo::parse(&buf.0[..size]),
+            Err(BootInfoError::BadMagic(0))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_tags() {
+        let first = align_up(HEADER_SIZE);
+        let mut buf = Buf([0; 256]);
+        let size = build(&mut buf);
+
+        // shorter than its own header
+        write_u32(&mut buf, first + 4, 4);
+        assert!(matches!(
+            BootInfo::parse(&buf.0[..size]),
+            Err(BootInfoError::BadTag { offset }) if offset == first
+        ));
+        // a size that would wrap the offset around
+        write_u32(&mut buf, first + 4, u32::MAX);
+        assert!(matches!(
+            BootInfo::parse(&buf.0[..size]),
+            Err(BootInfoError::BadTag { .. } | BootInfoError::Truncated)
+        ));
+        // running past total_size
+        write_u32(&mut buf, first + 4, size as u32);
+        assert!(matches!(
+            BootInfo::parse(&buf.0[..size]),
+            Err(BootInfoError::Truncated)
+        ));
+        // a memory map that isn't a whole number of regions
+        write_u32(
+            &mut buf,
+            first + 4,
+            (TAG_HEADER_SIZE + REGION_SIZE + 4) as u32,
+        );
+        assert!(matches!(
+            BootInfo::parse(&buf.0[..size]),
+            Err(BootInfoError::BadTag { offset }) if offset == first
+        ));
+    }
+
+    #[test]
+    fn builder_runs_out_of_space() {
+        let mut buf = Buf([0; 256]);
+        let mut builder = BootInfoBuilder::new(&mut buf.0[..48]).unwrap();
+        builder.add_board_name("short").unwrap();
+        assert!(matches!(
+            builder.add_command_line("far too long for what is left"),
+            Err(BootInfoError::NoSpace)
+        ));
+        let size = builder.finish();
+        let info = BootInfo::parse(&buf.0[..size]).unwrap();
+        assert_eq!(info.board_name(), Some("short"));
+        assert_eq!(info.command_line(), None);
+    }
+}
@@ -0,0 +1,67 @@
+//! Chainloading raw binaries, U-Boot or any other bare metal payload.
+//!
+//! The binary is copied to the entry's `address` and entered there in the
+//! state the CPU comes out of reset in: MMU and caches off, supervisor mode,
+//! interrupts masked and r0-r2 zero. Nothing is handed to it, no boot info,
+//! device tree or ATAGS. With `verified_boot` it has to be stamped with
+//! `tools/mkkernelimg.sh` and signed like a kernel, without it the file runs
+//! as is.
+use hal::dram::{DRAM_END, DRAM_START};
+use hal::{asm, mmu};
+
+#[derive(Debug)]
+pub enum ChainloadError {
+    /// Address is misaligned, outside DRAM or in a reserved region
+    BadAddress(usize),
+    /// Binary is bigger than the free DRAM from its address on
+    TooLarge { size: usize, space: usize },
+}
+
+impl core::fmt::Display for ChainloadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::BadAddress(address) => {
+                write!(f, "cannot load to 0x{:x}, it is not free DRAM", address)
+            }
+            Self::TooLarge { size, space } => write!(
+                f,
+                "{} byte binary does not fit in the {} bytes free at its address",
+                size, space
+            ),
+        }
+    }
+}
+
+/// Copy `image` to `address`, `space_end` being the first reserved address
+/// after it
+pub fn place(image: &[u8], address: usize, space_end: usize) -> Result<(), ChainloadError> {
+    let space = space_end.saturating_sub(address);
+    if !(DRAM_START..=DRAM_END).contains(&address) || !address.is_multiple_of(4) || space == 0 {
+        return Err(ChainloadError::BadAddress(address));
+    }
+    if image.len() > space {
+        return Err(ChainloadError::TooLarge {
+            size: image.len(),
+            space,
+        });
+    }
+    let dest = unsafe { core::slice::from_raw_parts_mut(address as *mut u8, image.len()) };
+    dest.copy_from_slice(image);
+    Ok(())
+}
+
+/// Write back and drop everything cached, turn the MMU off and jump to the
+/// binary at `address` as if the board had just reset
+pub fn enter(address: usize) -> ! {
+    // cleans and invalidates the data cache on the way
+    mmu::disable();
+    unsafe {
+        // nothing fetched or predicted from before may survive into the binary
+        asm::flush_i_cache();
+        asm::invalidate_branch_predictor();
+        asm::flush_tlb();
+        asm::dsb();
+        asm::isb();
+        asm::enter_reset_state(address as u32)
+    }
+}
@@ -0,0 +1,489 @@
+//! `/boot/boot.cfg` parser, the menu built on it is in the bootloader.
+//!
+//! ```text
+//! # comments start with '#'
+//! default = stable
+//! timeout = 3
+//! recovery = stable
+//!
+//! [stable]
+//! kernel = /boot/kernel.bin
+//! cmdline = console=ttyS0
+//!
+//! [testing]
+//! kernel = /boot/kernel-test.bin
+//! initrd = /boot/initrd.img
+//! options = verbose
+//!
+//! [linux]
+//! kernel = /boot/zImage.bin
+//! dtb = /boot/sun4i-a10-cubieboard.dtb
+//! cmdline = console=ttyS0,115200 earlycon
+//!
+//! [u-boot]
+//! type = chainload
+//! kernel = /boot/u-boot.bin
+//! address = 0x4a000000
+//! ```
+//!
+//! Keys before the first `[entry]` are global. `default` names an entry, or
+//! the first entry is used. `recovery` optionally names the entry booted when
+//! the chosen kernel fails verification. `kernel = slot` boots whichever of
+//! the A/B kernels is active, falling back to the other. `dtb` is only used
+//! when the kernel is a Linux zImage, which gets ATAGS without one. A
+//! `type = chainload` entry loads `kernel` as a raw binary to `address` and
+//! jumps to it. Nothing is allocated, every string borrows from the file
+//! contents.
+use crate::number;
+
+pub const CONFIG_PATH: &str = "/boot/boot.cfg";
+/// Kernel booted when there is no usable config file
+pub const FALLBACK_KERNEL: &str = "/boot/kernel.bin";
+/// `kernel` value that boots the active A/B slot, see [`crate::slot`]
+pub const SLOT_KERNEL: &str = "slot";
+
+const MAX_ENTRIES: usize = 8;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    NotUtf8,
+    /// Line that is neither a comment, a `[section]` nor `key = value`
+    Syntax {
+        line: usize,
+    },
+    UnknownKey {
+        line: usize,
+    },
+    /// Key given twice in the same section
+    DuplicateKey {
+        line: usize,
+    },
+    BadTimeout {
+        line: usize,
+    },
+    TooManyEntries,
+    /// Entry starting at `line` has no `kernel`
+    MissingKernel {
+        line: usize,
+    },
+    NoEntries,
+    /// `default` doesn't name an entry
+    UnknownDefault,
+    /// `recovery` doesn't name an entry
+    UnknownRecovery,
+    /// `type` is neither `kernel` nor `chainload`
+    UnknownType {
+        line: usize,
+    },
+    BadAddress {
+        line: usize,
+    },
+    /// Chainload entry starting at `line` has no `address`
+    MissingAddress {
+        line: usize,
+    },
+}
+
+impl core::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NotUtf8 => write!(f, "file is not valid UTF-8"),
+            Self::Syntax { line } => write!(f, "line {}: expected `key = value`", line),
+            Self::UnknownKey { line } => write!(f, "line {}: unknown key", line),
+            Self::DuplicateKey { line } => write!(f, "line {}: key already set", line),
+            Self::BadTimeout { line } => write!(f, "line {}: timeout is not a number", line),
+            Self::TooManyEntries => write!(f, "more than {} entries", MAX_ENTRIES),
+            Self::MissingKernel { line } => {
+                write!(f, "entry at line {} has no kernel", line)
+            }
+            Self::NoEntries => write!(f, "no entries"),
+            Self::UnknownDefault => write!(f, "default entry does not exist"),
+            Self::UnknownRecovery => write!(f, "recovery entry does not exist"),
+            Self::UnknownType { line } => write!(f, "line {}: unknown entry type", line),
+            Self::BadAddress { line } => write!(f, "line {}: address is not a number", line),
+            Self::MissingAddress { line } => {
+                write!(f, "chainload entry at line {} has no address", line)
+            }
+        }
+    }
+}
+
+/// What an entry boots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// One of our kernels, or a Linux zImage
+    Kernel,
+    /// A raw binary run from `address`, with nothing handed to it
+    Chainload { address: usize },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry<'a> {
+    pub name: &'a str,
+    pub kind: EntryKind,
+    pub kernel: &'a str,
+    pub initrd: Option<&'a str>,
+    /// Device tree handed to a Linux kernel
+    pub dtb: Option<&'a str>,
+    pub cmdline: &'a str,
+    /// Free form load options, interpreted by the boot path
+    pub options: &'a str,
+}
+
+impl<'a> Entry<'a> {
+    /// The entry booted when there is no config file
+    pub const fn fallback(cmdline: &'a str) -> Self {
+        Self {
+            name: "default",
+            kind: EntryKind::Kernel,
+            kernel: FALLBACK_KERNEL,
+            initrd: None,
+            dtb: None,
+            cmdline,
+            options: "",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Config<'a> {
+    entries: [Option<Entry<'a>>; MAX_ENTRIES],
+    count: usize,
+    default: usize,
+    recovery: Option<usize>,
+    /// Seconds to wait for a key before booting the default entry
+    pub timeout: u32,
+}
+
+/// An entry being filled in, `kernel` is only checked once it is complete
+struct PartialEntry<'a> {
+    line: usize,
+    name: &'a str,
+    kind: Option<&'a str>,
+    kind_line: usize,
+    address: Option<&'a str>,
+    address_line: usize,
+    kernel: Option<&'a str>,
+    initrd: Option<&'a str>,
+    dtb: Option<&'a str>,
+    cmdline: Option<&'a str>,
+    options: Option<&'a str>,
+}
+
+impl<'a> PartialEntry<'a> {
+    fn finish(self) -> Result<Entry<'a>, ConfigError> {
+        let kind = match self.kind {
+            None | Some("kernel") => EntryKind::Kernel,
+            Some("chainload") => {
+                let address = self
+                    .address
+                    .ok_or(ConfigError::MissingAddress { line: self.line })?;
+                EntryKind::Chainload {
+                    address: number::parse(address).ok_or(ConfigError::BadAddress {
+                        line: self.address_line,
+                    })?,
+                }
+            }
+            Some(_) => {
+                return Err(ConfigError::UnknownType {
+                    line: self.kind_line,
+                });
+            }
+        };
+        Ok(Entry {
+            name: self.name,
+            kind,
+            kernel: self
+                .kernel
+                .ok_or(ConfigError::MissingKernel { line: self.line })?,
+            initrd: self.initrd,
+            dtb: self.dtb,
+            cmdline: self.cmdline.unwrap_or(""),
+            options: self.options.unwrap_or(""),
+        })
+    }
+}
+
+fn set<'a>(slot: &mut Option<&'a str>, value: &'a str, line: usize) -> Result<(), ConfigError> {
+    if slot.replace(value).is_some() {
+        return Err(ConfigError::DuplicateKey { line });
+    }
+    Ok(())
+}
+
+impl<'a> Config<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, ConfigError> {
+        let text = core::str::from_utf8(data).map_err(|_| ConfigError::NotUtf8)?;
+
+        let mut config = Config {
+            entries: [None; MAX_ENTRIES],
+            count: 0,
+            default: 0,
+            recovery: None,
+            timeout: 0,
+        };
+        let mut default_name: Option<&str> = None;
+        let mut recovery_name: Option<&str> = None;
+        let mut timeout: Option<&str> = None;
+        let mut timeout_line = 0;
+        let mut current: Option<PartialEntry> = None;
+
+        for (index, raw) in text.lines().enumerate() {
+            let line = index + 1;
+            let raw = raw.trim();
+            if raw.is_empty() || raw.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = raw.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+                if let Some(entry) = current.take() {
+                    config.push(entry.finish()?)?;
+                }
+                current = Some(PartialEntry {
+                    line,
+                    name: name.trim(),
+                    kind: None,
+                    kind_line: 0,
+                    address: None,
+                    address_line: 0,
+                    kernel: None,
+                    initrd: None,
+                    dtb: None,
+                    cmdline: None,
+                    options: None,
+                });
+                continue;
+            }
+
+            let (key, value) = raw.split_once('=').ok_or(ConfigError::Syntax { line })?;
+            let (key, value) = (key.trim(), value.trim());
+            match (&mut current, key) {
+                (None, "default") => set(&mut default_name, value, line)?,
+                (None, "recovery") => set(&mut recovery_name, value, line)?,
+                (None, "timeout") => {
+                    set(&mut timeout, value, line)?;
+                    timeout_line = line;
+                }
+                (Some(entry), "type") => {
+                    set(&mut entry.kind, value, line)?;
+                    entry.kind_line = line;
+                }
+                (Some(entry), "address") => {
+                    set(&mut entry.address, value, line)?;
+                    entry.address_line = line;
+                }
+                (Some(entry), "kernel") => set(&mut entry.kernel, value, line)?,
+                (Some(entry), "initrd") => set(&mut entry.initrd, value, line)?,
+                (Some(entry), "dtb") => set(&mut entry.dtb, value, line)?,
+                (Some(entry), "cmdline") => set(&mut entry.cmdline, value, line)?,
+                (Some(entry), "options") => set(&mut entry.options, value, line)?,
+                _ => return Err(ConfigError::UnknownKey { line }),
+            }
+        }
+        if let Some(entry) = current.take() {
+            config.push(entry.finish()?)?;
+        }
+
+        if config.count == 0 {
+            return Err(ConfigError::NoEntries);
+        }
+        if let Some(timeout) = timeout {
+            config.timeout = timeout
+                .parse()
+                .map_err(|_| ConfigError::BadTimeout { line: timeout_line })?;
+        }
+        if let Some(name) = default_name {
+            let default = config
+                .entries()
+                .position(|entry| entry.name == name)
+                .ok_or(ConfigError::UnknownDefault)?;
+            config.default = default;
+        }
+        if let Some(name) = recovery_name {
+            let recovery = config
+                .entries()
+                .position(|entry| entry.name == name)
+                .ok_or(ConfigError::UnknownRecovery)?;
+            config.recovery = Some(recovery);
+        }
+        Ok(config)
+    }
+
+    fn push(&mut self, entry: Entry<'a>) -> Result<(), ConfigError> {
+        let slot = self
+            .entries
+            .get_mut(self.count)
+            .ok_or(ConfigError::TooManyEntries)?;
+        *slot = Some(entry);
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry<'a>> {
+        self.entries[..self.count].iter().flatten()
+    }
+
+    pub fn default_entry(&self) -> &Entry<'a> {
+        // parse() guarantees at least one entry and a valid default
+        self.entries[self.default]
+            .as_ref()
+            .expect("default entry exists")
+    }
+
+    /// Entry to fall back to when the chosen kernel fails verification
+    pub fn recovery_entry(&self) -> Option<&Entry<'a>> {
+        self.entry(self.recovery?)
+    }
+
+    /// Entry number `index`, counting from 0 in file order
+    pub fn entry(&self, index: usize) -> Option<&Entry<'a>> {
+        self.entries.get(index)?.as_ref()
+    }
+
+    /// Position of [`Self::default_entry`] among [`Self::entries`]
+    pub fn default_index(&self) -> usize {
+        self.default
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Config<'_>, ConfigError> {
+        Config::parse(text.as_bytes())
+    }
+
+    #[test]
+    fn parses_the_documented_example() {
+        let config = parse(
+            "# comments start with '#'\n\
+             default = testing\n\
+             timeout = 3\n\
+             recovery = stable\n\
+             \n\
+             [stable]\n\
+             kernel = /boot/kernel.bin\n\
+             cmdline = console=ttyS0\n\
+             \n\
+             [testing]\n\
+             kernel = /boot/kernel-test.bin\n\
+             initrd = /boot/initrd.img\n\
+             options = verbose\n\
+             \n\
+             [u-boot]\n\
+             type = chainload\n\
+             kernel = /boot/u-boot.bin\n\
+             address = 0x4a000000\n",
+        )
+        .unwrap();
+        assert_eq!(config.timeout, 3);
+        assert_eq!(config.entries().count(), 3);
+        assert_eq!(config.default_index(), 1);
+        assert_eq!(config.default_entry().initrd, Some("/boot/initrd.img"));
+        assert_eq!(config.default_entry().options, "verbose");
+        assert_eq!(config.recovery_entry().unwrap().cmdline, "console=ttyS0");
+        let u_boot = config.entry(2).unwrap();
+        assert_eq!(
+            u_boot.kind,
+            EntryKind::Chainload {
+                address: 0x4a00_0000
+            }
+        );
+        assert!(config.entry(3).is_none());
+    }
+
+    #[test]
+    fn first_entry_is_the_default() {
+        let config = parse("[one]\nkernel = /a\n[two]\nkernel = /b\n").unwrap();
+        assert_eq!(config.default_entry().name, "one");
+        assert_eq!(config.timeout, 0);
+        assert!(config.recovery_entry().is_none());
+    }
+
+    #[test]
+    fn rejects_duplicate_keys() {
+        assert!(matches!(
+            parse("timeout = 1\ntimeout = 2\n[a]\nkernel = /a\n"),
+            Err(ConfigError::DuplicateKey { line: 2 })
+        ));
+        assert!(matches!(
+            parse("[a]\nkernel = /a\ncmdline = x\nkernel = /b\n"),
+            Err(ConfigError::DuplicateKey { line: 4 })
+        ));
+        // the same key in different sections is fine
+        assert!(parse("[a]\nkernel = /a\n[b]\nkernel = /b\n").is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        assert!(matches!(
+            parse("default = missing\n[a]\nkernel = /a\n"),
+            Err(ConfigError::UnknownDefault)
+        ));
+        assert!(matches!(
+            parse("recovery = missing\n[a]\nkernel = /a\n"),
+            Err(ConfigError::UnknownRecovery)
+        ));
+    }
+
+    #[test]
+    fn chainload_needs_an_address() {
+        assert!(matches!(
+            parse("[a]\nkernel = /a\n[b]\ntype = chainload\nkernel = /b\n"),
+            Err(ConfigError::MissingAddress { line: 3 })
+        ));
+        assert!(matches!(
+            parse("[b]\ntype = chainload\nkernel = /b\naddress = 0xnope\n"),
+            Err(ConfigError::BadAddress { line: 4 })
+        ));
+        assert!(matches!(
+            parse("[b]\ntype = other\nkernel = /b\n"),
+            Err(ConfigError::UnknownType { line: 2 })
+        ));
+    }
+
+    #[test]
+    fn limits_entries() {
+        let mut text = std::string::String::new();
+        for i in 0..MAX_ENTRIES {
+            text += &std::format!("[e{}]\nkernel = /k\n", i);
+        }
+        assert_eq!(parse(&text).unwrap().entries().count(), MAX_ENTRIES);
+        text += "[one more]\nkernel = /k\n";
+        assert!(matches!(parse(&text), Err(ConfigError::TooManyEntries)));
+    }
+
+    #[test]
+    fn global_keys_only_before_sections() {
+        assert!(matches!(
+            parse("[a]\nkernel = /a\ntimeout = 3\n"),
+            Err(ConfigError::UnknownKey { line: 3 })
+        ));
+        assert!(matches!(
+            parse("kernel = /a\n[a]\nkernel = /a\n"),
+            Err(ConfigError::UnknownKey { line: 1 })
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(matches!(parse(""), Err(ConfigError::NoEntries)));
+        assert!(matches!(
+            parse("[a]\nkernel\n"),
+            Err(ConfigError::Syntax { line: 2 })
+        ));
+        assert!(matches!(
+            parse("[a]\ncmdline = x\n"),
+            Err(ConfigError::MissingKernel { line: 1 })
+        ));
+        assert!(matches!(
+            parse("timeout = soon\n[a]\nkernel = /a\n"),
+            Err(ConfigError::BadTimeout { line: 1 })
+        ));
+        assert!(matches!(
+            Config::parse(b"[a]\nkernel = /\xff\n"),
+            Err(ConfigError::NotUtf8)
+        ));
+    }
+}
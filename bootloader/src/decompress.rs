@@ -0,0 +1,321 @@
+//! Compressed kernel images.
+//!
+//! A whole `kernel.bin` may be compressed with `gzip` or `lz4` (frame format).
+//! [`Format::detect`] recognizes either by its magic and [`decompress`] streams
+//! it from a [`Source`] into an output buffer. The output doubles as the
+//! decompression window, so nothing else is allocated, and decompression stops
+//! with [`DecompressError::TooLarge`] rather than write past its end.
+use crate::{inflate, lz4};
+
+/// Bytes [`Format::detect`] needs to look at
+pub const MAGIC_SIZE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Lz4,
+}
+
+impl Format {
+    /// Format of the data starting with `magic`, `None` if it isn't compressed
+    pub fn detect(magic: &[u8]) -> Option<Self> {
+        match magic {
+            [0x1f, 0x8b, ..] => Some(Self::Gzip),
+            [0x04, 0x22, 0x4d, 0x18, ..] => Some(Self::Lz4),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Lz4 => "LZ4",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecompressError {
+    /// The [`Source`] failed, it knows why
+    Read,
+    /// Input ends in the middle of the stream
+    UnexpectedEnd,
+    /// Decompressed data doesn't fit in the output buffer
+    TooLarge,
+    Corrupt(&'static str),
+    Unsupported(&'static str),
+    ChecksumMismatch,
+    /// Decompressed size differs from the one the stream records
+    SizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl core::fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Read => write!(f, "read failed"),
+            Self::UnexpectedEnd => write!(f, "compressed data is truncated"),
+            Self::TooLarge => write!(f, "decompressed image does not fit in memory"),
+            Self::Corrupt(what) => write!(f, "corrupt data: {}", what),
+            Self::Unsupported(what) => write!(f, "unsupported: {}", what),
+            Self::ChecksumMismatch => write!(f, "checksum mismatch"),
+            Self::SizeMismatch { expected, actual } => write!(
+                f,
+                "size mismatch, stream says {} bytes but {} decompressed",
+                expected, actual
+            ),
+        }
+    }
+}
+
+/// Compressed input, consumed front to back
+pub trait Source {
+    /// The input not consumed yet, or the next part of it. Empty at the end.
+    fn fill(&mut self) -> Result<&[u8], DecompressError>;
+
+    /// Mark the first `amount` bytes returned by [`Source::fill`] as used
+    fn consume(&mut self, amount: usize);
+
+    fn read_u8(&mut self) -> Result<u8, DecompressError> {
+        let byte = *self.fill()?.first().ok_or(DecompressError::UnexpectedEnd)?;
+        self.consume(1);
+        Ok(byte)
+    }
+
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<(), DecompressError> {
+        while !buf.is_empty() {
+            let chunk = self.fill()?;
+            if chunk.is_empty() {
+                return Err(DecompressError::UnexpectedEnd);
+            }
+            let amount = chunk.len().min(buf.len());
+            buf[..amount].copy_from_slice(&chunk[..amount]);
+            self.consume(amount);
+            buf = &mut buf[amount..];
+        }
+        Ok(())
+    }
+
+    fn read_u16_le(&mut self) -> Result<u16, DecompressError> {
+        let mut bytes = [0u8; 2];
+        self.read_exact(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn read_u32_le(&mut self) -> Result<u32, DecompressError> {
+        let mut bytes = [0u8; 4];
+        self.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn skip(&mut self, mut amount: usize) -> Result<(), DecompressError> {
+        while amount > 0 {
+            let available = self.fill()?.len();
+            if available == 0 {
+                return Err(DecompressError::UnexpectedEnd);
+            }
+            let skipped = available.min(amount);
+            self.consume(skipped);
+            amount -= skipped;
+        }
+        Ok(())
+    }
+}
+
+impl Source for &[u8] {
+    fn fill(&mut self) -> Result<&[u8], DecompressError> {
+        Ok(self)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        *self = &self[amount..];
+    }
+}
+
+/// Decompress `source` into `out`, returning the decompressed size
+pub fn decompress<S: Source>(
+    format: Format,
+    source: &mut S,
+    out: &mut [u8],
+) -> Result<usize, DecompressError> {
+    match format {
+        Format::Gzip => inflate::gunzip(source, out),
+        Format::Lz4 => lz4::decode_frame(source, out),
+    }
+}
+
+/// Output cursor shared by the decoders, every write is bounds checked
+pub(crate) struct Output<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Output<'a> {
+    pub(crate) fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn written(&self) -> &[u8] {
+        &self.buf[..self.pos]
+    }
+
+    pub(crate) fn push(&mut self, byte: u8) -> Result<(), DecompressError> {
+        *self
+            .buf
+            .get_mut(self.pos)
+            .ok_or(DecompressError::TooLarge)? = byte;
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Copy `length` bytes from the source straight into the output
+    pub(crate) fn copy_from<S: Source>(
+        &mut self,
+        source: &mut S,
+        length: usize,
+    ) -> Result<(), DecompressError> {
+        let end = self
+            .pos
+            .checked_add(length)
+            .ok_or(DecompressError::TooLarge)?;
+        let dest = self
+            .buf
+            .get_mut(self.pos..end)
+            .ok_or(DecompressError::TooLarge)?;
+        source.read_exact(dest)?;
+        self.pos = end;
+        Ok(())
+    }
+
+    /// Repeat `length` bytes starting `distance` back, the ranges may overlap
+    pub(crate) fn copy_match(
+        &mut self,
+        distance: usize,
+        length: usize,
+    ) -> Result<(), DecompressError> {
+        if distance == 0 || distance > self.pos {
+            return Err(DecompressError::Corrupt("match distance too far back"));
+        }
+        let end = self
+            .pos
+            .checked_add(length)
+            .ok_or(DecompressError::TooLarge)?;
+        if end > self.buf.len() {
+            return Err(DecompressError::TooLarge);
+        }
+        for i in self.pos..end {
+            self.buf[i] = self.buf[i - distance];
+        }
+        self.pos = end;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    const KERNEL_LOG: &[u8] = include_bytes!("../testdata/kernel.log");
+    /// `gzip -9 -n kernel.log`
+    const KERNEL_LOG_GZ: &[u8] = include_bytes!("../testdata/kernel.log.gz");
+    /// `lz4 -9 --content-size kernel.log`
+    const KERNEL_LOG_LZ4: &[u8] = include_bytes!("../testdata/kernel.log.lz4");
+
+    const FIXTURES: [(Format, &[u8]); 2] =
+        [(Format::Gzip, KERNEL_LOG_GZ), (Format::Lz4, KERNEL_LOG_LZ4)];
+
+    /// Hands out the input a few bytes at a time, like a disk read in sectors
+    struct Chunked<'a>(&'a [u8]);
+
+    impl Source for Chunked<'_> {
+        fn fill(&mut self) -> Result<&[u8], DecompressError> {
+            Ok(&self.0[..self.0.len().min(7)])
+        }
+
+        fn consume(&mut self, amount: usize) {
+            self.0 = &self.0[amount..];
+        }
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(Format::detect(KERNEL_LOG_GZ), Some(Format::Gzip));
+        assert_eq!(Format::detect(KERNEL_LOG_LZ4), Some(Format::Lz4));
+        assert_eq!(Format::detect(KERNEL_LOG), None);
+        assert_eq!(Format::detect(&[0x1f]), None);
+    }
+
+    #[test]
+    fn round_trips() {
+        for (format, compressed) in FIXTURES {
+            let mut out = [0; KERNEL_LOG.len()];
+            let size = decompress(format, &mut &compressed[..], &mut out).unwrap();
+            assert_eq!(&out[..size], KERNEL_LOG, "{}", format.name());
+
+            let mut out = [0; KERNEL_LOG.len()];
+            let size = decompress(format, &mut Chunked(compressed), &mut out).unwrap();
+            assert_eq!(&out[..size], KERNEL_LOG, "{} in chunks", format.name());
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let mut out = [0; KERNEL_LOG.len()];
+        for (format, compressed) in FIXTURES {
+            for end in (0..compressed.len())
+                .step_by(61)
+                .chain([compressed.len() - 1])
+            {
+                assert_eq!(
+                    decompress(format, &mut &compressed[..end], &mut out),
+                    Err(DecompressError::UnexpectedEnd),
+                    "{} cut at {}",
+                    format.name(),
+                    end
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_output_larger_than_buffer() {
+        let mut out = [0; KERNEL_LOG.len() - 1];
+        for (format, compressed) in FIXTURES {
+            assert_eq!(
+                decompress(format, &mut &compressed[..], &mut out),
+                Err(DecompressError::TooLarge),
+                "{}",
+                format.name()
+            );
+        }
+    }
+
+    #[test]
+    fn match_distance_must_stay_in_output() {
+        let mut buf = [0; 8];
+        let mut out = Output::new(&mut buf);
+        assert!(matches!(
+            out.copy_match(1, 1),
+            Err(DecompressError::Corrupt(_))
+        ));
+        out.push(b'a').unwrap();
+        assert!(matches!(
+            out.copy_match(0, 1),
+            Err(DecompressError::Corrupt(_))
+        ));
+        assert!(matches!(
+            out.copy_match(2, 1),
+            Err(DecompressError::Corrupt(_))
+        ));
+        out.copy_match(1, 7).unwrap();
+        assert_eq!(out.written(), b"aaaaaaaa");
+        assert_eq!(out.copy_match(1, 1), Err(DecompressError::TooLarge));
+        assert_eq!(out.push(b'b'), Err(DecompressError::TooLarge));
+    }
+}
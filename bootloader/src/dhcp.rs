@@ -0,0 +1,216 @@
+//! DHCP client (RFC 2131), only the DISCOVER, OFFER, REQUEST and ACK
+//! exchange. The lease just has to outlast one download, so it is never
+//! renewed or released.
+use core::net::Ipv4Addr;
+
+use crate::net::{Config, Interface, NetError, REPLY_TIMEOUT};
+
+const CLIENT_PORT: u16 = 68;
+const SERVER_PORT: u16 = 67;
+
+const BOOTREQUEST: u8 = 1;
+const BOOTREPLY: u8 = 2;
+const HTYPE_ETHERNET: u8 = 1;
+/// Ask for replies to be broadcast, we can't receive unicast before we
+/// have an address
+const FLAG_BROADCAST: u16 = 0x8000;
+/// Identifies our transaction, any value does with a single client
+const XID: u32 = 0x4B42_4F4F;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+const CHADDR_OFFSET: usize = 28;
+const FILE_OFFSET: usize = 108;
+const FILE_SIZE: usize = 128;
+const OPTIONS_OFFSET: usize = 240;
+/// BOOTP messages are padded to this, some servers drop anything shorter
+const MIN_MESSAGE_SIZE: usize = 300;
+
+/// Messages sent, a second apart, before the server is given up on
+const RETRIES: u32 = 4;
+
+mod option {
+    pub const PAD: u8 = 0;
+    pub const SUBNET_MASK: u8 = 1;
+    pub const ROUTER: u8 = 3;
+    pub const REQUESTED_IP: u8 = 50;
+    pub const MESSAGE_TYPE: u8 = 53;
+    pub const SERVER_ID: u8 = 54;
+    pub const PARAMETER_LIST: u8 = 55;
+    pub const BOOTFILE_NAME: u8 = 67;
+    pub const END: u8 = 255;
+}
+
+mod message {
+    pub const DISCOVER: u8 = 1;
+    pub const OFFER: u8 = 2;
+    pub const REQUEST: u8 = 3;
+    pub const ACK: u8 = 5;
+    pub const NAK: u8 = 6;
+}
+
+/// What the DHCP server handed out
+pub struct Lease {
+    pub config: Config,
+    /// DHCP server that made the offer
+    pub server: Ipv4Addr,
+    /// Server to fetch the boot file from, `siaddr`
+    pub next_server: Ipv4Addr,
+    boot_file: [u8; FILE_SIZE],
+    boot_file_len: usize,
+}
+
+impl Lease {
+    /// Boot file the server named, if any
+    pub fn boot_file(&self) -> Option<&str> {
+        core::str::from_utf8(&self.boot_file[..self.boot_file_len])
+            .ok()
+            .filter(|name| !name.is_empty())
+    }
+}
+
+/// Get an address from the DHCP server and configure `iface` with it
+pub fn acquire(iface: &mut Interface) -> Result<Lease, NetError> {
+    let offer = exchange(iface, message::DISCOVER, None)?;
+    let lease = exchange(iface, message::REQUEST, Some(&offer))?;
+    iface.configure(lease.config);
+    Ok(lease)
+}
+
+/// Broadcast a `kind` message until the matching reply comes back
+fn exchange(iface: &mut Interface, kind: u8, offer: Option<&Lease>) -> Result<Lease, NetError> {
+    let (expected, waiting_for) = match kind {
+        message::DISCOVER => (message::OFFER, "a DHCP offer"),
+        _ => (message::ACK, "a DHCP acknowledgement"),
+    };
+    let mac = iface.mac();
+    for _ in 0..RETRIES {
+        let len = write_message(iface.udp_payload_mut(), &mac, kind, offer);
+        iface.send_udp(Ipv4Addr::BROADCAST, CLIENT_PORT, SERVER_PORT, len)?;
+        while let Some(datagram) = iface.receive_udp(CLIENT_PORT, REPLY_TIMEOUT)? {
+            if let Some(lease) = parse_reply(datagram.data, &mac, expected)? {
+                return Ok(lease);
+            }
+        }
+    }
+    Err(NetError::Timeout(waiting_for))
+}
+
+/// Write a `kind` message to `buf`, returning its length
+fn write_message(buf: &mut [u8], mac: &[u8; 6], kind: u8, offer: Option<&Lease>) -> usize {
+    buf[..MIN_MESSAGE_SIZE].fill(0);
+    buf[0] = BOOTREQUEST;
+    buf[1] = HTYPE_ETHERNET;
+    buf[2] = mac.len() as u8;
+    buf[4..8].copy_from_slice(&XID.to_be_bytes());
+    buf[10..12].copy_from_slice(&FLAG_BROADCAST.to_be_bytes());
+    buf[CHADDR_OFFSET..CHADDR_OFFSET + 6].copy_from_slice(mac);
+    buf[OPTIONS_OFFSET - 4..OPTIONS_OFFSET].copy_from_slice(&MAGIC_COOKIE);
+
+    let mut len = OPTIONS_OFFSET;
+    let mut put = |code: u8, value: &[u8]| {
+        buf[len] = code;
+        buf[len + 1] = value.len() as u8;
+        buf[len + 2..len + 2 + value.len()].copy_from_slice(value);
+        len += 2 + value.len();
+    };
+    put(option::MESSAGE_TYPE, &[kind]);
+    put(
+        option::PARAMETER_LIST,
+        &[
+            option::SUBNET_MASK,
+            option::ROUTER,
+            option::SERVER_ID,
+            option::BOOTFILE_NAME,
+        ],
+    );
+    if let Some(offer) = offer {
+        put(option::REQUESTED_IP, &offer.config.ip.octets());
+        put(option::SERVER_ID, &offer.server.octets());
+    }
+    buf[len] = option::END;
+    (len + 1).max(MIN_MESSAGE_SIZE)
+}
+
+fn read_ip(bytes: &[u8]) -> Option<Ipv4Addr> {
+    let octets: [u8; 4] = bytes.try_into().ok()?;
+    Some(Ipv4Addr::from(octets))
+}
+
+/// Parse `data` as a reply of type `expected` to our transaction, `None` if
+/// it is anything else
+fn parse_reply(data: &[u8], mac: &[u8; 6], expected: u8) -> Result<Option<Lease>, NetError> {
+    if data.len() < OPTIONS_OFFSET
+        || data[0] != BOOTREPLY
+        || data[4..8] != XID.to_be_bytes()
+        || data[CHADDR_OFFSET..CHADDR_OFFSET + 6] != mac[..]
+        || data[OPTIONS_OFFSET - 4..OPTIONS_OFFSET] != MAGIC_COOKIE
+    {
+        return Ok(None);
+    }
+
+    let mut lease = Lease {
+        config: Config {
+            ip: read_ip(&data[16..20]).unwrap(),
+            // without a mask everything goes through the router, if any
+            netmask: Ipv4Addr::BROADCAST,
+            router: None,
+        },
+        server: Ipv4Addr::UNSPECIFIED,
+        next_server: read_ip(&data[20..24]).unwrap(),
+        boot_file: [0; FILE_SIZE],
+        boot_file_len: 0,
+    };
+    let file = &data[FILE_OFFSET..FILE_OFFSET + FILE_SIZE];
+    let file_len = file.iter().position(|&b| b == 0).unwrap_or(FILE_SIZE);
+    lease.boot_file[..file_len].copy_from_slice(&file[..file_len]);
+    lease.boot_file_len = file_len;
+
+    let mut kind = None;
+    let mut options = &data[OPTIONS_OFFSET..];
+    while let [code, rest @ ..] = options {
+        match *code {
+            option::PAD => {
+                options = rest;
+                continue;
+            }
+            option::END => break,
+            _ => {}
+        }
+        let Some((&len, rest)) = rest.split_first() else {
+            break;
+        };
+        let Some((value, rest)) = rest.split_at_checked(len as usize) else {
+            break;
+        };
+        options = rest;
+
+        match *code {
+            option::MESSAGE_TYPE => kind = value.first().copied(),
+            option::SUBNET_MASK => {
+                lease.config.netmask = read_ip(value).unwrap_or(lease.config.netmask)
+            }
+            // the first router is the preferred one
+            option::ROUTER => lease.config.router = value.get(..4).and_then(read_ip),
+            option::SERVER_ID => lease.server = read_ip(value).unwrap_or(lease.server),
+            option::BOOTFILE_NAME if value.len() <= FILE_SIZE => {
+                let len = value.iter().position(|&b| b == 0).unwrap_or(value.len());
+                lease.boot_file[..len].copy_from_slice(&value[..len]);
+                lease.boot_file_len = len;
+            }
+            _ => {}
+        }
+    }
+
+    match kind {
+        Some(message::NAK) if expected == message::ACK => Err(NetError::Protocol(
+            "DHCP server refused the address it offered",
+        )),
+        Some(kind) if kind == expected => {
+            if lease.next_server.is_unspecified() {
+                lease.next_server = lease.server;
+            }
+            Ok(Some(lease))
+        }
+        _ => Ok(None),
+    }
+}
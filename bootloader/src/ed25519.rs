@@ -0,0 +1,481 @@
+//! Ed25519 signature verification (RFC 8032).
+//!
+//! Only verification is implemented, signing happens on the host (see
+//! `tools/signkernel.sh`). Nothing here handles secrets, so the code is
+//! variable time and favours being short over being fast; checking a kernel
+//! image costs one SHA-512 pass and two scalar multiplications.
+use crate::sha512::Sha512;
+
+pub const PUBLIC_KEY_SIZE: usize = 32;
+pub const SIGNATURE_SIZE: usize = 64;
+
+/// Edwards curve constant d = -121665/121666
+const D: [u8; 32] = [
+    0xa3, 0x78, 0x59, 0x13, 0xca, 0x4d, 0xeb, 0x75, 0xab, 0xd8, 0x41, 0x41, 0x4d, 0x0a, 0x70, 0x00,
+    0x98, 0xe8, 0x79, 0x77, 0x79, 0x40, 0xc7, 0x8c, 0x73, 0xfe, 0x6f, 0x2b, 0xee, 0x6c, 0x03, 0x52,
+];
+const SQRT_M1: [u8; 32] = [
+    0xb0, 0xa0, 0x0e, 0x4a, 0x27, 0x1b, 0xee, 0xc4, 0x78, 0xe4, 0x2f, 0xad, 0x06, 0x18, 0x43, 0x2f,
+    0xa7, 0xd7, 0xfb, 0x3d, 0x99, 0x00, 0x4d, 0x2b, 0x0b, 0xdf, 0xc1, 0x4f, 0x80, 0x24, 0x83, 0x2b,
+];
+/// Encoding of the base point, y = 4/5 with even x
+const BASE: [u8; 32] = [
+    0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+];
+/// Group order, as little endian 64-bit limbs
+const L: [u64; 4] = [
+    0x5812631a5cf5d3ed,
+    0x14def9dea2f79cd6,
+    0,
+    0x1000000000000000,
+];
+
+/// p - 2, inverts by Fermat's little theorem
+const EXP_INVERT: [u8; 32] = {
+    let mut exp = [0xff; 32];
+    exp[0] = 0xeb;
+    exp[31] = 0x7f;
+    exp
+};
+/// (p - 5) / 8, used for square roots
+const EXP_SQRT: [u8; 32] = {
+    let mut exp = [0xff; 32];
+    exp[0] = 0xfd;
+    exp[31] = 0x0f;
+    exp
+};
+
+const MASK: u64 = (1 << 51) - 1;
+
+fn bit(bytes: &[u8; 32], i: usize) -> bool {
+    bytes[i / 8] >> (i % 8) & 1 == 1
+}
+
+/// Element of GF(2^255 - 19) in five 51-bit limbs
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    /// Load 255 bits, ignoring the top bit
+    fn from_bytes(bytes: &[u8; 32]) -> Self {
+        let load = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        Fe([
+            load(0) & MASK,
+            (load(6) >> 3) & MASK,
+            (load(12) >> 6) & MASK,
+            (load(19) >> 1) & MASK,
+            (load(24) >> 12) & MASK,
+        ])
+    }
+
+    /// Canonical encoding, fully reduced mod p
+    fn to_bytes(self) -> [u8; 32] {
+        let mut h = self.carry().carry().0;
+
+        // h >= p exactly when h + 19 overflows 255 bits
+        let mut q = (h[0] + 19) >> 51;
+        for limb in &h[1..] {
+            q = (limb + q) >> 51;
+        }
+        h[0] += 19 * q;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[4] &= MASK;
+
+        let words = [
+            h[0] | h[1] << 51,
+            h[1] >> 13 | h[2] << 38,
+            h[2] >> 26 | h[3] << 25,
+            h[3] >> 39 | h[4] << 12,
+        ];
+        let mut bytes = [0u8; 32];
+        for (out, word) in bytes.as_chunks_mut::<8>().0.iter_mut().zip(words) {
+            *out = word.to_le_bytes();
+        }
+        bytes
+    }
+
+    /// Bring every limb back to about 51 bits
+    fn carry(self) -> Self {
+        let mut h = self.0;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[0] += 19 * (h[4] >> 51);
+        h[4] &= MASK;
+        Fe(h)
+    }
+
+    fn add(&self, other: &Fe) -> Fe {
+        let mut h = self.0;
+        for (limb, o) in h.iter_mut().zip(other.0) {
+            *limb += o;
+        }
+        Fe(h).carry()
+    }
+
+    fn sub(&self, other: &Fe) -> Fe {
+        // add 2p first so no limb underflows
+        let two_p = [2 * (MASK - 18), 2 * MASK, 2 * MASK, 2 * MASK, 2 * MASK];
+        let other = other.carry();
+        let mut h = self.0;
+        for i in 0..5 {
+            h[i] = h[i] + two_p[i] - other.0[i];
+        }
+        Fe(h).carry()
+    }
+
+    fn neg(&self) -> Fe {
+        Fe::ZERO.sub(self)
+    }
+
+    fn mul(&self, other: &Fe) -> Fe {
+        let [a0, a1, a2, a3, a4] = self.0;
+        let [b0, b1, b2, b3, b4] = other.0;
+        let m = |x: u64, y: u64| x as u128 * y as u128;
+        // 2^255 = 19 mod p, so limbs past the top wrap around times 19
+        let (b1_19, b2_19, b3_19, b4_19) = (b1 * 19, b2 * 19, b3 * 19, b4 * 19);
+
+        let mut r = [
+            m(a0, b0) + m(a1, b4_19) + m(a2, b3_19) + m(a3, b2_19) + m(a4, b1_19),
+            m(a0, b1) + m(a1, b0) + m(a2, b4_19) + m(a3, b3_19) + m(a4, b2_19),
+            m(a0, b2) + m(a1, b1) + m(a2, b0) + m(a3, b4_19) + m(a4, b3_19),
+            m(a0, b3) + m(a1, b2) + m(a2, b1) + m(a3, b0) + m(a4, b4_19),
+            m(a0, b4) + m(a1, b3) + m(a2, b2) + m(a3, b1) + m(a4, b0),
+        ];
+        for i in 0..4 {
+            r[i + 1] += r[i] >> 51;
+            r[i] &= MASK as u128;
+        }
+        r[0] += 19 * (r[4] >> 51);
+        r[4] &= MASK as u128;
+        r[1] += r[0] >> 51;
+        r[0] &= MASK as u128;
+        Fe(r.map(|limb| limb as u64)).carry()
+    }
+
+    fn square(&self) -> Fe {
+        self.mul(self)
+    }
+
+    fn pow(&self, exp: &[u8; 32]) -> Fe {
+        let mut result = Fe::ONE;
+        for i in (0..256).rev() {
+            result = result.square();
+            if bit(exp, i) {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+
+    fn invert(&self) -> Fe {
+        self.pow(&EXP_INVERT)
+    }
+
+    fn is_negative(&self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    fn equals(&self, other: &Fe) -> bool {
+        self.to_bytes() == other.to_bytes()
+    }
+}
+
+/// Curve point in extended coordinates, x = X/Z, y = Y/Z, xy = T/Z
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    const IDENTITY: Point = Point {
+        x: Fe::ZERO,
+        y: Fe::ONE,
+        z: Fe::ONE,
+        t: Fe::ZERO,
+    };
+
+    fn decompress(bytes: &[u8; 32]) -> Option<Point> {
+        let y = Fe::from_bytes(bytes);
+        let sign = bytes[31] >> 7 == 1;
+        // RFC 8032 rejects encodings of y that aren't reduced
+        let mut canonical = *bytes;
+        canonical[31] &= 0x7f;
+        if y.to_bytes() != canonical {
+            return None;
+        }
+
+        // x^2 = (y^2 - 1) / (d y^2 + 1)
+        let d = Fe::from_bytes(&D);
+        let y2 = y.square();
+        let u = y2.sub(&Fe::ONE);
+        let v = d.mul(&y2).add(&Fe::ONE);
+        let v3 = v.square().mul(&v);
+        let v7 = v3.square().mul(&v);
+        let mut x = u.mul(&v3).mul(&u.mul(&v7).pow(&EXP_SQRT));
+
+        let vx2 = v.mul(&x.square());
+        if !vx2.equals(&u) {
+            if !vx2.equals(&u.neg()) {
+                return None;
+            }
+            x = x.mul(&Fe::from_bytes(&SQRT_M1));
+        }
+        if sign && x.equals(&Fe::ZERO) {
+            return None;
+        }
+        if x.is_negative() != sign {
+            x = x.neg();
+        }
+
+        Some(Point {
+            x,
+            y,
+            z: Fe::ONE,
+            t: x.mul(&y),
+        })
+    }
+
+    fn compress(&self) -> [u8; 32] {
+        let z_inv = self.z.invert();
+        let x = self.x.mul(&z_inv);
+        let mut bytes = self.y.mul(&z_inv).to_bytes();
+        bytes[31] |= (x.is_negative() as u8) << 7;
+        bytes
+    }
+
+    fn neg(&self) -> Point {
+        Point {
+            x: self.x.neg(),
+            t: self.t.neg(),
+            ..*self
+        }
+    }
+
+    /// Complete addition for a = -1 twisted Edwards curves, also doubles
+    fn add(&self, other: &Point) -> Point {
+        let d2 = Fe::from_bytes(&D).add(&Fe::from_bytes(&D));
+        let a = self.y.sub(&self.x).mul(&other.y.sub(&other.x));
+        let b = self.y.add(&self.x).mul(&other.y.add(&other.x));
+        let c = self.t.mul(&d2).mul(&other.t);
+        let d = self.z.add(&self.z).mul(&other.z);
+        let (e, f, g, h) = (b.sub(&a), d.sub(&c), d.add(&c), b.add(&a));
+        Point {
+            x: e.mul(&f),
+            y: g.mul(&h),
+            z: f.mul(&g),
+            t: e.mul(&h),
+        }
+    }
+
+    /// [a]self + [b]other
+    fn double_mul(&self, a: &[u8; 32], other: &Point, b: &[u8; 32]) -> Point {
+        let mut result = Point::IDENTITY;
+        for i in (0..256).rev() {
+            result = result.add(&result);
+            if bit(a, i) {
+                result = result.add(self);
+            }
+            if bit(b, i) {
+                result = result.add(other);
+            }
+        }
+        result
+    }
+}
+
+fn scalar_limbs(bytes: &[u8; 32]) -> [u64; 4] {
+    let mut limbs = [0u64; 4];
+    for (limb, chunk) in limbs.iter_mut().zip(bytes.as_chunks::<8>().0) {
+        *limb = u64::from_le_bytes(*chunk);
+    }
+    limbs
+}
+
+fn less_than_l(limbs: &[u64; 4]) -> bool {
+    for i in (0..4).rev() {
+        if limbs[i] != L[i] {
+            return limbs[i] < L[i];
+        }
+    }
+    false
+}
+
+/// Reduce a 512-bit little endian number mod L
+fn reduce_scalar(wide: &[u8; 64]) -> [u8; 32] {
+    // shift bits in from the top, r < L < 2^253 so 2r + 1 never overflows
+    let mut r = [0u64; 4];
+    for i in (0..512).rev() {
+        let mut carry = (wide[i / 8] >> (i % 8) & 1) as u64;
+        for limb in &mut r {
+            let top = *limb >> 63;
+            *limb = *limb << 1 | carry;
+            carry = top;
+        }
+        if !less_than_l(&r) {
+            let mut borrow = false;
+            for (limb, l) in r.iter_mut().zip(L) {
+                let (value, b1) = limb.overflowing_sub(l);
+                let (value, b2) = value.overflowing_sub(borrow as u64);
+                *limb = value;
+                borrow = b1 || b2;
+            }
+        }
+    }
+
+    let mut bytes = [0u8; 32];
+    for (out, limb) in bytes.as_chunks_mut::<8>().0.iter_mut().zip(r) {
+        *out = limb.to_le_bytes();
+    }
+    bytes
+}
+
+/// Check `signature` over `message` against `public_key`
+pub fn verify(
+    public_key: &[u8; PUBLIC_KEY_SIZE],
+    message: &[u8],
+    signature: &[u8; SIGNATURE_SIZE],
+) -> bool {
+    let (r, s) = signature.split_at(32);
+    let r: &[u8; 32] = r.try_into().unwrap();
+    let s: &[u8; 32] = s.try_into().unwrap();
+    if !less_than_l(&scalar_limbs(s)) {
+        return false;
+    }
+    let Some(a) = Point::decompress(public_key) else {
+        return false;
+    };
+    let base = Point::decompress(&BASE).expect("base point decodes");
+
+    // k = SHA-512(R || A || M) mod L
+    let mut hasher = Sha512::new();
+    hasher.update(r);
+    hasher.update(public_key);
+    hasher.update(message);
+    let k = reduce_scalar(&hasher.finish());
+
+    // [S]B = R + [k]A  <=>  [S]B - [k]A = R
+    base.double_mul(s, &a.neg(), &k).compress() == *r
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    fn hex<const N: usize>(s: &str) -> [u8; N] {
+        let mut bytes = [0; N];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    /// RFC 8032 section 7.1, tests 1 to 3: public key, message, signature
+    const VECTORS: [(&str, &[u8], &str); 3] = [
+        (
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            b"",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555\
+             fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            &[0x72],
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da0\
+             85ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            &[0xaf, 0x82],
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac1\
+             8ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    fn vector(i: usize) -> ([u8; PUBLIC_KEY_SIZE], &'static [u8], [u8; SIGNATURE_SIZE]) {
+        let (public_key, message, signature) = VECTORS[i];
+        (hex(public_key), message, hex(signature))
+    }
+
+    #[test]
+    fn rfc8032_vectors() {
+        for i in 0..VECTORS.len() {
+            let (public_key, message, signature) = vector(i);
+            assert!(verify(&public_key, message, &signature), "test {}", i + 1);
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_message() {
+        let (public_key, _, signature) = vector(2);
+        assert!(!verify(&public_key, &[0xaf, 0x83], &signature));
+        assert!(!verify(&public_key, &[0xaf], &signature));
+        let (other_key, _, _) = vector(1);
+        assert!(!verify(&other_key, &[0xaf, 0x82], &signature));
+    }
+
+    #[test]
+    fn rejects_tampered_signature() {
+        let (public_key, message, mut signature) = vector(1);
+        signature[0] ^= 1;
+        assert!(!verify(&public_key, message, &signature));
+    }
+
+    #[test]
+    fn rejects_s_not_below_l() {
+        // S + L passes the group equation, only the range check stops it
+        let (public_key, message, mut signature) = vector(0);
+        let mut carry = false;
+        for (chunk, l) in signature[32..].as_chunks_mut::<8>().0.iter_mut().zip(L) {
+            let (value, c1) = u64::from_le_bytes(*chunk).overflowing_add(l);
+            let (value, c2) = value.overflowing_add(carry as u64);
+            *chunk = value.to_le_bytes();
+            carry = c1 || c2;
+        }
+        assert!(!carry);
+        assert!(!verify(&public_key, message, &signature));
+    }
+
+    #[test]
+    fn rejects_bad_public_keys() {
+        let (_, message, signature) = vector(0);
+
+        // y = p, the non-canonical encoding of y = 0
+        let mut y_is_p = [0xff; 32];
+        y_is_p[0] = 0xed;
+        y_is_p[31] = 0x7f;
+        assert!(Point::decompress(&y_is_p).is_none());
+        assert!(!verify(&y_is_p, message, &signature));
+
+        // y = 2 has no x on the curve
+        let mut off_curve = [0; 32];
+        off_curve[0] = 2;
+        assert!(Point::decompress(&off_curve).is_none());
+        assert!(!verify(&off_curve, message, &signature));
+
+        // y = 1 has x = 0, which can't have the sign bit set
+        let mut negative_zero = [0; 32];
+        negative_zero[0] = 1;
+        negative_zero[31] = 0x80;
+        assert!(Point::decompress(&negative_zero).is_none());
+        assert!(!verify(&negative_zero, message, &signature));
+    }
+
+    #[test]
+    fn base_point_round_trips() {
+        let base = Point::decompress(&BASE).unwrap();
+        assert_eq!(base.compress(), BASE);
+        assert_eq!(base.add(&base.neg()).compress(), Point::IDENTITY.compress());
+    }
+}
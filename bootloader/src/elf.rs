@@ -0,0 +1,516 @@
+//! ELF32 kernel images, checked before the bootloader loads them.
+//!
+//! [`ElfImage::validate`] makes sure every PT_LOAD segment can be copied to
+//! its physical address (`p_paddr`) before anything is written, and
+//! [`ElfImage::section_permissions`] says how each 1MB section of the
+//! virtual range is mapped, from the segments' `p_flags`.
+use core::ops::Range;
+
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_ARM: u16 = 40;
+
+const PT_LOAD: u32 = 1;
+
+const PF_X: u32 = 1 << 0;
+const PF_W: u32 = 1 << 1;
+
+const EHDR_SIZE: usize = 52;
+const PHDR_SIZE: usize = 32;
+
+/// Size of the sections segments are mapped in, `hal::mmu::SECTION_SIZE`
+pub const SECTION_SIZE: u32 = 0x10_0000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ElfError {
+    /// Image is shorter than the headers claim
+    Truncated,
+    /// Not an ELF file at all
+    BadMagic,
+    /// Not a little-endian ELF32 file
+    UnsupportedFormat,
+    /// ELF file that is not an executable (`e_type`)
+    NotExecutable(u16),
+    /// ELF file built for another architecture (`e_machine`)
+    WrongMachine(u16),
+    /// Program header table with an unexpected entry size
+    BadProgramHeaders,
+    /// No PT_LOAD segments to load
+    NoSegments,
+    /// Segment with `p_filesz > p_memsz`
+    BadSegment { vaddr: u32 },
+    /// Segment virtual and physical addresses differ within a 1MB section
+    Misaligned { vaddr: u32, paddr: u32 },
+    /// Segment destination outside of DRAM or over a reserved region
+    BadLoadAddress { paddr: u32, size: u32 },
+}
+
+impl core::fmt::Display for ElfError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "image is truncated"),
+            Self::BadMagic => write!(f, "not an ELF image"),
+            Self::UnsupportedFormat => write!(f, "not a little-endian ELF32 image"),
+            Self::NotExecutable(t) => write!(f, "ELF type {} is not EXEC", t),
+            Self::WrongMachine(m) => write!(f, "ELF machine {} is not ARM", m),
+            Self::BadProgramHeaders => write!(f, "malformed program header table"),
+            Self::NoSegments => write!(f, "no loadable segments"),
+            Self::BadSegment { vaddr } => write!(f, "segment at 0x{:x} has filesz > memsz", vaddr),
+            Self::Misaligned { vaddr, paddr } => write!(
+                f,
+                "segment 0x{:x} -> 0x{:x} is not section aligned",
+                vaddr, paddr
+            ),
+            Self::BadLoadAddress { paddr, size } => {
+                write!(f, "segment 0x{:x}+0x{:x} is outside free DRAM", paddr, size)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub offset: u32,
+    pub vaddr: u32,
+    pub paddr: u32,
+    pub filesz: u32,
+    pub memsz: u32,
+    pub flags: u32,
+}
+
+/// How a section of the image is mapped, the union of every segment in it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    pub writable: bool,
+    pub executable: bool,
+}
+
+/// A validated ELF32 ARM executable
+pub struct ElfImage<'a> {
+    data: &'a [u8],
+    entry: u32,
+    phoff: usize,
+    phnum: usize,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ElfError> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(ElfError::Truncated)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ElfError> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(ElfError::Truncated)
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+impl<'a> ElfImage<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        if data.len() < EHDR_SIZE {
+            return Err(ElfError::Truncated);
+        }
+        if data[0..4] != ELF_MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        if data[4] != ELFCLASS32 || data[5] != ELFDATA2LSB || data[6] != EV_CURRENT {
+            return Err(ElfError::UnsupportedFormat);
+        }
+
+        let e_type = read_u16(data, 16)?;
+        if e_type != ET_EXEC {
+            return Err(ElfError::NotExecutable(e_type));
+        }
+        let e_machine = read_u16(data, 18)?;
+        if e_machine != EM_ARM {
+            return Err(ElfError::WrongMachine(e_machine));
+        }
+
+        let entry = read_u32(data, 24)?;
+        let phoff = read_u32(data, 28)? as usize;
+        let phentsize = read_u16(data, 42)? as usize;
+        let phnum = read_u16(data, 44)? as usize;
+
+        if phentsize != PHDR_SIZE {
+            return Err(ElfError::BadProgramHeaders);
+        }
+        if phoff
+            .checked_add(phnum * PHDR_SIZE)
+            .is_none_or(|end| end > data.len())
+        {
+            return Err(ElfError::Truncated);
+        }
+
+        Ok(Self {
+            data,
+            entry,
+            phoff,
+            phnum,
+        })
+    }
+
+    pub fn entry(&self) -> usize {
+        self.entry as usize
+    }
+
+    /// Lowest and highest (exclusive) physical address the segments will
+    /// cover once loaded
+    pub fn phys_range(&self) -> Result<Range<usize>, ElfError> {
+        let mut phys_start = usize::MAX;
+        let mut phys_end = 0;
+        for ph in self.load_segments() {
+            let ph = ph?;
+            if ph.memsz == 0 {
+                continue;
+            }
+            phys_start = phys_start.min(ph.paddr as usize);
+            phys_end = phys_end.max((ph.paddr as usize).saturating_add(ph.memsz as usize));
+        }
+        if phys_start >= phys_end {
+            return Err(ElfError::NoSegments);
+        }
+        Ok(phys_start..phys_end)
+    }
+
+    /// File data that will be loaded from physical address `paddr` up to the
+    /// end of its segment's file data, empty if nothing is
+    pub fn data_at(&self, paddr: usize) -> Result<&'a [u8], ElfError> {
+        for ph in self.load_segments() {
+            let ph = ph?;
+            let start = ph.paddr as usize;
+            if paddr < start || paddr - start >= ph.filesz as usize {
+                continue;
+            }
+            let offset = ph.offset as usize;
+            return self
+                .data
+                .get(offset + (paddr - start)..offset + ph.filesz as usize)
+                .ok_or(ElfError::Truncated);
+        }
+        Ok(&[])
+    }
+
+    fn program_header(&self, index: usize) -> Result<ProgramHeader, ElfError> {
+        let base = self.phoff + index * PHDR_SIZE;
+        Ok(ProgramHeader {
+            p_type: read_u32(self.data, base)?,
+            offset: read_u32(self.data, base + 4)?,
+            vaddr: read_u32(self.data, base + 8)?,
+            paddr: read_u32(self.data, base + 12)?,
+            filesz: read_u32(self.data, base + 16)?,
+            memsz: read_u32(self.data, base + 20)?,
+            flags: read_u32(self.data, base + 24)?,
+        })
+    }
+
+    /// Every PT_LOAD program header, in file order
+    pub fn load_segments(&self) -> impl Iterator<Item = Result<ProgramHeader, ElfError>> + '_ {
+        (0..self.phnum)
+            .map(|i| self.program_header(i))
+            .filter(|ph| ph.as_ref().map_or(true, |ph| ph.p_type == PT_LOAD))
+    }
+
+    /// File data of a segment [`Self::validate`] passed
+    pub fn file_data(&self, ph: &ProgramHeader) -> Result<&'a [u8], ElfError> {
+        let offset = ph.offset as usize;
+        self.data
+            .get(offset..offset + ph.filesz as usize)
+            .ok_or(ElfError::Truncated)
+    }
+
+    /// Check every segment before anything is written, so a bad image
+    /// leaves memory untouched. Segments have to land inside `dram` and
+    /// clear of every range in `reserved`.
+    pub fn validate(&self, dram: Range<usize>, reserved: &[Range<usize>]) -> Result<(), ElfError> {
+        let mut count = 0;
+        for ph in self.load_segments() {
+            let ph = ph?;
+            count += 1;
+
+            if ph.filesz > ph.memsz {
+                return Err(ElfError::BadSegment { vaddr: ph.vaddr });
+            }
+            let file_end = ph
+                .offset
+                .checked_add(ph.filesz)
+                .ok_or(ElfError::Truncated)?;
+            if file_end as usize > self.data.len() {
+                return Err(ElfError::Truncated);
+            }
+            if (ph.vaddr ^ ph.paddr) & (SECTION_SIZE - 1) != 0 {
+                return Err(ElfError::Misaligned {
+                    vaddr: ph.vaddr,
+                    paddr: ph.paddr,
+                });
+            }
+
+            let bad_address = ElfError::BadLoadAddress {
+                paddr: ph.paddr,
+                size: ph.memsz,
+            };
+            let (Some(_), Some(phys_end)) = (
+                ph.vaddr.checked_add(ph.memsz),
+                (ph.paddr as usize).checked_add(ph.memsz as usize),
+            ) else {
+                return Err(bad_address);
+            };
+            let dest = ph.paddr as usize..phys_end;
+            if dest.start < dram.start || dest.end > dram.end {
+                return Err(bad_address);
+            }
+            if reserved.iter().any(|r| overlaps(&dest, r)) {
+                return Err(bad_address);
+            }
+        }
+
+        if count == 0 {
+            return Err(ElfError::NoSegments);
+        }
+        Ok(())
+    }
+
+    /// Permissions of the section at `section`, merging those of every
+    /// segment that touches it
+    pub fn section_permissions(&self, section: u32) -> Result<Permissions, ElfError> {
+        let mut permissions = Permissions {
+            writable: false,
+            executable: false,
+        };
+        for ph in self.load_segments() {
+            let ph = ph?;
+            if ph.memsz == 0 {
+                continue;
+            }
+            let first = ph.vaddr & !(SECTION_SIZE - 1);
+            let last = (ph.vaddr + ph.memsz - 1) & !(SECTION_SIZE - 1);
+            if (first..=last).contains(&section) {
+                permissions.writable |= ph.flags & PF_W != 0;
+                permissions.executable |= ph.flags & PF_X != 0;
+            }
+        }
+        Ok(permissions)
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    const DRAM: Range<usize> = 0x4000_0000..0x6000_0000;
+    const PF_R: u32 = 1 << 2;
+
+    struct Segment {
+        vaddr: u32,
+        paddr: u32,
+        filesz: u32,
+        memsz: u32,
+        flags: u32,
+    }
+
+    /// Identity mapped at `addr`, with `filesz` bytes of file data
+    fn segment(addr: u32, filesz: u32, memsz: u32, flags: u32) -> Segment {
+        Segment {
+            vaddr: addr,
+            paddr: addr,
+            filesz,
+            memsz,
+            flags,
+        }
+    }
+
+    /// An ARM executable with `segments`, their file data after the headers
+    fn build(segments: &[Segment]) -> Vec<u8> {
+        let mut data = std::vec![0u8; EHDR_SIZE + segments.len() * PHDR_SIZE];
+        data[..4].copy_from_slice(&ELF_MAGIC);
+        data[4..7].copy_from_slice(&[ELFCLASS32, ELFDATA2LSB, EV_CURRENT]);
+        data[16..18].copy_from_slice(&ET_EXEC.to_le_bytes());
+        data[18..20].copy_from_slice(&EM_ARM.to_le_bytes());
+        data[24..28].copy_from_slice(&segments.first().map_or(0, |s| s.vaddr).to_le_bytes());
+        data[28..32].copy_from_slice(&(EHDR_SIZE as u32).to_le_bytes());
+        data[42..44].copy_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+        data[44..46].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+        for (i, s) in segments.iter().enumerate() {
+            let offset = data.len() as u32;
+            data.resize(data.len() + s.filesz as usize, 0xa5);
+            let words = [
+                PT_LOAD, offset, s.vaddr, s.paddr, s.filesz, s.memsz, s.flags, 4,
+            ];
+            let base = EHDR_SIZE + i * PHDR_SIZE;
+            for (j, word) in words.into_iter().enumerate() {
+                data[base + 4 * j..base + 4 * j + 4].copy_from_slice(&word.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    fn kernel() -> Vec<u8> {
+        build(&[
+            segment(0x4100_0000, 0x100, 0x100, PF_R | PF_X),
+            segment(0x4110_0000, 0x80, 0x1000, PF_R | PF_W),
+        ])
+    }
+
+    #[test]
+    fn accepts_a_kernel() {
+        let data = kernel();
+        let elf = ElfImage::parse(&data).unwrap();
+        assert_eq!(elf.entry(), 0x4100_0000);
+        assert_eq!(elf.phys_range(), Ok(0x4100_0000..0x4110_1000));
+        assert_eq!(elf.validate(DRAM, &[]), Ok(()));
+        let segments: Vec<_> = elf.load_segments().map(Result::unwrap).collect();
+        assert_eq!(elf.file_data(&segments[1]).unwrap(), [0xa5; 0x80]);
+        assert_eq!(elf.data_at(0x4100_0010).unwrap().len(), 0xf0);
+    }
+
+    #[test]
+    fn rejects_other_executables() {
+        let good = kernel();
+
+        let mut data = good.clone();
+        data[18..20].copy_from_slice(&3u16.to_le_bytes());
+        assert_eq!(
+            ElfImage::parse(&data).err(),
+            Some(ElfError::WrongMachine(3))
+        );
+
+        let mut data = good.clone();
+        data[16..18].copy_from_slice(&3u16.to_le_bytes());
+        assert_eq!(
+            ElfImage::parse(&data).err(),
+            Some(ElfError::NotExecutable(3))
+        );
+
+        // ELFCLASS64
+        let mut data = good.clone();
+        data[4] = 2;
+        assert_eq!(
+            ElfImage::parse(&data).err(),
+            Some(ElfError::UnsupportedFormat)
+        );
+
+        let mut data = good.clone();
+        data[0] = 0;
+        assert_eq!(ElfImage::parse(&data).err(), Some(ElfError::BadMagic));
+        assert_eq!(
+            ElfImage::parse(&good[..EHDR_SIZE - 1]).err(),
+            Some(ElfError::Truncated)
+        );
+        // the program headers run past the end
+        assert_eq!(
+            ElfImage::parse(&good[..EHDR_SIZE + PHDR_SIZE]).err(),
+            Some(ElfError::Truncated)
+        );
+    }
+
+    #[test]
+    fn keeps_segments_off_reserved_memory() {
+        let data = kernel();
+        let elf = ElfImage::parse(&data).unwrap();
+        let bss = ElfError::BadLoadAddress {
+            paddr: 0x4110_0000,
+            size: 0x1000,
+        };
+        // the bootloader, and a staging buffer up against the kernel
+        let below = 0x4001_0000..0x4010_0000;
+        let staging = |start| start..0x4200_0000;
+        // touching the BSS tail counts, beyond the file data
+        assert_eq!(
+            elf.validate(DRAM, &[below.clone(), staging(0x4110_0fff)]),
+            Err(bss)
+        );
+        assert_eq!(
+            elf.validate(DRAM, &[below.clone(), staging(0x4110_1000)]),
+            Ok(())
+        );
+        assert_eq!(
+            elf.validate(DRAM, &[below, 0x4010_0000..0x4100_0000]),
+            Ok(())
+        );
+        // or outside DRAM
+        assert!(elf.validate(0x4000_0000..0x4110_0800, &[]).is_err());
+    }
+
+    #[test]
+    fn rejects_file_data_past_the_end() {
+        let mut data = kernel();
+        // the second segment's p_filesz
+        let filesz = EHDR_SIZE + PHDR_SIZE + 16;
+        data[filesz..filesz + 4].copy_from_slice(&0x81u32.to_le_bytes());
+        let elf = ElfImage::parse(&data).unwrap();
+        assert_eq!(elf.validate(DRAM, &[]), Err(ElfError::Truncated));
+
+        // or an offset so large the end wraps
+        data[filesz - 12..filesz - 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let elf = ElfImage::parse(&data).unwrap();
+        assert_eq!(elf.validate(DRAM, &[]), Err(ElfError::Truncated));
+    }
+
+    #[test]
+    fn rejects_bad_segments() {
+        let data = build(&[segment(0x4100_0000, 0x100, 0x80, PF_R)]);
+        let elf = ElfImage::parse(&data).unwrap();
+        assert_eq!(
+            elf.validate(DRAM, &[]),
+            Err(ElfError::BadSegment { vaddr: 0x4100_0000 })
+        );
+
+        let data = build(&[Segment {
+            vaddr: 0xc000_0000,
+            paddr: 0x4100_1000,
+            filesz: 0,
+            memsz: 0x1000,
+            flags: PF_R,
+        }]);
+        let elf = ElfImage::parse(&data).unwrap();
+        assert!(matches!(
+            elf.validate(DRAM, &[]),
+            Err(ElfError::Misaligned { .. })
+        ));
+
+        let data = build(&[]);
+        let elf = ElfImage::parse(&data).unwrap();
+        assert_eq!(elf.validate(DRAM, &[]), Err(ElfError::NoSegments));
+    }
+
+    #[test]
+    fn merges_permissions_within_a_section() {
+        // text and data sharing the first section, data running on into
+        // the next
+        let data = build(&[
+            segment(0x4100_0000, 0x100, 0x100, PF_R | PF_X),
+            segment(0x4108_0000, 0x100, SECTION_SIZE, PF_R | PF_W),
+            segment(0x4120_0000, 0x100, 0x100, PF_R),
+        ]);
+        let elf = ElfImage::parse(&data).unwrap();
+        assert_eq!(
+            elf.section_permissions(0x4100_0000),
+            Ok(Permissions {
+                writable: true,
+                executable: true,
+            })
+        );
+        assert_eq!(
+            elf.section_permissions(0x4110_0000),
+            Ok(Permissions {
+                writable: true,
+                executable: false,
+            })
+        );
+        assert_eq!(
+            elf.section_permissions(0x4120_0000),
+            Ok(Permissions {
+                writable: false,
+                executable: false,
+            })
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Kernel image container.
+//!
+//! `kernel.bin` is a [`KernelHeader`] followed by the kernel ELF (the payload).
+//! The header records the payload length and its SHA-256 digest so the
+//! bootloader can refuse truncated or corrupted images. All fields are little
+//! endian. `tools/mkkernelimg.sh` stamps a header onto a built kernel from the
+//! host, [`stamp`] does the same from Rust.
+//!
+//! A signed image has [`flags::SIGNED`] set and an Ed25519 signature over the
+//! header and payload appended after the payload, added by
+//! `tools/signkernel.sh`.
+use crate::ed25519::{self, PUBLIC_KEY_SIZE, SIGNATURE_SIZE};
+use crate::sha256::{DIGEST_SIZE, sha256};
+
+pub const KERNEL_MAGIC: [u8; 4] = *b"KIMG";
+pub const KERNEL_HEADER_VERSION: u32 = 1;
+/// Size of the header on disk, the payload starts right after it
+pub const KERNEL_HEADER_SIZE: usize = 64;
+
+/// Header flag bits
+pub mod flags {
+    /// An Ed25519 signature follows the payload
+    pub const SIGNED: u32 = 1 << 0;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelHeader {
+    pub version: u32,
+    /// Offset of the payload from the start of the image
+    pub header_size: u32,
+    /// Payload length in bytes
+    pub image_size: u32,
+    /// SHA-256 of the payload
+    pub sha256: [u8; DIGEST_SIZE],
+    pub flags: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ImageError {
+    /// Image is shorter than a header, or doesn't start with [`KERNEL_MAGIC`]
+    NoHeader,
+    UnsupportedVersion(u32),
+    /// Image ends before the length the header records
+    Truncated {
+        expected: usize,
+        actual: usize,
+    },
+    DigestMismatch,
+    /// Image is not signed but a signature is required
+    Unsigned,
+    BadSignature,
+    /// Output buffer passed to [`stamp`] is too small
+    NoSpace,
+}
+
+impl core::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NoHeader => write!(f, "no kernel image header, was it stamped?"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported image header version {}", v),
+            Self::Truncated { expected, actual } => write!(
+                f,
+                "image truncated, header says {} bytes but only {} present",
+                expected, actual
+            ),
+            Self::DigestMismatch => write!(f, "SHA-256 mismatch, image is corrupted"),
+            Self::Unsigned => write!(f, "image is not signed"),
+            Self::BadSignature => write!(f, "signature does not match the trusted key"),
+            Self::NoSpace => write!(f, "output buffer too small"),
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+impl KernelHeader {
+    /// Header describing `payload`
+    pub fn for_payload(payload: &[u8]) -> Self {
+        Self {
+            version: KERNEL_HEADER_VERSION,
+            header_size: KERNEL_HEADER_SIZE as u32,
+            image_size: payload.len() as u32,
+            sha256: sha256(payload),
+            flags: 0,
+        }
+    }
+
+    pub fn parse(image: &[u8]) -> Result<Self, ImageError> {
+        let bytes = image
+            .get(..KERNEL_HEADER_SIZE)
+            .filter(|bytes| bytes[..4] == KERNEL_MAGIC)
+            .ok_or(ImageError::NoHeader)?;
+
+        let version = read_u32(bytes, 4);
+        if version != KERNEL_HEADER_VERSION {
+            return Err(ImageError::UnsupportedVersion(version));
+        }
+        let header_size = read_u32(bytes, 8);
+        if (header_size as usize) < KERNEL_HEADER_SIZE {
+            return Err(ImageError::NoHeader);
+        }
+        Ok(Self {
+            version,
+            header_size,
+            image_size: read_u32(bytes, 12),
+            sha256: bytes[16..16 + DIGEST_SIZE].try_into().unwrap(),
+            flags: read_u32(bytes, 48),
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; KERNEL_HEADER_SIZE] {
+        let mut bytes = [0u8; KERNEL_HEADER_SIZE];
+        bytes[..4].copy_from_slice(&KERNEL_MAGIC);
+        bytes[4..8].copy_from_slice(&self.version.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.header_size.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.image_size.to_le_bytes());
+        bytes[16..16 + DIGEST_SIZE].copy_from_slice(&self.sha256);
+        bytes[48..52].copy_from_slice(&self.flags.to_le_bytes());
+        bytes
+    }
+
+    /// Offset of the end of the payload, and of the signature if there is one
+    fn payload_end(&self, image: &[u8]) -> Result<usize, ImageError> {
+        checked_end(image, self.header_size as usize, self.image_size as usize)
+    }
+
+    /// The payload of `image`, without checking its digest
+    pub fn payload<'a>(&self, image: &'a [u8]) -> Result<&'a [u8], ImageError> {
+        let expected = self.payload_end(image)?;
+        image
+            .get(self.header_size as usize..expected)
+            .ok_or(ImageError::Truncated {
+                expected,
+                actual: image.len(),
+            })
+    }
+
+    /// The signature appended to `image`
+    pub fn signature<'a>(&self, image: &'a [u8]) -> Result<&'a [u8; SIGNATURE_SIZE], ImageError> {
+        if self.flags & flags::SIGNED == 0 {
+            return Err(ImageError::Unsigned);
+        }
+        let start = self.payload_end(image)?;
+        let expected = checked_end(image, start, SIGNATURE_SIZE)?;
+        image
+            .get(start..expected)
+            .and_then(|signature| signature.try_into().ok())
+            .ok_or(ImageError::Truncated {
+                expected,
+                actual: image.len(),
+            })
+    }
+}
+
+/// `start + len`, an image can't be that long if it overflows
+fn checked_end(image: &[u8], start: usize, len: usize) -> Result<usize, ImageError> {
+    start.checked_add(len).ok_or(ImageError::Truncated {
+        expected: usize::MAX,
+        actual: image.len(),
+    })
+}
+
+/// Check the header and digest of `image` and return its payload
+pub fn verify(image: &[u8]) -> Result<(KernelHeader, &[u8]), ImageError> {
+    let header = KernelHeader::parse(image)?;
+    let payload = header.payload(image)?;
+    if sha256(payload) != header.sha256 {
+        return Err(ImageError::DigestMismatch);
+    }
+    Ok((header, payload))
+}
+
+/// Check that the header and payload of `image` were signed by the owner of
+/// `public_key`
+pub fn verify_signature(
+    image: &[u8],
+    header: &KernelHeader,
+    public_key: &[u8; PUBLIC_KEY_SIZE],
+) -> Result<(), ImageError> {
+    let signature = header.signature(image)?;
+    let signed = &image[..header.payload_end(image)?];
+    if !ed25519::verify(public_key, signed, signature) {
+        return Err(ImageError::BadSignature);
+    }
+    Ok(())
+}
+
+/// Write a header for `payload` followed by the payload to `out`, returning
+/// the image size
+pub fn stamp(payload: &[u8], out: &mut [u8]) -> Result<usize, ImageError> {
+    let size = KERNEL_HEADER_SIZE + payload.len();
+    let out = out.get_mut(..size).ok_or(ImageError::NoSpace)?;
+    out[..KERNEL_HEADER_SIZE].copy_from_slice(&KernelHeader::for_payload(payload).to_bytes());
+    out[KERNEL_HEADER_SIZE..].copy_from_slice(payload);
+    Ok(size)
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    const PAYLOAD: &[u8] = b"\x7fELF not really a kernel";
+
+    fn stamped(out: &mut [u8; 128]) -> usize {
+        stamp(PAYLOAD, out).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut out = [0; 128];
+        let size = stamped(&mut out);
+        assert_eq!(size, KERNEL_HEADER_SIZE + PAYLOAD.len());
+
+        let (header, payload) = verify(&out[..size]).unwrap();
+        assert_eq!(header, KernelHeader::for_payload(PAYLOAD));
+        assert_eq!(payload, PAYLOAD);
+        // trailing bytes after the payload are ignored
+        assert!(verify(&out).is_ok());
+        assert_eq!(stamp(PAYLOAD, &mut [0; 16]), Err(ImageError::NoSpace));
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut out = [0; 128];
+        let size = stamped(&mut out);
+
+        assert_eq!(
+            verify(&out[..KERNEL_HEADER_SIZE - 1]),
+            Err(ImageError::NoHeader)
+        );
+        let mut image = out;
+        image[0] = b'X';
+        assert_eq!(verify(&image[..size]), Err(ImageError::NoHeader));
+        let mut image = out;
+        image[4] = 2;
+        assert_eq!(
+            verify(&image[..size]),
+            Err(ImageError::UnsupportedVersion(2))
+        );
+        let mut image = out;
+        image[8] = KERNEL_HEADER_SIZE as u8 - 4;
+        assert_eq!(verify(&image[..size]), Err(ImageError::NoHeader));
+    }
+
+    #[test]
+    fn rejects_truncated_image() {
+        let mut out = [0; 128];
+        let size = stamped(&mut out);
+        assert_eq!(
+            verify(&out[..size - 1]),
+            Err(ImageError::Truncated {
+                expected: size,
+                actual: size - 1
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_oversized_image_size() {
+        let mut out = [0; 128];
+        let size = stamped(&mut out);
+        let mut header = KernelHeader::parse(&out).unwrap();
+
+        header.image_size = u32::MAX;
+        out[..KERNEL_HEADER_SIZE].copy_from_slice(&header.to_bytes());
+        assert!(matches!(
+            verify(&out[..size]),
+            Err(ImageError::Truncated { actual, .. }) if actual == size
+        ));
+
+        // header and image size that add up past the end of the address space
+        let header = KernelHeader {
+            header_size: u32::MAX,
+            flags: flags::SIGNED,
+            ..header
+        };
+        let image = &out[..size];
+        assert!(matches!(
+            header.payload(image),
+            Err(ImageError::Truncated { .. })
+        ));
+        assert!(matches!(
+            header.signature(image),
+            Err(ImageError::Truncated { .. })
+        ));
+        assert_eq!(
+            checked_end(image, usize::MAX, SIGNATURE_SIZE),
+            Err(ImageError::Truncated {
+                expected: usize::MAX,
+                actual: size
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_digest_mismatch() {
+        let mut out = [0; 128];
+        let size = stamped(&mut out);
+        out[KERNEL_HEADER_SIZE + 1] ^= 1;
+        assert_eq!(verify(&out[..size]), Err(ImageError::DigestMismatch));
+    }
+
+    #[test]
+    fn unsigned_image_has_no_signature() {
+        let mut out = [0; 128];
+        let size = stamped(&mut out);
+        let (header, _) = verify(&out[..size]).unwrap();
+        assert_eq!(header.signature(&out[..size]), Err(ImageError::Unsigned));
+        assert_eq!(
+            verify_signature(&out[..size], &header, &[0; PUBLIC_KEY_SIZE]),
+            Err(ImageError::Unsigned)
+        );
+    }
+}
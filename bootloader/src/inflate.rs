@@ -0,0 +1,469 @@
+//! gzip (RFC 1952) around DEFLATE (RFC 1951).
+//!
+//! Huffman codes are decoded a bit at a time with canonical code counts, as in
+//! zlib's `puff`. That is slower than lookup tables but needs under a
+//! kilobyte of stack, and reading the SD card dominates anyway.
+use crate::decompress::{DecompressError, Output, Source};
+
+const MAX_BITS: usize = 15;
+/// Literal/length codes, including the two that never appear in valid data
+const MAX_LCODES: usize = 288;
+const MAX_DCODES: usize = 30;
+const CODE_LENGTH_CODES: usize = 19;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const GZIP_METHOD_DEFLATE: u8 = 8;
+
+/// gzip header flag bits
+mod gzip_flags {
+    pub const HCRC: u8 = 1 << 1;
+    pub const EXTRA: u8 = 1 << 2;
+    pub const NAME: u8 = 1 << 3;
+    pub const COMMENT: u8 = 1 << 4;
+    pub const RESERVED: u8 = 0xe0;
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; MAX_DCODES] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; MAX_DCODES] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order code length code lengths are sent in
+const CODE_LENGTH_ORDER: [usize; CODE_LENGTH_CODES] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Decompress the gzip stream in `source` into `out`, returning its size
+pub fn gunzip<S: Source>(source: &mut S, out: &mut [u8]) -> Result<usize, DecompressError> {
+    let mut header = [0u8; 10];
+    source.read_exact(&mut header)?;
+    if header[..2] != GZIP_MAGIC {
+        return Err(DecompressError::Corrupt("not a gzip stream"));
+    }
+    if header[2] != GZIP_METHOD_DEFLATE {
+        return Err(DecompressError::Unsupported("gzip compression method"));
+    }
+    let flags = header[3];
+    if flags & gzip_flags::RESERVED != 0 {
+        return Err(DecompressError::Corrupt("reserved gzip flags set"));
+    }
+    if flags & gzip_flags::EXTRA != 0 {
+        let length = source.read_u16_le()?;
+        source.skip(length as usize)?;
+    }
+    if flags & gzip_flags::NAME != 0 {
+        while source.read_u8()? != 0 {}
+    }
+    if flags & gzip_flags::COMMENT != 0 {
+        while source.read_u8()? != 0 {}
+    }
+    if flags & gzip_flags::HCRC != 0 {
+        source.skip(2)?;
+    }
+
+    let mut out = Output::new(out);
+    inflate(source, &mut out)?;
+
+    let crc = source.read_u32_le()?;
+    // ISIZE is the length modulo 2^32, which kernels never reach
+    let size = source.read_u32_le()? as usize;
+    if size != out.len() {
+        return Err(DecompressError::SizeMismatch {
+            expected: size,
+            actual: out.len(),
+        });
+    }
+    if crc32(out.written()) != crc {
+        return Err(DecompressError::ChecksumMismatch);
+    }
+    Ok(out.len())
+}
+
+/// Decompress a raw DEFLATE stream, leaving `source` at the byte after it
+fn inflate<S: Source>(source: &mut S, out: &mut Output) -> Result<(), DecompressError> {
+    let mut bits = BitReader::new(source);
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => stored(&mut bits, out)?,
+            1 => fixed(&mut bits, out)?,
+            2 => dynamic(&mut bits, out)?,
+            _ => return Err(DecompressError::Corrupt("invalid DEFLATE block type")),
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+/// LSB first bit reader, pulls whole bytes only when it runs out of bits so
+/// the source is byte aligned once the leftover bits are dropped
+struct BitReader<'a, S> {
+    source: &'a mut S,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a, S: Source> BitReader<'a, S> {
+    fn new(source: &'a mut S) -> Self {
+        Self {
+            source,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn bits(&mut self, need: u32) -> Result<u32, DecompressError> {
+        while self.count < need {
+            self.buffer |= (self.source.read_u8()? as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << need) - 1);
+        self.buffer >>= need;
+        self.count -= need;
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+fn stored<S: Source>(bits: &mut BitReader<S>, out: &mut Output) -> Result<(), DecompressError> {
+    bits.align();
+    let length = bits.source.read_u16_le()?;
+    let complement = bits.source.read_u16_le()?;
+    if length != !complement {
+        return Err(DecompressError::Corrupt("stored block length check failed"));
+    }
+    out.copy_from(bits.source, length as usize)
+}
+
+/// Canonical Huffman code, `N` is the number of symbols
+struct Huffman<const N: usize> {
+    /// Number of codes of each length
+    count: [u16; MAX_BITS + 1],
+    /// Symbols ordered by code
+    symbol: [u16; N],
+}
+
+impl<const N: usize> Huffman<N> {
+    /// Code from the code length of each symbol, zero for unused symbols.
+    /// Over-subscribed lengths are rejected, incomplete ones are allowed here
+    /// and checked by the caller where they aren't.
+    fn new(lengths: &[u8]) -> Result<Self, DecompressError> {
+        let mut code = Self {
+            count: [0; MAX_BITS + 1],
+            symbol: [0; N],
+        };
+        for &length in lengths {
+            code.count[length as usize] += 1;
+        }
+        if code.left() < 0 {
+            return Err(DecompressError::Corrupt("over-subscribed Huffman code"));
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for length in 1..MAX_BITS {
+            offsets[length + 1] = offsets[length] + code.count[length];
+        }
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                code.symbol[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Ok(code)
+    }
+
+    /// Codes left unused, zero for a complete code
+    fn left(&self) -> i32 {
+        let mut left = 1i32;
+        for &count in &self.count[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                break;
+            }
+        }
+        left
+    }
+
+    /// Complete, or at most a single one bit code, which is the only
+    /// incomplete literal/length or distance code DEFLATE allows
+    fn is_usable(&self) -> bool {
+        self.left() == 0 || self.count[2..].iter().all(|&count| count == 0)
+    }
+
+    fn decode<S: Source>(&self, bits: &mut BitReader<S>) -> Result<u16, DecompressError> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for &count in &self.count[1..] {
+            code |= bits.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbol[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(DecompressError::Corrupt("invalid Huffman code"))
+    }
+}
+
+type LengthCode = Huffman<MAX_LCODES>;
+type DistanceCode = Huffman<MAX_DCODES>;
+
+fn fixed<S: Source>(bits: &mut BitReader<S>, out: &mut Output) -> Result<(), DecompressError> {
+    let mut lengths = [0u8; MAX_LCODES];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let length_code = LengthCode::new(&lengths)?;
+    let distance_code = DistanceCode::new(&[5; MAX_DCODES])?;
+    codes(bits, out, &length_code, &distance_code)
+}
+
+fn dynamic<S: Source>(bits: &mut BitReader<S>, out: &mut Output) -> Result<(), DecompressError> {
+    let length_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_length_count = bits.bits(4)? as usize + 4;
+    if length_count > 286 || distance_count > MAX_DCODES {
+        return Err(DecompressError::Corrupt("too many DEFLATE codes"));
+    }
+
+    let mut lengths = [0u8; MAX_LCODES + MAX_DCODES];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        lengths[symbol] = bits.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::<CODE_LENGTH_CODES>::new(&lengths[..CODE_LENGTH_CODES])?;
+    if code_length_code.left() != 0 {
+        return Err(DecompressError::Corrupt("incomplete code length code"));
+    }
+
+    let total = length_count + distance_count;
+    let mut index = 0;
+    while index < total {
+        let symbol = code_length_code.decode(bits)?;
+        let (length, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 if index == 0 => {
+                return Err(DecompressError::Corrupt(
+                    "code length repeat with no previous length",
+                ));
+            }
+            16 => (lengths[index - 1], 3 + bits.bits(2)? as usize),
+            17 => (0, 3 + bits.bits(3)? as usize),
+            _ => (0, 11 + bits.bits(7)? as usize),
+        };
+        let end = index + repeat;
+        if end > total {
+            return Err(DecompressError::Corrupt("too many code lengths"));
+        }
+        lengths[index..end].fill(length);
+        index = end;
+    }
+    if lengths[256] == 0 {
+        return Err(DecompressError::Corrupt("no end of block code"));
+    }
+
+    let length_code = LengthCode::new(&lengths[..length_count])?;
+    if !length_code.is_usable() {
+        return Err(DecompressError::Corrupt("incomplete literal/length code"));
+    }
+    let distance_code = DistanceCode::new(&lengths[length_count..total])?;
+    if !distance_code.is_usable() {
+        return Err(DecompressError::Corrupt("incomplete distance code"));
+    }
+    codes(bits, out, &length_code, &distance_code)
+}
+
+/// Decode literals and matches until the end of block code
+fn codes<S: Source>(
+    bits: &mut BitReader<S>,
+    out: &mut Output,
+    length_code: &LengthCode,
+    distance_code: &DistanceCode,
+) -> Result<(), DecompressError> {
+    loop {
+        let symbol = length_code.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8)?,
+            256 => return Ok(()),
+            _ => {
+                let symbol = symbol - 257;
+                if symbol >= LENGTH_BASE.len() {
+                    return Err(DecompressError::Corrupt("invalid length code"));
+                }
+                let length =
+                    LENGTH_BASE[symbol] as usize + bits.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+                let symbol = distance_code.decode(bits)? as usize;
+                if symbol >= MAX_DCODES {
+                    return Err(DecompressError::Corrupt("invalid distance code"));
+                }
+                let distance =
+                    DIST_BASE[symbol] as usize + bits.bits(DIST_EXTRA[symbol] as u32)? as usize;
+                out.copy_match(distance, length)?;
+            }
+        }
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 as used by gzip
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc = CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    const KERNEL_LOG: &[u8] = include_bytes!("../testdata/kernel.log");
+    const KERNEL_LOG_GZ: &[u8] = include_bytes!("../testdata/kernel.log.gz");
+
+    /// gzip header with no optional fields
+    const HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3];
+
+    fn gunzip_into(stream: &[u8], out: &mut [u8]) -> Result<usize, DecompressError> {
+        gunzip(&mut &stream[..], out)
+    }
+
+    /// `HEADER`, the DEFLATE stream `deflate` and a trailer for `data`
+    fn gzip(deflate: &[u8], data: &[u8], stream: &mut [u8; 64]) -> usize {
+        let trailer = [crc32(data).to_le_bytes(), (data.len() as u32).to_le_bytes()];
+        let parts = [&HEADER[..], deflate, trailer.as_flattened()];
+        let mut size = 0;
+        for part in parts {
+            stream[size..size + part.len()].copy_from_slice(part);
+            size += part.len();
+        }
+        size
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn stored_block() {
+        let mut stream = [0; 64];
+        let size = gzip(
+            &[0x01, 5, 0, !5, !0, b'h', b'e', b'l', b'l', b'o'],
+            b"hello",
+            &mut stream,
+        );
+        let mut out = [0; 5];
+        assert_eq!(gunzip_into(&stream[..size], &mut out), Ok(5));
+        assert_eq!(&out, b"hello");
+
+        stream[HEADER.len() + 3] ^= 1;
+        assert!(matches!(
+            gunzip_into(&stream[..size], &mut out),
+            Err(DecompressError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_distance_before_start() {
+        // fixed block whose first code is a length 3, distance 1 match
+        let mut stream = [0; 64];
+        let size = gzip(&[0x03, 0x02, 0x00], b"", &mut stream);
+        assert_eq!(
+            gunzip_into(&stream[..size], &mut [0; 16]),
+            Err(DecompressError::Corrupt("match distance too far back"))
+        );
+    }
+
+    #[test]
+    fn rejects_reserved_block_type() {
+        let mut stream = [0; 64];
+        let size = gzip(&[0x07], b"", &mut stream);
+        assert_eq!(
+            gunzip_into(&stream[..size], &mut [0; 16]),
+            Err(DecompressError::Corrupt("invalid DEFLATE block type"))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        let mut out = [0; 16];
+        let mut stream = HEADER;
+        stream[0] = 0;
+        assert_eq!(
+            gunzip_into(&stream, &mut out),
+            Err(DecompressError::Corrupt("not a gzip stream"))
+        );
+        let mut stream = HEADER;
+        stream[2] = 7;
+        assert!(matches!(
+            gunzip_into(&stream, &mut out),
+            Err(DecompressError::Unsupported(_))
+        ));
+        let mut stream = HEADER;
+        stream[3] = 0x80;
+        assert!(matches!(
+            gunzip_into(&stream, &mut out),
+            Err(DecompressError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_trailer() {
+        let mut stream = [0; KERNEL_LOG_GZ.len()];
+        let mut out = [0; KERNEL_LOG.len()];
+        let crc = KERNEL_LOG_GZ.len() - 8;
+
+        stream.copy_from_slice(KERNEL_LOG_GZ);
+        stream[crc] ^= 1;
+        assert_eq!(
+            gunzip_into(&stream, &mut out),
+            Err(DecompressError::ChecksumMismatch)
+        );
+
+        stream.copy_from_slice(KERNEL_LOG_GZ);
+        stream[crc + 4] ^= 1;
+        assert!(matches!(
+            gunzip_into(&stream, &mut out),
+            Err(DecompressError::SizeMismatch { actual, .. }) if actual == KERNEL_LOG.len()
+        ));
+    }
+}
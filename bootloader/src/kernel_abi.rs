@@ -0,0 +1,319 @@
+//! Contract between a kernel and the bootloader.
+//!
+//! Unlike the [`KernelHeader`](crate::image::KernelHeader) stamped onto
+//! `kernel.bin` after the build, this header is linked into the kernel itself,
+//! [`KERNEL_ABI_HEADER_OFFSET`] bytes into its lowest loaded segment. It says
+//! where the kernel expects to be, how big it is and what it needs from the
+//! bootloader, which refuses to jump into a kernel it can't satisfy. All
+//! fields are little endian `u32`s in this order:
+//!
+//! | offset | field                                    |
+//! |--------|------------------------------------------|
+//! | 0      | magic, `KABI`                            |
+//! | 4      | ABI version                              |
+//! | 8      | physical load address                    |
+//! | 12     | entry point, virtual                     |
+//! | 16     | image size, from the load address to BSS |
+//! | 20     | BSS size                                 |
+//! | 24     | minimum DRAM size                        |
+//! | 28     | boot info version                        |
+//!
+//! ABI version 1 enters the kernel in System mode with IRQ and FIQ masked, the
+//! MMU on with its segments mapped at their virtual addresses next to the
+//! identity map of DRAM, and the physical address of the boot info in r0.
+use crate::boot_info::BOOT_INFO_VERSION;
+
+pub const KERNEL_ABI_MAGIC: [u8; 4] = *b"KABI";
+pub const KERNEL_ABI_VERSION: u32 = 1;
+pub const KERNEL_ABI_HEADER_SIZE: usize = 32;
+/// Where the header sits in the lowest loaded segment
+pub const KERNEL_ABI_HEADER_OFFSET: usize = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelAbiHeader {
+    pub abi_version: u32,
+    /// Physical address the first segment is linked to load at
+    pub load_address: u32,
+    /// Virtual address of the entry point
+    pub entry: u32,
+    /// Bytes from the load address to the start of the BSS
+    pub image_size: u32,
+    pub bss_size: u32,
+    /// Least DRAM the kernel can run in, in bytes
+    pub min_ram: u32,
+    /// Boot info version the kernel reads, see [`BootInfo`](crate::BootInfo)
+    pub boot_info_version: u32,
+}
+
+/// What the bootloader knows about a kernel ELF and the board, checked
+/// against the kernel's header
+#[derive(Debug, Clone, Copy)]
+pub struct LoadInfo {
+    /// ELF entry point
+    pub entry: u32,
+    /// Lowest and highest (exclusive) physical address the segments cover
+    pub phys_start: u32,
+    pub phys_end: u32,
+    pub ram_size: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AbiError {
+    /// No [`KERNEL_ABI_MAGIC`] where the header should be
+    NoHeader,
+    UnsupportedAbi(u32),
+    /// Kernel reads another boot info version than the bootloader builds
+    UnsupportedBootInfo(u32),
+    NotEnoughRam {
+        required: u32,
+        available: u32,
+    },
+    /// Header and ELF disagree, the header is stale or belongs to another
+    /// kernel
+    LoadAddressMismatch {
+        header: u32,
+        elf: u32,
+    },
+    EntryMismatch {
+        header: u32,
+        elf: u32,
+    },
+    SizeMismatch {
+        header: u32,
+        elf: u32,
+    },
+}
+
+impl core::fmt::Display for AbiError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NoHeader => write!(
+                f,
+                "no kernel ABI header, is it a kernel for this bootloader?"
+            ),
+            Self::UnsupportedAbi(v) => write!(
+                f,
+                "kernel needs boot ABI version {}, bootloader provides {}",
+                v, KERNEL_ABI_VERSION
+            ),
+            Self::UnsupportedBootInfo(v) => write!(
+                f,
+                "kernel needs boot info version {}, bootloader provides {}",
+                v, BOOT_INFO_VERSION
+            ),
+            Self::NotEnoughRam {
+                required,
+                available,
+            } => write!(
+                f,
+                "kernel needs {} MB of RAM, board has {} MB",
+                required >> 20,
+                available >> 20
+            ),
+            Self::LoadAddressMismatch { header, elf } => write!(
+                f,
+                "header load address 0x{:x} does not match ELF 0x{:x}",
+                header, elf
+            ),
+            Self::EntryMismatch { header, elf } => write!(
+                f,
+                "header entry 0x{:x} does not match ELF 0x{:x}",
+                header, elf
+            ),
+            Self::SizeMismatch { header, elf } => write!(
+                f,
+                "header size {} bytes does not match ELF {} bytes",
+                header, elf
+            ),
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+impl KernelAbiHeader {
+    /// Parse the header at the start of `bytes`, the lowest loaded segment
+    pub fn parse(bytes: &[u8]) -> Result<Self, AbiError> {
+        let bytes = bytes
+            .get(KERNEL_ABI_HEADER_OFFSET..KERNEL_ABI_HEADER_OFFSET + KERNEL_ABI_HEADER_SIZE)
+            .filter(|bytes| bytes[..4] == KERNEL_ABI_MAGIC)
+            .ok_or(AbiError::NoHeader)?;
+        Ok(Self {
+            abi_version: read_u32(bytes, 4),
+            load_address: read_u32(bytes, 8),
+            entry: read_u32(bytes, 12),
+            image_size: read_u32(bytes, 16),
+            bss_size: read_u32(bytes, 20),
+            min_ram: read_u32(bytes, 24),
+            boot_info_version: read_u32(bytes, 28),
+        })
+    }
+
+    /// Check that the kernel is the one `load` describes and that this
+    /// bootloader can boot it
+    pub fn check(&self, load: &LoadInfo) -> Result<(), AbiError> {
+        if self.abi_version != KERNEL_ABI_VERSION {
+            return Err(AbiError::UnsupportedAbi(self.abi_version));
+        }
+        if self.boot_info_version != BOOT_INFO_VERSION {
+            return Err(AbiError::UnsupportedBootInfo(self.boot_info_version));
+        }
+        if self.min_ram > load.ram_size {
+            return Err(AbiError::NotEnoughRam {
+                required: self.min_ram,
+                available: load.ram_size,
+            });
+        }
+        if self.load_address != load.phys_start {
+            return Err(AbiError::LoadAddressMismatch {
+                header: self.load_address,
+                elf: load.phys_start,
+            });
+        }
+        if self.entry != load.entry {
+            return Err(AbiError::EntryMismatch {
+                header: self.entry,
+                elf: load.entry,
+            });
+        }
+        let size = self.image_size.wrapping_add(self.bss_size);
+        if size != load.phys_end - load.phys_start {
+            return Err(AbiError::SizeMismatch {
+                header: size,
+                elf: load.phys_end - load.phys_start,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    const HEADER: KernelAbiHeader = KernelAbiHeader {
+        abi_version: KERNEL_ABI_VERSION,
+        load_address: 0x4000_8000,
+        entry: 0xc000_8000,
+        image_size: 0x2_0000,
+        bss_size: 0x1000,
+        min_ram: 64 << 20,
+        boot_info_version: BOOT_INFO_VERSION,
+    };
+
+    /// What the bootloader sees for the kernel `HEADER` describes
+    const LOAD: LoadInfo = LoadInfo {
+        entry: 0xc000_8000,
+        phys_start: 0x4000_8000,
+        phys_end: 0x4002_9000,
+        ram_size: 512 << 20,
+    };
+
+    fn to_bytes(header: &KernelAbiHeader) -> [u8; KERNEL_ABI_HEADER_SIZE] {
+        let fields = [
+            header.abi_version,
+            header.load_address,
+            header.entry,
+            header.image_size,
+            header.bss_size,
+            header.min_ram,
+            header.boot_info_version,
+        ];
+        let mut bytes = [0; KERNEL_ABI_HEADER_SIZE];
+        bytes[..4].copy_from_slice(&KERNEL_ABI_MAGIC);
+        for (chunk, field) in bytes[4..].chunks_mut(4).zip(fields) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_header() {
+        let bytes = to_bytes(&HEADER);
+        assert_eq!(KernelAbiHeader::parse(&bytes), Ok(HEADER));
+        assert_eq!(HEADER.check(&LOAD), Ok(()));
+    }
+
+    #[test]
+    fn rejects_missing_header() {
+        let bytes = to_bytes(&HEADER);
+        assert_eq!(
+            KernelAbiHeader::parse(&bytes[..KERNEL_ABI_HEADER_SIZE - 1]),
+            Err(AbiError::NoHeader)
+        );
+        let mut bytes = bytes;
+        bytes[0] = b'X';
+        assert_eq!(KernelAbiHeader::parse(&bytes), Err(AbiError::NoHeader));
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        let header = KernelAbiHeader {
+            abi_version: 2,
+            ..HEADER
+        };
+        assert_eq!(header.check(&LOAD), Err(AbiError::UnsupportedAbi(2)));
+        let header = KernelAbiHeader {
+            boot_info_version: BOOT_INFO_VERSION + 1,
+            ..HEADER
+        };
+        assert_eq!(
+            header.check(&LOAD),
+            Err(AbiError::UnsupportedBootInfo(BOOT_INFO_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn rejects_small_board() {
+        let load = LoadInfo {
+            ram_size: 32 << 20,
+            ..LOAD
+        };
+        assert_eq!(
+            HEADER.check(&load),
+            Err(AbiError::NotEnoughRam {
+                required: 64 << 20,
+                available: 32 << 20
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_header_elf_mismatch() {
+        let load = LoadInfo {
+            phys_start: 0x4001_0000,
+            ..LOAD
+        };
+        assert_eq!(
+            HEADER.check(&load),
+            Err(AbiError::LoadAddressMismatch {
+                header: 0x4000_8000,
+                elf: 0x4001_0000
+            })
+        );
+        let load = LoadInfo {
+            entry: 0xc000_8004,
+            ..LOAD
+        };
+        assert_eq!(
+            HEADER.check(&load),
+            Err(AbiError::EntryMismatch {
+                header: 0xc000_8000,
+                elf: 0xc000_8004
+            })
+        );
+        let load = LoadInfo {
+            phys_end: 0x4002_8000,
+            ..LOAD
+        };
+        assert_eq!(
+            HEADER.check(&load),
+            Err(AbiError::SizeMismatch {
+                header: 0x2_1000,
+                elf: 0x2_0000
+            })
+        );
+    }
+}
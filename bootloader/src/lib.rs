@@ -1,33 +1,47 @@
 #![no_std]
-#![cfg_attr(test, feature(custom_test_frameworks))]
-#![cfg_attr(test, test_runner(crate::test_runner))]
-#![cfg_attr(test, reexport_test_harness_main = "test_main")]
+#![cfg_attr(all(test, target_os = "none"), feature(custom_test_frameworks))]
+#![cfg_attr(all(test, target_os = "none"), test_runner(crate::test_runner))]
+#![cfg_attr(
+    all(test, target_os = "none"),
+    reexport_test_harness_main = "test_main"
+)]
+
+// unit tests run on the host with std, `make test`
+#[cfg(all(test, not(target_os = "none")))]
+extern crate std;
+
+pub mod boot_info;
+pub mod config;
+pub mod decompress;
+pub mod ed25519;
+pub mod elf;
+pub mod image;
+pub mod inflate;
+pub mod kernel_abi;
+pub mod lz4;
+pub mod number;
+pub mod panic_record;
+pub mod sha256;
+pub mod sha512;
+pub mod slot;
+pub mod ymodem_block;
+
+pub use boot_info::{BootInfo, BootInfoHeader};
+pub use image::KernelHeader;
 
 // TODO testing
-#[cfg(test)]
+#[cfg(all(test, target_os = "none"))]
 fn test_runner(tests: &[&dyn Fn()]) {
     for test in tests {
         test(); // Simply run each test
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "none"))]
 use core::panic::PanicInfo;
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "none"))]
 #[panic_handler]
 fn test_panic(_info: &PanicInfo) -> ! {
     loop {} // Halt the system on panic
 }
-
-#[derive(Debug)]
-pub struct BootInfoHeader {
-    pub boot_entry: usize,
-    pub boot_size: usize,
-}
-
-#[derive(Debug)]
-pub struct KernelHeader {
-    pub kernel_entry: usize,
-    pub kernel_size: usize,
-}
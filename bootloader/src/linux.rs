@@ -0,0 +1,224 @@
+//! ARM Linux boot protocol, see `Documentation/arch/arm/booting.rst`.
+//!
+//! A zImage is copied to [`ZIMAGE_OFFSET`] into DRAM, where it has room to
+//! decompress the kernel below itself, and entered with the MMU and caches
+//! off, r0 = 0, r1 = the machine type and r2 = the physical address of a
+//! device tree or an ATAGS list. Either is built in the params area right
+//! after the staging region. A device tree gets the command line and initrd
+//! added to `/chosen`, ATAGS are only there for kernels without one and need
+//! `mach=<number>` in the entry's options.
+//!
+//! The zImage goes through the same header and signature checks as our own
+//! kernel, so it has to be stamped with `tools/mkkernelimg.sh` first. The DTB
+//! and initrd are not covered by them.
+use bootloader_types::number;
+use core::ops::Range;
+use hal::dram::{self, DRAM_START};
+use hal::fdt::{Fdt, FdtError, Property};
+use hal::{asm, mmu};
+
+/// Where the `zImage` magic sits in its header
+const ZIMAGE_MAGIC_OFFSET: usize = 0x24;
+const ZIMAGE_MAGIC: u32 = 0x016f_2818;
+
+/// zImages run 32MB into DRAM, clear of the kernel they decompress to the
+/// start of it
+pub const ZIMAGE_OFFSET: usize = 0x0200_0000;
+
+/// DTB or ATAGS handed to the kernel. The lower half is what it gets, the
+/// upper half holds the DTB as read from the card.
+const PARAMS_START: usize = DRAM_START + crate::STAGING_OFFSET + crate::STAGING_SIZE;
+const PARAMS_SIZE: usize = 0x0020_0000;
+/// First byte after the params area, free for the initrd
+pub const PARAMS_END: usize = PARAMS_START + PARAMS_SIZE;
+
+/// r1 without a `mach=` option, the kernel ignores it when given a DTB
+pub const MACH_TYPE_NONE: u32 = 0xffff_ffff;
+
+mod atag {
+    pub const NONE: u32 = 0x0000_0000;
+    pub const CORE: u32 = 0x5441_0001;
+    pub const MEM: u32 = 0x5441_0002;
+    pub const INITRD2: u32 = 0x5442_0005;
+    pub const CMDLINE: u32 = 0x5441_0009;
+}
+
+#[derive(Debug)]
+pub enum LinuxError {
+    /// zImage is bigger than the DRAM between [`ZIMAGE_OFFSET`] and the next
+    /// reserved region
+    TooLarge {
+        size: usize,
+        space: usize,
+    },
+    Dtb(FdtError),
+    /// `mach=` in the entry's options isn't a number
+    BadMachineType,
+}
+
+impl core::fmt::Display for LinuxError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::TooLarge { size, space } => write!(
+                f,
+                "{} byte zImage does not fit in the {} bytes set aside for it",
+                size, space
+            ),
+            Self::Dtb(e) => write!(f, "bad device tree: {}", e),
+            Self::BadMachineType => write!(f, "mach= option is not a number"),
+        }
+    }
+}
+
+impl From<FdtError> for LinuxError {
+    fn from(e: FdtError) -> Self {
+        Self::Dtb(e)
+    }
+}
+
+/// Whether `image` starts with a Linux zImage header
+pub fn is_zimage(image: &[u8]) -> bool {
+    image
+        .get(ZIMAGE_MAGIC_OFFSET..ZIMAGE_MAGIC_OFFSET + 4)
+        .is_some_and(|magic| u32::from_le_bytes(magic.try_into().unwrap()) == ZIMAGE_MAGIC)
+}
+
+/// Machine type from a `mach=<number>` load option, decimal or `0x` hex, see
+/// [`number::parse`]
+pub fn machine_type(options: &str) -> Result<u32, LinuxError> {
+    let Some(value) = options
+        .split_whitespace()
+        .find_map(|option| option.strip_prefix("mach="))
+    else {
+        return Ok(MACH_TYPE_NONE);
+    };
+    number::parse(value)
+        .and_then(|mach| u32::try_from(mach).ok())
+        .ok_or(LinuxError::BadMachineType)
+}
+
+/// Copy `zimage` to where it runs from, `space_end` being the first reserved
+/// address after [`ZIMAGE_OFFSET`]. Returns its entry point.
+pub fn place_zimage(zimage: &[u8], space_end: usize) -> Result<usize, LinuxError> {
+    let start = DRAM_START + ZIMAGE_OFFSET;
+    let space = space_end.saturating_sub(start);
+    if zimage.len() > space {
+        return Err(LinuxError::TooLarge {
+            size: zimage.len(),
+            space,
+        });
+    }
+    let dest = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, zimage.len()) };
+    dest.copy_from_slice(zimage);
+    Ok(start)
+}
+
+fn params_region() -> (&'static mut [u8], &'static mut [u8]) {
+    let params = unsafe { core::slice::from_raw_parts_mut(PARAMS_START as *mut u8, PARAMS_SIZE) };
+    params.split_at_mut(PARAMS_SIZE / 2)
+}
+
+/// Where the DTB file is read to before [`write_dtb`] patches it
+pub fn dtb_buffer() -> &'static mut [u8] {
+    params_region().1
+}
+
+/// Copy the `dtb_size` byte DTB in [`dtb_buffer`] to the kernel's params with
+/// `/chosen` pointing at `cmdline` and `initrd`, returning its address
+pub fn write_dtb(
+    dtb_size: usize,
+    cmdline: &str,
+    initrd: Option<&Range<usize>>,
+) -> Result<usize, LinuxError> {
+    let (out, buf) = params_region();
+    // bootargs must be NUL terminated, build it behind the DTB
+    let (dtb, scratch) = buf.split_at_mut(dtb_size);
+    let bootargs = scratch
+        .get_mut(..cmdline.len() + 1)
+        .ok_or(FdtError::NoSpace)?;
+    bootargs[..cmdline.len()].copy_from_slice(cmdline.as_bytes());
+    bootargs[cmdline.len()] = 0;
+
+    let initrd_start;
+    let initrd_end;
+    let mut props = [Property {
+        name: "bootargs",
+        value: bootargs,
+    }; 3];
+    let mut count = 1;
+    if let Some(initrd) = initrd {
+        initrd_start = (initrd.start as u32).to_be_bytes();
+        initrd_end = (initrd.end as u32).to_be_bytes();
+        props[1] = Property {
+            name: "linux,initrd-start",
+            value: &initrd_start,
+        };
+        props[2] = Property {
+            name: "linux,initrd-end",
+            value: &initrd_end,
+        };
+        count = 3;
+    }
+
+    Fdt::new(dtb)?.write_with_chosen(out, &props[..count])?;
+    Ok(out.as_ptr() as usize)
+}
+
+/// Appends tags to the params area, which is far bigger than any list we build
+struct Atags {
+    buf: &'static mut [u8],
+    len: usize,
+}
+
+impl Atags {
+    fn put_u32(&mut self, value: u32) {
+        self.buf[self.len..self.len + 4].copy_from_slice(&value.to_le_bytes());
+        self.len += 4;
+    }
+
+    /// Tag header, `words` counts the data after it
+    fn header(&mut self, tag: u32, words: usize) {
+        self.put_u32(words as u32 + 2);
+        self.put_u32(tag);
+    }
+}
+
+/// Build an ATAGS list describing DRAM, `initrd` and `cmdline` in the
+/// kernel's params, returning its address
+pub fn write_atags(cmdline: &str, initrd: Option<&Range<usize>>) -> usize {
+    let (buf, _) = params_region();
+    let address = buf.as_ptr() as usize;
+    let mut atags = Atags { buf, len: 0 };
+
+    atags.header(atag::CORE, 0);
+    atags.header(atag::MEM, 2);
+    let ram = dram::ram();
+    atags.put_u32(ram.len() as u32);
+    atags.put_u32(ram.start as u32);
+    if let Some(initrd) = initrd {
+        atags.header(atag::INITRD2, 2);
+        atags.put_u32(initrd.start as u32);
+        atags.put_u32((initrd.end - initrd.start) as u32);
+    }
+    if !cmdline.is_empty() {
+        // NUL terminated and padded to a whole word
+        let words = (cmdline.len() + 4) / 4;
+        atags.header(atag::CMDLINE, words);
+        let text = &mut atags.buf[atags.len..atags.len + words * 4];
+        text.fill(0);
+        text[..cmdline.len()].copy_from_slice(cmdline.as_bytes());
+        atags.len += words * 4;
+    }
+    // the terminator is the one tag whose size doesn't count its header
+    atags.put_u32(0);
+    atags.put_u32(atag::NONE);
+    address
+}
+
+/// Turn the MMU and caches off and jump to the zImage at `entry` in SVC mode
+/// with IRQ and FIQ masked, as the boot protocol asks. [`mmu::disable`]
+/// writes back everything it reads, so nothing is lost from the caches.
+pub fn enter(entry: usize, machine: u32, params: usize) -> ! {
+    mmu::disable();
+    unsafe { asm::enter_linux(entry as u32, machine, params as u32) }
+}
@@ -0,0 +1,93 @@
+//! ELF32 kernel loader.
+//!
+//! The whole image is staged in DRAM first and checked with
+//! [`ElfImage::validate`], then every PT_LOAD segment is copied to its
+//! physical address (`p_paddr`), the BSS tail is zeroed and the virtual range
+//! is mapped with permissions taken from `p_flags`.
+use core::ops::Range;
+
+use bootloader_types::elf::{self, ElfError, ElfImage, Permissions};
+use hal::dram::{DRAM_END, DRAM_START};
+use hal::mmu;
+
+const _: () = assert!(elf::SECTION_SIZE == mmu::SECTION_SIZE);
+
+/// Where a loaded image ended up
+#[derive(Debug, Clone, Copy)]
+pub struct LoadedImage {
+    pub entry: usize,
+    /// Lowest and highest (exclusive) physical address written
+    pub phys_start: usize,
+    pub phys_end: usize,
+}
+
+fn section_flags(permissions: Permissions) -> u32 {
+    let access = if permissions.writable {
+        mmu::L1_ACCESS_RW_NO
+    } else {
+        mmu::L1_ACCESS_RO_NO
+    };
+    let execute = if permissions.executable {
+        mmu::L1_ACCESS_X
+    } else {
+        mmu::L1_ACCESS_NX
+    };
+    // the same memory type as the identity map, so the two stay coherent
+    access | execute | mmu::L1_NORMAL_WB | mmu::L1_GLOBAL
+}
+
+/// Copy every PT_LOAD segment of `elf` into place and map it.
+///
+/// `reserved` lists physical ranges no segment may touch (the bootloader,
+/// its page tables and the staging buffer holding this image).
+pub fn load(elf: &ElfImage, reserved: &[Range<usize>]) -> Result<LoadedImage, ElfError> {
+    elf.validate(DRAM_START..DRAM_END + 1, reserved)?;
+
+    let mut phys_start = usize::MAX;
+    let mut phys_end = 0;
+    for ph in elf.load_segments() {
+        let ph = ph?;
+        let src = elf.file_data(&ph)?;
+        unsafe {
+            let dest = ph.paddr as *mut u8;
+            core::ptr::copy_nonoverlapping(src.as_ptr(), dest, src.len());
+            core::ptr::write_bytes(
+                dest.add(ph.filesz as usize),
+                0,
+                (ph.memsz - ph.filesz) as usize,
+            );
+        }
+        // instruction fetches don't look in the data cache
+        mmu::clean_d_cache_range(ph.paddr as usize, ph.memsz as usize);
+        if ph.memsz == 0 {
+            continue;
+        }
+        phys_start = phys_start.min(ph.paddr as usize);
+        phys_end = phys_end.max((ph.paddr + ph.memsz) as usize);
+
+        // map section by section, a section shared with another
+        // segment gets the union of both segments' permissions
+        let first = ph.vaddr & !(mmu::SECTION_SIZE - 1);
+        let last = (ph.vaddr + ph.memsz - 1) & !(mmu::SECTION_SIZE - 1);
+        for section in (first..=last).step_by(mmu::SECTION_SIZE as usize) {
+            let flags = section_flags(elf.section_permissions(section)?);
+            let phys = ph.paddr.wrapping_add(section.wrapping_sub(ph.vaddr));
+            mmu::map_range(section, phys, mmu::SECTION_SIZE, flags);
+        }
+    }
+
+    // nothing fetched or predicted from before the segments were written
+    // may be used
+    unsafe {
+        hal::asm::dsb();
+        hal::asm::flush_i_cache();
+        hal::asm::invalidate_branch_predictor();
+        hal::asm::isb();
+    }
+
+    Ok(LoadedImage {
+        entry: elf.entry(),
+        phys_start,
+        phys_end,
+    })
+}
@@ -0,0 +1,330 @@
+//! LZ4 frame format, as written by the `lz4` command line tool.
+//!
+//! Dictionaries and the legacy frame format are not supported. Block
+//! checksums are skipped, the content checksum and the SHA-256 in the kernel
+//! image header cover the data anyway.
+use crate::decompress::{DecompressError, Output, Source};
+
+const FRAME_MAGIC: u32 = 0x184d_2204;
+const FRAME_VERSION: u8 = 0b01;
+/// Shortest match, match lengths are stored minus this
+const MIN_MATCH: usize = 4;
+
+/// Frame descriptor FLG bits
+mod frame_flags {
+    pub const DICT_ID: u8 = 1 << 0;
+    pub const CONTENT_CHECKSUM: u8 = 1 << 2;
+    pub const CONTENT_SIZE: u8 = 1 << 3;
+    pub const BLOCK_CHECKSUM: u8 = 1 << 4;
+    pub const RESERVED: u8 = 1 << 1;
+}
+
+/// Block size field bit marking a block stored uncompressed
+const BLOCK_UNCOMPRESSED: u32 = 1 << 31;
+
+/// Decompress the LZ4 frame in `source` into `out`, returning its size
+pub fn decode_frame<S: Source>(source: &mut S, out: &mut [u8]) -> Result<usize, DecompressError> {
+    if source.read_u32_le()? != FRAME_MAGIC {
+        return Err(DecompressError::Corrupt("not an LZ4 frame"));
+    }
+
+    // FLG, BD, content size, dictionary id; only the first two are always there
+    let mut descriptor = [0u8; 14];
+    source.read_exact(&mut descriptor[..2])?;
+    let [flags, block_descriptor, ..] = descriptor;
+    if flags >> 6 != FRAME_VERSION {
+        return Err(DecompressError::Unsupported("LZ4 frame version"));
+    }
+    if flags & frame_flags::RESERVED != 0 || block_descriptor & 0x8f != 0 {
+        return Err(DecompressError::Corrupt("reserved LZ4 frame bits set"));
+    }
+    if flags & frame_flags::DICT_ID != 0 {
+        return Err(DecompressError::Unsupported("LZ4 dictionaries"));
+    }
+    let max_block_size = match (block_descriptor >> 4) & 0x7 {
+        4 => 64 << 10,
+        5 => 256 << 10,
+        6 => 1 << 20,
+        7 => 4 << 20,
+        _ => return Err(DecompressError::Corrupt("invalid LZ4 block size")),
+    };
+
+    let mut descriptor_len = 2;
+    let content_size = if flags & frame_flags::CONTENT_SIZE != 0 {
+        source.read_exact(&mut descriptor[2..10])?;
+        descriptor_len = 10;
+        let size = u64::from_le_bytes(descriptor[2..10].try_into().unwrap());
+        if size > out.len() as u64 {
+            return Err(DecompressError::TooLarge);
+        }
+        Some(size as usize)
+    } else {
+        None
+    };
+    let header_checksum = source.read_u8()?;
+    if (xxh32(&descriptor[..descriptor_len], 0) >> 8) as u8 != header_checksum {
+        return Err(DecompressError::Corrupt("LZ4 frame header checksum"));
+    }
+
+    let mut out = Output::new(out);
+    loop {
+        let block_size = source.read_u32_le()?;
+        if block_size == 0 {
+            break;
+        }
+        let length = (block_size & !BLOCK_UNCOMPRESSED) as usize;
+        if length > max_block_size {
+            return Err(DecompressError::Corrupt(
+                "LZ4 block larger than the frame allows",
+            ));
+        }
+        if block_size & BLOCK_UNCOMPRESSED != 0 {
+            out.copy_from(source, length)?;
+        } else {
+            decode_block(
+                &mut Block {
+                    source,
+                    left: length,
+                },
+                &mut out,
+            )?;
+        }
+        if flags & frame_flags::BLOCK_CHECKSUM != 0 {
+            source.skip(4)?;
+        }
+    }
+
+    if let Some(expected) = content_size
+        && expected != out.len()
+    {
+        return Err(DecompressError::SizeMismatch {
+            expected,
+            actual: out.len(),
+        });
+    }
+    if flags & frame_flags::CONTENT_CHECKSUM != 0
+        && source.read_u32_le()? != xxh32(out.written(), 0)
+    {
+        return Err(DecompressError::ChecksumMismatch);
+    }
+    Ok(out.len())
+}
+
+/// The compressed bytes of one block, reads past its end are errors
+struct Block<'a, S> {
+    source: &'a mut S,
+    left: usize,
+}
+
+impl<S: Source> Source for Block<'_, S> {
+    fn fill(&mut self) -> Result<&[u8], DecompressError> {
+        let left = self.left;
+        let chunk = self.source.fill()?;
+        Ok(&chunk[..chunk.len().min(left)])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.source.consume(amount);
+        self.left -= amount;
+    }
+}
+
+/// Literal or match length: the token nibble, then while it is saturated,
+/// bytes to add until one isn't 255
+fn read_length<S: Source>(block: &mut Block<S>, nibble: u8) -> Result<usize, DecompressError> {
+    let mut length = nibble as usize;
+    if nibble == 15 {
+        loop {
+            let byte = block.read_u8()?;
+            length += byte as usize;
+            if byte != 255 {
+                break;
+            }
+        }
+    }
+    Ok(length)
+}
+
+/// Decode sequences of literals and a match until the block is used up. The
+/// last sequence has literals only.
+fn decode_block<S: Source>(block: &mut Block<S>, out: &mut Output) -> Result<(), DecompressError> {
+    while block.left > 0 {
+        let token = block.read_u8()?;
+        let literals = read_length(block, token >> 4)?;
+        out.copy_from(block, literals)?;
+        if block.left == 0 {
+            break;
+        }
+
+        let offset = block.read_u16_le()? as usize;
+        let length = read_length(block, token & 0xf)? + MIN_MATCH;
+        out.copy_match(offset, length)?;
+    }
+    Ok(())
+}
+
+const PRIME32_1: u32 = 0x9e37_79b1;
+const PRIME32_2: u32 = 0x85eb_ca77;
+const PRIME32_3: u32 = 0xc2b2_ae3d;
+const PRIME32_4: u32 = 0x27d4_eb2f;
+const PRIME32_5: u32 = 0x1656_67b1;
+
+fn xxh32_round(acc: u32, lane: u32) -> u32 {
+    acc.wrapping_add(lane.wrapping_mul(PRIME32_2))
+        .rotate_left(13)
+        .wrapping_mul(PRIME32_1)
+}
+
+/// xxHash32, the checksum used by LZ4 frames
+fn xxh32(data: &[u8], seed: u32) -> u32 {
+    let (stripes, rest) = data.as_chunks::<16>();
+    let mut hash = if stripes.is_empty() {
+        seed.wrapping_add(PRIME32_5)
+    } else {
+        let mut acc = [
+            seed.wrapping_add(PRIME32_1).wrapping_add(PRIME32_2),
+            seed.wrapping_add(PRIME32_2),
+            seed,
+            seed.wrapping_sub(PRIME32_1),
+        ];
+        for stripe in stripes {
+            for (acc, lane) in acc.iter_mut().zip(stripe.as_chunks::<4>().0) {
+                *acc = xxh32_round(*acc, u32::from_le_bytes(*lane));
+            }
+        }
+        acc[0]
+            .rotate_left(1)
+            .wrapping_add(acc[1].rotate_left(7))
+            .wrapping_add(acc[2].rotate_left(12))
+            .wrapping_add(acc[3].rotate_left(18))
+    };
+    hash = hash.wrapping_add(data.len() as u32);
+
+    let (words, bytes) = rest.as_chunks::<4>();
+    for word in words {
+        hash = hash
+            .wrapping_add(u32::from_le_bytes(*word).wrapping_mul(PRIME32_3))
+            .rotate_left(17)
+            .wrapping_mul(PRIME32_4);
+    }
+    for &byte in bytes {
+        hash = hash
+            .wrapping_add((byte as u32).wrapping_mul(PRIME32_5))
+            .rotate_left(11)
+            .wrapping_mul(PRIME32_1);
+    }
+
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(PRIME32_2);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(PRIME32_3);
+    hash ^= hash >> 16;
+    hash
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    const KERNEL_LOG: &[u8] = include_bytes!("../testdata/kernel.log");
+    const KERNEL_LOG_LZ4: &[u8] = include_bytes!("../testdata/kernel.log.lz4");
+
+    /// A frame with no optional fields around the single block `block`,
+    /// `uncompressed` sets the block's stored bit
+    fn frame(block: &[u8], uncompressed: bool, stream: &mut [u8; 64]) -> usize {
+        // version 01 and no other flags, 64 KiB blocks
+        let descriptor = [FRAME_VERSION << 6, 4 << 4];
+        let header_checksum = (xxh32(&descriptor, 0) >> 8) as u8;
+        let mut size = block.len() as u32;
+        if uncompressed {
+            size |= BLOCK_UNCOMPRESSED;
+        }
+        let parts = [
+            &FRAME_MAGIC.to_le_bytes()[..],
+            &descriptor,
+            &[header_checksum],
+            &size.to_le_bytes(),
+            block,
+            &[0; 4],
+        ];
+        let mut len = 0;
+        for part in parts {
+            stream[len..len + part.len()].copy_from_slice(part);
+            len += part.len();
+        }
+        len
+    }
+
+    #[test]
+    fn xxh32_reference_values() {
+        assert_eq!(xxh32(b"", 0), 0x02cc_5d05);
+        assert_eq!(xxh32(b"abc", 0), 0x32d1_53ff);
+        assert_eq!(
+            xxh32(b"Nobody inspects the spammish repetition", 0),
+            0xe229_3b2f
+        );
+    }
+
+    #[test]
+    fn decodes_blocks() {
+        let mut stream = [0; 64];
+        let mut out = [0; 16];
+
+        // "ab", then a match 2 back for 6 bytes, then "c" as the last literals
+        let len = frame(&[0x22, b'a', b'b', 2, 0, 0x10, b'c'], false, &mut stream);
+        let size = decode_frame(&mut &stream[..len], &mut out).unwrap();
+        assert_eq!(&out[..size], b"ababababc");
+
+        let len = frame(b"stored", true, &mut stream);
+        let size = decode_frame(&mut &stream[..len], &mut out).unwrap();
+        assert_eq!(&out[..size], b"stored");
+    }
+
+    #[test]
+    fn rejects_offset_before_start() {
+        let mut stream = [0; 64];
+        let mut out = [0; 16];
+        for offset in [0, 2] {
+            let len = frame(&[0x10, b'a', offset, 0, 0x10, b'b'], false, &mut stream);
+            assert_eq!(
+                decode_frame(&mut &stream[..len], &mut out),
+                Err(DecompressError::Corrupt("match distance too far back")),
+                "offset {}",
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn block_cannot_read_past_its_end() {
+        // claims two literals but the block holds one, the next bytes are
+        // the end mark and must not be taken as data
+        let mut stream = [0; 64];
+        let len = frame(&[0x20, b'a'], false, &mut stream);
+        assert_eq!(
+            decode_frame(&mut &stream[..len], &mut [0; 16]),
+            Err(DecompressError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_checksums() {
+        let mut stream = [0; KERNEL_LOG_LZ4.len()];
+        let mut out = [0; KERNEL_LOG.len()];
+
+        stream.copy_from_slice(KERNEL_LOG_LZ4);
+        *stream.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            decode_frame(&mut &stream[..], &mut out),
+            Err(DecompressError::ChecksumMismatch)
+        );
+
+        // the header checksum follows the magic, FLG, BD and content size
+        stream.copy_from_slice(KERNEL_LOG_LZ4);
+        stream[14] ^= 1;
+        assert_eq!(
+            decode_frame(&mut &stream[..], &mut out),
+            Err(DecompressError::Corrupt("LZ4 frame header checksum"))
+        );
+    }
+}
@@ -7,78 +7,547 @@
 
 // use alloc::vec;
 
+mod chainload;
+#[cfg(feature = "boot_net")]
+mod dhcp;
+mod linux;
+mod loader;
+mod menu;
+mod monitor;
+#[cfg(feature = "boot_net")]
+mod net;
 mod panic;
+mod profile;
+#[cfg(feature = "boot_net")]
+mod tftp;
+mod ymodem;
 
+use chainload::ChainloadError;
+use core::convert::Infallible;
 pub use core::ffi::c_void;
-use hal::{ccm, dram, i2c, mmu, println, uart};
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, Ordering};
+use fat32::{Fat32Error, Fat32File, Fat32FileSystem};
+use hal::dram::{DRAM_END, DRAM_START};
+use hal::fdt::{self, Fdt};
+use hal::mmc::{self, MMCError};
+#[cfg(feature = "boot_sata")]
+use hal::sata::{self, SataError};
+#[cfg(feature = "boot_semihost")]
+use hal::semihosting::{self, OpenMode, SemihostingError};
+use hal::{ccm, dram, i2c, mmu, print, println, timer, uart};
+use linux::LinuxError;
+use loader::LoadedImage;
+#[cfg(feature = "boot_net")]
+use net::NetError;
+use ymodem::YmodemError;
 
-#[cfg(feature = "boot_mmc")]
-mod boot_mmc_imports {
-    pub use core::ffi::c_uchar;
-    pub use fat32::{Fat32Error, Fat32FileSystem};
-    pub use hal::dram::DRAM_START;
-    pub use hal::mmc;
+use bootloader_types::boot_info::{BootInfoBuilder, BootInfoError, MemoryKind, MemoryRegion};
+use bootloader_types::config::{CONFIG_PATH, Config, Entry, EntryKind, SLOT_KERNEL};
+use bootloader_types::decompress::{self, DecompressError, Format, Source};
+use bootloader_types::elf::{ElfError, ElfImage};
+use bootloader_types::image::{self, ImageError};
+use bootloader_types::kernel_abi::{AbiError, KernelAbiHeader, LoadInfo};
+use bootloader_types::slot::{self, Slot, SlotState};
+
+#[cfg(feature = "verified_boot")]
+include!(concat!(env!("OUT_DIR"), "/boot_pubkey.rs"));
+
+/// Seconds to wait for a key that enters the monitor instead of booting
+const AUTOBOOT_DELAY: u32 = 2;
+
+#[derive(Debug)]
+pub enum BootError {
+    Mmc(MMCError),
+    #[cfg(feature = "boot_sata")]
+    Sata(SataError),
+    Fs(Fat32Error),
+    Serial(YmodemError),
+    #[cfg(feature = "boot_semihost")]
+    Semihost(SemihostingError),
+    #[cfg(feature = "boot_net")]
+    Net(NetError),
+    Image(ImageError),
+    Decompress(DecompressError),
+    Elf(ElfError),
+    Abi(AbiError),
+    Linux(LinuxError),
+    Chainload(ChainloadError),
+    /// Initrd is bigger than the free DRAM after the kernel
+    InitrdTooLarge {
+        size: usize,
+        space: usize,
+    },
+    /// Kernel is bigger than the staging region
+    #[cfg(feature = "boot_semihost")]
+    KernelTooLarge {
+        size: usize,
+        space: usize,
+    },
+    /// `boot <entry>` named an entry that isn't in the config
+    NoEntry,
+}
+
+impl core::fmt::Display for BootError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Mmc(e) => write!(f, "SD card error: {:?}", e),
+            #[cfg(feature = "boot_sata")]
+            Self::Sata(e) => write!(f, "SATA error: {:?}", e),
+            Self::Fs(e) => write!(f, "filesystem error: {:?}", e),
+            Self::Serial(e) => write!(f, "serial download failed: {}", e),
+            #[cfg(feature = "boot_semihost")]
+            Self::Semihost(e) => write!(f, "semihosting error: {:?}", e),
+            #[cfg(feature = "boot_net")]
+            Self::Net(e) => write!(f, "network download failed: {}", e),
+            Self::Image(e) => write!(f, "refusing to boot: {}", e),
+            Self::Decompress(e) => write!(f, "cannot decompress kernel: {}", e),
+            Self::Elf(e) => write!(f, "bad kernel image: {}", e),
+            Self::Abi(e) => write!(f, "incompatible kernel: {}", e),
+            Self::Linux(e) => write!(f, "cannot boot Linux: {}", e),
+            Self::Chainload(e) => write!(f, "cannot chainload: {}", e),
+            Self::InitrdTooLarge { size, space } => write!(
+                f,
+                "{} byte initrd does not fit in the {} bytes after the kernel",
+                size, space
+            ),
+            #[cfg(feature = "boot_semihost")]
+            Self::KernelTooLarge { size, space } => write!(
+                f,
+                "{} byte kernel does not fit in the {} byte staging region",
+                size, space
+            ),
+            Self::NoEntry => write!(f, "no such boot entry"),
+        }
+    }
+}
+
+impl From<MMCError> for BootError {
+    fn from(e: MMCError) -> Self {
+        Self::Mmc(e)
+    }
+}
+
+#[cfg(feature = "boot_sata")]
+impl From<SataError> for BootError {
+    fn from(e: SataError) -> Self {
+        Self::Sata(e)
+    }
+}
+
+impl From<Fat32Error> for BootError {
+    fn from(e: Fat32Error) -> Self {
+        Self::Fs(e)
+    }
+}
+
+impl From<YmodemError> for BootError {
+    fn from(e: YmodemError) -> Self {
+        Self::Serial(e)
+    }
+}
+
+#[cfg(feature = "boot_net")]
+impl From<NetError> for BootError {
+    fn from(e: NetError) -> Self {
+        Self::Net(e)
+    }
+}
+
+#[cfg(feature = "boot_semihost")]
+impl From<SemihostingError> for BootError {
+    fn from(e: SemihostingError) -> Self {
+        Self::Semihost(e)
+    }
+}
+
+impl From<ImageError> for BootError {
+    fn from(e: ImageError) -> Self {
+        Self::Image(e)
+    }
+}
+
+impl From<DecompressError> for BootError {
+    fn from(e: DecompressError) -> Self {
+        Self::Decompress(e)
+    }
 }
 
-#[cfg(feature = "boot_mmc")]
-use boot_mmc_imports::*;
+impl From<ElfError> for BootError {
+    fn from(e: ElfError) -> Self {
+        Self::Elf(e)
+    }
+}
+
+impl From<AbiError> for BootError {
+    fn from(e: AbiError) -> Self {
+        Self::Abi(e)
+    }
+}
 
-use bootloader_types::BootInfoHeader;
+impl From<LinuxError> for BootError {
+    fn from(e: LinuxError) -> Self {
+        Self::Linux(e)
+    }
+}
+
+impl From<ChainloadError> for BootError {
+    fn from(e: ChainloadError) -> Self {
+        Self::Chainload(e)
+    }
+}
 
-#[cfg(feature = "boot_mmc")]
 unsafe extern "C" fn read_sector(sector: u32, buffer: *mut u8) -> i32 {
     if buffer.is_null() {
         return -1;
     }
 
     let buffer_slice: &mut [u8; 512] = unsafe { &mut *(buffer as *mut [u8; 512]) };
-    hal::mmc::read_sector(sector, buffer_slice).expect("Failed to read sector");
-    0
+    match hal::mmc::read_sector(sector, buffer_slice) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
 }
 
-#[cfg(feature = "boot_mmc")]
-fn copy_kernel_to_phys() -> Result<(), Fat32Error> {
-    let mut fs = Fat32FileSystem::from_read_fn(read_sector)?;
-    let file = fs
-        .open_file("/boot/kernel.bin\0")
-        .expect("Failed to open kernel.bin");
-    let file_size = file.size();
-    let start_of_memory = DRAM_START as *mut c_uchar;
-    println!("Copying kernel to 0x{:x}", start_of_memory as usize);
-    println!("Kernel size: {}", file_size);
+unsafe extern "C" fn write_sector(sector: u32, buffer: *const u8) -> i32 {
+    if buffer.is_null() {
+        return -1;
+    }
 
-    unsafe {
-        let memory_slice: &mut [u8] =
-            core::slice::from_raw_parts_mut(start_of_memory, file_size as usize);
-        file.read(memory_slice)
-            .expect("Failed to read file into memory");
+    let buffer_slice: &[u8; 512] = unsafe { &*(buffer as *const [u8; 512]) };
+    match hal::mmc::write_sector(sector, buffer_slice) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Initialize the MMC controller the first time it is needed
+fn init_mmc() -> Result<(), MMCError> {
+    static MMC_READY: AtomicBool = AtomicBool::new(false);
+    if !MMC_READY.load(Ordering::Relaxed) {
+        mmc::init()?;
+        println!("Initialized MMC controller");
+        MMC_READY.store(true, Ordering::Relaxed);
     }
     Ok(())
 }
 
+fn mount() -> Result<Fat32FileSystem, BootError> {
+    init_mmc()?;
+    Ok(Fat32FileSystem::from_fns(read_sector, write_sector)?)
+}
+
+#[cfg(feature = "boot_sata")]
+unsafe extern "C" fn sata_read_sector(sector: u32, buffer: *mut u8) -> i32 {
+    if buffer.is_null() {
+        return -1;
+    }
+
+    let buffer_slice: &mut [u8; 512] = unsafe { &mut *(buffer as *mut [u8; 512]) };
+    match sata::read_sector(sector, buffer_slice) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+#[cfg(feature = "boot_sata")]
+unsafe extern "C" fn sata_write_sector(sector: u32, buffer: *const u8) -> i32 {
+    if buffer.is_null() {
+        return -1;
+    }
+
+    let buffer_slice: &[u8; 512] = unsafe { &*(buffer as *const [u8; 512]) };
+    match sata::write_sector(sector, buffer_slice) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Mount the FAT32 filesystem on the SATA disk, same layout as the SD card
+#[cfg(feature = "boot_sata")]
+fn mount_sata() -> Result<Fat32FileSystem, BootError> {
+    let disk = sata::init()?;
+    println!(
+        "SATA disk '{}', {} MB",
+        disk.model(),
+        (disk.sectors * sata::SECTOR_SIZE as u64) >> 20
+    );
+    Ok(Fat32FileSystem::from_fns(
+        sata_read_sector,
+        sata_write_sector,
+    )?)
+}
+
+fn read_file(fs: &mut Fat32FileSystem, path: &str, dest: &mut [u8]) -> Result<usize, Fat32Error> {
+    let file = fs.open_file(path)?;
+    let file_size = file.size() as usize;
+    if file_size > dest.len() {
+        return Err(Fat32Error::NoSpace);
+    }
+    file.read(&mut dest[..file_size])
+}
+
+/// Compressed kernels are read through this much of the end of the staging
+/// region, the rest is what they can decompress to
+const INPUT_CHUNK_SIZE: usize = 0x1_0000;
+
+/// [`Source`] reading a file a chunk at a time
+struct FileSource<'a> {
+    file: Fat32File,
+    buf: &'a mut [u8],
+    start: usize,
+    end: usize,
+    /// Why the last read failed, [`DecompressError::Read`] can't carry it
+    error: Option<Fat32Error>,
+}
+
+impl Source for FileSource<'_> {
+    fn fill(&mut self) -> Result<&[u8], DecompressError> {
+        if self.start == self.end {
+            self.start = 0;
+            self.end = self.file.read(self.buf).map_err(|e| {
+                self.error = Some(e);
+                DecompressError::Read
+            })?;
+        }
+        Ok(&self.buf[self.start..self.end])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.start += amount;
+    }
+}
+
+/// Read the kernel at `path` into `dest` like [`read_file`], decompressing it
+/// on the way if it is gzip or LZ4 compressed
+fn read_kernel(fs: &mut Fat32FileSystem, path: &str, dest: &mut [u8]) -> Result<usize, BootError> {
+    let mut file = fs.open_file(path)?;
+    let mut magic = [0u8; decompress::MAGIC_SIZE];
+    let read = file.read(&mut magic)?;
+    let Some(format) = Format::detect(&magic[..read]) else {
+        return Ok(read_file(fs, path, dest)?);
+    };
+    file.seek(0)?;
+
+    println!(
+        "Decompressing {} byte {} kernel",
+        file.size(),
+        format.name()
+    );
+    // no room for the input chunk leaves none for the output either
+    let output_size = dest
+        .len()
+        .checked_sub(INPUT_CHUNK_SIZE)
+        .ok_or(BootError::Decompress(DecompressError::TooLarge))?;
+    let (dest, buf) = dest.split_at_mut(output_size);
+    let mut source = FileSource {
+        file,
+        buf,
+        start: 0,
+        end: 0,
+        error: None,
+    };
+    decompress::decompress(format, &mut source, dest).map_err(|e| match source.error.take() {
+        Some(e) => BootError::Fs(e),
+        None => BootError::Decompress(e),
+    })
+}
+
+/// Largest `/boot/boot.cfg` we read, carved off the end of the staging region
+const CONFIG_MAX_SIZE: usize = 0x1_0000;
+
+/// DRAM window kernel images are read into before the ELF loader places them
+const STAGING_OFFSET: usize = 0x0800_0000;
+const STAGING_SIZE: usize = 0x0400_0000;
+
+fn staging_region() -> &'static mut [u8] {
+    unsafe {
+        core::slice::from_raw_parts_mut((DRAM_START + STAGING_OFFSET) as *mut u8, STAGING_SIZE)
+    }
+}
+
+/// Kernel command line when there is no config file, set with `BOOT_CMDLINE` at build time
+const DEFAULT_CMDLINE: &str = match option_env!("BOOT_CMDLINE") {
+    Some(cmdline) => cmdline,
+    None => "",
+};
+
+fn bootloader_range() -> Range<usize> {
+    unsafe extern "C" {
+        static __StackStart: u8;
+    }
+    get_boot_entry()..unsafe { &__StackStart as *const u8 as usize }
+}
+
+fn boot_tables_range() -> Range<usize> {
+    let tables = mmu::get_boot_tables();
+    let start = tables.as_ptr() as usize;
+    start..start + core::mem::size_of_val(tables)
+}
+
+/// Region reserved by the linker script for the boot info handed to the kernel
+fn boot_info_region() -> &'static mut [u8] {
+    unsafe extern "C" {
+        static mut _boot_info_start: u8;
+        static _boot_info_end: u8;
+    }
+    unsafe {
+        let start = &raw mut _boot_info_start;
+        let end = &_boot_info_end as *const u8;
+        core::slice::from_raw_parts_mut(start, end as usize - start as usize)
+    }
+}
+
+/// Region reserved by the linker script for the record of a panic, kept
+/// across warm resets
+fn panic_record_region() -> &'static mut [u8] {
+    unsafe extern "C" {
+        static mut _panic_record_start: u8;
+        static _panic_record_end: u8;
+    }
+    unsafe {
+        let start = &raw mut _panic_record_start;
+        let end = &_panic_record_end as *const u8;
+        core::slice::from_raw_parts_mut(start, end as usize - start as usize)
+    }
+}
+
+fn panic_record_range() -> Range<usize> {
+    let panic_record = panic_record_region().as_ptr_range();
+    panic_record.start as usize..panic_record.end as usize
+}
+
+/// Memory the bootloader needs until it jumps to the kernel
+fn protected_regions() -> [Range<usize>; 4] {
+    let boot_info = boot_info_region().as_ptr_range();
+    [
+        bootloader_range(),
+        boot_tables_range(),
+        boot_info.start as usize..boot_info.end as usize,
+        panic_record_range(),
+    ]
+}
+
+/// Physical ranges a kernel segment must never be loaded over
+fn reserved_regions() -> [Range<usize>; 5] {
+    let [bootloader, tables, boot_info, panic_record] = protected_regions();
+    let staging_start = DRAM_START + STAGING_OFFSET;
+    [
+        bootloader,
+        tables,
+        boot_info,
+        panic_record,
+        staging_start..staging_start + STAGING_SIZE,
+    ]
+}
+
+/// Check the ABI header of the ELF kernel staged in `image`, then load it
+fn load_elf(image: &[u8]) -> Result<LoadedImage, BootError> {
+    let elf = ElfImage::parse(image)?;
+    let phys = elf.phys_range()?;
+    let header = KernelAbiHeader::parse(elf.data_at(phys.start)?)?;
+    header.check(&LoadInfo {
+        entry: elf.entry() as u32,
+        phys_start: phys.start as u32,
+        phys_end: phys.end as u32,
+        ram_size: dram::ram().len() as u32,
+    })?;
+    println!(
+        "Kernel ABI {}, needs {} MB of RAM",
+        header.abi_version,
+        header.min_ram >> 20
+    );
+
+    let loaded = loader::load(&elf, &reserved_regions())?;
+    println!(
+        "Loaded kernel to 0x{:x}-0x{:x}, entry 0x{:x}",
+        loaded.phys_start, loaded.phys_end, loaded.entry
+    );
+    profile::checkpoint("load");
+    Ok(loaded)
+}
+
+fn region(range: Range<usize>, kind: MemoryKind) -> MemoryRegion {
+    MemoryRegion::new(range.start as u32, (range.end - range.start) as u32, kind)
+}
+
+/// Everything the kernel is told besides where it was loaded
+pub struct BootParams<'a> {
+    pub cmdline: &'a str,
+    /// A/B slot the kernel was booted from
+    pub slot: Option<SlotState>,
+    /// Physical range the initrd was loaded to
+    pub initrd: Option<Range<usize>>,
+}
+
+impl<'a> BootParams<'a> {
+    pub const fn new(cmdline: &'a str) -> Self {
+        Self {
+            cmdline,
+            slot: None,
+            initrd: None,
+        }
+    }
+}
+
+/// Write the boot info for `kernel` into its reserved region, returning its address
+fn build_boot_info(kernel: &LoadedImage, params: &BootParams) -> Result<usize, BootInfoError> {
+    let buf = boot_info_region();
+    let boot_info = buf.as_ptr_range();
+    let mut memory_map = [
+        region(dram::ram(), MemoryKind::Ram),
+        region(bootloader_range(), MemoryKind::Bootloader),
+        region(boot_tables_range(), MemoryKind::PageTables),
+        region(
+            boot_info.start as usize..boot_info.end as usize,
+            MemoryKind::BootInfo,
+        ),
+        region(kernel.phys_start..kernel.phys_end, MemoryKind::Kernel),
+        region(panic_record_range(), MemoryKind::PanicRecord),
+        region(0..0, MemoryKind::Initrd),
+    ];
+    let mut regions = memory_map.len() - 1;
+    if let Some(initrd) = &params.initrd {
+        memory_map[regions] = region(initrd.clone(), MemoryKind::Initrd);
+        regions += 1;
+    }
+
+    let address = buf.as_ptr() as usize;
+    let mut builder = BootInfoBuilder::new(buf)?;
+    builder.add_memory_map(&memory_map[..regions])?;
+    let board = hal::board::get_board_info();
+    let board_name = board.name_str().trim_end_matches('\0');
+    if !board_name.is_empty() {
+        builder.add_board_name(board_name)?;
+    }
+    builder.add_command_line(params.cmdline)?;
+    if let Some(slot) = &params.slot {
+        builder.add_boot_slot(slot)?;
+    }
+    builder.add_boot_times(profile::stages().as_slice())?;
+    let size = builder.finish();
+    println!("Boot info at 0x{:x} ({} bytes)", address, size);
+    Ok(address)
+}
+
 #[unsafe(no_mangle)]
-pub fn load_kernel() -> ! {
+pub fn load_kernel(kernel: &LoadedImage, params: &BootParams) -> ! {
     unsafe {
-        let kernel_entry = get_kernel_entry();
-        assert!(kernel_entry % 4 == 0, "Kernel must be 4-byte aligned");
+        assert!(kernel.entry % 4 == 0, "Kernel must be 4-byte aligned");
 
-        let info = BootInfoHeader {
-            boot_entry: get_boot_entry(),
-            boot_size: 0xdeadbeef,
+        profile::checkpoint("handoff");
+        let info_ptr = match build_boot_info(kernel, params) {
+            Ok(address) => address,
+            Err(e) => panic!("Failed to build boot info: {:?}", e),
         };
-        let info_ptr = &info as *const BootInfoHeader as usize;
+        profile::report();
 
         // for now, cast kernel_entry with transmutate into a function pointer that takes one argument and never returns
-        let kernel_entry = core::mem::transmute::<usize, fn(usize) -> !>(kernel_entry);
+        let kernel_entry = core::mem::transmute::<usize, fn(usize) -> !>(kernel.entry);
         kernel_entry(info_ptr);
     }
 }
 
-pub fn get_kernel_entry() -> usize {
-    let s = env!("KERNEL_ENTRY");
-    usize::from_str_radix(s.trim_start_matches("0x"), 16).unwrap()
-}
-
 pub fn get_boot_entry() -> usize {
     unsafe extern "C" {
         static _init: u8;
@@ -86,28 +555,472 @@ pub fn get_boot_entry() -> usize {
     unsafe { &_init as *const u8 as usize }
 }
 
-#[cfg(feature = "boot_mmc")]
-fn boot_mmc() -> ! {
-    mmc::init().expect("Failed to initialize MMC");
-    println!("Initialized MMC controller");
-    copy_kernel_to_phys().expect("Failed to copy kernel to memory");
-    println!("Copied kernel, jumping to kernel");
-    load_kernel();
+/// Verify the kernel image staged in `image`, then load its ELF payload and
+/// jump to it
+fn boot_image(image: &[u8], params: &BootParams) -> Result<Infallible, BootError> {
+    let kernel = load_elf(verify_image(image)?)?;
+    println!("Jumping to kernel");
+    load_kernel(&kernel, params);
+}
+
+/// Check the header, and signature if required, of the kernel image staged in
+/// `image`, returning its payload
+fn verify_image(image: &[u8]) -> Result<&[u8], BootError> {
+    let (header, payload) = image::verify(image)?;
+    print!(
+        "Kernel image verified, {} bytes, sha256 ",
+        header.image_size
+    );
+    for byte in header.sha256 {
+        print!("{:02x}", byte);
+    }
+    println!();
+
+    #[cfg(feature = "verified_boot")]
+    {
+        image::verify_signature(image, &header, &BOOT_PUBLIC_KEY)?;
+        println!("Kernel signature verified");
+    }
+
+    profile::checkpoint("verify");
+    Ok(payload)
+}
+
+/// Initrds start on a section boundary so the kernel can map them with 1MB
+/// sections
+const INITRD_ALIGN: usize = mmu::SECTION_SIZE as usize;
+
+/// End of the free DRAM from `start` up to whatever reserved region or end of
+/// DRAM comes first. A region already covering `start` leaves no room at all.
+fn free_space_end(start: usize) -> usize {
+    reserved_regions()
+        .iter()
+        .filter(|reserved| reserved.end > start)
+        .fold(dram::ram().end, |end, reserved| end.min(reserved.start))
+}
+
+/// Read the initrd at `path` into the free DRAM from `after` on, usually the
+/// end of the kernel, returning where it went
+fn load_initrd(
+    fs: &mut Fat32FileSystem,
+    path: &str,
+    after: usize,
+) -> Result<Range<usize>, BootError> {
+    let start = after.next_multiple_of(INITRD_ALIGN);
+    let space = free_space_end(start).saturating_sub(start);
+
+    let size = fs.open_file(path)?.size() as usize;
+    if size > space {
+        return Err(BootError::InitrdTooLarge { size, space });
+    }
+    println!("Loading initrd {} to 0x{:x}", path, start);
+    let dest = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, size) };
+    let size = read_file(fs, path, dest)?;
+    println!("Initrd size: {}", size);
+    Ok(start..start + size)
+}
+
+/// How [`boot_from`] picks the `/boot/boot.cfg` entry to boot
+#[derive(Clone, Copy)]
+pub enum EntryChoice<'a> {
+    /// Count down and let the user pick from a menu
+    Menu,
+    Default,
+    Named(&'a str),
+}
+
+/// Read `/boot/boot.cfg`, warning and returning `None` if it is missing or broken
+fn load_config<'a>(fs: &mut Fat32FileSystem, buf: &'a mut [u8]) -> Option<Config<'a>> {
+    let size = match read_file(fs, CONFIG_PATH, buf) {
+        Ok(size) => size,
+        Err(e) => {
+            println!("Warning: cannot read {}: {:?}", CONFIG_PATH, e);
+            return None;
+        }
+    };
+    match Config::parse(&buf[..size]) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            println!("Warning: ignoring {}: {}", CONFIG_PATH, e);
+            None
+        }
+    }
+}
+
+// also the monitor's disk when booting from neither
+#[cfg(any(feature = "boot_mmc", not(feature = "boot_sata")))]
+fn boot_mmc(choice: EntryChoice) -> Result<Infallible, BootError> {
+    boot_from(&mut mount()?, choice)
+}
+
+#[cfg(feature = "boot_sata")]
+fn boot_sata(choice: EntryChoice) -> Result<Infallible, BootError> {
+    boot_from(&mut mount_sata()?, choice)
+}
+
+/// Boot the entry `choice` picks from the `/boot/boot.cfg` on `fs`, or the
+/// default kernel without one
+fn boot_from(fs: &mut Fat32FileSystem, choice: EntryChoice) -> Result<Infallible, BootError> {
+    let (staging, config_buf) = staging_region().split_at_mut(STAGING_SIZE - CONFIG_MAX_SIZE);
+
+    let config = load_config(fs, config_buf);
+    let mut fallback = Entry::fallback(DEFAULT_CMDLINE);
+    if fs.open_file(slot::STATE_PATH).is_ok() {
+        fallback.kernel = SLOT_KERNEL;
+    }
+    let entry = match (&config, choice) {
+        (Some(config), EntryChoice::Menu) => menu::select(config),
+        (Some(config), EntryChoice::Default) => config.default_entry(),
+        (Some(config), EntryChoice::Named(name)) => config
+            .entries()
+            .find(|entry| entry.name == name)
+            .ok_or(BootError::NoEntry)?,
+        (None, EntryChoice::Named(name)) if name != fallback.name => {
+            return Err(BootError::NoEntry);
+        }
+        (None, _) => &fallback,
+    };
+
+    let Err(e) = boot_entry(fs, staging, entry);
+    // an image that fails verification must not run, but a known good
+    // recovery kernel still can
+    match config.as_ref().and_then(Config::recovery_entry) {
+        Some(recovery) if matches!(e, BootError::Image(_)) && recovery.name != entry.name => {
+            println!("{}, falling back to '{}'", e, recovery.name);
+            boot_entry(fs, staging, recovery)
+        }
+        _ => Err(e),
+    }
+}
+
+fn boot_entry(
+    fs: &mut Fat32FileSystem,
+    staging: &mut [u8],
+    entry: &Entry,
+) -> Result<Infallible, BootError> {
+    println!("Booting '{}' from {}", entry.name, entry.kernel);
+    if !entry.options.is_empty() {
+        println!("Load options: {}", entry.options);
+    }
+
+    if let EntryKind::Chainload { address } = entry.kind {
+        return boot_chainload(fs, staging, entry.kernel, address);
+    }
+    if entry.kernel == SLOT_KERNEL {
+        return boot_slot(fs, staging, entry);
+    }
+    let mut params = BootParams::new(entry.cmdline);
+    boot_kernel(fs, staging, entry.kernel, entry, &mut params)
+}
+
+/// Load the kernel at `path` and the initrd `entry` names, if any, and jump
+/// to the kernel. Linux zImages are booted with [`boot_linux`] instead.
+fn boot_kernel(
+    fs: &mut Fat32FileSystem,
+    staging: &mut [u8],
+    path: &str,
+    entry: &Entry,
+    params: &mut BootParams,
+) -> Result<Infallible, BootError> {
+    println!("Copying kernel to 0x{:x}", staging.as_ptr() as usize);
+    let size = read_kernel(fs, path, staging)?;
+    println!("Kernel size: {}", size);
+    profile::checkpoint("kernel read");
+    let payload = verify_image(&staging[..size])?;
+    if linux::is_zimage(payload) {
+        return boot_linux(fs, payload, entry, params.cmdline);
+    }
+
+    let kernel = load_elf(payload)?;
+    // placed after the kernel, so it can only be read once the kernel is loaded
+    params.initrd = match entry.initrd {
+        Some(initrd) => Some(load_initrd(fs, initrd, kernel.phys_end)?),
+        None => None,
+    };
+    println!("Jumping to kernel");
+    load_kernel(&kernel, params);
+}
+
+/// Boot the Linux `zimage` with the initrd and DTB `entry` names, passing
+/// ATAGS if it has no DTB
+fn boot_linux(
+    fs: &mut Fat32FileSystem,
+    zimage: &[u8],
+    entry: &Entry,
+    cmdline: &str,
+) -> Result<Infallible, BootError> {
+    let machine = linux::machine_type(entry.options)?;
+    let zimage_start = DRAM_START + linux::ZIMAGE_OFFSET;
+    let start = linux::place_zimage(zimage, free_space_end(zimage_start))?;
+    println!("Copied Linux zImage to 0x{:x}", start);
+
+    let initrd = match entry.initrd {
+        Some(initrd) => Some(load_initrd(fs, initrd, linux::PARAMS_END)?),
+        None => None,
+    };
+    let params = match entry.dtb {
+        Some(dtb) => {
+            let size = read_file(fs, dtb, linux::dtb_buffer())?;
+            println!("Loaded device tree {} ({} bytes)", dtb, size);
+            linux::write_dtb(size, cmdline, initrd.as_ref())?
+        }
+        None => {
+            if machine == linux::MACH_TYPE_NONE {
+                println!("Warning: booting Linux with ATAGS but no mach= option");
+            }
+            linux::write_atags(cmdline, initrd.as_ref())
+        }
+    };
+
+    profile::checkpoint("handoff");
+    profile::report();
+    println!(
+        "Jumping to Linux, machine type 0x{:x}, params at 0x{:x}",
+        machine, params
+    );
+    linux::enter(start, machine, params);
+}
+
+/// Load the raw binary at `path` to `address` and jump to it, see
+/// [`chainload`]
+fn boot_chainload(
+    fs: &mut Fat32FileSystem,
+    staging: &mut [u8],
+    path: &str,
+    address: usize,
+) -> Result<Infallible, BootError> {
+    let size = read_file(fs, path, staging)?;
+    profile::checkpoint("kernel read");
+    #[cfg(feature = "verified_boot")]
+    let image = verify_image(&staging[..size])?;
+    #[cfg(not(feature = "verified_boot"))]
+    let image = &staging[..size];
+
+    chainload::place(image, address, free_space_end(address))?;
+    println!(
+        "Chainloading {} ({} bytes) at 0x{:x}",
+        path,
+        image.len(),
+        address
+    );
+    profile::checkpoint("handoff");
+    profile::report();
+    chainload::enter(address);
+}
+
+/// Boot the active A/B slot, or the other one if its kernel can't be loaded
+fn boot_slot(
+    fs: &mut Fat32FileSystem,
+    staging: &mut [u8],
+    entry: &Entry,
+) -> Result<Infallible, BootError> {
+    let mut state = read_slot_state(fs);
+    let slot = state.start_boot();
+    // count the attempt before jumping, a kernel that hangs still used it up
+    save_slot_state(fs, &state);
+    if state.confirmed {
+        println!("Booting slot {}", slot.name());
+    } else {
+        println!(
+            "Booting unconfirmed slot {}, {} tries left",
+            slot.name(),
+            state.tries_left
+        );
+    }
+
+    let mut params = BootParams::new(entry.cmdline);
+    params.slot = Some(state);
+    let Err(e) = boot_kernel(fs, staging, slot.kernel_path(), entry, &mut params);
+
+    state.fail();
+    save_slot_state(fs, &state);
+    println!(
+        "Slot {} failed: {}, trying slot {}",
+        slot.name(),
+        e,
+        state.active.name()
+    );
+    params.slot = Some(state);
+    boot_kernel(fs, staging, state.active.kernel_path(), entry, &mut params)
+}
+
+fn read_slot_state(fs: &mut Fat32FileSystem) -> SlotState {
+    let mut bytes = [0u8; slot::STATE_SIZE];
+    let state = read_file(fs, slot::STATE_PATH, &mut bytes)
+        .ok()
+        .and_then(|size| SlotState::parse(&bytes[..size]));
+    state.unwrap_or_else(|| {
+        println!(
+            "Warning: {} is missing or corrupt, using slot a",
+            slot::STATE_PATH
+        );
+        SlotState::new(Slot::A)
+    })
+}
+
+/// Rewrite the slot state in place. Failing only costs the tries counter, so
+/// it is not fatal.
+fn save_slot_state(fs: &mut Fat32FileSystem, state: &SlotState) {
+    let written = fs
+        .open_file(slot::STATE_PATH)
+        .and_then(|mut file| file.write(&state.to_bytes()));
+    match written {
+        Ok(slot::STATE_SIZE) => {}
+        Ok(_) => println!("Warning: {} is truncated", slot::STATE_PATH),
+        Err(e) => println!("Warning: cannot save {}: {:?}", slot::STATE_PATH, e),
+    }
 }
 
 #[cfg(feature = "boot_uart")]
-fn boot_uart() -> ! {
-    todo!("do this shit");
+fn boot_uart() -> Result<Infallible, BootError> {
+    let staging = staging_region();
+    println!(
+        "Waiting for kernel over YMODEM/XMODEM-1K (max {} bytes)...",
+        staging.len()
+    );
+    let size = ymodem::receive(staging)?;
+    println!("Received {} byte kernel", size);
+    profile::checkpoint("kernel read");
+    boot_image(&staging[..size], &BootParams::new(DEFAULT_CMDLINE))
 }
 
+/// Host file [`boot_semihost`] loads, relative to where QEMU runs. Set with
+/// `SEMIHOST_KERNEL` at build time.
+#[cfg(feature = "boot_semihost")]
+const SEMIHOST_KERNEL: &str = match option_env!("SEMIHOST_KERNEL") {
+    Some(path) => path,
+    None => "deploy/qemu/kernel.bin",
+};
+
+#[cfg(feature = "boot_semihost")]
+fn boot_semihost() -> Result<Infallible, BootError> {
+    let staging = staging_region();
+    let mut file = semihosting::File::open(SEMIHOST_KERNEL, OpenMode::Read)?;
+    let size = file.size()?;
+    if size > staging.len() {
+        return Err(BootError::KernelTooLarge {
+            size,
+            space: staging.len(),
+        });
+    }
+
+    println!("Loading {} ({} bytes) from the host", SEMIHOST_KERNEL, size);
+    let mut read = 0;
+    while read < size {
+        match file.read(&mut staging[read..size])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    profile::checkpoint("kernel read");
+    boot_image(&staging[..read], &BootParams::new(DEFAULT_CMDLINE))
+}
+
+/// Kernel fetched over TFTP when the DHCP server doesn't name a boot file
+#[cfg(feature = "boot_net")]
+const NET_BOOT_FILE: &str = "kernel.bin";
+
+#[cfg(feature = "boot_net")]
+fn boot_net() -> Result<Infallible, BootError> {
+    let mac = hal::eth::init().map_err(NetError::from)?;
+    println!("Ethernet up, MAC {}", net::DisplayMac(&mac));
+
+    // the stack is far too small for the frame buffers
+    let (staging, buffers) = staging_region().split_at_mut(STAGING_SIZE - net::BUFFERS_SIZE);
+    let mut iface = net::Interface::new(mac, buffers.try_into().unwrap());
+
+    let lease = dhcp::acquire(&mut iface)?;
+    let path = lease.boot_file().unwrap_or(NET_BOOT_FILE);
+    println!(
+        "DHCP lease {} from {}, fetching {} from {}",
+        lease.config.ip, lease.server, path, lease.next_server
+    );
+    let size = tftp::download(&mut iface, lease.next_server, path, staging)?;
+    println!("Received {} byte kernel", size);
+    profile::checkpoint("kernel read");
+    boot_image(&staging[..size], &BootParams::new(DEFAULT_CMDLINE))
+}
+
+/// Try every boot source the bootloader was built with, in order, printing
+/// why each one failed. `choice` picks the `/boot/boot.cfg` entry on the disks,
+/// the other sources have a single kernel and are skipped for a named entry.
+/// Without a disk to boot from, named entries come from the SD card the
+/// monitor browses.
+fn boot_configured(choice: EntryChoice) {
+    #[cfg(not(any(feature = "boot_mmc", feature = "boot_sata")))]
+    if matches!(choice, EntryChoice::Named(_)) {
+        let Err(e) = boot_mmc(choice);
+        println!("SD card boot failed: {}", e);
+    }
+
+    #[cfg(feature = "boot_mmc")]
+    {
+        let Err(e) = boot_mmc(choice);
+        println!("SD card boot failed: {}", e);
+    }
+
+    #[cfg(feature = "boot_sata")]
+    {
+        let Err(e) = boot_sata(choice);
+        println!("SATA boot failed: {}", e);
+    }
+
+    #[cfg(feature = "boot_semihost")]
+    if !matches!(choice, EntryChoice::Named(_)) {
+        let Err(e) = boot_semihost();
+        println!("Semihosting boot failed: {}", e);
+    }
+
+    #[cfg(feature = "boot_net")]
+    if !matches!(choice, EntryChoice::Named(_)) {
+        let Err(e) = boot_net();
+        println!("Network boot failed: {}", e);
+    }
+
+    #[cfg(feature = "boot_uart")]
+    if !matches!(choice, EntryChoice::Named(_)) {
+        let Err(e) = boot_uart();
+        println!("Serial boot failed: {}", e);
+    }
+}
+
+/// Devices described by the device tree at `addr`, if there is one there.
+/// QEMU's loader passes one when started with `-dtb`.
+fn boot_devices(addr: usize) -> Option<fdt::Devices> {
+    if !(DRAM_START..=DRAM_END).contains(&addr) {
+        return None;
+    }
+    // SAFETY: inside DRAM, and nothing has written to DRAM yet
+    let fdt = unsafe { Fdt::from_ptr(addr as *const u8) }.ok()?;
+    Some(fdt::discover(&fdt))
+}
+
+/// Entered from `boot.S` with whatever was in r2 at reset
 #[unsafe(no_mangle)]
-pub extern "C" fn rust_main() -> ! {
+pub extern "C" fn rust_main(boot_params: usize) -> ! {
+    timer::init();
+    // before the memory test wipes the tree
+    let devices = boot_devices(boot_params);
+    if let Some(devices) = &devices {
+        devices.apply();
+    }
     uart::init();
+    if let Some(devices) = &devices {
+        println!("Device tree at 0x{:x}: {:x?}", boot_params, devices);
+    }
+    profile::checkpoint("uart");
     i2c::init();
+    profile::checkpoint("i2c");
     ccm::init();
+    profile::checkpoint("ccm");
     dram::init();
-    mmu::init(get_kernel_entry() as u32);
+    // before the memory test wipes it
+    panic::report_last_panic();
+    dram::simple_memtest();
+    profile::checkpoint("dram");
+    mmu::init();
     mmu::enable();
+    profile::checkpoint("mmu");
 
     println!("Finished initializing hardware, enabling MMU");
     println!(
@@ -115,15 +1028,13 @@ pub extern "C" fn rust_main() -> ! {
         get_boot_entry()
     );
 
-    #[cfg(feature = "boot_mmc")]
-    boot_mmc();
-
-    #[cfg(feature = "boot_uart")]
-    boot_uart();
+    if monitor::interrupted(AUTOBOOT_DELAY, "autoboot") {
+        monitor::run();
+    }
+    profile::checkpoint("autoboot");
 
-    #[cfg(not(feature = "boot_uart"))]
-    #[cfg(not(feature = "boot_mmc"))]
-    unreachable!("End of bootloader main without jumping!");
+    boot_configured(EntryChoice::Menu);
+    monitor::run();
 }
 
 // TODO testing
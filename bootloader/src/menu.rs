@@ -0,0 +1,66 @@
+//! Boot menu on the UART for a parsed [`Config`].
+use core::time::Duration;
+
+use bootloader_types::config::{Config, Entry};
+use hal::println;
+use hal::uart::UartDevice;
+
+fn print_menu(config: &Config) {
+    for (i, entry) in config.entries().enumerate() {
+        let marker = if i == config.default_index() {
+            '*'
+        } else {
+            ' '
+        };
+        println!("{} {}) {} ({})", marker, i + 1, entry.name, entry.kernel);
+    }
+}
+
+/// Count down the config's `timeout` seconds on the UART. Digits pick an
+/// entry right away, any other key stops the countdown and waits for a
+/// choice.
+pub fn select<'c, 'a>(config: &'c Config<'a>) -> &'c Entry<'a> {
+    if config.timeout == 0 {
+        return config.default_entry();
+    }
+
+    print_menu(config);
+    let mut uart = UartDevice::new();
+    uart.flush_input();
+    let mut key = None;
+    for remaining in (1..=config.timeout).rev() {
+        println!(
+            "Booting '{}' in {}s, press a key for the menu",
+            config.default_entry().name,
+            remaining
+        );
+        if let Ok(byte) = uart.read_byte_timeout(Duration::from_secs(1)) {
+            key = Some(byte);
+            break;
+        }
+    }
+
+    let Some(mut key) = key else {
+        return config.default_entry();
+    };
+    loop {
+        match key {
+            b'\r' | b'\n' => return config.default_entry(),
+            b'1'..=b'9' => {
+                if let Some(entry) = config.entry((key - b'1') as usize) {
+                    return entry;
+                }
+            }
+            _ => {}
+        }
+        println!(
+            "Select 1-{}, or Enter for the default:",
+            config.entries().count()
+        );
+        key = loop {
+            if let Some(byte) = uart.read_byte() {
+                break byte;
+            }
+        };
+    }
+}
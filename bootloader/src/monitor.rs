@@ -0,0 +1,465 @@
+//! Interactive monitor on UART0.
+//!
+//! Entered by pressing a key during the autoboot countdown, or when every boot
+//! path failed. Numbers are decimal, or hexadecimal with a `0x` prefix.
+use core::ops::Range;
+use core::time::Duration;
+
+use bootloader_types::number;
+use fat32::Fat32Error;
+use hal::mmc::MMCError;
+use hal::timer::Instant;
+use hal::uart::UartDevice;
+use hal::{board, dram, mmc, print, println};
+
+use crate::ymodem::{self, YmodemError};
+use crate::{BootError, EntryChoice};
+
+const LINE_MAX: usize = 80;
+const HISTORY_LEN: usize = 4;
+/// Bytes shown by `md` without a length
+const DEFAULT_DUMP_LEN: usize = 0x100;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+const ESCAPE: u8 = 0x1B;
+const CTRL_C: u8 = 0x03;
+
+const COMMANDS: &[(&str, &str)] = &[
+    ("help", "show this list"),
+    ("md <addr> [len]", "display memory"),
+    ("mw <addr> <value> [count]", "write 32-bit words"),
+    ("mmc read <sector>", "dump an SD card sector"),
+    ("ls [path]", "list a directory on the SD card"),
+    ("cat <path>", "print a file from the SD card"),
+    ("loadx", "receive a kernel over YMODEM/XMODEM-1K"),
+    ("bootx", "boot the kernel received with loadx"),
+    ("boardinfo", "show the board EEPROM contents"),
+    ("memtest [start end]", "test DRAM (staging area by default)"),
+    (
+        "boot [entry]",
+        "boot a boot.cfg entry, the default without one",
+    ),
+];
+
+#[derive(Debug)]
+enum MonitorError {
+    /// Wrong arguments, holds the expected usage
+    Usage(&'static str),
+    BadNumber,
+    UnknownCommand,
+    /// `bootx` before a successful `loadx`
+    NothingLoaded,
+    /// `memtest` range covering the bootloader or its tables
+    Protected,
+    Boot(BootError),
+}
+
+impl core::fmt::Display for MonitorError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Usage(usage) => write!(f, "usage: {}", usage),
+            Self::BadNumber => write!(f, "bad number"),
+            Self::UnknownCommand => write!(f, "unknown command, try 'help'"),
+            Self::NothingLoaded => write!(f, "nothing loaded, use 'loadx' first"),
+            Self::Protected => write!(f, "range overlaps the bootloader"),
+            Self::Boot(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<BootError> for MonitorError {
+    fn from(e: BootError) -> Self {
+        Self::Boot(e)
+    }
+}
+
+impl From<Fat32Error> for MonitorError {
+    fn from(e: Fat32Error) -> Self {
+        Self::Boot(e.into())
+    }
+}
+
+impl From<MMCError> for MonitorError {
+    fn from(e: MMCError) -> Self {
+        Self::Boot(e.into())
+    }
+}
+
+impl From<YmodemError> for MonitorError {
+    fn from(e: YmodemError) -> Self {
+        Self::Boot(e.into())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Line {
+    buf: [u8; LINE_MAX],
+    len: usize,
+}
+
+impl Line {
+    const fn new() -> Self {
+        Self {
+            buf: [0; LINE_MAX],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // only printable ASCII is ever stored
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+/// The last few non-empty lines, oldest overwritten first
+struct History {
+    lines: [Line; HISTORY_LEN],
+    count: usize,
+}
+
+impl History {
+    const fn new() -> Self {
+        Self {
+            lines: [Line::new(); HISTORY_LEN],
+            count: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.count.min(HISTORY_LEN)
+    }
+
+    /// `n`th most recent line, starting at 1
+    fn get(&self, n: usize) -> Option<&Line> {
+        if n == 0 || n > self.len() {
+            return None;
+        }
+        Some(&self.lines[(self.count - n) % HISTORY_LEN])
+    }
+
+    fn push(&mut self, line: &Line) {
+        if line.len == 0
+            || self
+                .get(1)
+                .is_some_and(|last| last.as_str() == line.as_str())
+        {
+            return;
+        }
+        self.lines[self.count % HISTORY_LEN] = *line;
+        self.count += 1;
+    }
+}
+
+/// Count down `seconds` to `action`, returning whether a key was pressed to
+/// enter the monitor instead
+pub fn interrupted(seconds: u32, action: &str) -> bool {
+    let mut uart = UartDevice::new();
+    uart.flush_input();
+    for remaining in (1..=seconds).rev() {
+        println!(
+            "Press any key for the monitor, {} in {}s",
+            action, remaining
+        );
+        let end = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < end {
+            if uart.read_byte().is_some() {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+pub fn run() -> ! {
+    println!("Entering monitor, type 'help' for commands");
+    let mut monitor = Monitor {
+        uart: UartDevice::new(),
+        history: History::new(),
+        loaded: 0,
+    };
+    loop {
+        print!("> ");
+        let line = monitor.read_line();
+        if let Err(e) = monitor.execute(line.as_str()) {
+            println!("Error: {}", e);
+        }
+        monitor.history.push(&line);
+    }
+}
+
+struct Monitor {
+    uart: UartDevice,
+    history: History,
+    /// Size of the image `loadx` left in the staging region
+    loaded: usize,
+}
+
+impl Monitor {
+    fn wait_byte(&mut self) -> u8 {
+        loop {
+            if let Some(byte) = self.uart.read_byte() {
+                return byte;
+            }
+        }
+    }
+
+    /// Replace the line being edited with `new`, on screen too
+    fn replace_line(line: &mut Line, new: &Line) {
+        for _ in 0..line.len {
+            print!("\x08 \x08");
+        }
+        *line = *new;
+        print!("{}", line.as_str());
+    }
+
+    fn read_line(&mut self) -> Line {
+        let mut line = Line::new();
+        // 0 while editing a fresh line, otherwise the history entry shown
+        let mut browsing = 0;
+        loop {
+            match self.wait_byte() {
+                b'\r' | b'\n' => {
+                    println!();
+                    return line;
+                }
+                BACKSPACE | DELETE => {
+                    if line.len > 0 {
+                        line.len -= 1;
+                        print!("\x08 \x08");
+                    }
+                }
+                CTRL_C => {
+                    println!("^C");
+                    return Line::new();
+                }
+                ESCAPE => {
+                    if self.wait_byte() != b'[' {
+                        continue;
+                    }
+                    let wanted = match self.wait_byte() {
+                        b'A' if browsing < self.history.len() => browsing + 1,
+                        b'B' if browsing > 0 => browsing - 1,
+                        _ => continue,
+                    };
+                    let new = self.history.get(wanted).copied().unwrap_or(Line::new());
+                    Self::replace_line(&mut line, &new);
+                    browsing = wanted;
+                }
+                byte @ 0x20..=0x7E if line.len < LINE_MAX => {
+                    line.buf[line.len] = byte;
+                    line.len += 1;
+                    print!("{}", byte as char);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn execute(&mut self, line: &str) -> Result<(), MonitorError> {
+        let mut args = line.split_whitespace();
+        let Some(command) = args.next() else {
+            return Ok(());
+        };
+        match command {
+            "help" => {
+                for (usage, description) in COMMANDS {
+                    println!("  {:<28}{}", usage, description);
+                }
+                Ok(())
+            }
+            "md" => memory_display(args.next(), args.next()),
+            "mw" => memory_write(args.next(), args.next(), args.next()),
+            "mmc" => mmc_read(args.next(), args.next()),
+            "ls" => list(args.next().unwrap_or("/")),
+            "cat" => cat(args.next().ok_or(MonitorError::Usage("cat <path>"))?),
+            "loadx" => self.load_serial(),
+            "bootx" => self.boot_serial(),
+            "boardinfo" => {
+                println!("{:?}", board::get_board_info());
+                Ok(())
+            }
+            "memtest" => self.memory_test(args.next(), args.next()),
+            "boot" => {
+                let choice = args.next().map_or(EntryChoice::Default, EntryChoice::Named);
+                // only returns if every source failed, each already said why
+                crate::boot_configured(choice);
+                Ok(())
+            }
+            _ => Err(MonitorError::UnknownCommand),
+        }
+    }
+
+    fn load_serial(&mut self) -> Result<(), MonitorError> {
+        let staging = crate::staging_region();
+        println!("Send the kernel now (max {} bytes)", staging.len());
+        self.loaded = 0;
+        self.loaded = ymodem::receive(staging)?;
+        println!(
+            "Received {} bytes at 0x{:x}",
+            self.loaded,
+            staging.as_ptr() as usize
+        );
+        Ok(())
+    }
+
+    fn memory_test(&mut self, start: Option<&str>, end: Option<&str>) -> Result<(), MonitorError> {
+        const USAGE: &str = "memtest [start end]";
+        let range = match start {
+            Some(_) => parse_number(start, USAGE)?..parse_number(end, USAGE)?,
+            None => {
+                let staging = crate::staging_region().as_ptr_range();
+                staging.start as usize..staging.end as usize
+            }
+        };
+        if range.is_empty() {
+            return Err(MonitorError::Usage(USAGE));
+        }
+        if crate::protected_regions()
+            .iter()
+            .any(|r| overlaps(&range, r))
+        {
+            return Err(MonitorError::Protected);
+        }
+        // the test pattern may have overwritten a loaded image
+        self.loaded = 0;
+        let errors = dram::memtest(range.start, range.end);
+        println!("memtest: {} errors", errors);
+        Ok(())
+    }
+
+    fn boot_serial(&mut self) -> Result<(), MonitorError> {
+        if self.loaded == 0 {
+            return Err(MonitorError::NothingLoaded);
+        }
+        let image = &crate::staging_region()[..self.loaded];
+        match crate::boot_image(image, &crate::BootParams::new(crate::DEFAULT_CMDLINE)) {
+            Ok(never) => match never {},
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+fn parse_number(arg: Option<&str>, usage: &'static str) -> Result<usize, MonitorError> {
+    let arg = arg.ok_or(MonitorError::Usage(usage))?;
+    number::parse(arg).ok_or(MonitorError::BadNumber)
+}
+
+/// Print `len` bytes, 16 per line with an ASCII column, labelled from `base`
+fn hexdump(base: usize, len: usize, byte_at: impl Fn(usize) -> u8) {
+    for line in (0..len).step_by(16) {
+        let count = (len - line).min(16);
+        print!("{:08x}: ", base.wrapping_add(line));
+        for i in 0..16 {
+            if i < count {
+                print!("{:02x} ", byte_at(line + i));
+            } else {
+                print!("   ");
+            }
+        }
+        print!(" ");
+        for i in 0..count {
+            let byte = byte_at(line + i);
+            let c = if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            };
+            print!("{}", c);
+        }
+        println!();
+    }
+}
+
+fn memory_display(addr: Option<&str>, len: Option<&str>) -> Result<(), MonitorError> {
+    const USAGE: &str = "md <addr> [len]";
+    let addr = parse_number(addr, USAGE)?;
+    let len = match len {
+        Some(_) => parse_number(len, USAGE)?,
+        None => DEFAULT_DUMP_LEN,
+    };
+    hexdump(addr, len, |offset| unsafe {
+        (addr.wrapping_add(offset) as *const u8).read_volatile()
+    });
+    Ok(())
+}
+
+fn memory_write(
+    addr: Option<&str>,
+    value: Option<&str>,
+    count: Option<&str>,
+) -> Result<(), MonitorError> {
+    const USAGE: &str = "mw <addr> <value> [count]";
+    let addr = parse_number(addr, USAGE)?;
+    let value = parse_number(value, USAGE)? as u32;
+    let count = match count {
+        Some(_) => parse_number(count, USAGE)?,
+        None => 1,
+    };
+    if !addr.is_multiple_of(4) {
+        return Err(MonitorError::Usage("mw needs a 4-byte aligned address"));
+    }
+    if count == 0 {
+        return Ok(());
+    }
+    let last = (count - 1)
+        .checked_mul(4)
+        .and_then(|offset| addr.checked_add(offset))
+        .ok_or(MonitorError::Usage("mw count runs past the end of memory"))?;
+    for word in (addr..=last).step_by(4) {
+        unsafe { (word as *mut u32).write_volatile(value) };
+    }
+    Ok(())
+}
+
+fn mmc_read(subcommand: Option<&str>, sector: Option<&str>) -> Result<(), MonitorError> {
+    const USAGE: &str = "mmc read <sector>";
+    if subcommand != Some("read") {
+        return Err(MonitorError::Usage(USAGE));
+    }
+    let sector = parse_number(sector, USAGE)? as u32;
+    crate::init_mmc()?;
+    let mut buffer = [0u8; 512];
+    mmc::read_sector(sector, &mut buffer)?;
+    hexdump(0, buffer.len(), |offset| buffer[offset]);
+    Ok(())
+}
+
+fn list(path: &str) -> Result<(), MonitorError> {
+    let mut fs = crate::mount()?;
+    for entry in fs.open_dir(path)? {
+        let entry = entry?;
+        if entry.is_dir() {
+            println!("{:>10}  {}/", "<DIR>", entry.name());
+        } else {
+            println!("{:>10}  {}", entry.size(), entry.name());
+        }
+    }
+    Ok(())
+}
+
+fn cat(path: &str) -> Result<(), MonitorError> {
+    let mut fs = crate::mount()?;
+    let file = fs.open_file(path)?;
+    let mut buffer = [0u8; 512];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        for &byte in &buffer[..read] {
+            let c = match byte {
+                b'\n' | b'\r' | b'\t' => byte as char,
+                _ if byte.is_ascii_graphic() || byte == b' ' => byte as char,
+                _ => '.',
+            };
+            print!("{}", c);
+        }
+    }
+    println!();
+    Ok(())
+}
@@ -0,0 +1,401 @@
+//! Just enough IPv4 to fetch a kernel over TFTP.
+//!
+//! [`Interface`] speaks Ethernet II, answers and sends ARP, and sends and
+//! receives UDP datagrams that aren't fragmented. Everything is polled, and
+//! timeouts are [`Instant`] deadlines on the hal timer. UDP checksums are
+//! neither sent nor checked, the kernel image header carries a SHA-256 of
+//! everything that matters.
+use core::net::Ipv4Addr;
+use core::time::Duration;
+
+use hal::eth::{self, EthError, MAX_FRAME_SIZE, MacAddress};
+use hal::timer::Instant;
+
+/// How long a peer gets to answer before a request is sent again
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+/// Bytes [`Interface::new`] needs for its receive and transmit buffers
+pub const BUFFERS_SIZE: usize = 2 * MAX_FRAME_SIZE;
+
+const BROADCAST_MAC: MacAddress = [0xFF; 6];
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const IP_PROTOCOL_UDP: u8 = 17;
+/// Don't Fragment, we never send anything that would need it
+const IP_FLAG_DF: u16 = 0x4000;
+const IP_TTL: u8 = 64;
+
+const ETH_HEADER_SIZE: usize = 14;
+const IP_HEADER_SIZE: usize = 20;
+const UDP_HEADER_SIZE: usize = 8;
+const ARP_PACKET_SIZE: usize = 28;
+/// Where the UDP payload starts in the frames we send
+const UDP_PAYLOAD_OFFSET: usize = ETH_HEADER_SIZE + IP_HEADER_SIZE + UDP_HEADER_SIZE;
+/// Largest UDP payload that fits in one frame
+pub const MAX_UDP_PAYLOAD: usize = MAX_FRAME_SIZE - UDP_PAYLOAD_OFFSET;
+
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+/// ARP requests sent, a second apart, before a neighbour is given up on
+const ARP_RETRIES: u32 = 4;
+
+#[derive(Debug)]
+pub enum NetError {
+    Eth(EthError),
+    /// Nothing answered in time, holds what we were waiting for
+    Timeout(&'static str),
+    /// TFTP server refused the transfer, holds its error code
+    Tftp(u16),
+    /// File is bigger than the buffer it is downloaded to
+    TooLarge {
+        available: usize,
+    },
+    /// A server broke its protocol
+    Protocol(&'static str),
+}
+
+impl core::fmt::Display for NetError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Eth(e) => write!(f, "Ethernet error: {:?}", e),
+            Self::Timeout(what) => write!(f, "timed out waiting for {}", what),
+            Self::Tftp(code) => write!(f, "TFTP server refused the transfer, error {}", code),
+            Self::TooLarge { available } => {
+                write!(f, "file does not fit in {} bytes", available)
+            }
+            Self::Protocol(what) => write!(f, "protocol error: {}", what),
+        }
+    }
+}
+
+impl From<EthError> for NetError {
+    fn from(e: EthError) -> Self {
+        Self::Eth(e)
+    }
+}
+
+/// Formats a MAC address the usual way
+pub struct DisplayMac<'a>(pub &'a MacAddress);
+
+impl core::fmt::Display for DisplayMac<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+/// Our address and how to reach the rest of the network
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    pub ip: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    /// Gateway for addresses outside of `netmask`
+    pub router: Option<Ipv4Addr>,
+}
+
+/// A UDP datagram addressed to us
+pub struct Datagram<'a> {
+    pub src: Ipv4Addr,
+    pub src_port: u16,
+    pub data: &'a [u8],
+}
+
+pub struct Interface<'a> {
+    mac: MacAddress,
+    /// Unspecified address until [`Interface::configure`]
+    config: Config,
+    rx: &'a mut [u8; MAX_FRAME_SIZE],
+    tx: &'a mut [u8; MAX_FRAME_SIZE],
+    /// The last address resolved with ARP, we only ever talk to one host
+    neighbour: Option<(Ipv4Addr, MacAddress)>,
+    next_ip_id: u16,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_ip(bytes: &[u8], offset: usize) -> Ipv4Addr {
+    Ipv4Addr::new(
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    )
+}
+
+/// Internet checksum of `bytes`, a 16-bit ones' complement sum
+fn checksum(bytes: &[u8]) -> u16 {
+    let (words, rest) = bytes.as_chunks::<2>();
+    let mut sum: u32 = words
+        .iter()
+        .map(|&word| u16::from_be_bytes(word) as u32)
+        .sum();
+    if let [last] = rest {
+        sum += (*last as u32) << 8;
+    }
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Ethernet header for a frame from `src` to `dst`
+fn write_eth_header(frame: &mut [u8], dst: &MacAddress, src: &MacAddress, ethertype: u16) {
+    frame[0..6].copy_from_slice(dst);
+    frame[6..12].copy_from_slice(src);
+    frame[12..14].copy_from_slice(&ethertype.to_be_bytes());
+}
+
+impl<'a> Interface<'a> {
+    /// Interface on the controller with address `mac`, keeping its frames in
+    /// `buffers`
+    pub fn new(mac: MacAddress, buffers: &'a mut [u8; BUFFERS_SIZE]) -> Self {
+        let (rx, tx) = buffers.split_at_mut(MAX_FRAME_SIZE);
+        Self {
+            mac,
+            config: Config {
+                ip: Ipv4Addr::UNSPECIFIED,
+                netmask: Ipv4Addr::UNSPECIFIED,
+                router: None,
+            },
+            rx: rx.try_into().unwrap(),
+            tx: tx.try_into().unwrap(),
+            neighbour: None,
+            next_ip_id: 0,
+        }
+    }
+
+    pub fn mac(&self) -> MacAddress {
+        self.mac
+    }
+
+    pub fn configure(&mut self, config: Config) {
+        self.config = config;
+        self.neighbour = None;
+    }
+
+    /// Where the payload of the next [`Interface::send_udp`] goes
+    pub fn udp_payload_mut(&mut self) -> &mut [u8] {
+        &mut self.tx[UDP_PAYLOAD_OFFSET..]
+    }
+
+    /// Send the first `len` bytes of [`Interface::udp_payload_mut`] to `dst`
+    pub fn send_udp(
+        &mut self,
+        dst: Ipv4Addr,
+        src_port: u16,
+        dst_port: u16,
+        len: usize,
+    ) -> Result<(), NetError> {
+        let dst_mac = if dst.is_broadcast() {
+            BROADCAST_MAC
+        } else {
+            self.resolve(self.next_hop(dst))?
+        };
+
+        let udp_len = UDP_HEADER_SIZE + len;
+        let ip_len = IP_HEADER_SIZE + udp_len;
+        let id = self.next_ip_id;
+        self.next_ip_id = id.wrapping_add(1);
+
+        let frame = &mut self.tx[..ETH_HEADER_SIZE + ip_len];
+        write_eth_header(frame, &dst_mac, &self.mac, ETHERTYPE_IPV4);
+
+        let ip = &mut frame[ETH_HEADER_SIZE..];
+        ip[0] = 0x45; // version 4, 5 word header
+        ip[1] = 0;
+        ip[2..4].copy_from_slice(&(ip_len as u16).to_be_bytes());
+        ip[4..6].copy_from_slice(&id.to_be_bytes());
+        ip[6..8].copy_from_slice(&IP_FLAG_DF.to_be_bytes());
+        ip[8] = IP_TTL;
+        ip[9] = IP_PROTOCOL_UDP;
+        ip[10..12].fill(0);
+        ip[12..16].copy_from_slice(&self.config.ip.octets());
+        ip[16..20].copy_from_slice(&dst.octets());
+        let sum = checksum(&ip[..IP_HEADER_SIZE]);
+        ip[10..12].copy_from_slice(&sum.to_be_bytes());
+
+        let udp = &mut ip[IP_HEADER_SIZE..];
+        udp[0..2].copy_from_slice(&src_port.to_be_bytes());
+        udp[2..4].copy_from_slice(&dst_port.to_be_bytes());
+        udp[4..6].copy_from_slice(&(udp_len as u16).to_be_bytes());
+        udp[6..8].fill(0);
+
+        Ok(eth::send(frame)?)
+    }
+
+    /// Wait up to `timeout` for a datagram to `port`, answering ARP
+    /// requests meanwhile. Anything else that arrives is dropped.
+    pub fn receive_udp(
+        &mut self,
+        port: u16,
+        timeout: Duration,
+    ) -> Result<Option<Datagram<'_>>, NetError> {
+        let end = Instant::now() + timeout;
+        while Instant::now() < end {
+            let Some(len) = eth::receive(self.rx)? else {
+                continue;
+            };
+            if let Some((src, src_port, data)) = self.handle_frame(len, port)? {
+                return Ok(Some(Datagram {
+                    src,
+                    src_port,
+                    data: &self.rx[data.start..data.end],
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Act on the `len` byte frame in the receive buffer. Returns the
+    /// sender, source port and payload range of a datagram to `port`.
+    fn handle_frame(
+        &mut self,
+        len: usize,
+        port: u16,
+    ) -> Result<Option<(Ipv4Addr, u16, core::ops::Range<usize>)>, NetError> {
+        if len < ETH_HEADER_SIZE {
+            return Ok(None);
+        }
+        match read_u16(&self.rx[..], 12) {
+            ETHERTYPE_ARP => {
+                self.handle_arp(len)?;
+                Ok(None)
+            }
+            ETHERTYPE_IPV4 => Ok(self.parse_udp(len, port)),
+            _ => Ok(None),
+        }
+    }
+
+    fn parse_udp(&self, len: usize, port: u16) -> Option<(Ipv4Addr, u16, core::ops::Range<usize>)> {
+        let ip = &self.rx[ETH_HEADER_SIZE..len];
+        if ip.len() < IP_HEADER_SIZE || ip[0] >> 4 != 4 {
+            return None;
+        }
+        let header_len = (ip[0] & 0xF) as usize * 4;
+        let total_len = read_u16(ip, 2) as usize;
+        if header_len < IP_HEADER_SIZE
+            || total_len < header_len + UDP_HEADER_SIZE
+            || total_len > ip.len()
+            || checksum(&ip[..header_len]) != 0
+        {
+            return None;
+        }
+        // more fragments, or not the first one
+        if read_u16(ip, 6) & 0x3FFF != 0 || ip[9] != IP_PROTOCOL_UDP {
+            return None;
+        }
+        let dst = read_ip(ip, 16);
+        let for_us = dst == self.config.ip
+            || dst.is_broadcast()
+            // before DHCP is done, replies can go to the address on offer
+            || self.config.ip.is_unspecified();
+        if !for_us {
+            return None;
+        }
+
+        let udp = &ip[header_len..total_len];
+        let udp_len = read_u16(udp, 4) as usize;
+        if read_u16(udp, 2) != port || udp_len < UDP_HEADER_SIZE || udp_len > udp.len() {
+            return None;
+        }
+        let start = ETH_HEADER_SIZE + header_len + UDP_HEADER_SIZE;
+        Some((
+            read_ip(ip, 12),
+            read_u16(udp, 0),
+            start..start + udp_len - UDP_HEADER_SIZE,
+        ))
+    }
+
+    fn handle_arp(&mut self, len: usize) -> Result<(), NetError> {
+        let Some(arp) = self.rx[..len].get(ETH_HEADER_SIZE..ETH_HEADER_SIZE + ARP_PACKET_SIZE)
+        else {
+            return Ok(());
+        };
+        // Ethernet and IPv4 addresses only
+        if arp[0..6] != [0, 1, 8, 0, 6, 4] {
+            return Ok(());
+        }
+        let op = read_u16(arp, 6);
+        let sender_mac: MacAddress = arp[8..14].try_into().unwrap();
+        let sender_ip = read_ip(arp, 14);
+        let target_ip = read_ip(arp, 24);
+        if self.config.ip.is_unspecified() || target_ip != self.config.ip {
+            return Ok(());
+        }
+
+        match op {
+            ARP_REQUEST => self.send_arp(ARP_REPLY, &sender_mac, sender_ip),
+            ARP_REPLY => {
+                self.neighbour = Some((sender_ip, sender_mac));
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Send an ARP packet from a buffer of its own, the transmit buffer may
+    /// hold a datagram waiting for this very resolution
+    fn send_arp(
+        &self,
+        op: u16,
+        target_mac: &MacAddress,
+        target_ip: Ipv4Addr,
+    ) -> Result<(), NetError> {
+        let mut frame = [0u8; ETH_HEADER_SIZE + ARP_PACKET_SIZE];
+        let dst = if op == ARP_REQUEST {
+            &BROADCAST_MAC
+        } else {
+            target_mac
+        };
+        write_eth_header(&mut frame, dst, &self.mac, ETHERTYPE_ARP);
+
+        let arp = &mut frame[ETH_HEADER_SIZE..];
+        arp[0..6].copy_from_slice(&[0, 1, 8, 0, 6, 4]);
+        arp[6..8].copy_from_slice(&op.to_be_bytes());
+        arp[8..14].copy_from_slice(&self.mac);
+        arp[14..18].copy_from_slice(&self.config.ip.octets());
+        arp[18..24].copy_from_slice(target_mac);
+        arp[24..28].copy_from_slice(&target_ip.octets());
+        Ok(eth::send(&frame)?)
+    }
+
+    /// The host on our network `dst` is reached through
+    fn next_hop(&self, dst: Ipv4Addr) -> Ipv4Addr {
+        let mask = self.config.netmask.to_bits();
+        match self.config.router {
+            Some(router) if dst.to_bits() & mask != self.config.ip.to_bits() & mask => router,
+            _ => dst,
+        }
+    }
+
+    /// MAC address of `ip`, asking with ARP unless it was the last one
+    fn resolve(&mut self, ip: Ipv4Addr) -> Result<MacAddress, NetError> {
+        for _ in 0..ARP_RETRIES {
+            if let Some((known, mac)) = self.neighbour
+                && known == ip
+            {
+                return Ok(mac);
+            }
+            self.send_arp(ARP_REQUEST, &[0; 6], ip)?;
+            let end = Instant::now() + REPLY_TIMEOUT;
+            while Instant::now() < end {
+                if let Some(len) = eth::receive(self.rx)? {
+                    // port 0 is never ours, anything but ARP is dropped
+                    self.handle_frame(len, 0)?;
+                    if self.neighbour.is_some_and(|(known, _)| known == ip) {
+                        break;
+                    }
+                }
+            }
+        }
+        match self.neighbour {
+            Some((known, mac)) if known == ip => Ok(mac),
+            _ => Err(NetError::Timeout("an ARP reply")),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+//! Numbers written by people, in `boot.cfg`, load options and the monitor.
+
+/// Hex with a `0x` or `0X` prefix, decimal without
+pub fn parse(text: &str) -> Option<usize> {
+    let (digits, radix) = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => (hex, 16),
+        None => (text, 10),
+    };
+    usize::from_str_radix(digits, radix).ok()
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_and_decimal() {
+        assert_eq!(parse("0"), Some(0));
+        assert_eq!(parse("4096"), Some(4096));
+        assert_eq!(parse("0x4a000000"), Some(0x4a00_0000));
+        assert_eq!(parse("0X4A000000"), Some(0x4a00_0000));
+        assert_eq!(parse("0xffffffff"), Some(0xffff_ffff));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for text in ["", "0x", "x10", "12ab", "0x12g", " 12", "-1", "0b101"] {
+            assert_eq!(parse(text), None, "{:?}", text);
+        }
+    }
+}
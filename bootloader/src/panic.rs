@@ -1,17 +1,67 @@
+//! Panic handling.
+//!
+//! A panic prints its message, the registers and a backtrace, and leaves a
+//! [`PanicRecord`] for the next boot to report with [`report_last_panic`].
+//! It then counts down `PANIC_RESET_DELAY` seconds to a watchdog reset, a
+//! key press entering the monitor instead.
+use bootloader_types::panic_record::PanicRecord;
+use hal::println;
+
+/// Print and clear the record of the last boot's panic, if it ended in one
+pub fn report_last_panic() {
+    if let Some(record) = PanicRecord::take(crate::panic_record_region()) {
+        println!(
+            "Last boot panicked after stage {}: {}",
+            record.stage(),
+            record.message()
+        );
+        println!(
+            "  cpsr 0x{:08x} sp 0x{:08x} lr 0x{:08x}",
+            record.cpsr, record.sp, record.lr
+        );
+    }
+}
+
 #[cfg(not(test))]
 mod panic_handler {
-    use crate::println;
+    use crate::{monitor, println, profile};
+    use bootloader_types::panic_record::{PanicRecord, RECORD_SIZE};
+    use core::fmt::Write;
     use core::panic::PanicInfo;
-    use hal::asm;
+    use core::sync::atomic::{AtomicBool, Ordering, fence};
+    use hal::backtrace::{self, Registers};
+    use hal::{mmu, watchdog};
+
+    static PANICKING: AtomicBool = AtomicBool::new(false);
 
     /// Panic handler (required for `no_std`)
     #[panic_handler]
     fn panic(info: &PanicInfo) -> ! {
-        println!("{}", info);
-        loop {
-            unsafe {
-                asm::wfi();
-            }
+        let registers = Registers::capture();
+        if PANICKING.swap(true, Ordering::Relaxed) {
+            // reporting the first panic panicked, don't try again
+            watchdog::reset();
+        }
+
+        // the stage that failed is the one after the last checkpoint
+        let stages = profile::stages();
+        let stage = stages
+            .as_slice()
+            .last()
+            .map_or("reset", |stage| stage.name());
+        println!("\nPanic after stage {}: {}", stage, info);
+        backtrace::print(&registers);
+
+        let mut record = PanicRecord::new(stage, registers.cpsr, registers.sp, registers.lr);
+        let _ = write!(record, "{}", info.message());
+        record.store(crate::panic_record_region());
+        // the record has to be in DRAM before the watchdog fires
+        fence(Ordering::SeqCst);
+        mmu::clean_d_cache_range(crate::panic_record_region().as_ptr() as usize, RECORD_SIZE);
+
+        if monitor::interrupted(watchdog::PANIC_RESET_DELAY, "reset") {
+            monitor::run();
         }
+        watchdog::reset();
     }
 }
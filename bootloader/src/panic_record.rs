@@ -0,0 +1,215 @@
+//! Why the last boot ended in a panic.
+//!
+//! The bootloader and kernel panic handlers leave a [`PanicRecord`] in a
+//! small reserved area of DRAM before resetting the board, which a warm reset
+//! leaves alone. The next boot takes it out with [`PanicRecord::take`] and
+//! reports it. The kernel finds the area through the
+//! [`MemoryKind::PanicRecord`](crate::boot_info::MemoryKind::PanicRecord)
+//! entry of its memory map.
+//!
+//! A record is [`RECORD_SIZE`] bytes: magic, version, the NUL padded stage
+//! name, CPSR, SP and LR as little endian words, the message length as a
+//! little endian `u16`, then the message.
+use core::fmt;
+
+pub const RECORD_SIZE: usize = 256;
+/// Longest stage name, longer ones are cut short
+pub const STAGE_SIZE: usize = 16;
+/// Longest message, the rest of a longer one is dropped
+pub const MESSAGE_SIZE: usize = RECORD_SIZE - MESSAGE_OFFSET;
+
+const RECORD_MAGIC: [u8; 4] = *b"PANC";
+const RECORD_VERSION: u8 = 1;
+const STAGE_OFFSET: usize = 8;
+const REGISTERS_OFFSET: usize = STAGE_OFFSET + STAGE_SIZE;
+const MESSAGE_LEN_OFFSET: usize = REGISTERS_OFFSET + 12;
+const MESSAGE_OFFSET: usize = MESSAGE_LEN_OFFSET + 4;
+
+/// The longest prefix of `s` that fits in `size` bytes
+fn truncate(s: &str, size: usize) -> &str {
+    let mut len = s.len().min(size);
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    &s[..len]
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// A panic, written into with [`fmt::Write`] to fill in the message
+#[derive(Clone, Copy)]
+pub struct PanicRecord {
+    stage: [u8; STAGE_SIZE],
+    pub cpsr: u32,
+    pub sp: u32,
+    pub lr: u32,
+    message: [u8; MESSAGE_SIZE],
+    message_len: usize,
+}
+
+impl PanicRecord {
+    /// A panic in `stage` with an empty message
+    pub fn new(stage: &str, cpsr: u32, sp: u32, lr: u32) -> Self {
+        let stage = truncate(stage, STAGE_SIZE);
+        let mut record = Self {
+            stage: [0; STAGE_SIZE],
+            cpsr,
+            sp,
+            lr,
+            message: [0; MESSAGE_SIZE],
+            message_len: 0,
+        };
+        record.stage[..stage.len()].copy_from_slice(stage.as_bytes());
+        record
+    }
+
+    pub fn stage(&self) -> &str {
+        let len = self
+            .stage
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(STAGE_SIZE);
+        core::str::from_utf8(&self.stage[..len]).unwrap_or("?")
+    }
+
+    pub fn message(&self) -> &str {
+        core::str::from_utf8(&self.message[..self.message_len]).unwrap_or("?")
+    }
+
+    /// `None` if `bytes` doesn't start with a record this version understands
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..RECORD_SIZE)?;
+        if bytes[..4] != RECORD_MAGIC || bytes[4] != RECORD_VERSION {
+            return None;
+        }
+        let message_len =
+            u16::from_le_bytes([bytes[MESSAGE_LEN_OFFSET], bytes[MESSAGE_LEN_OFFSET + 1]]) as usize;
+        if message_len > MESSAGE_SIZE {
+            return None;
+        }
+        Some(Self {
+            stage: bytes[STAGE_OFFSET..REGISTERS_OFFSET].try_into().unwrap(),
+            cpsr: read_u32(bytes, REGISTERS_OFFSET),
+            sp: read_u32(bytes, REGISTERS_OFFSET + 4),
+            lr: read_u32(bytes, REGISTERS_OFFSET + 8),
+            message: bytes[MESSAGE_OFFSET..].try_into().unwrap(),
+            message_len,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0u8; RECORD_SIZE];
+        bytes[..4].copy_from_slice(&RECORD_MAGIC);
+        bytes[4] = RECORD_VERSION;
+        bytes[STAGE_OFFSET..REGISTERS_OFFSET].copy_from_slice(&self.stage);
+        for (i, register) in [self.cpsr, self.sp, self.lr].into_iter().enumerate() {
+            let offset = REGISTERS_OFFSET + 4 * i;
+            bytes[offset..offset + 4].copy_from_slice(&register.to_le_bytes());
+        }
+        bytes[MESSAGE_LEN_OFFSET..MESSAGE_LEN_OFFSET + 2]
+            .copy_from_slice(&(self.message_len as u16).to_le_bytes());
+        bytes[MESSAGE_OFFSET..].copy_from_slice(&self.message);
+        bytes
+    }
+
+    /// Write the record to the start of `area`, the magic last so a record
+    /// cut short by a reset is never taken for a whole one
+    pub fn store(&self, area: &mut [u8]) {
+        let bytes = self.to_bytes();
+        area[4..RECORD_SIZE].copy_from_slice(&bytes[4..]);
+        area[..4].copy_from_slice(&bytes[..4]);
+    }
+
+    /// Read the record at the start of `area`, if there is one, and clear it
+    /// so it is only reported once
+    pub fn take(area: &mut [u8]) -> Option<Self> {
+        let record = Self::parse(area)?;
+        area[..4].fill(0);
+        Some(record)
+    }
+}
+
+impl fmt::Write for PanicRecord {
+    /// Append to the message, silently dropping what doesn't fit
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let s = truncate(s, MESSAGE_SIZE - self.message_len);
+        self.message[self.message_len..self.message_len + s.len()].copy_from_slice(s.as_bytes());
+        self.message_len += s.len();
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    fn record() -> PanicRecord {
+        let mut record = PanicRecord::new("dram", 0x6000_01d3, 0x4000_8000, 0x4001_2345);
+        write!(record, "memory test failed at 0x{:x}", 0x4200_0000).unwrap();
+        record
+    }
+
+    #[test]
+    fn store_then_take() {
+        let mut area = [0xaa; RECORD_SIZE + 8];
+        record().store(&mut area);
+
+        let taken = PanicRecord::take(&mut area).unwrap();
+        assert_eq!(taken.stage(), "dram");
+        assert_eq!(
+            (taken.cpsr, taken.sp, taken.lr),
+            (0x6000_01d3, 0x4000_8000, 0x4001_2345)
+        );
+        assert_eq!(taken.message(), "memory test failed at 0x42000000");
+        // only reported once
+        assert_eq!(area[..4], [0; 4]);
+        assert!(PanicRecord::take(&mut area).is_none());
+        // past the record is left alone
+        assert_eq!(area[RECORD_SIZE..], [0xaa; 8]);
+    }
+
+    #[test]
+    fn rejects_bad_records() {
+        let good = record().to_bytes();
+
+        let mut bad = good;
+        bad[4] = RECORD_VERSION + 1;
+        assert!(PanicRecord::parse(&bad).is_none(), "version");
+        bad = good;
+        bad[MESSAGE_LEN_OFFSET..MESSAGE_LEN_OFFSET + 2]
+            .copy_from_slice(&(MESSAGE_SIZE as u16 + 1).to_le_bytes());
+        assert!(PanicRecord::parse(&bad).is_none(), "message length");
+        assert!(
+            PanicRecord::parse(&good[..RECORD_SIZE - 1]).is_none(),
+            "short"
+        );
+
+        bad = good;
+        bad[MESSAGE_LEN_OFFSET..MESSAGE_LEN_OFFSET + 2]
+            .copy_from_slice(&(MESSAGE_SIZE as u16).to_le_bytes());
+        assert!(PanicRecord::parse(&bad).is_some(), "full message");
+    }
+
+    #[test]
+    fn truncates_on_char_boundaries() {
+        let mut record = PanicRecord::new("a stage name that is too long", 0, 0, 0);
+        assert_eq!(record.stage(), "a stage name tha");
+
+        record.write_str(&"x".repeat(MESSAGE_SIZE - 1)).unwrap();
+        // two bytes of UTF-8 with room for one
+        record.write_str("é").unwrap();
+        assert_eq!(record.message().len(), MESSAGE_SIZE - 1);
+        record.write_str("y").unwrap();
+        assert_eq!(record.message().len(), MESSAGE_SIZE);
+        assert!(record.message().ends_with("xy"));
+        record.write_str("z").unwrap();
+        assert_eq!(record.message().len(), MESSAGE_SIZE);
+
+        // 'é' would straddle the 16 byte limit
+        let record = PanicRecord::new("panic in stage é", 0, 0, 0);
+        assert_eq!(record.stage(), "panic in stage ");
+    }
+}
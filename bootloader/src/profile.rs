@@ -0,0 +1,74 @@
+//! Boot-stage profiler.
+//!
+//! [`checkpoint`] records when a named stage of the boot finished, measured
+//! on [`hal::timer`] from the start of `rust_main`. [`report`] prints
+//! them before the jump, and the kernel gets the same list in its boot info.
+use core::cell::UnsafeCell;
+
+use bootloader_types::boot_info::BootStage;
+use hal::{interrupts, println, timer};
+
+/// Checkpoints kept, later ones are dropped
+const MAX_STAGES: usize = 16;
+
+/// The checkpoints recorded so far
+#[derive(Clone, Copy)]
+pub struct Stages {
+    list: [BootStage; MAX_STAGES],
+    len: usize,
+}
+
+impl Stages {
+    pub fn as_slice(&self) -> &[BootStage] {
+        &self.list[..self.len]
+    }
+}
+
+/// Only touched with interrupts masked
+struct Profile(UnsafeCell<Stages>);
+
+unsafe impl Sync for Profile {}
+
+static PROFILE: Profile = Profile(UnsafeCell::new(Stages {
+    list: [BootStage::new("", 0); MAX_STAGES],
+    len: 0,
+}));
+
+fn with<R>(f: impl FnOnce(&mut Stages) -> R) -> R {
+    interrupts::free(|| f(unsafe { &mut *PROFILE.0.get() }))
+}
+
+/// Record that stage `name` finished just now
+pub fn checkpoint(name: &str) {
+    let time_us = timer::uptime().as_micros() as u64;
+    with(|stages| {
+        if let Some(stage) = stages.list.get_mut(stages.len) {
+            *stage = BootStage::new(name, time_us);
+            stages.len += 1;
+        }
+    })
+}
+
+/// Every checkpoint so far, in order
+pub fn stages() -> Stages {
+    with(|stages| *stages)
+}
+
+/// Print when each stage finished and how long it took
+pub fn report() {
+    println!("Boot timing (ms):");
+    println!("  {:<16} {:>10} {:>10}", "stage", "at", "took");
+    let mut last = 0;
+    for stage in stages().as_slice() {
+        let took = stage.time_us - last;
+        println!(
+            "  {:<16} {:>6}.{:03} {:>6}.{:03}",
+            stage.name(),
+            stage.time_us / 1000,
+            stage.time_us % 1000,
+            took / 1000,
+            took % 1000
+        );
+        last = stage.time_us;
+    }
+}
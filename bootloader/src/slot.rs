@@ -0,0 +1,201 @@
+//! A/B kernel slots.
+//!
+//! Two kernels live in `/boot/a/kernel.bin` and `/boot/b/kernel.bin`, and the
+//! [`SlotState`] in [`STATE_PATH`] records which one boots. An update writes
+//! the new kernel to the inactive slot and makes it active with
+//! [`SlotState::activate`], which gives it [`MAX_TRIES`] boots to call
+//! [`SlotState::confirm`]. If it never does, the bootloader switches back to
+//! the other slot.
+//!
+//! The state file is [`STATE_SIZE`] bytes and is only ever rewritten in place:
+//! magic, version, active slot, tries left, confirmed flag, then reserved
+//! zeros. The same bytes are handed to the kernel in the boot info.
+
+pub const STATE_PATH: &str = "/boot/slot.state";
+pub const STATE_SIZE: usize = 16;
+/// Boots an activated slot gets before the bootloader gives up on it
+pub const MAX_TRIES: u8 = 3;
+
+const STATE_MAGIC: [u8; 4] = *b"ABST";
+const STATE_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    A = 0,
+    B = 1,
+}
+
+impl Slot {
+    pub fn from_u8(slot: u8) -> Option<Self> {
+        match slot {
+            0 => Some(Self::A),
+            1 => Some(Self::B),
+            _ => None,
+        }
+    }
+
+    pub fn other(self) -> Self {
+        match self {
+            Self::A => Self::B,
+            Self::B => Self::A,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::A => "a",
+            Self::B => "b",
+        }
+    }
+
+    pub fn kernel_path(self) -> &'static str {
+        match self {
+            Self::A => "/boot/a/kernel.bin",
+            Self::B => "/boot/b/kernel.bin",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotState {
+    pub active: Slot,
+    /// Boots left before an unconfirmed slot is abandoned
+    pub tries_left: u8,
+    /// The active slot's kernel came up at least once
+    pub confirmed: bool,
+}
+
+impl SlotState {
+    /// A known good `slot`
+    pub const fn new(active: Slot) -> Self {
+        Self {
+            active,
+            tries_left: 0,
+            confirmed: true,
+        }
+    }
+
+    /// `None` if `bytes` isn't a state this version understands
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..STATE_SIZE)?;
+        if bytes[..4] != STATE_MAGIC || bytes[4] != STATE_VERSION {
+            return None;
+        }
+        Some(Self {
+            active: Slot::from_u8(bytes[5])?,
+            tries_left: bytes[6],
+            confirmed: bytes[7] != 0,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; STATE_SIZE] {
+        let mut bytes = [0u8; STATE_SIZE];
+        bytes[..4].copy_from_slice(&STATE_MAGIC);
+        bytes[4] = STATE_VERSION;
+        bytes[5] = self.active as u8;
+        bytes[6] = self.tries_left;
+        bytes[7] = self.confirmed as u8;
+        bytes
+    }
+
+    /// Boot `slot` from now on, on probation until it is confirmed
+    pub fn activate(&mut self, slot: Slot) {
+        self.active = slot;
+        self.tries_left = MAX_TRIES;
+        self.confirmed = false;
+    }
+
+    /// Called by the kernel once it is up
+    pub fn confirm(&mut self) {
+        self.confirmed = true;
+    }
+
+    /// Count a boot attempt and return the slot to boot. An unconfirmed slot
+    /// with no tries left is abandoned for the other, previously good, one.
+    pub fn start_boot(&mut self) -> Slot {
+        if !self.confirmed {
+            if self.tries_left == 0 {
+                *self = Self::new(self.active.other());
+            } else {
+                self.tries_left -= 1;
+            }
+        }
+        self.active
+    }
+
+    /// The active slot's kernel could not be loaded at all, switch right away
+    pub fn fail(&mut self) {
+        *self = Self::new(self.active.other());
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        for state in [
+            SlotState::new(Slot::A),
+            SlotState {
+                active: Slot::B,
+                tries_left: 2,
+                confirmed: false,
+            },
+        ] {
+            assert_eq!(SlotState::parse(&state.to_bytes()), Some(state));
+        }
+    }
+
+    #[test]
+    fn rejects_foreign_state() {
+        let good = SlotState::new(Slot::B).to_bytes();
+
+        let mut bad = good;
+        bad[0] = b'X';
+        assert_eq!(SlotState::parse(&bad), None, "magic");
+        bad = good;
+        bad[4] = STATE_VERSION + 1;
+        assert_eq!(SlotState::parse(&bad), None, "version");
+        bad = good;
+        bad[5] = 2;
+        assert_eq!(SlotState::parse(&bad), None, "slot");
+        assert_eq!(SlotState::parse(&good[..STATE_SIZE - 1]), None, "short");
+    }
+
+    #[test]
+    fn activated_slot_falls_back_after_its_tries() {
+        let mut state = SlotState::new(Slot::A);
+        state.activate(Slot::B);
+        assert_eq!(state.tries_left, MAX_TRIES);
+        assert!(!state.confirmed);
+
+        for left in (0..MAX_TRIES).rev() {
+            assert_eq!(state.start_boot(), Slot::B);
+            assert_eq!(state.tries_left, left);
+        }
+        assert_eq!(state.start_boot(), Slot::A);
+        assert_eq!(state, SlotState::new(Slot::A));
+    }
+
+    #[test]
+    fn confirmed_slot_keeps_booting() {
+        let mut state = SlotState::new(Slot::A);
+        state.activate(Slot::B);
+        state.start_boot();
+        state.confirm();
+        for _ in 0..2 * MAX_TRIES {
+            assert_eq!(state.start_boot(), Slot::B);
+        }
+    }
+
+    #[test]
+    fn fail_switches_right_away() {
+        let mut state = SlotState::new(Slot::A);
+        state.activate(Slot::B);
+        state.fail();
+        assert_eq!(state, SlotState::new(Slot::A));
+        state.fail();
+        assert_eq!(state.start_boot(), Slot::B);
+    }
+}
@@ -0,0 +1,200 @@
+//! TFTP client (RFC 1350) reading a single file in octet mode.
+//!
+//! Asks for the largest block size that fits in one frame (RFC 2348), a
+//! server that ignores options sends 512 byte blocks instead. The transfer
+//! is lock-step: every block is ACKed before the next one comes, and a lost
+//! packet is recovered by sending the last ACK again.
+use core::net::Ipv4Addr;
+
+use hal::println;
+
+use crate::net::{Interface, MAX_UDP_PAYLOAD, NetError, REPLY_TIMEOUT};
+
+const SERVER_PORT: u16 = 69;
+/// Our end of the transfer, the first dynamic port
+const CLIENT_PORT: u16 = 49152;
+
+const HEADER_SIZE: usize = 4;
+const DEFAULT_BLOCK_SIZE: usize = 512;
+/// Block size we ask for
+const BLOCK_SIZE: usize = MAX_UDP_PAYLOAD - HEADER_SIZE;
+
+/// Seconds without a packet from the server before the transfer is given up
+const RETRIES: u32 = 5;
+
+mod opcode {
+    pub const RRQ: u16 = 1;
+    pub const DATA: u16 = 3;
+    pub const ACK: u16 = 4;
+    pub const ERROR: u16 = 5;
+    pub const OACK: u16 = 6;
+}
+
+const MALFORMED: NetError = NetError::Protocol("malformed TFTP packet");
+
+enum Packet<'a> {
+    Data {
+        block: u16,
+        payload: &'a [u8],
+    },
+    /// Option acknowledgement, holds the block size the server settled on
+    Oack {
+        block_size: usize,
+    },
+    Error {
+        code: u16,
+        message: &'a str,
+    },
+}
+
+fn parse(data: &[u8]) -> Result<Packet<'_>, NetError> {
+    let ([op_hi, op_lo], rest) = data.split_first_chunk::<2>().ok_or(MALFORMED)?;
+    let number = |rest: &[u8]| rest.get(..2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    match u16::from_be_bytes([*op_hi, *op_lo]) {
+        opcode::DATA => Ok(Packet::Data {
+            block: number(rest).ok_or(MALFORMED)?,
+            payload: &rest[2..],
+        }),
+        opcode::ERROR => {
+            let code = number(rest).ok_or(MALFORMED)?;
+            let text = &rest[2..];
+            let text = &text[..text.iter().position(|&b| b == 0).unwrap_or(text.len())];
+            Ok(Packet::Error {
+                code,
+                message: core::str::from_utf8(text).unwrap_or("?"),
+            })
+        }
+        opcode::OACK => {
+            // name and value pairs, each NUL terminated
+            let mut block_size = DEFAULT_BLOCK_SIZE;
+            let mut fields = rest.split(|&b| b == 0);
+            while let (Some(name), Some(value)) = (fields.next(), fields.next()) {
+                if name.eq_ignore_ascii_case(b"blksize") {
+                    block_size = core::str::from_utf8(value)
+                        .ok()
+                        .and_then(|value| value.parse().ok())
+                        .filter(|&size| size > 0 && size <= BLOCK_SIZE)
+                        .ok_or(NetError::Protocol("server chose a bad TFTP block size"))?;
+                }
+            }
+            Ok(Packet::Oack { block_size })
+        }
+        _ => Err(NetError::Protocol("unexpected TFTP packet")),
+    }
+}
+
+/// Write a read request for `path` to `buf`, returning its length
+fn write_request(buf: &mut [u8], path: &str) -> Result<usize, NetError> {
+    buf[..2].copy_from_slice(&opcode::RRQ.to_be_bytes());
+    let mut len = 2;
+    let mut put = |bytes: &[u8]| -> Result<(), NetError> {
+        let field = buf
+            .get_mut(len..len + bytes.len() + 1)
+            .ok_or(NetError::Protocol("TFTP path too long"))?;
+        field[..bytes.len()].copy_from_slice(bytes);
+        field[bytes.len()] = 0;
+        len += bytes.len() + 1;
+        Ok(())
+    };
+    put(path.as_bytes())?;
+    put(b"octet")?;
+    put(b"blksize")?;
+    let mut digits = [0u8; 8];
+    put(format_decimal(BLOCK_SIZE, &mut digits))?;
+    Ok(len)
+}
+
+fn format_decimal(mut value: usize, buf: &mut [u8; 8]) -> &[u8] {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            return &buf[start..];
+        }
+    }
+}
+
+fn send_ack(
+    iface: &mut Interface,
+    server: Ipv4Addr,
+    port: u16,
+    block: u16,
+) -> Result<(), NetError> {
+    let buf = iface.udp_payload_mut();
+    buf[0..2].copy_from_slice(&opcode::ACK.to_be_bytes());
+    buf[2..4].copy_from_slice(&block.to_be_bytes());
+    iface.send_udp(server, CLIENT_PORT, port, HEADER_SIZE)
+}
+
+/// Download `path` from `server` into `dest`, returning its size
+pub fn download(
+    iface: &mut Interface,
+    server: Ipv4Addr,
+    path: &str,
+    dest: &mut [u8],
+) -> Result<usize, NetError> {
+    let send_request = |iface: &mut Interface| -> Result<(), NetError> {
+        let len = write_request(iface.udp_payload_mut(), path)?;
+        iface.send_udp(server, CLIENT_PORT, SERVER_PORT, len)
+    };
+    send_request(iface)?;
+
+    // the server answers from a port of its own, the transfer ID
+    let mut peer: Option<u16> = None;
+    let mut block_size = DEFAULT_BLOCK_SIZE;
+    let mut expected: u16 = 1;
+    let mut size = 0;
+    let mut retries = 0;
+    loop {
+        let Some(datagram) = iface.receive_udp(CLIENT_PORT, REPLY_TIMEOUT)? else {
+            retries += 1;
+            if retries > RETRIES {
+                return Err(NetError::Timeout("TFTP data"));
+            }
+            match peer {
+                None => send_request(iface)?,
+                Some(port) => send_ack(iface, server, port, expected.wrapping_sub(1))?,
+            }
+            continue;
+        };
+        if datagram.src != server || peer.is_some_and(|port| port != datagram.src_port) {
+            continue;
+        }
+        let port = datagram.src_port;
+
+        // what to ACK once the datagram has been dealt with
+        let ack = match parse(datagram.data)? {
+            Packet::Error { code, message } => {
+                println!("TFTP error {}: {}", code, message);
+                return Err(NetError::Tftp(code));
+            }
+            Packet::Oack { block_size: chosen } if expected == 1 => {
+                block_size = chosen;
+                0
+            }
+            Packet::Oack { .. } => continue,
+            Packet::Data { block, payload } if block == expected => {
+                let end = size + payload.len();
+                let available = dest.len();
+                dest.get_mut(size..end)
+                    .ok_or(NetError::TooLarge { available })?
+                    .copy_from_slice(payload);
+                size = end;
+                expected = expected.wrapping_add(1);
+                if payload.len() < block_size {
+                    send_ack(iface, server, port, block)?;
+                    return Ok(size);
+                }
+                block
+            }
+            // our ACK for it got lost, the server is sending it again
+            Packet::Data { block, .. } if block == expected.wrapping_sub(1) => block,
+            Packet::Data { .. } => continue,
+        };
+        peer = Some(port);
+        retries = 0;
+        send_ack(iface, server, port, ack)?;
+    }
+}
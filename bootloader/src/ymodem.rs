@@ -0,0 +1,300 @@
+//! YMODEM receiver (with XMODEM-1K fallback) over UART0.
+//!
+//! The receiver always asks for CRC-16 mode. A sender that opens with block 0
+//! is treated as YMODEM batch (filename + size header), one that opens with
+//! block 1 is treated as plain XMODEM(-1K) and the payload length is whatever
+//! was received, including the trailing padding.
+//!
+//! Nothing may be printed on the console while a transfer is running, the
+//! sender would read it as protocol bytes.
+use core::time::Duration;
+
+use bootloader_types::ymodem_block::{BadHeader, crc16_update, parse_header};
+use hal::uart::{UartDevice, UartError};
+
+const SOH: u8 = 0x01; // 128 byte block
+const STX: u8 = 0x02; // 1024 byte block
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const CRC_REQUEST: u8 = b'C';
+
+/// How long until a byte is considered lost
+const BYTE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Shorter timeout used to drain line noise after a bad packet
+const PURGE_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// How many times we ask for a transfer to start before giving up
+const MAX_START_ATTEMPTS: u32 = 30;
+/// Consecutive bad packets before the transfer is cancelled
+const MAX_ERRORS: u32 = 10;
+
+#[derive(Debug)]
+pub enum YmodemError {
+    /// No sender started a transfer
+    Timeout,
+    /// The sender cancelled the transfer
+    Cancelled,
+    /// Too many consecutive bad packets
+    TooManyErrors,
+    /// A block arrived out of sequence
+    BadSequence { expected: u8, got: u8 },
+    /// The YMODEM header block could not be parsed
+    BadHeader,
+    /// A YMODEM batch with no files in it
+    NoFile,
+    /// The file does not fit in the destination buffer
+    BufferTooSmall { needed: usize, available: usize },
+}
+
+impl core::fmt::Display for YmodemError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "no transfer started"),
+            Self::Cancelled => write!(f, "cancelled by sender"),
+            Self::TooManyErrors => write!(f, "too many bad packets"),
+            Self::BadSequence { expected, got } => {
+                write!(f, "expected block {}, got block {}", expected, got)
+            }
+            Self::BadHeader => write!(f, "malformed YMODEM header"),
+            Self::NoFile => write!(f, "empty YMODEM batch"),
+            Self::BufferTooSmall { needed, available } => write!(
+                f,
+                "file needs {} bytes, only {} available",
+                needed, available
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Ymodem,
+    Xmodem,
+}
+
+enum Packet {
+    Data { block: u8, len: usize },
+    Eot,
+    Cancel,
+}
+
+#[derive(Debug)]
+enum PacketError {
+    Timeout,
+    BadStart,
+    BadBlockNumber,
+    BadCrc,
+}
+
+fn read(uart: &mut UartDevice) -> Result<u8, PacketError> {
+    uart.read_byte_timeout(BYTE_TIMEOUT)
+        .map_err(|_: UartError| PacketError::Timeout)
+}
+
+/// Read one packet. The payload is written to the front of `buf`, bytes that
+/// don't fit are still checked against the CRC but dropped.
+fn read_packet(uart: &mut UartDevice, buf: &mut [u8]) -> Result<Packet, PacketError> {
+    let len = match read(uart)? {
+        SOH => 128,
+        STX => 1024,
+        EOT => return Ok(Packet::Eot),
+        CAN => {
+            // a single CAN can be line noise, the sender always sends two
+            return match read(uart)? {
+                CAN => Ok(Packet::Cancel),
+                _ => Err(PacketError::BadStart),
+            };
+        }
+        _ => return Err(PacketError::BadStart),
+    };
+
+    let block = read(uart)?;
+    let block_inv = read(uart)?;
+
+    let mut crc: u16 = 0;
+    for i in 0..len {
+        let byte = read(uart)?;
+        crc = crc16_update(crc, byte);
+        if let Some(slot) = buf.get_mut(i) {
+            *slot = byte;
+        }
+    }
+    let crc_hi = read(uart)?;
+    let crc_lo = read(uart)?;
+
+    if block != !block_inv {
+        return Err(PacketError::BadBlockNumber);
+    }
+    if crc != u16::from_be_bytes([crc_hi, crc_lo]) {
+        return Err(PacketError::BadCrc);
+    }
+
+    Ok(Packet::Data { block, len })
+}
+
+fn cancel(uart: &mut UartDevice) {
+    uart.write_all(&[CAN, CAN, CAN]);
+}
+
+/// Drain whatever is left of a bad packet so the sender sees our NAK cleanly
+fn purge(uart: &mut UartDevice) {
+    while uart.read_byte_timeout(PURGE_TIMEOUT).is_ok() {}
+}
+
+/// Receive a single file into `dest`, returning its length in bytes.
+pub fn receive(dest: &mut [u8]) -> Result<usize, YmodemError> {
+    let mut uart = UartDevice::new();
+    uart.flush_input();
+
+    let mut mode: Option<Mode> = None;
+    let mut expected: u8 = 0;
+    let mut offset = 0;
+    let mut file_size: Option<usize> = None;
+    let mut start_attempts = 0;
+    let mut errors = 0;
+    let mut eot_seen = false;
+
+    loop {
+        if mode.is_none() {
+            uart.write_byte(CRC_REQUEST);
+        }
+
+        let packet = match read_packet(&mut uart, &mut dest[offset..]) {
+            Ok(packet) => packet,
+            Err(PacketError::Timeout) if mode.is_none() => {
+                start_attempts += 1;
+                if start_attempts >= MAX_START_ATTEMPTS {
+                    return Err(YmodemError::Timeout);
+                }
+                continue;
+            }
+            Err(_) => {
+                errors += 1;
+                if errors >= MAX_ERRORS {
+                    cancel(&mut uart);
+                    return Err(YmodemError::TooManyErrors);
+                }
+                purge(&mut uart);
+                // before the first block the next 'C' doubles as the NAK
+                if mode.is_some() {
+                    uart.write_byte(NAK);
+                }
+                continue;
+            }
+        };
+        errors = 0;
+
+        match packet {
+            Packet::Cancel => return Err(YmodemError::Cancelled),
+            Packet::Eot => {
+                if mode.is_none() {
+                    continue;
+                }
+                // NAK the first EOT so a noise byte can't end the transfer, the sender repeats it
+                if !eot_seen {
+                    eot_seen = true;
+                    uart.write_byte(NAK);
+                    continue;
+                }
+                uart.write_byte(ACK);
+                break;
+            }
+            Packet::Data { block, len } => {
+                if mode.is_none() {
+                    mode = match block {
+                        0 => Some(Mode::Ymodem),
+                        1 => {
+                            expected = 1;
+                            Some(Mode::Xmodem)
+                        }
+                        got => {
+                            cancel(&mut uart);
+                            return Err(YmodemError::BadSequence { expected: 0, got });
+                        }
+                    };
+                }
+
+                // our ACK was lost, the sender is repeating the previous block
+                if block == expected.wrapping_sub(1) && (offset > 0 || file_size.is_some()) {
+                    uart.write_byte(ACK);
+                    if block == 0 {
+                        uart.write_byte(CRC_REQUEST);
+                    }
+                    continue;
+                }
+                if block != expected {
+                    cancel(&mut uart);
+                    return Err(YmodemError::BadSequence {
+                        expected,
+                        got: block,
+                    });
+                }
+
+                if mode == Some(Mode::Ymodem) && block == 0 && file_size.is_none() {
+                    match parse_header(&dest[..len.min(dest.len())]) {
+                        Ok(Some(size)) => file_size = Some(size),
+                        Ok(None) => {
+                            uart.write_byte(ACK);
+                            return Err(YmodemError::NoFile);
+                        }
+                        Err(BadHeader) => {
+                            cancel(&mut uart);
+                            return Err(YmodemError::BadHeader);
+                        }
+                    }
+                    if let Some(size) = file_size.filter(|&size| size > dest.len()) {
+                        cancel(&mut uart);
+                        return Err(YmodemError::BufferTooSmall {
+                            needed: size,
+                            available: dest.len(),
+                        });
+                    }
+                    expected = 1;
+                    uart.write_byte(ACK);
+                    // ask for the data blocks
+                    uart.write_byte(CRC_REQUEST);
+                    continue;
+                }
+
+                // the last block of a YMODEM file may be padding past the end of dest
+                let stored = len.min(dest.len() - offset);
+                let wanted = file_size.map_or(len, |size| len.min(size.saturating_sub(offset)));
+                if stored < wanted {
+                    cancel(&mut uart);
+                    return Err(YmodemError::BufferTooSmall {
+                        needed: offset + len,
+                        available: dest.len(),
+                    });
+                }
+
+                offset += stored;
+                expected = expected.wrapping_add(1);
+                uart.write_byte(ACK);
+            }
+        }
+    }
+
+    if mode == Some(Mode::Ymodem) {
+        finish_batch(&mut uart);
+    }
+
+    Ok(file_size.map_or(offset, |size| size.min(offset)))
+}
+
+/// A YMODEM sender follows the file with an empty block 0 to end the batch.
+/// We only take one file, so anything valid is acknowledged and ignored.
+fn finish_batch(uart: &mut UartDevice) {
+    for _ in 0..MAX_ERRORS {
+        uart.write_byte(CRC_REQUEST);
+        match read_packet(uart, &mut []) {
+            Ok(Packet::Data { block: 0, .. }) => {
+                uart.write_byte(ACK);
+                return;
+            }
+            Ok(Packet::Cancel) | Err(PacketError::Timeout) => return,
+            _ => purge(uart),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! The parts of a YMODEM block that can be checked without a UART: the
+//! CRC-16 trailing every block and the file header in block 0.
+
+/// CRC-16/XMODEM (poly 0x1021, init 0), one byte at a time
+pub fn crc16_update(crc: u16, byte: u8) -> u16 {
+    let mut crc = crc ^ ((byte as u16) << 8);
+    for _ in 0..8 {
+        if crc & 0x8000 != 0 {
+            crc = (crc << 1) ^ 0x1021;
+        } else {
+            crc <<= 1;
+        }
+    }
+    crc
+}
+
+/// Block 0 with no file name, or no size after it
+#[derive(Debug, PartialEq, Eq)]
+pub struct BadHeader;
+
+/// Parse the file size out of a YMODEM block 0: `name\0size [mtime mode ...]\0`.
+/// Returns `Ok(None)` for the empty header that terminates a batch.
+pub fn parse_header(block: &[u8]) -> Result<Option<usize>, BadHeader> {
+    if block.first().copied().unwrap_or(0) == 0 {
+        return Ok(None);
+    }
+    let name_end = block.iter().position(|&b| b == 0).ok_or(BadHeader)?;
+
+    let mut size: usize = 0;
+    let mut digits = 0;
+    for &b in &block[name_end + 1..] {
+        match b {
+            b'0'..=b'9' => {
+                size = size
+                    .checked_mul(10)
+                    .and_then(|s| s.checked_add((b - b'0') as usize))
+                    .ok_or(BadHeader)?;
+                digits += 1;
+            }
+            _ => break,
+        }
+    }
+    if digits == 0 {
+        return Err(BadHeader);
+    }
+    Ok(Some(size))
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    fn crc16(data: &[u8]) -> u16 {
+        data.iter().fold(0, |crc, &byte| crc16_update(crc, byte))
+    }
+
+    /// A 128 byte block 0 starting with `header`, NUL padded
+    fn block(header: &[u8]) -> [u8; 128] {
+        let mut block = [0; 128];
+        block[..header.len()].copy_from_slice(header);
+        block
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(crc16(b""), 0);
+        // a block followed by its own CRC checks out to zero
+        let mut data = block(b"kernel.bin\x001234").to_vec();
+        let crc = crc16(&data);
+        data.extend_from_slice(&crc.to_be_bytes());
+        assert_eq!(crc16(&data), 0);
+    }
+
+    #[test]
+    fn parses_file_sizes() {
+        assert_eq!(parse_header(&block(b"kernel.bin\x001234")), Ok(Some(1234)));
+        // sz and lrzsz send the mtime and mode after the size
+        assert_eq!(
+            parse_header(&block(
+                b"kernel.bin\x00524288 14671745013 100644 0 1 524288"
+            )),
+            Ok(Some(524288))
+        );
+        assert_eq!(parse_header(&block(b"empty\x000")), Ok(Some(0)));
+    }
+
+    #[test]
+    fn empty_header_ends_the_batch() {
+        assert_eq!(parse_header(&[0; 128]), Ok(None));
+        assert_eq!(parse_header(&[]), Ok(None));
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        // no size
+        assert_eq!(parse_header(&block(b"kernel.bin")), Err(BadHeader));
+        assert_eq!(parse_header(&block(b"kernel.bin\x00 12")), Err(BadHeader));
+        // name running to the end of the block
+        assert_eq!(parse_header(&[b'k'; 128]), Err(BadHeader));
+        // more than fits in a usize
+        assert_eq!(
+            parse_header(&block(b"big\x00999999999999999999999999")),
+            Err(BadHeader)
+        );
+    }
+}
@@ -48,7 +48,7 @@
             pkgs.llvmPackages.libclang
           ];
           commonEnv = {
-            RUSTFLAGS = "";
+            RUSTFLAGS = "-C force-frame-pointers=yes";
             LIBCLANG_PATH = "${pkgs.llvmPackages.libclang.lib}/lib";
           };
         in
@@ -1,6 +1,21 @@
 #![allow(dead_code)]
+// host builds only exist to unit test the code around these
+#![cfg_attr(
+    not(target_arch = "arm"),
+    allow(unused_variables, unreachable_code, unused_unsafe)
+)]
+#[cfg(target_arch = "arm")]
 use core::arch::asm;
 
+/// Stands in for `asm!` when hal is built for the host to run its unit
+/// tests, which never execute an ARM instruction
+#[cfg(not(target_arch = "arm"))]
+macro_rules! asm {
+    ($($tokens:tt)*) => {
+        unimplemented!("ARM instructions only run on the board")
+    };
+}
+
 /// # Get the current value of the DACR register
 ///
 /// # Safety
@@ -701,3 +716,795 @@ pub unsafe fn svc(num: u8) {
         );
     }
 }
+
+/// # Safety
+/// This function uses raw assembly to set the PMCR (Performance Monitor Control Register)
+/// in the ARM system control coprocessor. PMCR enables the performance counters, resets them
+/// and selects whether the cycle counter ticks every cycle or every 64th. The caller must
+/// ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+/// 2. Nothing else relies on the counters being reset or reconfigured
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Parameters
+/// * `pmcr` - The value to write to the PMCR register
+///
+/// # Assembly
+/// mcr p15, 0, {input}, c9, c12, 0
+#[inline(always)]
+pub unsafe fn set_pmcr(pmcr: u32) {
+    unsafe {
+        asm!(
+            "mcr p15, 0, {pmcr}, c9, c12, 0",
+            pmcr = in(reg) pmcr,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to write the PMCNTENSET (Count Enable Set Register) in the
+/// ARM system control coprocessor. Every 1 bit starts the matching counter, bit 31 being the
+/// cycle counter, 0 bits are ignored. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+/// 2. The counters are enabled globally in PMCR for them to count
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Parameters
+/// * `counters` - Bit mask of the counters to enable
+///
+/// # Assembly
+/// mcr p15, 0, {input}, c9, c12, 1
+#[inline(always)]
+pub unsafe fn set_pmcntenset(counters: u32) {
+    unsafe {
+        asm!(
+            "mcr p15, 0, {counters}, c9, c12, 1",
+            counters = in(reg) counters,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to read the PMCCNTR (Cycle Count Register) from the ARM
+/// system control coprocessor. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers, or user access was
+///    granted through PMUSERENR
+/// 2. The returned count is interpreted with the divider set in PMCR
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the current value of the cycle counter.
+///
+/// # Assembly
+/// mrc p15, 0, {output}, c9, c13, 0
+#[inline(always)]
+pub unsafe fn read_pmccntr() -> u32 {
+    let count: u32;
+    unsafe {
+        asm!(
+            "mrc p15, 0, {count}, c9, c13, 0",
+            count = out(reg) count,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    count
+}
+
+/// # Safety
+/// This function uses raw assembly to make an ARM semihosting call, a request serviced by
+/// the attached debugger or emulator (QEMU with `-semihosting`) instead of the target. The
+/// caller must ensure:
+///
+/// 1. Semihosting is enabled on the host, otherwise the SVC is taken as a normal supervisor
+///    call exception
+/// 2. `param` is what operation `op` expects, usually the address of its argument block,
+///    and any memory it points to is valid for the host to read or write
+///
+/// The host reads and writes target memory, so unlike [`svc`] this is not `nomem`. The link
+/// register is treated as clobbered, it is what a real supervisor call would overwrite.
+///
+/// # Parameters
+/// * `op` - Semihosting operation number, passed in r0
+/// * `param` - Operation parameter, passed in r1
+///
+/// # Returns
+/// The value the host left in r0, its meaning depends on the operation.
+///
+/// # Assembly
+///
+/// ```asm
+/// svc 0x123456
+/// ```
+#[inline(always)]
+pub unsafe fn semihosting_call(op: u32, param: usize) -> usize {
+    let result: usize;
+    unsafe {
+        asm!(
+            "svc 0x123456",
+            inout("r0") op as usize => result,
+            in("r1") param,
+            out("lr") _,
+            options(nostack, preserves_flags)
+        );
+    }
+    result
+}
+
+/// # Safety
+/// This function uses raw assembly to read the CPSR (Current Program Status Register), which
+/// holds the condition flags, the interrupt masks and the processor mode. Reading it has no
+/// side effects, the caller only has to interpret the value for the current mode.
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the value of the CPSR.
+///
+/// # Assembly
+/// mrs {output}, cpsr
+#[inline(always)]
+pub unsafe fn read_cpsr() -> u32 {
+    let cpsr: u32;
+    unsafe {
+        asm!(
+            "mrs {cpsr}, cpsr",
+            cpsr = out(reg) cpsr,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    cpsr
+}
+
+/// # Safety
+/// This function uses raw assembly to read the stack pointer of the current mode. The value
+/// is only meaningful where the function is inlined, which it always is.
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the value of SP.
+///
+/// # Assembly
+/// mov {output}, sp
+#[inline(always)]
+pub unsafe fn read_sp() -> u32 {
+    let sp: u32;
+    unsafe {
+        asm!(
+            "mov {sp}, sp",
+            sp = out(reg) sp,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    sp
+}
+
+/// # Safety
+/// This function uses raw assembly to read the link register. It only holds the caller's
+/// return address until the first call made after the function prologue, so the caller must
+/// read it before calling anything else.
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the value of LR.
+///
+/// # Assembly
+/// mov {output}, lr
+#[inline(always)]
+pub unsafe fn read_lr() -> u32 {
+    let lr: u32;
+    unsafe {
+        asm!(
+            "mov {lr}, lr",
+            lr = out(reg) lr,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    lr
+}
+
+/// # Safety
+/// This function uses raw assembly to read the frame pointer, r11 in ARM state. It is only
+/// a frame pointer if the code was built with `-C force-frame-pointers=yes`, as
+/// `.cargo/config.toml` does, otherwise it is an ordinary callee saved register.
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the value of r11.
+///
+/// # Assembly
+/// mov {output}, r11
+#[inline(always)]
+pub unsafe fn read_fp() -> u32 {
+    let fp: u32;
+    unsafe {
+        asm!(
+            "mov {fp}, r11",
+            fp = out(reg) fp,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    fp
+}
+
+/// # Safety
+/// This function uses raw assembly to read the CLIDR (Cache Level ID Register) from the ARM
+/// system control coprocessor. It describes which cache levels exist, what kind each is, and
+/// the level of coherency that set/way maintenance has to reach. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the value of the CLIDR.
+///
+/// # Assembly
+/// mrc p15, 1, {output}, c0, c0, 1
+#[inline(always)]
+pub unsafe fn read_clidr() -> u32 {
+    let clidr: u32;
+    unsafe {
+        asm!(
+            "mrc p15, 1, {clidr}, c0, c0, 1",
+            clidr = out(reg) clidr,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    clidr
+}
+
+/// # Safety
+/// This function uses raw assembly to write the CSSELR (Cache Size Selection Register) in the
+/// ARM system control coprocessor, which picks the cache [`read_ccsidr`] describes. The
+/// caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+/// 2. An ISB follows before CCSIDR is read
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Parameters
+/// * `csselr` - Cache level shifted left by one, bit 0 set for the instruction cache
+///
+/// # Assembly
+/// mcr p15, 2, {input}, c0, c0, 0
+#[inline(always)]
+pub unsafe fn set_csselr(csselr: u32) {
+    unsafe {
+        asm!(
+            "mcr p15, 2, {csselr}, c0, c0, 0",
+            csselr = in(reg) csselr,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to read the CCSIDR (Cache Size ID Register) from the ARM
+/// system control coprocessor, giving the line size, associativity and number of sets of the
+/// cache selected with [`set_csselr`]. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the value of the CCSIDR.
+///
+/// # Assembly
+/// mrc p15, 1, {output}, c0, c0, 0
+#[inline(always)]
+pub unsafe fn read_ccsidr() -> u32 {
+    let ccsidr: u32;
+    unsafe {
+        asm!(
+            "mrc p15, 1, {ccsidr}, c0, c0, 0",
+            ccsidr = out(reg) ccsidr,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    ccsidr
+}
+
+/// # Safety
+/// This function uses raw assembly to clean and invalidate one data cache line by set/way
+/// (DCCISW) in the ARM system control coprocessor. A dirty line is written back to memory
+/// first, then dropped. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+/// 2. `set_way` was built from the geometry CCSIDR reports for that level
+/// 3. A DSB follows once every line that matters has been cleaned
+///
+/// Memory changes underneath the compiler, so unlike most functions here this is not
+/// `nomem`.
+///
+/// # Parameters
+/// * `set_way` - Way in the top bits, set shifted by the line size, and level shifted left
+///   by one
+///
+/// # Assembly
+/// mcr p15, 0, {input}, c7, c14, 2
+#[inline(always)]
+pub unsafe fn clean_invalidate_d_cache_line(set_way: u32) {
+    unsafe {
+        asm!(
+            "mcr p15, 0, {set_way}, c7, c14, 2",
+            set_way = in(reg) set_way,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to invalidate all branch predictor entries (BPIALL) in the
+/// ARM system control coprocessor. It is needed whenever the code at an address changes, or
+/// the mapping of it does. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+/// 2. An ISB follows before the new code runs
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Assembly
+/// mcr p15, 0, {zero}, c7, c5, 6
+#[inline(always)]
+pub unsafe fn invalidate_branch_predictor() {
+    unsafe {
+        asm!(
+            "mcr p15, 0, {zero}, c7, c5, 6",
+            zero = in(reg) 0,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to jump to `entry` in the state the CPU is in out of reset:
+/// supervisor mode, IRQ and FIQ masked, ARM state, and r0-r2 zeroed. The caller must ensure:
+///
+/// 1. The MMU and caches are off, and the caches were cleaned and invalidated before
+/// 2. `entry` is the physical address of ARM code that expects to be started like this
+/// 3. Nothing still running needs this stack or anything else of ours, it never returns
+///
+/// # Parameters
+/// * `entry` - Physical address to branch to
+///
+/// # Assembly
+///
+/// ```asm
+/// msr cpsr_c, #0xD3
+/// mov r0, #0
+/// mov r1, #0
+/// mov r2, #0
+/// bx r3 // entry
+/// ```
+#[inline(always)]
+pub unsafe fn enter_reset_state(entry: u32) -> ! {
+    unsafe {
+        asm!(
+            "msr cpsr_c, #0xD3",
+            "mov r0, #0",
+            "mov r1, #0",
+            "mov r2, #0",
+            "bx r3",
+            in("r3") entry,
+            options(noreturn, nostack)
+        );
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to jump to a Linux zImage at `entry` the way the ARM boot
+/// protocol wants it: supervisor mode, IRQ and FIQ masked, ARM state, r0 zero, r1 the machine
+/// type and r2 the device tree or ATAGS. The caller must ensure:
+///
+/// 1. The MMU and data cache are off, and the caches were cleaned and invalidated before
+/// 2. `entry` is the physical address of a zImage and `params` of the tree or tags it is given
+/// 3. Nothing still running needs this stack or anything else of ours, it never returns
+///
+/// # Parameters
+/// * `entry` - Physical address of the zImage
+/// * `machine` - Machine type number, passed in r1
+/// * `params` - Physical address of the device tree or ATAGS, passed in r2
+///
+/// # Assembly
+///
+/// ```asm
+/// msr cpsr_c, #0xD3
+/// mov r0, #0
+/// bx r3 // entry, with r1 = machine and r2 = params
+/// ```
+#[inline(always)]
+pub unsafe fn enter_linux(entry: u32, machine: u32, params: u32) -> ! {
+    unsafe {
+        asm!(
+            "msr cpsr_c, #0xD3",
+            "mov r0, #0",
+            "bx r3",
+            in("r1") machine,
+            in("r2") params,
+            in("r3") entry,
+            options(noreturn, nostack)
+        );
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to read the CTR (Cache Type Register) from the ARM system
+/// control coprocessor, which gives the smallest cache line sizes that maintenance by address
+/// has to step by. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the value of the CTR.
+///
+/// # Assembly
+/// mrc p15, 0, {output}, c0, c0, 1
+#[inline(always)]
+pub unsafe fn read_ctr() -> u32 {
+    let ctr: u32;
+    unsafe {
+        asm!(
+            "mrc p15, 0, {ctr}, c0, c0, 1",
+            ctr = out(reg) ctr,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    ctr
+}
+
+/// # Safety
+/// This function uses raw assembly to clean the data cache line holding `mva` to the point of
+/// coherency (DCCMVAC) in the ARM system control coprocessor, so memory and anything reading
+/// it without the cache, like a DMA master, see what the CPU wrote. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+/// 2. A DSB follows before anything relies on the line having been written back
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Parameters
+/// * `mva` - Any virtual address inside the line
+///
+/// # Assembly
+/// mcr p15, 0, {input}, c7, c10, 1
+#[inline(always)]
+pub unsafe fn clean_d_cache_line(mva: u32) {
+    unsafe {
+        asm!(
+            "mcr p15, 0, {mva}, c7, c10, 1",
+            mva = in(reg) mva,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to invalidate the data cache line holding `mva` to the point
+/// of coherency (DCIMVAC) in the ARM system control coprocessor, so the next read comes from
+/// memory. Dirty data in the line is thrown away, not written back. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+/// 2. Nothing else shares the line, or losing its unwritten data is acceptable
+/// 3. A DSB follows before the memory is read
+///
+/// Memory changes underneath the compiler, so unlike most functions here this is not
+/// `nomem`.
+///
+/// # Parameters
+/// * `mva` - Any virtual address inside the line
+///
+/// # Assembly
+/// mcr p15, 0, {input}, c7, c6, 1
+#[inline(always)]
+pub unsafe fn invalidate_d_cache_line(mva: u32) {
+    unsafe {
+        asm!(
+            "mcr p15, 0, {mva}, c7, c6, 1",
+            mva = in(reg) mva,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to read the VBAR (Vector Base Address Register) from the ARM system control
+/// coprocessor, which holds the address exception vectors are fetched from while SCTLR.V is clear. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the value of the VBAR.
+///
+/// # Assembly
+/// mrc p15, 0, {output}, c12, c0, 0
+#[inline(always)]
+pub unsafe fn read_vbar() -> u32 {
+    let vbar: u32;
+    unsafe {
+        asm!(
+            "mrc p15, 0, {vbar}, c12, c0, 0",
+            vbar = out(reg) vbar,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    vbar
+}
+
+/// # Safety
+/// This function uses raw assembly to set the VBAR (Vector Base Address Register) in the ARM
+/// system control coprocessor, moving the exception vectors. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+/// 2. `vbar` is 32-byte aligned and a valid vector table is mapped there, executable
+/// 3. SCTLR.V is clear, with high vectors selected VBAR is ignored
+/// 4. An ISB follows before an exception can be taken
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Parameters
+/// * `vbar` - Virtual address of the vector table
+///
+/// # Assembly
+/// mcr p15, 0, {input}, c12, c0, 0
+#[inline(always)]
+pub unsafe fn set_vbar(vbar: u32) {
+    unsafe {
+        asm!(
+            "mcr p15, 0, {vbar}, c12, c0, 0",
+            vbar = in(reg) vbar,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to read the DFSR (Data Fault Status Register) from the ARM system control
+/// coprocessor, which says why the last data abort happened: the fault status, domain and whether it was a write. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the value of the DFSR.
+///
+/// # Assembly
+/// mrc p15, 0, {output}, c5, c0, 0
+#[inline(always)]
+pub unsafe fn read_dfsr() -> u32 {
+    let dfsr: u32;
+    unsafe {
+        asm!(
+            "mrc p15, 0, {dfsr}, c5, c0, 0",
+            dfsr = out(reg) dfsr,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    dfsr
+}
+
+/// # Safety
+/// This function uses raw assembly to read the DFAR (Data Fault Address Register) from the ARM system control
+/// coprocessor, the address the last synchronous data abort faulted on. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the value of the DFAR.
+///
+/// # Assembly
+/// mrc p15, 0, {output}, c6, c0, 0
+#[inline(always)]
+pub unsafe fn read_dfar() -> u32 {
+    let dfar: u32;
+    unsafe {
+        asm!(
+            "mrc p15, 0, {dfar}, c6, c0, 0",
+            dfar = out(reg) dfar,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    dfar
+}
+
+/// # Safety
+/// This function uses raw assembly to read the IFSR (Instruction Fault Status Register) from the ARM system control
+/// coprocessor, which says why the last prefetch abort happened. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the value of the IFSR.
+///
+/// # Assembly
+/// mrc p15, 0, {output}, c5, c0, 1
+#[inline(always)]
+pub unsafe fn read_ifsr() -> u32 {
+    let ifsr: u32;
+    unsafe {
+        asm!(
+            "mrc p15, 0, {ifsr}, c5, c0, 1",
+            ifsr = out(reg) ifsr,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    ifsr
+}
+
+/// # Safety
+/// This function uses raw assembly to read the IFAR (Instruction Fault Address Register) from the ARM system control
+/// coprocessor, the address of the instruction fetch that caused the last prefetch abort. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode with access to CP15 registers
+///
+/// The function internally uses inline assembly that does not access memory or stack
+/// and preserves processor flags.
+///
+/// # Returns
+/// The function returns the value of the IFAR.
+///
+/// # Assembly
+/// mrc p15, 0, {output}, c6, c0, 2
+#[inline(always)]
+pub unsafe fn read_ifar() -> u32 {
+    let ifar: u32;
+    unsafe {
+        asm!(
+            "mrc p15, 0, {ifar}, c6, c0, 2",
+            ifar = out(reg) ifar,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    ifar
+}
+
+/// # Safety
+/// This function uses raw assembly to set the stack pointer of another processor mode. It
+/// switches to `mode` with interrupts masked, loads SP, which is banked per mode, and switches
+/// back, leaving the current mode's SP alone. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode other than user mode, which can't switch modes
+/// 2. `mode` is a valid privileged mode number and not the current mode
+/// 3. `top` is the 8-byte aligned top of memory reserved for that mode's stack for good
+///
+/// The function internally uses inline assembly that does not access memory or stack.
+///
+/// # Parameters
+/// * `mode` - CPSR mode bits of the mode whose stack is set, e.g. 0x17 for abort mode
+/// * `top` - Initial stack pointer, stacks grow down from it
+///
+/// # Assembly
+///
+/// ```asm
+/// mrs {saved}, cpsr
+/// bic {tmp}, {saved}, #0x1F
+/// orr {tmp}, {tmp}, {mode}
+/// orr {tmp}, {tmp}, #0xC0
+/// msr cpsr_c, {tmp}
+/// mov sp, {top}
+/// msr cpsr_c, {saved}
+/// ```
+#[inline(always)]
+pub unsafe fn set_mode_stack(mode: u32, top: u32) {
+    unsafe {
+        asm!(
+            "mrs {saved}, cpsr",
+            "bic {tmp}, {saved}, #0x1F",
+            "orr {tmp}, {tmp}, {mode}",
+            "orr {tmp}, {tmp}, #0xC0",
+            "msr cpsr_c, {tmp}",
+            "mov sp, {top}",
+            "msr cpsr_c, {saved}",
+            mode = in(reg) mode,
+            top = in(reg) top,
+            saved = out(reg) _,
+            tmp = out(reg) _,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to mask IRQs and FIQs by setting the CPSR I and F bits.
+/// Nothing is interrupted until they are unmasked again. The caller must ensure:
+///
+/// 1. The code runs in a privileged mode, user mode can't change the mask bits
+/// 2. Interrupts are unmasked again, or it was meant for them to stay masked
+///
+/// The function internally uses inline assembly that does not access the stack and preserves
+/// processor flags. It acts as a compiler barrier, memory accesses are not moved across it.
+///
+/// # Assembly
+///
+/// ```asm
+/// cpsid if
+/// ```
+#[inline(always)]
+pub unsafe fn disable_interrupts() {
+    unsafe {
+        asm!("cpsid if", options(nostack, preserves_flags));
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to unmask IRQs by clearing the CPSR I bit. The caller must
+/// ensure:
+///
+/// 1. The code runs in a privileged mode, user mode can't change the mask bits
+/// 2. An IRQ vector and a stack for IRQ mode are in place
+///
+/// The function internally uses inline assembly that does not access the stack and preserves
+/// processor flags. It acts as a compiler barrier, memory accesses are not moved across it.
+///
+/// # Assembly
+///
+/// ```asm
+/// cpsie i
+/// ```
+#[inline(always)]
+pub unsafe fn enable_irq() {
+    unsafe {
+        asm!("cpsie i", options(nostack, preserves_flags));
+    }
+}
+
+/// # Safety
+/// This function uses raw assembly to unmask FIQs by clearing the CPSR F bit. The caller must
+/// ensure:
+///
+/// 1. The code runs in a privileged mode, user mode can't change the mask bits
+/// 2. A FIQ vector and a stack for FIQ mode are in place
+///
+/// The function internally uses inline assembly that does not access the stack and preserves
+/// processor flags. It acts as a compiler barrier, memory accesses are not moved across it.
+///
+/// # Assembly
+///
+/// ```asm
+/// cpsie f
+/// ```
+#[inline(always)]
+pub unsafe fn enable_fiq() {
+    unsafe {
+        asm!("cpsie f", options(nostack, preserves_flags));
+    }
+}
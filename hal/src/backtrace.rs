@@ -0,0 +1,109 @@
+//! Registers and frame pointer backtraces for panic reports.
+//!
+//! Everything is built with `-C force-frame-pointers=yes`, so in ARM state
+//! r11 points at a frame record of the caller's r11 followed by the return
+//! address, and the records chain up the stack. Nothing checks the chain is
+//! intact, so [`Frames`] stops at anything outside DRAM, misaligned or not
+//! further up the stack than the last record.
+use crate::asm;
+use crate::dram::{DRAM_END, DRAM_START};
+
+/// Most frames [`Frames`] walks
+const MAX_FRAMES: usize = 32;
+
+const CPSR_MODE_MASK: u32 = 0x1F;
+
+/// Registers of the code that called [`Registers::capture`]
+#[derive(Debug, Clone, Copy)]
+pub struct Registers {
+    pub cpsr: u32,
+    pub sp: u32,
+    pub lr: u32,
+    pub fp: u32,
+}
+
+impl Registers {
+    /// LR is only the caller's return address if nothing was called before
+    /// this in the function
+    #[inline(always)]
+    pub fn capture() -> Self {
+        unsafe {
+            Self {
+                lr: asm::read_lr(),
+                cpsr: asm::read_cpsr(),
+                sp: asm::read_sp(),
+                fp: asm::read_fp(),
+            }
+        }
+    }
+
+    /// Processor mode the CPSR is in
+    pub fn mode(&self) -> &'static str {
+        match self.cpsr & CPSR_MODE_MASK {
+            0x10 => "usr",
+            0x11 => "fiq",
+            0x12 => "irq",
+            0x13 => "svc",
+            0x17 => "abt",
+            0x1B => "und",
+            0x1F => "sys",
+            _ => "?",
+        }
+    }
+}
+
+/// Return addresses up the stack from a frame, the newest first
+pub struct Frames {
+    fp: usize,
+    remaining: usize,
+}
+
+impl Frames {
+    pub fn new(fp: u32) -> Self {
+        Self {
+            fp: fp as usize,
+            remaining: MAX_FRAMES,
+        }
+    }
+}
+
+impl Iterator for Frames {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let record = self.fp;
+        // both words of the record have to be in DRAM
+        let records = DRAM_START..=DRAM_END - 7;
+        if self.remaining == 0 || !record.is_multiple_of(4) || !records.contains(&record) {
+            return None;
+        }
+        self.remaining -= 1;
+        let (caller_fp, lr) = unsafe {
+            let record = record as *const u32;
+            (record.read_volatile(), record.add(1).read_volatile())
+        };
+        // the stack grows down, so older frames are always higher up
+        self.fp = if caller_fp as usize > record {
+            caller_fp as usize
+        } else {
+            0
+        };
+        (lr != 0).then_some(lr)
+    }
+}
+
+/// Print `registers` and the return addresses up the stack from them
+pub fn print(registers: &Registers) {
+    println!(
+        "cpsr 0x{:08x} ({}) sp 0x{:08x} lr 0x{:08x} fp 0x{:08x}",
+        registers.cpsr,
+        registers.mode(),
+        registers.sp,
+        registers.lr,
+        registers.fp
+    );
+    println!("Backtrace:");
+    for (i, address) in Frames::new(registers.fp).enumerate() {
+        println!("  #{:<2} 0x{:08x}", i, address);
+    }
+}
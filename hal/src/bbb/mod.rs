@@ -5,5 +5,7 @@ pub mod dram;
 pub mod eeprom;
 pub mod i2c;
 pub mod mmc;
+pub mod pmu;
 pub mod tps;
 pub mod uart;
+pub mod wdt;
@@ -0,0 +1,47 @@
+//! Clock source from the PMU cycle counter.
+//!
+//! The Cortex-A8 has no generic timer, but its cycle counter only needs CP15
+//! to start, so it can time everything from the first line of `rust_main`.
+//! It ticks once every 64 CPU cycles, which makes the 32-bit counter last
+//! minutes instead of seconds, and [`now`] widens it to 64 bits as long as
+//! it is called at least once per wrap.
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::timer::widen;
+use crate::{asm, interrupts};
+
+/// PMCR bits
+const PMCR_ENABLE: u32 = 1 << 0;
+const PMCR_CYCLE_RESET: u32 = 1 << 2;
+const PMCR_CYCLE_DIV64: u32 = 1 << 3;
+/// PMCNTENSET bit of the cycle counter
+const PMCNTEN_CYCLES: u32 = 1 << 31;
+
+/// MPU clock once `ccm::init` picked the top OPP, slower before that
+const CPU_HZ: u64 = 1_000_000_000;
+pub const TICKS_PER_SECOND: u64 = CPU_HZ / 64;
+
+/// What the last [`now`] returned, the counter in its low half and how
+/// often it wrapped in the high half
+static LAST: AtomicU64 = AtomicU64::new(0);
+
+/// Reset the counter and start it, [`now`] counts from here
+pub fn init() {
+    LAST.store(0, Ordering::Relaxed);
+    unsafe {
+        asm::set_pmcr(PMCR_ENABLE | PMCR_CYCLE_RESET | PMCR_CYCLE_DIV64);
+        asm::set_pmcntenset(PMCNTEN_CYCLES);
+    }
+}
+
+/// Ticks since [`init`]
+pub fn now() -> u64 {
+    // same race as the QEMU clock: a handler reading the counter between
+    // our read and store would leave LAST ahead and fake a wrap
+    interrupts::free(|| {
+        let count = unsafe { asm::read_pmccntr() };
+        let now = widen(LAST.load(Ordering::Relaxed), count);
+        LAST.store(now, Ordering::Relaxed);
+        now
+    })
+}
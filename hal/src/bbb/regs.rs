@@ -6,9 +6,16 @@ pub mod base {
     pub const CONTROL_MODULE_BASE: u32 = 0x44E10000;
     pub const I2C_BASE_ADDR: u32 = 0x44E0_B000;
     pub const UART0_BASE: u32 = 0x44E09000;
+    pub const WDT1_BASE: u32 = 0x44E35000;
     pub const DDR_PHY_CTRL_BASE: u32 = CONTROL_MODULE_BASE + 0x2000;
 }
 
+/// Device tree `compatible` strings of the devices in [`base`]
+pub mod compatible {
+    pub const UART0: &[&str] = &["ti,am3352-uart", "ti,omap3-uart"];
+    pub const MMC0: &[&str] = &["ti,am335-sdhci", "ti,omap4-hsmmc"];
+}
+
 pub mod cm {
     pub const CONTROL_MODULE_CONF_UART0_RXD: u32 = 0x970;
     pub const CONTROL_MODULE_CONF_UART0_TXD: u32 = 0x974;
@@ -1440,3 +1447,18 @@ pub mod control {
     pub const CONTROL_CONF_MMC0_CMD_CONF_MMC0_CMD_SLEWCTRL: u32 = 0x00000040;
     pub const CONTROL_CONF_MMC0_CMD_CONF_MMC0_CMD_SLEWCTRL_SHIFT: u32 = 0x00000006;
 }
+
+pub mod wdt {
+    pub const WDT_WCLR: u32 = 0x24; // Control
+    pub const WDT_WCRR: u32 = 0x28; // Counter
+    pub const WDT_WLDR: u32 = 0x2C; // Load
+    pub const WDT_WTGR: u32 = 0x30; // Trigger
+    pub const WDT_WWPS: u32 = 0x34; // Write Posting Status
+    pub const WDT_WSPR: u32 = 0x48; // Start/Stop
+
+    /// Counter clock, the 32K oscillator with no prescaler
+    pub const WDT_CLOCK_HZ: u32 = 32768;
+    /// WSPR sequences, each write waits for the last to post
+    pub const WDT_START: [u32; 2] = [0xBBBB, 0x4444];
+    pub const WDT_STOP: [u32; 2] = [0xAAAA, 0x5555];
+}
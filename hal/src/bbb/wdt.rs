@@ -0,0 +1,37 @@
+//! AM335x watchdog timer 1, the one the ROM leaves running.
+//!
+//! Every register write is posted, and has to land before the next one to the
+//! same register, which is what [`write`] waits for.
+use super::regs::{base::WDT1_BASE, wdt::*};
+use crate::util::{reg32_read, reg32_write};
+
+pub const MAX_TIMEOUT: u32 = u32::MAX / WDT_CLOCK_HZ;
+
+unsafe fn write(offset: u32, value: u32) {
+    unsafe {
+        reg32_write(WDT1_BASE, offset, value);
+        while reg32_read(WDT1_BASE, WDT_WWPS) != 0 {}
+    }
+}
+
+unsafe fn sequence(values: [u32; 2]) {
+    for value in values {
+        unsafe { write(WDT_WSPR, value) };
+    }
+}
+
+pub fn start(seconds: u32) {
+    // the counter counts up and resets the board when it overflows
+    let load = 0u32.wrapping_sub(seconds.clamp(1, MAX_TIMEOUT) * WDT_CLOCK_HZ);
+    unsafe {
+        sequence(WDT_STOP);
+        write(WDT_WCLR, 0);
+        write(WDT_WLDR, load);
+        write(WDT_WCRR, load);
+        sequence(WDT_START);
+    }
+}
+
+pub fn stop() {
+    unsafe { sequence(WDT_STOP) };
+}
@@ -1,24 +1,60 @@
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use crate::println;
 
 pub use platform::{DRAM_END, DRAM_START};
 
-/// Initialize the DRAM, then run a quick test
+/// End of the DRAM the board has, see [`set_ram`]
+static RAM_END: AtomicUsize = AtomicUsize::new(DRAM_END + 1);
+
+/// DRAM the board actually has, [`DRAM_START`] to [`DRAM_END`] unless
+/// [`set_ram`] found less
+pub fn ram() -> Range<usize> {
+    DRAM_START..RAM_END.load(Ordering::Relaxed)
+}
+
+/// Use the DRAM at `base`, as a device tree's `/memory` node describes it.
+/// The bootloader's layout and page tables are built for the window starting
+/// at [`DRAM_START`], so this can only shrink [`ram`], anything else is
+/// ignored.
+pub fn set_ram(base: u64, size: u64) {
+    if base != DRAM_START as u64 || size == 0 {
+        return;
+    }
+    let end = base.saturating_add(size).min(DRAM_END as u64 + 1);
+    RAM_END.store(end as usize, Ordering::Relaxed);
+}
+
+/// Initialize the DRAM controller. DRAM still holds what it did before a warm
+/// reset until [`simple_memtest`] runs.
 pub fn init() {
     platform::init();
-    simple_memtest();
 }
 
-fn simple_memtest() {
+/// Quick test of all of DRAM the bootloader isn't running from
+pub fn simple_memtest() {
     #[cfg(feature = "bbb")]
-    simple_memtest_from(DRAM_START, DRAM_END);
+    simple_memtest_from(DRAM_START, ram().end - 1);
 
     // On qemu, the bootloader is loaded immediately
     // into dram, we do this so we dont overwrite it
     #[cfg(feature = "qemu")]
-    simple_memtest_from(DRAM_START + 0x20000, DRAM_END);
+    simple_memtest_from(DRAM_START + 0x20000, ram().end - 1);
 }
 
 fn simple_memtest_from(start: usize, end: usize) {
+    let errors = memtest(start, end);
+    if errors == 0 {
+        println!("DRAM test passed!");
+    } else {
+        panic!("DRAM test failed with {} errors", errors);
+    }
+}
+
+/// Run the pattern and walking ones tests over `start..end`, returning the
+/// number of mismatches. Everything in the range is overwritten.
+pub fn memtest(start: usize, end: usize) -> usize {
     let mut errors = 0;
     let size = end - start;
     unsafe {
@@ -96,11 +132,7 @@ fn simple_memtest_from(start: usize, end: usize) {
             println!("Walking ones test failed with {} errors", errors);
         }
     }
-    if errors == 0 {
-        println!("DRAM test passed!");
-    } else {
-        panic!("DRAM test failed with {} errors", errors);
-    }
+    errors
 }
 
 // Platform-specific UART functions
@@ -0,0 +1,56 @@
+//! Ethernet controller, polled.
+//!
+//! Frames are passed without the FCS, the controller appends it on send and
+//! strips it on receive.
+
+/// Largest frame [`send`] accepts and [`receive`] returns, without the FCS
+pub const MAX_FRAME_SIZE: usize = 1514;
+
+pub type MacAddress = [u8; 6];
+
+/// Set up the controller and return its MAC address
+pub fn init() -> Result<MacAddress, EthError> {
+    platform::init()
+}
+
+pub fn send(frame: &[u8]) -> Result<(), EthError> {
+    if frame.len() > MAX_FRAME_SIZE {
+        return Err(EthError::FrameTooLarge);
+    }
+    platform::send(frame)
+}
+
+/// Copy the next good frame into `buf` and return its length, `None` if no
+/// frame is waiting. Frames that don't fit are dropped.
+pub fn receive(buf: &mut [u8; MAX_FRAME_SIZE]) -> Result<Option<usize>, EthError> {
+    platform::receive(buf)
+}
+
+#[derive(Debug)]
+pub enum EthError {
+    FrameTooLarge,
+    /// The transmitter never finished the previous frame
+    TxTimeout,
+    /// The receive FIFO lost frame alignment and was reset
+    RxDesync,
+    Unimplemented,
+}
+
+#[cfg(feature = "qemu")]
+mod platform {
+    pub use crate::qemu::emac::{init, receive, send};
+}
+
+#[cfg(feature = "bbb")]
+mod platform {
+    use super::{EthError, MAX_FRAME_SIZE, MacAddress};
+    pub fn init() -> Result<MacAddress, EthError> {
+        Err(EthError::Unimplemented)
+    }
+    pub fn send(_frame: &[u8]) -> Result<(), EthError> {
+        Err(EthError::Unimplemented)
+    }
+    pub fn receive(_buf: &mut [u8; MAX_FRAME_SIZE]) -> Result<Option<usize>, EthError> {
+        Err(EthError::Unimplemented)
+    }
+}
@@ -0,0 +1,1003 @@
+//! Flattened Device Tree (DTB) parser.
+//!
+//! Works directly on the blob, nothing is copied or allocated. The whole
+//! structure block is checked once in [`Fdt::new`], so walking the tree
+//! afterwards can't run off the end of the blob.
+//!
+//! [`Fdt::write_with_chosen`] copies a blob with `/chosen` properties
+//! replaced, which is all a bootloader needs to hand a tree to a kernel.
+//! [`discover`] finds the devices hal drives in a tree and
+//! [`Devices::apply`] points the drivers at them.
+#[cfg(feature = "bbb")]
+use crate::bbb::regs::compatible;
+#[cfg(feature = "qemu")]
+use crate::qemu::regs::compatible;
+
+const FDT_MAGIC: u32 = 0xD00D_FEED;
+/// Oldest blob layout we understand (the one with `size_dt_struct`)
+const FDT_MIN_VERSION: u32 = 16;
+const FDT_VERSION: u32 = 17;
+const HEADER_SIZE: usize = 40;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// Deepest nesting we track `#address-cells`/`#size-cells` for
+const MAX_DEPTH: usize = 16;
+/// Cell counts the spec gives nodes without `#address-cells`/`#size-cells`
+const DEFAULT_CELLS: Cells = Cells {
+    address: 2,
+    size: 1,
+};
+/// Largest `#interrupt-cells` we decode
+pub const MAX_INTERRUPT_CELLS: usize = 4;
+
+#[derive(Debug)]
+pub enum FdtError {
+    /// Not a device tree blob
+    BadMagic(u32),
+    /// Blob is shorter than its header claims
+    Truncated,
+    /// Blob layout older or newer than we can read
+    UnsupportedVersion(u32),
+    /// Malformed structure block at the given offset
+    BadStructure(usize),
+    /// Nodes nested deeper than [`MAX_DEPTH`]
+    TooDeep,
+    /// Output buffer too small for the rewritten blob
+    NoSpace,
+}
+
+impl core::fmt::Display for FdtError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::BadMagic(magic) => write!(f, "bad magic 0x{:08x}", magic),
+            Self::Truncated => write!(f, "blob is truncated"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported version {}", v),
+            Self::BadStructure(offset) => {
+                write!(f, "malformed structure block at 0x{:x}", offset)
+            }
+            Self::TooDeep => write!(f, "nodes nested deeper than {}", MAX_DEPTH),
+            Self::NoSpace => write!(f, "output buffer too small"),
+        }
+    }
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+const fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// Read a value of `cells` 32-bit cells, at most two
+fn read_cells(data: &[u8], cells: u32) -> Option<u64> {
+    match cells {
+        0 => Some(0),
+        1 => be32(data, 0).map(u64::from),
+        2 => Some((u64::from(be32(data, 0)?) << 32) | u64::from(be32(data, 4)?)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Cells {
+    address: u32,
+    size: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Token<'a> {
+    BeginNode(&'a str),
+    EndNode,
+    Prop(Property<'a>),
+    Nop,
+    End,
+}
+
+/// A validated device tree blob
+#[derive(Clone, Copy)]
+pub struct Fdt<'a> {
+    structs: &'a [u8],
+    strings: &'a [u8],
+    mem_rsvmap: &'a [u8],
+    boot_cpuid: u32,
+}
+
+impl<'a> Fdt<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, FdtError> {
+        let field = |index: usize| be32(data, index * 4).ok_or(FdtError::Truncated);
+        let magic = field(0)?;
+        if magic != FDT_MAGIC {
+            return Err(FdtError::BadMagic(magic));
+        }
+        if data.len() < HEADER_SIZE {
+            return Err(FdtError::Truncated);
+        }
+        let total_size = field(1)? as usize;
+        let off_struct = field(2)? as usize;
+        let off_strings = field(3)? as usize;
+        let off_rsvmap = field(4)? as usize;
+        let version = field(5)?;
+        let last_comp_version = field(6)?;
+        let boot_cpuid = field(7)?;
+        let size_strings = field(8)? as usize;
+        let size_struct = field(9)? as usize;
+
+        if version < FDT_MIN_VERSION || last_comp_version > FDT_VERSION {
+            return Err(FdtError::UnsupportedVersion(version));
+        }
+        let data = data.get(..total_size).ok_or(FdtError::Truncated)?;
+        let block = |offset: usize, size: usize| {
+            offset
+                .checked_add(size)
+                .and_then(|end| data.get(offset..end))
+                .ok_or(FdtError::Truncated)
+        };
+
+        let fdt = Self {
+            structs: block(off_struct, size_struct)?,
+            strings: block(off_strings, size_strings)?,
+            mem_rsvmap: data
+                .get(off_rsvmap..off_struct)
+                .ok_or(FdtError::Truncated)?,
+            boot_cpuid,
+        };
+        fdt.validate()?;
+        Ok(fdt)
+    }
+
+    /// Parse the blob at `ptr`, trusting the size in its header
+    ///
+    /// # Safety
+    /// `ptr` must point to readable memory holding a whole blob if the magic
+    /// matches, and the blob must not change while the result is used.
+    pub unsafe fn from_ptr(ptr: *const u8) -> Result<Fdt<'static>, FdtError> {
+        let header = unsafe { core::slice::from_raw_parts(ptr, 8) };
+        let magic = be32(header, 0).ok_or(FdtError::Truncated)?;
+        if magic != FDT_MAGIC {
+            return Err(FdtError::BadMagic(magic));
+        }
+        let total_size = be32(header, 4).ok_or(FdtError::Truncated)? as usize;
+        Fdt::new(unsafe { core::slice::from_raw_parts(ptr, total_size) })
+    }
+
+    /// Walk the whole structure block once so later walks can't fail
+    fn validate(&self) -> Result<(), FdtError> {
+        let mut offset = 0;
+        let mut depth = 0usize;
+        let mut nodes = 0;
+        loop {
+            let (token, next) = self.token(offset).ok_or(FdtError::BadStructure(offset))?;
+            match token {
+                Token::BeginNode(_) if depth == 0 && nodes > 0 => {
+                    // a second root node
+                    return Err(FdtError::BadStructure(offset));
+                }
+                Token::BeginNode(_) => {
+                    depth += 1;
+                    nodes += 1;
+                    if depth > MAX_DEPTH {
+                        return Err(FdtError::TooDeep);
+                    }
+                }
+                Token::EndNode => {
+                    depth = depth.checked_sub(1).ok_or(FdtError::BadStructure(offset))?;
+                }
+                Token::Prop(_) if depth == 0 => return Err(FdtError::BadStructure(offset)),
+                Token::Prop(_) | Token::Nop => {}
+                Token::End if depth == 0 && nodes > 0 => return Ok(()),
+                Token::End => return Err(FdtError::BadStructure(offset)),
+            }
+            offset = next;
+        }
+    }
+
+    /// Decode the token at `offset`, returning it and the offset of the next one
+    fn token(&self, offset: usize) -> Option<(Token<'a>, usize)> {
+        let structs = self.structs;
+        let body = offset + 4;
+        match be32(structs, offset)? {
+            FDT_BEGIN_NODE => {
+                let len = structs.get(body..)?.iter().position(|&b| b == 0)?;
+                let name = core::str::from_utf8(&structs[body..body + len]).ok()?;
+                Some((Token::BeginNode(name), align4(body + len + 1)))
+            }
+            FDT_END_NODE => Some((Token::EndNode, body)),
+            FDT_PROP => {
+                let len = be32(structs, body)? as usize;
+                let name_offset = be32(structs, body + 4)? as usize;
+                let value = structs.get(body + 8..(body + 8).checked_add(len)?)?;
+                let name_len = self
+                    .strings
+                    .get(name_offset..)?
+                    .iter()
+                    .position(|&b| b == 0)?;
+                let name = core::str::from_utf8(&self.strings[name_offset..name_offset + name_len])
+                    .ok()?;
+                Some((
+                    Token::Prop(Property { name, value }),
+                    align4(body + 8 + len),
+                ))
+            }
+            FDT_NOP => Some((Token::Nop, body)),
+            FDT_END => Some((Token::End, body)),
+            _ => None,
+        }
+    }
+
+    pub fn boot_cpuid(&self) -> u32 {
+        self.boot_cpuid
+    }
+
+    pub fn root(&self) -> Node<'a> {
+        self.nodes().next().expect("validated blob has a root node")
+    }
+
+    /// Every node, depth first, starting with the root
+    pub fn nodes(&self) -> NodeIter<'a> {
+        NodeIter {
+            fdt: *self,
+            offset: 0,
+            depth: 0,
+            cells: [DEFAULT_CELLS; MAX_DEPTH + 1],
+        }
+    }
+
+    /// Look a node up by path, e.g. `/chosen` or `/soc/serial@1c28000`. A
+    /// component without a unit address matches any unit address.
+    pub fn find_node(&self, path: &str) -> Option<Node<'a>> {
+        path.split('/')
+            .filter(|component| !component.is_empty())
+            .try_fold(self.root(), |node, component| {
+                node.children().find(|child| child.matches_name(component))
+            })
+    }
+
+    pub fn find_phandle(&self, phandle: u32) -> Option<Node<'a>> {
+        self.nodes().find(|node| {
+            node.property("phandle")
+                .or_else(|| node.property("linux,phandle"))
+                .and_then(|prop| prop.as_u32())
+                == Some(phandle)
+        })
+    }
+
+    /// First enabled node compatible with any of `compatible`
+    pub fn find_compatible(&self, compatible: &[&str]) -> Option<Node<'a>> {
+        self.nodes()
+            .filter(|node| node.is_enabled())
+            .find(|node| compatible.iter().any(|c| node.is_compatible(c)))
+    }
+
+    pub fn chosen(&self) -> Option<Chosen<'a>> {
+        self.find_node("/chosen").map(|node| Chosen { node })
+    }
+
+    /// `reg` entries of every `/memory` node
+    pub fn memory(&self) -> impl Iterator<Item = Region> + 'a {
+        self.root()
+            .children()
+            .filter(|node| {
+                node.property("device_type").and_then(|p| p.as_str()) == Some("memory")
+                    || node.matches_name("memory")
+            })
+            .flat_map(|node| node.reg().into_iter().flatten())
+    }
+
+    /// Entries of the memory reservation block
+    pub fn reserved_memory(&self) -> impl Iterator<Item = Region> + 'a {
+        let (entries, _) = self.mem_rsvmap.as_chunks::<16>();
+        entries
+            .iter()
+            .map(|entry| Region {
+                address: read_cells(entry, 2).unwrap_or(0),
+                size: read_cells(&entry[8..], 2),
+            })
+            .take_while(|region| region.address != 0 || region.size != Some(0))
+    }
+
+    /// Copy the blob to `out` with `props` set on `/chosen`, replacing any
+    /// existing values and creating the node if there is none. Returns the
+    /// size of the new blob. NOPs are dropped along the way.
+    pub fn write_with_chosen(&self, out: &mut [u8], props: &[Property]) -> Result<usize, FdtError> {
+        let mut writer = Writer { out, len: 0 };
+        writer.put(&[0; HEADER_SIZE])?;
+        let off_rsvmap = writer.len;
+        writer.put(self.mem_rsvmap)?;
+        writer.align(4)?;
+
+        // strings keep their offsets, names not in the table yet go after it
+        let mut strings_len = self.strings.len();
+        let mut name_offsets = [0usize; MAX_CHOSEN_PROPS];
+        if props.len() > MAX_CHOSEN_PROPS {
+            return Err(FdtError::NoSpace);
+        }
+        for (prop, offset) in props.iter().zip(&mut name_offsets) {
+            *offset = match self.string_offset(prop.name) {
+                Some(offset) => offset,
+                None => {
+                    strings_len += prop.name.len() + 1;
+                    strings_len - prop.name.len() - 1
+                }
+            };
+        }
+
+        let off_struct = writer.len;
+        let chosen = |writer: &mut Writer| -> Result<(), FdtError> {
+            for (prop, &name_offset) in props.iter().zip(&name_offsets) {
+                writer.put_prop(name_offset, prop.value)?;
+            }
+            Ok(())
+        };
+        let mut offset = 0;
+        let mut depth = 0usize;
+        let mut in_chosen = false;
+        let mut found_chosen = false;
+        loop {
+            // validate() already walked every token
+            let (token, next) = self.token(offset).ok_or(FdtError::BadStructure(offset))?;
+            let raw = &self.structs[offset..next];
+            offset = next;
+            match token {
+                Token::BeginNode(name) => {
+                    writer.put(raw)?;
+                    depth += 1;
+                    if depth == 2 && name == "chosen" {
+                        in_chosen = true;
+                        found_chosen = true;
+                        chosen(&mut writer)?;
+                    }
+                }
+                Token::EndNode => {
+                    if depth == 1 && !found_chosen {
+                        writer.put_u32(FDT_BEGIN_NODE)?;
+                        writer.put(b"chosen\0")?;
+                        writer.align(4)?;
+                        chosen(&mut writer)?;
+                        writer.put_u32(FDT_END_NODE)?;
+                    }
+                    if depth == 2 {
+                        in_chosen = false;
+                    }
+                    depth -= 1;
+                    writer.put(raw)?;
+                }
+                Token::Prop(prop)
+                    if in_chosen && depth == 2 && props.iter().any(|p| p.name == prop.name) => {}
+                Token::Prop(_) => writer.put(raw)?,
+                Token::Nop => {}
+                Token::End => {
+                    writer.put(raw)?;
+                    break;
+                }
+            }
+        }
+        let size_struct = writer.len - off_struct;
+
+        let off_strings = writer.len;
+        writer.put(self.strings)?;
+        for (prop, &name_offset) in props.iter().zip(&name_offsets) {
+            if name_offset >= self.strings.len() {
+                writer.put(prop.name.as_bytes())?;
+                writer.put(&[0])?;
+            }
+        }
+        let total_size = writer.len;
+
+        let header = [
+            FDT_MAGIC,
+            total_size as u32,
+            off_struct as u32,
+            off_strings as u32,
+            off_rsvmap as u32,
+            FDT_VERSION,
+            FDT_MIN_VERSION,
+            self.boot_cpuid,
+            strings_len as u32,
+            size_struct as u32,
+        ];
+        for (bytes, field) in writer.out.as_chunks_mut::<4>().0.iter_mut().zip(header) {
+            *bytes = field.to_be_bytes();
+        }
+        Ok(total_size)
+    }
+
+    /// Offset of a NUL terminated `name` in the strings block
+    fn string_offset(&self, name: &str) -> Option<usize> {
+        let name = name.as_bytes();
+        self.strings
+            .windows(name.len() + 1)
+            .position(|window| window[..name.len()] == *name && window[name.len()] == 0)
+    }
+
+    /// `#interrupt-cells` of the controller `node`'s interrupts are routed to.
+    /// Only the node itself and the root are searched for `interrupt-parent`.
+    fn interrupt_cells(&self, node: &Node<'a>) -> Option<u32> {
+        let phandle = node
+            .property("interrupt-parent")
+            .or_else(|| self.root().property("interrupt-parent"))?
+            .as_u32()?;
+        self.find_phandle(phandle)?
+            .property("#interrupt-cells")?
+            .as_u32()
+    }
+}
+
+/// Most properties [`Fdt::write_with_chosen`] sets at once
+const MAX_CHOSEN_PROPS: usize = 8;
+
+/// Appends to a blob being built
+struct Writer<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn put(&mut self, bytes: &[u8]) -> Result<(), FdtError> {
+        let end = self.len + bytes.len();
+        self.out
+            .get_mut(self.len..end)
+            .ok_or(FdtError::NoSpace)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn put_u32(&mut self, value: u32) -> Result<(), FdtError> {
+        self.put(&value.to_be_bytes())
+    }
+
+    fn align(&mut self, align: usize) -> Result<(), FdtError> {
+        while !self.len.is_multiple_of(align) {
+            self.put(&[0])?;
+        }
+        Ok(())
+    }
+
+    fn put_prop(&mut self, name_offset: usize, value: &[u8]) -> Result<(), FdtError> {
+        self.put_u32(FDT_PROP)?;
+        self.put_u32(value.len() as u32)?;
+        self.put_u32(name_offset as u32)?;
+        self.put(value)?;
+        self.align(4)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Property<'a> {
+    pub name: &'a str,
+    pub value: &'a [u8],
+}
+
+impl<'a> Property<'a> {
+    pub fn as_u32(&self) -> Option<u32> {
+        match self.value.len() {
+            4 => be32(self.value, 0),
+            _ => None,
+        }
+    }
+
+    /// A one or two cell value
+    pub fn as_u64(&self) -> Option<u64> {
+        read_cells(self.value, (self.value.len() / 4) as u32)
+            .filter(|_| self.value.len().is_multiple_of(4))
+    }
+
+    /// A single NUL terminated string
+    pub fn as_str(&self) -> Option<&'a str> {
+        let (last, value) = self.value.split_last()?;
+        if *last != 0 {
+            return None;
+        }
+        core::str::from_utf8(value).ok()
+    }
+
+    /// A list of NUL terminated strings, like `compatible`
+    pub fn as_str_list(self) -> impl Iterator<Item = &'a str> + 'a {
+        self.value
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| core::str::from_utf8(s).ok())
+    }
+
+    /// The value as big-endian 32-bit cells
+    pub fn cells(self) -> impl Iterator<Item = u32> + 'a {
+        let (cells, _) = self.value.as_chunks::<4>();
+        cells.iter().map(|c| u32::from_be_bytes(*c))
+    }
+}
+
+/// A `reg` entry. `size` is `None` when the parent has `#size-cells = <0>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub address: u64,
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Interrupt {
+    cells: [u32; MAX_INTERRUPT_CELLS],
+    len: usize,
+}
+
+impl Interrupt {
+    /// Raw specifier cells, their meaning depends on the interrupt controller
+    pub fn cells(&self) -> &[u32] {
+        &self.cells[..self.len]
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+    fdt: Fdt<'a>,
+    name: &'a str,
+    /// Offset of the first token after the node's name
+    body: usize,
+    /// `#address-cells`/`#size-cells` of the parent
+    cells: Cells,
+}
+
+impl<'a> Node<'a> {
+    /// Full node name, including the unit address
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    fn matches_name(&self, name: &str) -> bool {
+        self.name == name || (!name.contains('@') && self.name.split('@').next() == Some(name))
+    }
+
+    pub fn properties(&self) -> impl Iterator<Item = Property<'a>> + 'a {
+        let fdt = self.fdt;
+        let mut offset = self.body;
+        core::iter::from_fn(move || {
+            loop {
+                let (token, next) = fdt.token(offset)?;
+                offset = next;
+                match token {
+                    Token::Prop(prop) => return Some(prop),
+                    Token::Nop => continue,
+                    // properties always come before child nodes
+                    _ => return None,
+                }
+            }
+        })
+    }
+
+    pub fn property(&self, name: &str) -> Option<Property<'a>> {
+        self.properties().find(|prop| prop.name == name)
+    }
+
+    /// Cell counts this node's children use for their `reg`
+    fn child_cells(&self) -> Cells {
+        let get = |name| self.property(name).and_then(|p| p.as_u32());
+        Cells {
+            address: get("#address-cells").unwrap_or(DEFAULT_CELLS.address),
+            size: get("#size-cells").unwrap_or(DEFAULT_CELLS.size),
+        }
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = Node<'a>> + 'a {
+        let fdt = self.fdt;
+        let cells = self.child_cells();
+        let mut offset = self.body;
+        let mut depth = 0usize;
+        core::iter::from_fn(move || {
+            loop {
+                let (token, next) = fdt.token(offset)?;
+                offset = next;
+                match token {
+                    Token::BeginNode(name) => {
+                        depth += 1;
+                        if depth == 1 {
+                            return Some(Node {
+                                fdt,
+                                name,
+                                body: next,
+                                cells,
+                            });
+                        }
+                    }
+                    Token::EndNode if depth == 0 => return None,
+                    Token::EndNode => depth -= 1,
+                    Token::End => return None,
+                    Token::Prop(_) | Token::Nop => {}
+                }
+            }
+        })
+    }
+
+    pub fn compatible(&self) -> impl Iterator<Item = &'a str> + 'a {
+        self.property("compatible")
+            .into_iter()
+            .flat_map(|prop| prop.as_str_list())
+    }
+
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.compatible().any(|c| c == compatible)
+    }
+
+    /// Nodes without a `status` property are enabled
+    pub fn is_enabled(&self) -> bool {
+        matches!(
+            self.property("status").and_then(|p| p.as_str()),
+            None | Some("okay") | Some("ok")
+        )
+    }
+
+    /// `reg` entries, `None` without a `reg` property or with cell counts
+    /// wider than 64 bits
+    pub fn reg(&self) -> Option<impl Iterator<Item = Region> + 'a> {
+        let Cells { address, size } = self.cells;
+        if address > 2 || size > 2 {
+            return None;
+        }
+        let entry = (address + size) as usize * 4;
+        let value = self.property("reg")?.value;
+        Some(value.chunks_exact(entry.max(4)).map(move |chunk| Region {
+            address: read_cells(chunk, address).unwrap_or(0),
+            size: (size > 0).then(|| read_cells(&chunk[address as usize * 4..], size).unwrap_or(0)),
+        }))
+    }
+
+    /// Address of the first `reg` entry
+    pub fn base(&self) -> Option<u64> {
+        self.reg()?.next().map(|region| region.address)
+    }
+
+    /// `interrupts` specifiers, split using the interrupt parent's
+    /// `#interrupt-cells`
+    pub fn interrupts(&self) -> Option<impl Iterator<Item = Interrupt> + 'a> {
+        let cells = self.fdt.interrupt_cells(self)? as usize;
+        if cells == 0 || cells > MAX_INTERRUPT_CELLS {
+            return None;
+        }
+        let value = self.property("interrupts")?.value;
+        Some(value.chunks_exact(cells * 4).map(move |chunk| {
+            let mut interrupt = Interrupt {
+                cells: [0; MAX_INTERRUPT_CELLS],
+                len: cells,
+            };
+            let (words, _) = chunk.as_chunks::<4>();
+            for (cell, bytes) in interrupt.cells.iter_mut().zip(words) {
+                *cell = u32::from_be_bytes(*bytes);
+            }
+            interrupt
+        }))
+    }
+}
+
+impl core::fmt::Debug for Node<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Node").field("name", &self.name).finish()
+    }
+}
+
+/// Depth first walk over every node, tracking the cell counts each level uses
+pub struct NodeIter<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+    depth: usize,
+    /// `cells[d]` is what nodes at depth `d` use for their `reg`
+    cells: [Cells; MAX_DEPTH + 1],
+}
+
+impl<'a> Iterator for NodeIter<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Node<'a>> {
+        loop {
+            let (token, next) = self.fdt.token(self.offset)?;
+            self.offset = next;
+            match token {
+                Token::BeginNode(name) => {
+                    let node = Node {
+                        fdt: self.fdt,
+                        name,
+                        body: next,
+                        cells: self.cells[self.depth],
+                    };
+                    self.depth += 1;
+                    // validate() bounds the depth
+                    if let Some(cells) = self.cells.get_mut(self.depth) {
+                        *cells = node.child_cells();
+                    }
+                    return Some(node);
+                }
+                Token::EndNode => self.depth = self.depth.saturating_sub(1),
+                Token::End => return None,
+                Token::Prop(_) | Token::Nop => {}
+            }
+        }
+    }
+}
+
+/// The `/chosen` node
+#[derive(Debug, Clone, Copy)]
+pub struct Chosen<'a> {
+    node: Node<'a>,
+}
+
+impl<'a> Chosen<'a> {
+    pub fn bootargs(&self) -> Option<&'a str> {
+        self.node.property("bootargs")?.as_str()
+    }
+
+    /// Path (or alias) of the console, without any `:options` suffix
+    pub fn stdout_path(&self) -> Option<&'a str> {
+        let path = self.node.property("stdout-path")?.as_str()?;
+        path.split(':').next()
+    }
+
+    /// Initrd range set by a previous stage, start and end address
+    pub fn initrd(&self) -> Option<(u64, u64)> {
+        let start = self.node.property("linux,initrd-start")?.as_u64()?;
+        let end = self.node.property("linux,initrd-end")?.as_u64()?;
+        Some((start, end))
+    }
+}
+
+/// Addresses of the devices the HAL drives, as described by a device tree
+#[derive(Debug, Clone, Copy)]
+pub struct Devices {
+    pub uart0: Option<u32>,
+    pub mmc0: Option<u32>,
+    pub dram: Option<Region>,
+}
+
+impl Devices {
+    /// Point the drivers at the devices found, anything the tree doesn't
+    /// describe keeps its built-in address. Call it before initializing them.
+    pub fn apply(&self) {
+        if let Some(base) = self.uart0 {
+            crate::uart::set_base(base);
+        }
+        if let Some(base) = self.mmc0 {
+            crate::mmc::set_base(base);
+        }
+        if let Some(Region {
+            address,
+            size: Some(size),
+        }) = self.dram
+        {
+            crate::dram::set_ram(address, size);
+        }
+    }
+}
+
+/// Look up the devices this platform's drivers handle, falling back to
+/// nothing when the tree doesn't describe them
+pub fn discover(fdt: &Fdt) -> Devices {
+    let base = |compatible: &[&str]| {
+        fdt.find_compatible(compatible)
+            .and_then(|node| node.base())
+            .and_then(|base| u32::try_from(base).ok())
+    };
+    Devices {
+        uart0: base(compatible::UART0),
+        mmc0: base(compatible::MMC0),
+        dram: fdt.memory().next(),
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// testdata/cubieboard.dts, built with dtc
+    const CUBIEBOARD: &[u8] = include_bytes!("../testdata/cubieboard.dtb");
+
+    fn cubieboard() -> Fdt<'static> {
+        Fdt::new(CUBIEBOARD).unwrap()
+    }
+
+    fn set_be32(blob: &mut [u8], offset: usize, value: u32) {
+        blob[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    #[test]
+    fn walks_the_tree() {
+        let fdt = cubieboard();
+        let root = fdt.root();
+        assert_eq!(root.name(), "");
+        assert_eq!(
+            root.property("model").and_then(|p| p.as_str()),
+            Some("Cubietech Cubieboard")
+        );
+        assert!(root.is_compatible("allwinner,sun4i-a10"));
+        assert_eq!(fdt.boot_cpuid(), 0);
+
+        let names: Vec<_> = root.children().map(|node| node.name()).collect();
+        assert_eq!(
+            names,
+            ["aliases", "chosen", "cpus", "memory@40000000", "soc"]
+        );
+        assert_eq!(fdt.nodes().count(), 13);
+    }
+
+    #[test]
+    fn finds_nodes() {
+        let fdt = cubieboard();
+        let uart = fdt.find_node("/soc/serial@1c28400").unwrap();
+        assert_eq!(uart.base(), Some(0x01c2_8400));
+        // without a unit address the first match wins
+        assert_eq!(
+            fdt.find_node("/soc/serial").unwrap().name(),
+            "serial@1c28000"
+        );
+        assert!(fdt.find_node("/soc/serial@0").is_none());
+        assert!(fdt.find_node("/nothing").is_none());
+
+        assert_eq!(
+            fdt.find_phandle(1).unwrap().name(),
+            "interrupt-controller@1c20400"
+        );
+        assert!(fdt.find_phandle(2).is_none());
+
+        // the disabled MMC and UART are skipped
+        let mmc = fdt.find_compatible(&["allwinner,sun4i-a10-mmc"]).unwrap();
+        assert_eq!(mmc.name(), "mmc@1c0f000");
+        let uart = fdt
+            .find_compatible(&["ns16550a", "snps,dw-apb-uart"])
+            .unwrap();
+        assert_eq!(uart.name(), "serial@1c28000");
+        assert!(fdt.find_compatible(&["allwinner,sun4i-a10-sata"]).is_none());
+    }
+
+    #[test]
+    fn decodes_reg_and_interrupts() {
+        let fdt = cubieboard();
+        let emac = fdt.find_node("/soc/ethernet").unwrap();
+        let regs: Vec<_> = emac.reg().unwrap().collect();
+        assert_eq!(
+            regs,
+            [Region {
+                address: 0x01c0_b000,
+                size: Some(0x1000)
+            }]
+        );
+        let irqs: Vec<_> = emac
+            .interrupts()
+            .unwrap()
+            .map(|irq| irq.cells().to_vec())
+            .collect();
+        assert_eq!(irqs, [[55]]);
+
+        // #size-cells = <0> under /cpus
+        let cpu = fdt.find_node("/cpus/cpu@0").unwrap();
+        let regs: Vec<_> = cpu.reg().unwrap().collect();
+        assert_eq!(
+            regs,
+            [Region {
+                address: 0,
+                size: None
+            }]
+        );
+        assert!(cpu.interrupts().is_none());
+    }
+
+    #[test]
+    fn reads_memory_and_chosen() {
+        let fdt = cubieboard();
+        let memory: Vec<_> = fdt.memory().collect();
+        assert_eq!(
+            memory,
+            [Region {
+                address: 0x4000_0000,
+                size: Some(0x2000_0000)
+            }]
+        );
+        let reserved: Vec<_> = fdt.reserved_memory().collect();
+        assert_eq!(
+            reserved,
+            [Region {
+                address: 0x5ff0_0000,
+                size: Some(0x10_0000)
+            }]
+        );
+
+        let chosen = fdt.chosen().unwrap();
+        assert_eq!(chosen.bootargs(), Some("console=ttyS0,115200"));
+        assert_eq!(chosen.stdout_path(), Some("serial0"));
+        assert_eq!(chosen.initrd(), None);
+    }
+
+    #[test]
+    fn discovers_devices() {
+        let devices = discover(&cubieboard());
+        assert_eq!(devices.uart0, Some(0x01c2_8000));
+        assert_eq!(devices.mmc0, Some(0x01c0_f000));
+        assert_eq!(
+            devices.dram,
+            Some(Region {
+                address: 0x4000_0000,
+                size: Some(0x2000_0000)
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_blobs() {
+        assert!(matches!(Fdt::new(&[]), Err(FdtError::Truncated)));
+        assert!(matches!(
+            Fdt::new(&CUBIEBOARD[..CUBIEBOARD.len() - 1]),
+            Err(FdtError::Truncated)
+        ));
+
+        let mut blob = CUBIEBOARD.to_vec();
+        set_be32(&mut blob, 0, 0xFEED_D00D);
+        assert!(matches!(
+            Fdt::new(&blob),
+            Err(FdtError::BadMagic(0xFEED_D00D))
+        ));
+
+        let mut blob = CUBIEBOARD.to_vec();
+        set_be32(&mut blob, 20, 15);
+        assert!(matches!(
+            Fdt::new(&blob),
+            Err(FdtError::UnsupportedVersion(15))
+        ));
+
+        // a struct block running past the end of the blob
+        let mut blob = CUBIEBOARD.to_vec();
+        set_be32(&mut blob, 36, CUBIEBOARD.len() as u32);
+        assert!(matches!(Fdt::new(&blob), Err(FdtError::Truncated)));
+
+        // an unknown token where the root node should start
+        let mut blob = CUBIEBOARD.to_vec();
+        let off_struct = be32(&blob, 8).unwrap() as usize;
+        set_be32(&mut blob, off_struct, 0x7);
+        assert!(matches!(Fdt::new(&blob), Err(FdtError::BadStructure(0))));
+
+        // the root node never ends
+        let mut blob = CUBIEBOARD.to_vec();
+        let size_struct = be32(&blob, 36).unwrap() as usize;
+        set_be32(&mut blob, off_struct + size_struct - 8, FDT_NOP);
+        assert!(matches!(Fdt::new(&blob), Err(FdtError::BadStructure(_))));
+    }
+
+    #[test]
+    fn rewrites_chosen() {
+        let fdt = cubieboard();
+        let start = 0x4800_0000u32.to_be_bytes();
+        let end = 0x4880_0000u32.to_be_bytes();
+        let props = [
+            Property {
+                name: "bootargs",
+                value: b"console=ttyS0 root=/dev/ram0\0",
+            },
+            Property {
+                name: "linux,initrd-start",
+                value: &start,
+            },
+            Property {
+                name: "linux,initrd-end",
+                value: &end,
+            },
+        ];
+        let mut out = [0; 4096];
+        let size = fdt.write_with_chosen(&mut out, &props).unwrap();
+
+        let new = Fdt::new(&out[..size]).unwrap();
+        let chosen = new.chosen().unwrap();
+        assert_eq!(chosen.bootargs(), Some("console=ttyS0 root=/dev/ram0"));
+        assert_eq!(chosen.initrd(), Some((0x4800_0000, 0x4880_0000)));
+        // everything else comes through untouched
+        assert_eq!(chosen.stdout_path(), Some("serial0"));
+        assert_eq!(new.nodes().count(), fdt.nodes().count());
+        assert_eq!(discover(&new).uart0, Some(0x01c2_8000));
+        assert_eq!(new.reserved_memory().count(), 1);
+
+        assert!(matches!(
+            fdt.write_with_chosen(&mut out[..size - 1], &props),
+            Err(FdtError::NoSpace)
+        ));
+    }
+}
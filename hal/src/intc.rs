@@ -0,0 +1,61 @@
+//! Interrupt controller, routing device interrupt lines to the CPU's IRQ.
+//!
+//! Lines are numbered as the SoC manual numbers them. Everything here takes
+//! a line below [`LINES`], callers check.
+pub use platform::{LINES, MAX_PRIORITY};
+
+/// Disable and mask every line
+pub fn init() {
+    platform::init()
+}
+
+pub fn enable(line: u32) {
+    platform::enable(line)
+}
+
+pub fn disable(line: u32) {
+    platform::disable(line)
+}
+
+/// Hold off IRQs from an enabled `line` until [`unmask`]
+pub fn mask(line: u32) {
+    platform::mask(line)
+}
+
+pub fn unmask(line: u32) {
+    platform::unmask(line)
+}
+
+/// Set `line`'s priority, higher is more urgent
+pub fn set_priority(line: u32, priority: u8) {
+    platform::set_priority(line, priority)
+}
+
+/// The line the IRQ being taken is for, `None` if it went away
+pub fn pending() -> Option<u32> {
+    platform::pending()
+}
+
+#[cfg(feature = "qemu")]
+mod platform {
+    pub use crate::qemu::intc::{
+        LINES, MAX_PRIORITY, disable, enable, init, mask, pending, set_priority, unmask,
+    };
+}
+
+#[cfg(feature = "bbb")]
+mod platform {
+    /// No driver for the AM335x INTC yet, so no line can be used
+    pub const LINES: u32 = 0;
+    pub const MAX_PRIORITY: u8 = 0;
+
+    pub fn init() {}
+    pub fn enable(_line: u32) {}
+    pub fn disable(_line: u32) {}
+    pub fn mask(_line: u32) {}
+    pub fn unmask(_line: u32) {}
+    pub fn set_priority(_line: u32, _priority: u8) {}
+    pub fn pending() -> Option<u32> {
+        None
+    }
+}
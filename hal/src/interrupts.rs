@@ -0,0 +1,54 @@
+//! Masking of IRQs and FIQs on the CPU, as opposed to at the interrupt
+//! controller.
+use crate::asm;
+
+const CPSR_IRQ_MASKED: u32 = 1 << 7;
+const CPSR_FIQ_MASKED: u32 = 1 << 6;
+
+/// Keeps IRQs and FIQs masked while it lives, and on drop unmasks whichever
+/// of them were unmasked when it was made, so guards nest
+pub struct Guard {
+    cpsr: u32,
+}
+
+impl Guard {
+    pub fn new() -> Self {
+        let cpsr = unsafe { asm::read_cpsr() };
+        unsafe { asm::disable_interrupts() };
+        Self { cpsr }
+    }
+}
+
+impl Default for Guard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        // only ever undoes our own masking, so the vectors were in place
+        unsafe {
+            if self.cpsr & CPSR_IRQ_MASKED == 0 {
+                asm::enable_irq();
+            }
+            if self.cpsr & CPSR_FIQ_MASKED == 0 {
+                asm::enable_fiq();
+            }
+        }
+    }
+}
+
+/// Run `f` with IRQs and FIQs masked
+pub fn free<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = Guard::new();
+    f()
+}
+
+/// Unmask IRQs
+///
+/// # Safety
+/// An IRQ vector and an IRQ mode stack must be in place to take them.
+pub unsafe fn enable_irq() {
+    unsafe { asm::enable_irq() };
+}
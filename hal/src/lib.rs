@@ -1,8 +1,16 @@
 #![no_std]
-#![cfg_attr(test, no_main)]
-#![cfg_attr(test, feature(custom_test_frameworks))]
-#![cfg_attr(test, test_runner(crate::test_runner))]
-#![cfg_attr(test, reexport_test_harness_main = "test_main")]
+#![cfg_attr(all(test, target_os = "none"), no_main)]
+#![cfg_attr(all(test, target_os = "none"), feature(custom_test_frameworks))]
+#![cfg_attr(all(test, target_os = "none"), test_runner(crate::test_runner))]
+#![cfg_attr(
+    all(test, target_os = "none"),
+    reexport_test_harness_main = "test_main"
+)]
+
+// unit tests run on the host with std, `make test`
+#[cfg(all(test, not(target_os = "none")))]
+extern crate std;
+
 pub mod asm;
 // register module
 
@@ -11,13 +19,22 @@ pub mod asm;
 pub mod macros;
 
 // component modules
+pub mod backtrace;
 pub mod board;
 pub mod ccm;
 pub mod dram;
+pub mod eth;
+pub mod fdt;
 pub mod i2c;
+pub mod intc;
+pub mod interrupts;
 pub mod mmc;
 pub mod mmu;
+pub mod sata;
+pub mod semihosting;
+pub mod timer;
 pub mod uart;
+pub mod watchdog;
 
 // utilities
 pub use uart::Writer;
@@ -29,17 +46,17 @@ pub mod bbb;
 #[cfg(feature = "qemu")]
 pub mod qemu;
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "none"))]
 fn test_runner(tests: &[&dyn Fn()]) {
     for test in tests {
         test(); // Simply run each test
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "none"))]
 use core::panic::PanicInfo;
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "none"))]
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     loop {} // Halt the system on panic
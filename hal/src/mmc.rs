@@ -3,11 +3,22 @@ pub fn init() -> Result<(), MMCError> {
     Ok(())
 }
 
+/// Drive an MMC0 found at `base`, e.g. by [`crate::fdt::discover`]. Call it
+/// before [`init`].
+pub fn set_base(base: u32) {
+    platform::set_base(base);
+}
+
 pub fn read_sector(sector: u32, buffer: &mut [u8; 512]) -> Result<(), MMCError> {
     platform::read_sector(sector, buffer)?;
     Ok(())
 }
 
+pub fn write_sector(sector: u32, buffer: &[u8; 512]) -> Result<(), MMCError> {
+    platform::write_sector(sector, buffer)?;
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum MMCError {
     NoResponse,
@@ -18,14 +29,20 @@ pub enum MMCError {
 
 #[cfg(feature = "qemu")]
 mod platform {
-    pub use crate::qemu::mmc::{init, read_sector};
+    pub use crate::qemu::mmc::{init, read_sector, set_base, write_sector};
 }
 
 #[cfg(feature = "bbb")]
 mod platform {
     use super::MMCError;
     pub use crate::bbb::mmc::init;
+    /// The AM335x ROM starts the bootloader without a device tree, MMC0
+    /// never moves
+    pub fn set_base(_base: u32) {}
     pub fn read_sector(_sector: u32, _buffer: &mut [u8; 512]) -> Result<(), MMCError> {
         todo!()
     }
+    pub fn write_sector(_sector: u32, _buffer: &[u8; 512]) -> Result<(), MMCError> {
+        Err(MMCError::Unimplemented)
+    }
 }
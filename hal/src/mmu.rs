@@ -4,7 +4,8 @@ use core::cell::UnsafeCell;
 use core::fmt;
 use core::mem::size_of;
 
-use crate::{dram, mmu};
+use crate::dram::{DRAM_END, DRAM_START};
+use crate::mmu;
 
 const VIRT_MEM_START: u32 = 0x8000_0000;
 const VIRT_DRAM_START: u32 = 0x8000_0000;
@@ -30,6 +31,7 @@ const RAW_TEX_XR: u32 = 0b001;
 const RAW_TEX_XRW: u32 = 0b010;
 const RAW_TEX_XRWB: u32 = 0b011;
 
+const L1_TEX_SHIFT: u32 = 12;
 const L1_AP_SHIFT: u32 = 10;
 const L1_AP2_SHIFT: u32 = 15;
 
@@ -38,6 +40,7 @@ const L2_AP2_SHIFT: u32 = 9;
 
 pub const L1_SHAREABLE: u32 = 1 << 16;
 pub const L1_CACHEABLE: u32 = 1 << 3;
+pub const L1_BUFFERABLE: u32 = 1 << 2;
 pub const L1_NOT_GLOBAL: u32 = 1 << 17;
 pub const L1_GLOBAL: u32 = 0 << 17;
 pub const L1_NON_SECURE: u32 = 1 << 19;
@@ -53,11 +56,23 @@ pub const L1_ACCESS_RW_RW: u32 = (RAW_AP_RW_RW << L1_AP_SHIFT) | (RAW_AP2_0 << L
 pub const L1_ACCESS_RO_NO: u32 = (RAW_AP_RW_NO << L1_AP_SHIFT) | (RAW_AP2_1 << L1_AP2_SHIFT);
 pub const L1_ACCESS_RO_RO: u32 = (RAW_AP_RW_RW << L1_AP_SHIFT) | (RAW_AP2_1 << L1_AP2_SHIFT);
 
-pub const L1_KERNEL_CODE_FLAGS: u32 =
-    L1_ACCESS_RO_NO | L1_ACCESS_X | L1_SHAREABLE | L1_CACHEABLE | L1_GLOBAL;
+/// Memory types, from the TEX, C and B bits. Every access to strongly ordered
+/// memory completes in order before the next one starts, which is what
+/// everything got before memory types were set.
+pub const L1_STRONGLY_ORDERED: u32 = 0;
+/// Shareable device memory, for peripheral registers. Accesses are never
+/// cached, merged or repeated, but writes may be buffered.
+pub const L1_DEVICE: u32 = L1_BUFFERABLE;
+/// Normal memory, write-back write-allocate in the inner and outer caches.
+/// Not shareable, there is one core and nothing else snoops its caches, so
+/// DMA buffers need [`clean_d_cache_range`] and [`invalidate_d_cache_range`].
+/// Every mapping of the same DRAM must use it, mismatched aliases aren't
+/// coherent.
+pub const L1_NORMAL_WB: u32 = (0b001 << L1_TEX_SHIFT) | L1_CACHEABLE | L1_BUFFERABLE;
 
-pub const L1_KERNEL_DATA_FLAGS: u32 =
-    L1_ACCESS_RW_NO | L1_ACCESS_NX | L1_SHAREABLE | L1_CACHEABLE | L1_GLOBAL;
+pub const L1_KERNEL_CODE_FLAGS: u32 = L1_ACCESS_RO_NO | L1_ACCESS_X | L1_NORMAL_WB | L1_GLOBAL;
+
+pub const L1_KERNEL_DATA_FLAGS: u32 = L1_ACCESS_RW_NO | L1_ACCESS_NX | L1_NORMAL_WB | L1_GLOBAL;
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -155,20 +170,77 @@ pub fn clear_boot_tables() {
     }
 }
 
-// for now, just map everything and 1MB of kernel space
-pub fn init(kernel_base: u32) {
+pub const SECTION_SIZE: u32 = 0x10_0000;
+
+/// Identity map all 4GB, the kernel is mapped by the loader. DRAM is normal
+/// write-back memory, the platform's peripheral windows are device memory
+/// that can't be executed, and the rest, ROM and SRAM, stays strongly ordered.
+pub fn init() {
     clear_boot_tables();
     set_domains();
 
-    // for now, map everything, no caching
     let tables = get_boot_tables();
     for (i, entry) in tables.iter_mut().enumerate() {
-        entry.map_section(i as u32 * 0x100000, L1_ACCESS_RW_RW);
+        let section = i as u32 * SECTION_SIZE;
+        entry.map_section(section, L1_ACCESS_RW_RW | memory_type(section));
     }
+}
 
-    // for now, map the first 1MB of kernel space to dram
-    let first_page = mmu::get_boot_entry_at_virt(kernel_base);
-    first_page.map_section(dram::DRAM_START as u32, mmu::L1_KERNEL_CODE_FLAGS);
+/// Memory type flags for the identity mapped section at `section`
+fn memory_type(section: u32) -> u32 {
+    let section = section as usize;
+    if (DRAM_START..=DRAM_END).contains(&section) {
+        L1_NORMAL_WB
+    } else if platform::PERIPHERALS
+        .iter()
+        .any(|&(base, size)| (base..base + size).contains(&section))
+    {
+        L1_DEVICE | L1_ACCESS_NX
+    } else {
+        L1_STRONGLY_ORDERED
+    }
+}
+
+/// Map `size` bytes at `virt` to `phys` using 1MB sections.
+///
+/// Both addresses must sit at the same offset within a section, the range is
+/// widened to whole sections. Safe to call with the MMU and data cache
+/// already enabled.
+pub fn map_range(virt: u32, phys: u32, size: u32, flags: u32) {
+    assert_eq!(
+        virt & !SECTION_ADDR_MASK,
+        phys & !SECTION_ADDR_MASK,
+        "virt and phys must share a section offset"
+    );
+    if size == 0 {
+        return;
+    }
+
+    let first = virt & SECTION_ADDR_MASK;
+    let last = (virt + (size - 1)) & SECTION_ADDR_MASK;
+    let phys_base = phys & SECTION_ADDR_MASK;
+
+    let mut section = first;
+    loop {
+        let entry = mmu::get_boot_entry_at_virt(section);
+        entry.map_section(phys_base + (section - first), flags);
+        // table walks don't look in the data cache, the descriptor has to be
+        // in DRAM before the stale TLB entry goes
+        clean_d_cache_range(
+            entry as *const L1PageTableEntry as usize,
+            size_of::<L1PageTableEntry>(),
+        );
+        unsafe { asm::flush_tlb_entry(section) };
+        if section == last {
+            break;
+        }
+        section += SECTION_SIZE;
+    }
+
+    unsafe {
+        asm::dsb();
+        asm::isb();
+    }
 }
 
 pub fn enable() {
@@ -183,11 +255,82 @@ pub fn enable() {
     }
 }
 
+/// Turn the MMU and caches off, writing everything the data cache holds back
+/// to DRAM first, so whatever runs next finds it there
 pub fn disable() {
     unsafe {
-        asm::mmu_disable();
+        // nothing new may be cached while it is being cleaned
         asm::d_cache_disable();
+        clean_invalidate_d_cache();
+        asm::mmu_disable();
         asm::i_cache_disable();
+        asm::flush_i_cache();
+        asm::isb();
+    }
+}
+
+/// Smallest data cache line, the step of maintenance by address
+fn d_cache_line_size() -> usize {
+    // CTR.DminLine is log2 of the line size in words
+    4 << ((unsafe { asm::read_ctr() } >> 16) & 0xF)
+}
+
+/// Every cache line address covering `start..start + len`
+fn d_cache_lines(start: usize, len: usize) -> impl Iterator<Item = u32> {
+    let line = d_cache_line_size();
+    let first = start & !(line - 1);
+    (first..start + len)
+        .step_by(line)
+        .map(|address| address as u32)
+}
+
+/// Write `len` bytes from `start` back to DRAM if they are dirty in the data
+/// cache, before something reads them without it, like a DMA master
+pub fn clean_d_cache_range(start: usize, len: usize) {
+    for line in d_cache_lines(start, len) {
+        unsafe { asm::clean_d_cache_line(line) };
+    }
+    unsafe { asm::dsb() };
+}
+
+/// Drop `len` bytes from `start` from the data cache, after something wrote
+/// them without it. Lines only partly in the range lose their other data too,
+/// so DMA buffers should be cache line aligned.
+pub fn invalidate_d_cache_range(start: usize, len: usize) {
+    for line in d_cache_lines(start, len) {
+        unsafe { asm::invalidate_d_cache_line(line) };
+    }
+    unsafe { asm::dsb() };
+}
+
+/// Clean and invalidate every data and unified cache level by set/way, up to
+/// the level of coherency, so DRAM holds everything written through them
+pub fn clean_invalidate_d_cache() {
+    unsafe {
+        let clidr = asm::read_clidr();
+        let levels = (clidr >> 24) & 0b111;
+        for level in 0..levels {
+            // 0 is no cache, 1 an instruction cache only
+            if (clidr >> (level * 3)) & 0b111 < 2 {
+                continue;
+            }
+            asm::set_csselr(level << 1);
+            asm::isb();
+            let ccsidr = asm::read_ccsidr();
+            let line_shift = (ccsidr & 0b111) + 4;
+            let ways = ((ccsidr >> 3) & 0x3FF) + 1;
+            let sets = ((ccsidr >> 13) & 0x7FFF) + 1;
+            // the way goes in the top bits, however many it takes
+            let way_shift = (ways - 1).leading_zeros();
+            for way in 0..ways {
+                for set in 0..sets {
+                    let way = way.checked_shl(way_shift).unwrap_or(0);
+                    asm::clean_invalidate_d_cache_line(way | set << line_shift | level << 1);
+                }
+            }
+        }
+        asm::dsb();
+        asm::isb();
     }
 }
 
@@ -197,3 +340,22 @@ pub fn set_domains() {
         asm::set_dacr(0x1);
     }
 }
+
+#[cfg(feature = "qemu")]
+mod platform {
+    /// Base and size of the A10's I/O window, every peripheral from the SRAM
+    /// controller to the UARTs
+    pub const PERIPHERALS: &[(usize, usize)] = &[(0x01C0_0000, 0x40_0000)];
+}
+
+#[cfg(feature = "bbb")]
+mod platform {
+    /// Base and size of the AM335x L4 wakeup, peripheral and fast
+    /// interconnects, and the EMIF
+    pub const PERIPHERALS: &[(usize, usize)] = &[
+        (0x44C0_0000, 0x40_0000),
+        (0x4800_0000, 0x100_0000),
+        (0x4A00_0000, 0x100_0000),
+        (0x4C00_0000, 0x100_0000),
+    ];
+}
@@ -0,0 +1,284 @@
+//! Allwinner A10 AHCI SATA controller, port 0 only.
+//!
+//! Commands go through slot 0 of the command list with a single PRD entry
+//! pointing at a bounce buffer, one sector at a time. All of it lives in
+//! [`DMA`], which is cleaned from the data cache before every command and
+//! invalidated after it, the controller doesn't snoop the cache. The PHY
+//! calibration real A10s need in the vendor registers is left out, QEMU has
+//! nothing behind them.
+use core::cell::UnsafeCell;
+use core::sync::atomic::{Ordering, fence};
+use core::time::Duration;
+
+use super::regs::{ahci::*, base::AHCI_BASE};
+use crate::mmu;
+use crate::sata::{DiskInfo, SECTOR_SIZE, SataError};
+use crate::timer::Instant;
+use crate::util::{reg32_read, reg32_write};
+
+/// How long the controller or disk gets before it is given up on
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+const FIS_TYPE_REG_H2D: u8 = 0x27;
+/// Register FIS carries a command, not a device control update
+const FIS_H2D_COMMAND: u8 = 1 << 7;
+/// Words in a host to device register FIS
+const FIS_H2D_WORDS: u32 = 5;
+/// FIS device field, the address is an LBA
+const ATA_DEVICE_LBA: u8 = 1 << 6;
+
+const CMD_HEADER_WRITE: u32 = 1 << 6;
+const CMD_HEADER_PRDTL_SHIFT: u32 = 16;
+
+mod ata {
+    pub const READ_DMA_EXT: u8 = 0x25;
+    pub const WRITE_DMA_EXT: u8 = 0x35;
+    pub const IDENTIFY_DEVICE: u8 = 0xEC;
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CommandHeader {
+    /// FIS length in words, direction and PRDT length
+    flags: u32,
+    /// Bytes transferred, updated by the controller
+    prdbc: u32,
+    ctba: u32,
+    ctbau: u32,
+    reserved: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PrdEntry {
+    dba: u32,
+    dbau: u32,
+    reserved: u32,
+    /// Byte count minus one
+    dbc: u32,
+}
+
+#[repr(C, align(128))]
+struct CommandTable {
+    cfis: [u8; 64],
+    acmd: [u8; 16],
+    reserved: [u8; 48],
+    prdt: [PrdEntry; 1],
+}
+
+/// Everything the controller reads or writes, aligned as AHCI requires
+#[repr(C, align(1024))]
+struct DmaArea {
+    /// Command list, only slot 0 is used
+    commands: [CommandHeader; 32],
+    /// Received FIS area
+    fis: [u8; 256],
+    table: CommandTable,
+    /// Every transfer goes through here, callers' buffers may be misaligned
+    buffer: [u8; SECTOR_SIZE],
+}
+
+const EMPTY_HEADER: CommandHeader = CommandHeader {
+    flags: 0,
+    prdbc: 0,
+    ctba: 0,
+    ctbau: 0,
+    reserved: [0; 4],
+};
+
+/// Only ever touched from the boot CPU with interrupts off
+struct Dma(UnsafeCell<DmaArea>);
+
+unsafe impl Sync for Dma {}
+
+static DMA: Dma = Dma(UnsafeCell::new(DmaArea {
+    commands: [EMPTY_HEADER; 32],
+    fis: [0; 256],
+    table: CommandTable {
+        cfis: [0; 64],
+        acmd: [0; 16],
+        reserved: [0; 48],
+        prdt: [PrdEntry {
+            dba: 0,
+            dbau: 0,
+            reserved: 0,
+            dbc: 0,
+        }],
+    },
+    buffer: [0; SECTOR_SIZE],
+}));
+
+fn dma() -> &'static mut DmaArea {
+    unsafe { &mut *DMA.0.get() }
+}
+
+unsafe fn port_read(reg: u32) -> u32 {
+    unsafe { reg32_read(AHCI_BASE, AHCI_PORT_BASE + reg) }
+}
+
+unsafe fn port_write(reg: u32, value: u32) {
+    unsafe { reg32_write(AHCI_BASE, AHCI_PORT_BASE + reg, value) }
+}
+
+/// Poll the register at `offset` until its `mask` bits read `value`
+unsafe fn wait(offset: u32, mask: u32, value: u32) -> Result<(), SataError> {
+    let end = Instant::now() + TIMEOUT;
+    while Instant::now() < end {
+        if unsafe { reg32_read(AHCI_BASE, offset) } & mask == value {
+            return Ok(());
+        }
+    }
+    Err(SataError::Timeout)
+}
+
+/// Stop port 0 processing its command list, and receiving FISes with it
+unsafe fn stop_port(fis_receive: bool) -> Result<(), SataError> {
+    unsafe {
+        let mut cmd = port_read(AHCI_PX_CMD) & !AHCI_PX_CMD_ST;
+        let mut running = AHCI_PX_CMD_CR;
+        if fis_receive {
+            cmd &= !AHCI_PX_CMD_FRE;
+            running |= AHCI_PX_CMD_FR;
+        }
+        port_write(AHCI_PX_CMD, cmd);
+        wait(AHCI_PORT_BASE + AHCI_PX_CMD, running, 0)
+    }
+}
+
+pub fn init() -> Result<DiskInfo, SataError> {
+    unsafe {
+        reg32_write(AHCI_BASE, AHCI_GHC, AHCI_GHC_AE);
+        reg32_write(AHCI_BASE, AHCI_GHC, AHCI_GHC_AE | AHCI_GHC_HR);
+        wait(AHCI_GHC, AHCI_GHC_HR, 0)?;
+        reg32_write(AHCI_BASE, AHCI_GHC, AHCI_GHC_AE);
+        if reg32_read(AHCI_BASE, AHCI_PI) & 1 == 0 {
+            return Err(SataError::NoPort);
+        }
+
+        // the command list and FIS area can only move while the port is idle
+        stop_port(true)?;
+        let dma = dma();
+        port_write(AHCI_PX_CLB, dma.commands.as_ptr() as u32);
+        port_write(AHCI_PX_CLBU, 0);
+        port_write(AHCI_PX_FB, dma.fis.as_ptr() as u32);
+        port_write(AHCI_PX_FBU, 0);
+        port_write(AHCI_PX_IE, 0);
+        port_write(AHCI_PX_SERR, u32::MAX);
+        port_write(AHCI_PX_IS, u32::MAX);
+        port_write(
+            AHCI_PX_CMD,
+            AHCI_PX_CMD_SUD | AHCI_PX_CMD_POD | AHCI_PX_CMD_FRE,
+        );
+
+        wait(
+            AHCI_PORT_BASE + AHCI_PX_SSTS,
+            AHCI_PX_SSTS_DET_MASK,
+            AHCI_PX_SSTS_DET_PRESENT,
+        )
+        .map_err(|_| SataError::NoDisk)?;
+        wait(
+            AHCI_PORT_BASE + AHCI_PX_TFD,
+            AHCI_PX_TFD_BSY | AHCI_PX_TFD_DRQ,
+            0,
+        )?;
+        let signature = port_read(AHCI_PX_SIG);
+        if signature != AHCI_SIG_ATA {
+            return Err(SataError::NotAta(signature));
+        }
+
+        port_write(AHCI_PX_CMD, port_read(AHCI_PX_CMD) | AHCI_PX_CMD_ST);
+        command(ata::IDENTIFY_DEVICE, 0, false)?;
+    }
+
+    let id = &dma().buffer;
+    let word = |index: usize| u16::from_le_bytes([id[2 * index], id[2 * index + 1]]);
+    let lba48 = word(83) & (1 << 10) != 0;
+    let sectors = if lba48 {
+        (0..4).fold(0, |sectors, i| sectors | (word(100 + i) as u64) << (16 * i))
+    } else {
+        word(60) as u64 | (word(61) as u64) << 16
+    };
+    // two characters per word, the first in the high byte
+    let mut model = [0u8; 40];
+    for (i, pair) in model.as_chunks_mut::<2>().0.iter_mut().enumerate() {
+        *pair = word(27 + i).to_be_bytes();
+    }
+    Ok(DiskInfo::new(sectors, model))
+}
+
+/// Run `command` on sector `lba` through slot 0, moving one sector between
+/// the disk and the bounce buffer
+unsafe fn command(command: u8, lba: u64, write: bool) -> Result<(), SataError> {
+    let dma = dma();
+    let direction = if write { CMD_HEADER_WRITE } else { 0 };
+    dma.commands[0] = CommandHeader {
+        flags: FIS_H2D_WORDS | direction | 1 << CMD_HEADER_PRDTL_SHIFT,
+        ctba: &raw const dma.table as u32,
+        ..EMPTY_HEADER
+    };
+
+    let fis = &mut dma.table.cfis;
+    fis.fill(0);
+    fis[0] = FIS_TYPE_REG_H2D;
+    fis[1] = FIS_H2D_COMMAND;
+    fis[2] = command;
+    let lba = lba.to_le_bytes();
+    fis[4..7].copy_from_slice(&lba[0..3]);
+    fis[7] = ATA_DEVICE_LBA;
+    fis[8..11].copy_from_slice(&lba[3..6]);
+    fis[12] = 1; // sector count
+    dma.table.prdt[0] = PrdEntry {
+        dba: dma.buffer.as_ptr() as u32,
+        dbau: 0,
+        reserved: 0,
+        dbc: SECTOR_SIZE as u32 - 1,
+    };
+
+    let area = &raw const *dma as usize;
+    // the command has to be in memory before the controller fetches it
+    fence(Ordering::SeqCst);
+    mmu::clean_d_cache_range(area, size_of::<DmaArea>());
+    unsafe {
+        port_write(AHCI_PX_IS, u32::MAX);
+        port_write(AHCI_PX_CI, 1);
+
+        let end = Instant::now() + TIMEOUT;
+        while Instant::now() < end {
+            let failed = port_read(AHCI_PX_IS) & AHCI_PX_IS_TFES != 0;
+            if failed || port_read(AHCI_PX_CI) & 1 == 0 {
+                mmu::invalidate_d_cache_range(area, size_of::<DmaArea>());
+                fence(Ordering::SeqCst);
+                let status = port_read(AHCI_PX_TFD);
+                if failed || status & AHCI_PX_TFD_ERR != 0 {
+                    recover()?;
+                    return Err(SataError::DeviceError(status));
+                }
+                return Ok(());
+            }
+        }
+        recover()?;
+    }
+    Err(SataError::Timeout)
+}
+
+/// Restart port 0 after a failed command, clearing its error state
+unsafe fn recover() -> Result<(), SataError> {
+    unsafe {
+        stop_port(false)?;
+        port_write(AHCI_PX_SERR, u32::MAX);
+        port_write(AHCI_PX_IS, u32::MAX);
+        port_write(AHCI_PX_CMD, port_read(AHCI_PX_CMD) | AHCI_PX_CMD_ST);
+    }
+    Ok(())
+}
+
+pub fn read_sector(sector: u32, buffer: &mut [u8; SECTOR_SIZE]) -> Result<(), SataError> {
+    unsafe { command(ata::READ_DMA_EXT, sector as u64, false)? };
+    buffer.copy_from_slice(&dma().buffer);
+    Ok(())
+}
+
+pub fn write_sector(sector: u32, buffer: &[u8; SECTOR_SIZE]) -> Result<(), SataError> {
+    dma().buffer.copy_from_slice(buffer);
+    unsafe { command(ata::WRITE_DMA_EXT, sector as u64, true) }
+}
@@ -0,0 +1,145 @@
+//! Allwinner A10 EMAC, FIFO mode on TX channel 0.
+//!
+//! Every received frame comes out of `EMAC_RX_IO_DATA` as [`EMAC_RX_MAGIC`],
+//! a length and status word, then the frame and its FCS padded to whole
+//! words.
+use core::time::Duration;
+
+use super::regs::{base::EMAC_BASE, emac::*};
+use crate::eth::{EthError, MAX_FRAME_SIZE, MacAddress};
+use crate::timer::Instant;
+use crate::util::{reg32_read, reg32_write};
+
+/// QEMU's default NIC address, used when nothing set one in the controller
+const DEFAULT_MAC: MacAddress = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+/// Frames shorter than this are padded, the FCS makes up the 64 byte minimum
+const MIN_FRAME_SIZE: usize = 60;
+/// How long the previous frame may take to go out before it is considered
+/// stuck
+const TX_TIMEOUT: Duration = Duration::from_millis(100);
+
+pub fn init() -> Result<MacAddress, EthError> {
+    unsafe {
+        reg32_write(EMAC_BASE, EMAC_CTL, 0);
+        reg32_write(EMAC_BASE, EMAC_MAC_CTL0, EMAC_MAC_CTL0_SOFT_RESET);
+        reg32_write(EMAC_BASE, EMAC_MAC_CTL0, 0);
+
+        let high = reg32_read(EMAC_BASE, EMAC_MAC_A0);
+        let low = reg32_read(EMAC_BASE, EMAC_MAC_A1);
+        let mut mac = [
+            (high >> 16) as u8,
+            (high >> 8) as u8,
+            high as u8,
+            (low >> 16) as u8,
+            (low >> 8) as u8,
+            low as u8,
+        ];
+        if mac == [0; 6] {
+            mac = DEFAULT_MAC;
+            reg32_write(
+                EMAC_BASE,
+                EMAC_MAC_A0,
+                u32::from_be_bytes([0, mac[0], mac[1], mac[2]]),
+            );
+            reg32_write(
+                EMAC_BASE,
+                EMAC_MAC_A1,
+                u32::from_be_bytes([0, mac[3], mac[4], mac[5]]),
+            );
+        }
+
+        reg32_write(
+            EMAC_BASE,
+            EMAC_MAC_CTL1,
+            EMAC_MAC_CTL1_CRC | EMAC_MAC_CTL1_PAD,
+        );
+        reg32_write(EMAC_BASE, EMAC_TX_MODE, 0);
+        reg32_write(
+            EMAC_BASE,
+            EMAC_RX_CTL,
+            EMAC_RX_CTL_ACCEPT_UNICAST | EMAC_RX_CTL_DA_FILTER | EMAC_RX_CTL_ACCEPT_BROADCAST,
+        );
+        // polled, but clear anything left over
+        reg32_write(EMAC_BASE, EMAC_INT_CTL, 0);
+        reg32_write(EMAC_BASE, EMAC_INT_STA, reg32_read(EMAC_BASE, EMAC_INT_STA));
+
+        reg32_write(
+            EMAC_BASE,
+            EMAC_CTL,
+            EMAC_CTL_RESET | EMAC_CTL_TX_EN | EMAC_CTL_RX_EN,
+        );
+        Ok(mac)
+    }
+}
+
+pub fn send(frame: &[u8]) -> Result<(), EthError> {
+    unsafe {
+        let end = Instant::now() + TX_TIMEOUT;
+        while reg32_read(EMAC_BASE, EMAC_TX_CTL0) & EMAC_TX_CTL_START != 0 {
+            if Instant::now() >= end {
+                return Err(EthError::TxTimeout);
+            }
+        }
+
+        reg32_write(EMAC_BASE, EMAC_TX_INS, 0);
+        let (words, rest) = frame.as_chunks::<4>();
+        for word in words {
+            reg32_write(EMAC_BASE, EMAC_TX_IO_DATA, u32::from_le_bytes(*word));
+        }
+        let mut last = [0u8; 4];
+        last[..rest.len()].copy_from_slice(rest);
+        if !rest.is_empty() {
+            reg32_write(EMAC_BASE, EMAC_TX_IO_DATA, u32::from_le_bytes(last));
+        }
+        // zero words up to the minimum, the length below keeps the padding
+        // in the frame and the odd bytes of the last word out of it
+        let mut written = frame.len().next_multiple_of(4);
+        while written < MIN_FRAME_SIZE {
+            reg32_write(EMAC_BASE, EMAC_TX_IO_DATA, 0);
+            written += 4;
+        }
+
+        reg32_write(
+            EMAC_BASE,
+            EMAC_TX_PL0,
+            frame.len().max(MIN_FRAME_SIZE) as u32,
+        );
+        reg32_write(EMAC_BASE, EMAC_TX_CTL0, EMAC_TX_CTL_START);
+    }
+    Ok(())
+}
+
+pub fn receive(buf: &mut [u8; MAX_FRAME_SIZE]) -> Result<Option<usize>, EthError> {
+    unsafe {
+        while reg32_read(EMAC_BASE, EMAC_RX_FBC) != 0 {
+            if reg32_read(EMAC_BASE, EMAC_RX_IO_DATA) != EMAC_RX_MAGIC {
+                // start over with an empty FIFO
+                reg32_write(EMAC_BASE, EMAC_CTL, EMAC_CTL_RESET | EMAC_CTL_TX_EN);
+                reg32_write(
+                    EMAC_BASE,
+                    EMAC_CTL,
+                    EMAC_CTL_RESET | EMAC_CTL_TX_EN | EMAC_CTL_RX_EN,
+                );
+                return Err(EthError::RxDesync);
+            }
+            let header = reg32_read(EMAC_BASE, EMAC_RX_IO_DATA);
+            let len = (header & 0xFFFF) as usize;
+            let status = header >> EMAC_RX_STATUS_SHIFT;
+
+            // the whole frame has to come out of the FIFO, kept or not
+            for offset in (0..len).step_by(4) {
+                let word = reg32_read(EMAC_BASE, EMAC_RX_IO_DATA).to_le_bytes();
+                let end = (offset + 4).min(MAX_FRAME_SIZE);
+                if offset < end {
+                    buf[offset..end].copy_from_slice(&word[..end - offset]);
+                }
+            }
+
+            let frame_len = len.saturating_sub(4);
+            if status & EMAC_RX_STATUS_OK != 0 && frame_len <= MAX_FRAME_SIZE {
+                return Ok(Some(frame_len));
+            }
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,93 @@
+//! Allwinner A10 interrupt controller.
+//!
+//! Lines are level triggered, a handler has to quiet its device before the
+//! line stops pending. QEMU's model raises IRQs for any unmasked pending
+//! line and ignores the enable registers, so a disabled line is masked as
+//! well, and priorities only order [`pending`] on real hardware.
+use super::regs::{base::INTC_BASE, intc::*};
+use crate::util::{reg32_clear_bits, reg32_read, reg32_write, reg32_write_masked};
+
+pub const LINES: u32 = INTC_BANKS * 32;
+pub const MAX_PRIORITY: u8 = (1 << INTC_PRIO_BITS) - 1;
+
+/// Register of `line`'s bank in the per bank registers at `offset`, and
+/// its bit in it
+fn bank_bit(offset: u32, line: u32) -> (u32, u32) {
+    (offset + line / 32 * 4, 1 << (line % 32))
+}
+
+/// Every line disabled and masked, delivered as IRQs at the lowest priority
+pub fn init() {
+    unsafe {
+        for bank in 0..INTC_BANKS {
+            reg32_write(INTC_BASE, INTC_EN + bank * 4, 0);
+            reg32_write(INTC_BASE, INTC_MASK + bank * 4, u32::MAX);
+            reg32_write(INTC_BASE, INTC_SEL + bank * 4, 0);
+            // only clears edge triggered lines, level ones pend until quiet
+            reg32_write(INTC_BASE, INTC_IRQ_PEND + bank * 4, u32::MAX);
+            reg32_write(INTC_BASE, INTC_FIQ_PEND + bank * 4, u32::MAX);
+        }
+        for reg in 0..LINES * INTC_PRIO_BITS / 32 {
+            reg32_write(INTC_BASE, INTC_PRIO + reg * 4, 0);
+        }
+        // VECTOR holds the line number times 4
+        reg32_write(INTC_BASE, INTC_BASE_ADDR, 0);
+        reg32_write(INTC_BASE, INTC_PROTECT, 1);
+    }
+}
+
+/// Let `line` raise IRQs
+pub fn enable(line: u32) {
+    let (en, bit) = bank_bit(INTC_EN, line);
+    let (mask, _) = bank_bit(INTC_MASK, line);
+    unsafe {
+        reg32_write_masked(INTC_BASE, en, bit, bit);
+        reg32_clear_bits(INTC_BASE, mask, bit);
+    }
+}
+
+pub fn disable(line: u32) {
+    let (en, bit) = bank_bit(INTC_EN, line);
+    let (mask, _) = bank_bit(INTC_MASK, line);
+    unsafe {
+        reg32_write_masked(INTC_BASE, mask, bit, bit);
+        reg32_clear_bits(INTC_BASE, en, bit);
+    }
+}
+
+/// Hold off IRQs from an enabled `line` until [`unmask`]
+pub fn mask(line: u32) {
+    let (mask, bit) = bank_bit(INTC_MASK, line);
+    unsafe { reg32_write_masked(INTC_BASE, mask, bit, bit) };
+}
+
+/// Undo [`mask`], only for lines that are enabled
+pub fn unmask(line: u32) {
+    let (mask, bit) = bank_bit(INTC_MASK, line);
+    unsafe { reg32_clear_bits(INTC_BASE, mask, bit) };
+}
+
+/// Set `line`'s priority, 0 is the lowest and anything above
+/// [`MAX_PRIORITY`] is cut to it
+pub fn set_priority(line: u32, priority: u8) {
+    let lines_per_reg = 32 / INTC_PRIO_BITS;
+    let shift = line % lines_per_reg * INTC_PRIO_BITS;
+    let field = (1 << INTC_PRIO_BITS) - 1;
+    let priority = priority.min(MAX_PRIORITY) as u32;
+    unsafe {
+        reg32_write_masked(
+            INTC_BASE,
+            INTC_PRIO + line / lines_per_reg * 4,
+            field << shift,
+            priority << shift,
+        );
+    }
+}
+
+/// The highest priority line with an IRQ pending, if any
+pub fn pending() -> Option<u32> {
+    let line = unsafe { reg32_read(INTC_BASE, INTC_VECTOR) } >> 2;
+    // VECTOR reads 0 with nothing pending, which is also line 0's number
+    let (pend, bit) = bank_bit(INTC_IRQ_PEND, line);
+    (line < LINES && unsafe { reg32_read(INTC_BASE, pend) } & bit != 0).then_some(line)
+}
@@ -1,14 +1,36 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use crate::qemu::regs::{base::MMC0_BASE, mmc::*};
 
 use crate::mmc::MMCError;
 use crate::util::{reg32_read, reg32_read_masked, reg32_write, reg32_write_masked};
 
+/// Where MMC0's registers are, [`MMC0_BASE`] unless a device tree moved it
+static BASE: AtomicU32 = AtomicU32::new(MMC0_BASE);
+
+pub fn set_base(base: u32) {
+    BASE.store(base, Ordering::Relaxed);
+}
+
 pub fn read_sector(sector: u32, buffer: &mut [u8; 512]) -> Result<(), MMCError> {
+    let base = BASE.load(Ordering::Relaxed);
     mmc_send_cmd(17, sector * 512)?;
     let buffer_ptr = buffer.as_mut_ptr() as *mut u32; // cast to u32 ptr to read 32 bits at a time
     unsafe {
         for i in 0..128 {
-            *buffer_ptr.add(i) = reg32_read(MMC0_BASE, MMC_FIFO);
+            *buffer_ptr.add(i) = reg32_read(base, MMC_FIFO);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn write_sector(sector: u32, buffer: &[u8; 512]) -> Result<(), MMCError> {
+    let base = BASE.load(Ordering::Relaxed);
+    mmc_send_cmd(24, sector * 512)?;
+    for word in buffer.as_chunks::<4>().0 {
+        unsafe {
+            reg32_write(base, MMC_FIFO, u32::from_ne_bytes(*word));
         }
     }
 
@@ -16,6 +38,7 @@ pub fn read_sector(sector: u32, buffer: &mut [u8; 512]) -> Result<(), MMCError>
 }
 
 pub fn mmc_send_cmd(cmd: u32, arg: u32) -> Result<(), MMCError> {
+    let base = BASE.load(Ordering::Relaxed);
     let cmd_flags = match cmd {
         0 => SD_CMDR_NO_RESP,
         2 => SD_CMDR_LONG_RESP,
@@ -41,17 +64,12 @@ pub fn mmc_send_cmd(cmd: u32, arg: u32) -> Result<(), MMCError> {
     };
 
     unsafe {
-        reg32_write(MMC0_BASE, MMC_ARG, arg);
-        reg32_write(MMC0_BASE, MMC_CMD, cmd & 0x3F | cmd_flags | SD_CMDR_LOAD);
+        reg32_write(base, MMC_ARG, arg);
+        reg32_write(base, MMC_CMD, cmd & 0x3F | cmd_flags | SD_CMDR_LOAD);
 
-        while reg32_read_masked(
-            MMC0_BASE,
-            MMC_RINT,
-            SD_RISR_CMD_COMPLETE | SD_RISR_NO_RESPONSE,
-        ) == 0
-        {}
+        while reg32_read_masked(base, MMC_RINT, SD_RISR_CMD_COMPLETE | SD_RISR_NO_RESPONSE) == 0 {}
 
-        if (reg32_read(MMC0_BASE, MMC_RINT) & SD_RISR_NO_RESPONSE) != 0 {
+        if (reg32_read(base, MMC_RINT) & SD_RISR_NO_RESPONSE) != 0 {
             Err(MMCError::NoResponse)
         } else {
             Ok(())
@@ -60,15 +78,16 @@ pub fn mmc_send_cmd(cmd: u32, arg: u32) -> Result<(), MMCError> {
 }
 
 pub fn init() -> Result<(), MMCError> {
+    let base = BASE.load(Ordering::Relaxed);
     unsafe {
-        reg32_write_masked(MMC0_BASE, MMC_GCTRL, SD_GCTL_SOFT_RST, SD_GCTL_SOFT_RST);
-        while reg32_read_masked(MMC0_BASE, MMC_GCTRL, SD_GCTL_SOFT_RST) == 1 {}
+        reg32_write_masked(base, MMC_GCTRL, SD_GCTL_SOFT_RST, SD_GCTL_SOFT_RST);
+        while reg32_read_masked(base, MMC_GCTRL, SD_GCTL_SOFT_RST) == 1 {}
 
-        reg32_write(MMC0_BASE, MMC_CLKCR, 59 | (1 << 16)); // 24MHz/(59+1) = 400kHz
+        reg32_write(base, MMC_CLKCR, 59 | (1 << 16)); // 24MHz/(59+1) = 400kHz
 
         mmc_send_cmd(0, 0)?;
         mmc_send_cmd(8, 0x1AA)?;
-        let resp = reg32_read(MMC0_BASE, MMC_RESP0);
+        let resp = reg32_read(base, MMC_RESP0);
         if (resp & 0xFF) != 0xAA || (resp >> 8) != 0x1 {
             return Err(MMCError::BadCMD8Response);
         }
@@ -76,7 +95,7 @@ pub fn init() -> Result<(), MMCError> {
         loop {
             mmc_send_cmd(55, 0)?;
             mmc_send_cmd(41, 0x40FF8000)?;
-            let resp = reg32_read(MMC0_BASE, MMC_RESP0);
+            let resp = reg32_read(base, MMC_RESP0);
             if (resp & (1 << 31)) != 0 {
                 break;
             }
@@ -84,12 +103,12 @@ pub fn init() -> Result<(), MMCError> {
 
         mmc_send_cmd(2, 0)?;
         mmc_send_cmd(3, 0)?;
-        let rca = (reg32_read(MMC0_BASE, MMC_RESP0) >> 16) & 0xFFFF;
+        let rca = (reg32_read(base, MMC_RESP0) >> 16) & 0xFFFF;
         mmc_send_cmd(7, rca << 16)?;
 
-        reg32_write(MMC0_BASE, MMC_CLKCR, 1 << 16);
-        reg32_write(MMC0_BASE, MMC_IDIE, (1 << 4) | (1 << 3));
-        reg32_write(MMC0_BASE, MMC_BLKSZ, 512);
+        reg32_write(base, MMC_CLKCR, 1 << 16);
+        reg32_write(base, MMC_IDIE, (1 << 4) | (1 << 3));
+        reg32_write(base, MMC_BLKSZ, 512);
     }
 
     Ok(())
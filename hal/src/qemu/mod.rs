@@ -1,4 +1,9 @@
+pub mod ahci;
 pub mod dram;
+pub mod emac;
+pub mod intc;
 pub mod mmc;
 pub mod regs;
+pub mod timer;
 pub mod uart;
+pub mod wdog;
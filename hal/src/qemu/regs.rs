@@ -1,8 +1,20 @@
 pub mod base {
     pub const MMC0_BASE: u32 = 0x01C0F000;
+    pub const EMAC_BASE: u32 = 0x01C0B000;
+    pub const AHCI_BASE: u32 = 0x01C18000;
+    pub const INTC_BASE: u32 = 0x01C20400;
+    pub const TIMER_BASE: u32 = 0x01C20C00;
     pub const UART0_BASE: u32 = 0x01C28000;
 }
 
+/// Device tree `compatible` strings of the devices in [`base`]
+pub mod compatible {
+    pub const UART0: &[&str] = &["snps,dw-apb-uart"];
+    pub const MMC0: &[&str] = &["allwinner,sun4i-a10-mmc"];
+    pub const EMAC: &[&str] = &["allwinner,sun4i-a10-emac"];
+    pub const AHCI: &[&str] = &["allwinner,sun4i-a10-ahci"];
+}
+
 pub mod mmc {
     pub const MMC_GCTRL: u32 = 0x00; // Global Control
     pub const MMC_CLKCR: u32 = 0x04; // Clock Control
@@ -68,6 +80,147 @@ pub mod mmc {
     pub const DESC_STATUS_LAST: u32 = 1 << 2;
 }
 
+pub mod emac {
+    pub const EMAC_CTL: u32 = 0x00; // Control
+    pub const EMAC_TX_MODE: u32 = 0x04; // TX Mode
+    pub const EMAC_TX_CTL0: u32 = 0x0C; // TX Channel 0 Control
+    pub const EMAC_TX_INS: u32 = 0x14; // TX Channel Select
+    pub const EMAC_TX_PL0: u32 = 0x18; // TX Channel 0 Packet Length
+    pub const EMAC_TX_IO_DATA: u32 = 0x24; // TX FIFO Data
+    pub const EMAC_RX_CTL: u32 = 0x3C; // RX Control
+    pub const EMAC_RX_IO_DATA: u32 = 0x4C; // RX FIFO Data
+    pub const EMAC_RX_FBC: u32 = 0x50; // RX Frame Count
+    pub const EMAC_INT_CTL: u32 = 0x54; // Interrupt Enable
+    pub const EMAC_INT_STA: u32 = 0x58; // Interrupt Status
+    pub const EMAC_MAC_CTL0: u32 = 0x5C; // MAC Control 0
+    pub const EMAC_MAC_CTL1: u32 = 0x60; // MAC Control 1
+    pub const EMAC_MAC_A0: u32 = 0x98; // MAC Address High
+    pub const EMAC_MAC_A1: u32 = 0x9C; // MAC Address Low
+
+    // EMAC_CTL, reset is active low
+    pub const EMAC_CTL_RESET: u32 = 1 << 0;
+    pub const EMAC_CTL_TX_EN: u32 = 1 << 1;
+    pub const EMAC_CTL_RX_EN: u32 = 1 << 2;
+
+    pub const EMAC_TX_CTL_START: u32 = 1 << 0;
+
+    pub const EMAC_RX_CTL_ACCEPT_UNICAST: u32 = 1 << 16;
+    pub const EMAC_RX_CTL_DA_FILTER: u32 = 1 << 17;
+    pub const EMAC_RX_CTL_ACCEPT_BROADCAST: u32 = 1 << 22;
+
+    pub const EMAC_MAC_CTL0_SOFT_RESET: u32 = 1 << 15;
+    pub const EMAC_MAC_CTL1_CRC: u32 = 1 << 4;
+    pub const EMAC_MAC_CTL1_PAD: u32 = 1 << 5;
+
+    /// First word of every frame read from the RX FIFO
+    pub const EMAC_RX_MAGIC: u32 = 0x0143_414D;
+    // second word: frame length including the FCS, then status
+    pub const EMAC_RX_STATUS_SHIFT: u32 = 16;
+    pub const EMAC_RX_STATUS_OK: u32 = 1 << 7;
+}
+
+pub mod ahci {
+    pub const AHCI_CAP: u32 = 0x00; // Host Capabilities
+    pub const AHCI_GHC: u32 = 0x04; // Global Host Control
+    pub const AHCI_IS: u32 = 0x08; // Interrupt Status
+    pub const AHCI_PI: u32 = 0x0C; // Ports Implemented
+    pub const AHCI_VS: u32 = 0x10; // Version
+
+    pub const AHCI_GHC_HR: u32 = 1 << 0; // HBA Reset
+    pub const AHCI_GHC_AE: u32 = 1 << 31; // AHCI Enable
+
+    /// Port registers, `AHCI_PORT_SIZE` bytes per port from here
+    pub const AHCI_PORT_BASE: u32 = 0x100;
+    pub const AHCI_PORT_SIZE: u32 = 0x80;
+
+    pub const AHCI_PX_CLB: u32 = 0x00; // Command List Base Address
+    pub const AHCI_PX_CLBU: u32 = 0x04; // Command List Base Address Upper
+    pub const AHCI_PX_FB: u32 = 0x08; // FIS Base Address
+    pub const AHCI_PX_FBU: u32 = 0x0C; // FIS Base Address Upper
+    pub const AHCI_PX_IS: u32 = 0x10; // Interrupt Status
+    pub const AHCI_PX_IE: u32 = 0x14; // Interrupt Enable
+    pub const AHCI_PX_CMD: u32 = 0x18; // Command and Status
+    pub const AHCI_PX_TFD: u32 = 0x20; // Task File Data
+    pub const AHCI_PX_SIG: u32 = 0x24; // Signature
+    pub const AHCI_PX_SSTS: u32 = 0x28; // SATA Status
+    pub const AHCI_PX_SERR: u32 = 0x30; // SATA Error
+    pub const AHCI_PX_CI: u32 = 0x38; // Command Issue
+
+    pub const AHCI_PX_CMD_ST: u32 = 1 << 0; // Start
+    pub const AHCI_PX_CMD_SUD: u32 = 1 << 1; // Spin-Up Device
+    pub const AHCI_PX_CMD_POD: u32 = 1 << 2; // Power On Device
+    pub const AHCI_PX_CMD_FRE: u32 = 1 << 4; // FIS Receive Enable
+    pub const AHCI_PX_CMD_FR: u32 = 1 << 14; // FIS Receive Running
+    pub const AHCI_PX_CMD_CR: u32 = 1 << 15; // Command List Running
+
+    pub const AHCI_PX_IS_TFES: u32 = 1 << 30; // Task File Error
+
+    pub const AHCI_PX_TFD_ERR: u32 = 1 << 0;
+    pub const AHCI_PX_TFD_DRQ: u32 = 1 << 3;
+    pub const AHCI_PX_TFD_BSY: u32 = 1 << 7;
+
+    pub const AHCI_PX_SSTS_DET_MASK: u32 = 0xF;
+    /// Device present and PHY communication established
+    pub const AHCI_PX_SSTS_DET_PRESENT: u32 = 3;
+
+    /// `AHCI_PX_SIG` of an ATA disk, ATAPI and port multipliers differ
+    pub const AHCI_SIG_ATA: u32 = 0x0000_0101;
+}
+
+/// Interrupt controller, 96 lines in three banks of 32
+pub mod intc {
+    pub const INTC_VECTOR: u32 = 0x00; // Highest priority pending line, as BASE_ADDR + line * 4
+    pub const INTC_BASE_ADDR: u32 = 0x04; // Added to the line in VECTOR
+    pub const INTC_PROTECT: u32 = 0x08; // Registers only writable in privileged modes
+    pub const INTC_IRQ_PEND: u32 = 0x10; // IRQ Pending, per bank
+    pub const INTC_FIQ_PEND: u32 = 0x20; // FIQ Pending, per bank
+    pub const INTC_SEL: u32 = 0x30; // Deliver as FIQ instead of IRQ, per bank
+    pub const INTC_EN: u32 = 0x40; // Enable, per bank
+    pub const INTC_MASK: u32 = 0x50; // Mask, per bank
+    pub const INTC_PRIO: u32 = 0x80; // Priority, 2 bits per line, 16 lines per register
+
+    pub const INTC_BANKS: u32 = 3;
+    pub const INTC_PRIO_BITS: u32 = 2;
+}
+
+/// Interrupt controller lines of the devices we take interrupts from
+pub mod irq {
+    pub const TIMER0: u32 = 22;
+    pub const TIMER1: u32 = 23;
+}
+
+/// Timer block: six down counting timers, the AVS counters and the watchdog
+pub mod timer {
+    pub const TMR_IRQ_EN: u32 = 0x00; // IRQ Enable, a bit per timer
+    pub const TMR_IRQ_STA: u32 = 0x04; // IRQ Status, write 1 to clear
+
+    /// Timer `n`'s Control, Interval Value and Current Value registers
+    pub const fn tmr_ctrl(n: u32) -> u32 {
+        0x10 + n * 0x10
+    }
+    pub const fn tmr_intv(n: u32) -> u32 {
+        0x14 + n * 0x10
+    }
+    pub const fn tmr_cur(n: u32) -> u32 {
+        0x18 + n * 0x10
+    }
+
+    pub const TMR_CTRL_EN: u32 = 1 << 0;
+    pub const TMR_CTRL_RELOAD: u32 = 1 << 1; // Load the interval into the counter
+    pub const TMR_CTRL_SRC_OSC24M: u32 = 1 << 2;
+    pub const TMR_CTRL_PRES_SHIFT: u32 = 4; // Prescaler, divides by 1 << value
+    pub const TMR_CTRL_SINGLE: u32 = 1 << 7; // Stop at 0 instead of reloading
+
+    pub const WDOG_CTRL: u32 = 0x90; // Watchdog Control
+    pub const WDOG_MODE: u32 = 0x94; // Watchdog Mode
+
+    pub const WDOG_CTRL_RESTART: u32 = 1 << 0;
+    pub const WDOG_CTRL_KEY: u32 = 0xA57 << 1; // Required for CTRL writes to count
+    pub const WDOG_MODE_EN: u32 = 1 << 0;
+    pub const WDOG_MODE_RST_EN: u32 = 1 << 1; // Reset the system, not just interrupt
+    pub const WDOG_MODE_INTV_SHIFT: u32 = 3;
+}
+
 pub mod uart {
     pub const RBR_THR_DLL: u32 = 0x00;
     pub const IER_DLH: u32 = 0x04;
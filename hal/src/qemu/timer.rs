@@ -0,0 +1,96 @@
+//! Allwinner A10 timers, a clock source and an alarm.
+//!
+//! Timer 0 free-runs down from `u32::MAX` off the 24MHz oscillator divided
+//! by 8, so it needs no calibration and wraps about every 24 minutes.
+//! [`now`] widens it to 64 bits as long as it is called at least once per
+//! wrap. Timer 1 is the alarm, raising [`ALARM_IRQ`] when it runs out.
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::regs::{base::TIMER_BASE, irq, timer::*};
+use crate::interrupts;
+use crate::timer::widen;
+use crate::util::{reg32_clear_bits, reg32_read, reg32_write, reg32_write_masked};
+
+const CLOCK: u32 = 0;
+const ALARM: u32 = 1;
+
+const PRESCALE_SHIFT: u32 = 3;
+pub const TICKS_PER_SECOND: u64 = 24_000_000 >> PRESCALE_SHIFT;
+/// Longest the alarm can wait, in ticks
+pub const MAX_ALARM: u64 = u32::MAX as u64;
+
+pub const ALARM_IRQ: u32 = irq::TIMER1;
+
+const CTRL_OSC24M_DIV8: u32 = TMR_CTRL_SRC_OSC24M | PRESCALE_SHIFT << TMR_CTRL_PRES_SHIFT;
+
+/// What the last [`now`] returned, the counter in its low half and how
+/// often it wrapped in the high half
+static LAST: AtomicU64 = AtomicU64::new(0);
+
+/// Start the clock source from 0, with the alarm stopped
+pub fn init() {
+    LAST.store(0, Ordering::Relaxed);
+    unsafe {
+        reg32_write(TIMER_BASE, TMR_IRQ_EN, 0);
+        reg32_write(TIMER_BASE, TMR_IRQ_STA, u32::MAX);
+        reg32_write(TIMER_BASE, tmr_ctrl(ALARM), 0);
+        reg32_write(TIMER_BASE, tmr_ctrl(CLOCK), 0);
+        reg32_write(TIMER_BASE, tmr_intv(CLOCK), u32::MAX);
+        reg32_write(
+            TIMER_BASE,
+            tmr_ctrl(CLOCK),
+            CTRL_OSC24M_DIV8 | TMR_CTRL_RELOAD | TMR_CTRL_EN,
+        );
+    }
+}
+
+/// Ticks since [`init`]
+pub fn now() -> u64 {
+    // an IRQ handler reading the clock between our read and update would
+    // leave LAST ahead of the count, which looks like a wrap
+    interrupts::free(|| {
+        // the timer counts down
+        let count = !unsafe { reg32_read(TIMER_BASE, tmr_cur(CLOCK)) };
+        let now = widen(LAST.load(Ordering::Relaxed), count);
+        LAST.store(now, Ordering::Relaxed);
+        now
+    })
+}
+
+/// Raise [`ALARM_IRQ`] in `ticks`, and every `ticks` after that if
+/// `periodic`. Replaces an alarm already set.
+pub fn start_alarm(ticks: u64, periodic: bool) {
+    let ticks = ticks.clamp(1, MAX_ALARM) as u32;
+    let mode = if periodic { 0 } else { TMR_CTRL_SINGLE };
+    let bit = 1 << ALARM;
+    unsafe {
+        reg32_write(TIMER_BASE, tmr_ctrl(ALARM), 0);
+        reg32_write(TIMER_BASE, TMR_IRQ_STA, bit);
+        reg32_write(TIMER_BASE, tmr_intv(ALARM), ticks);
+        reg32_write_masked(TIMER_BASE, TMR_IRQ_EN, bit, bit);
+        reg32_write(
+            TIMER_BASE,
+            tmr_ctrl(ALARM),
+            CTRL_OSC24M_DIV8 | mode | TMR_CTRL_RELOAD | TMR_CTRL_EN,
+        );
+    }
+}
+
+pub fn stop_alarm() {
+    let bit = 1 << ALARM;
+    unsafe {
+        reg32_write(TIMER_BASE, tmr_ctrl(ALARM), 0);
+        reg32_clear_bits(TIMER_BASE, TMR_IRQ_EN, bit);
+        reg32_write(TIMER_BASE, TMR_IRQ_STA, bit);
+    }
+}
+
+/// Acknowledge the alarm, returning whether it had gone off
+pub fn clear_alarm() -> bool {
+    let bit = 1 << ALARM;
+    unsafe {
+        let fired = reg32_read(TIMER_BASE, TMR_IRQ_STA) & bit != 0;
+        reg32_write(TIMER_BASE, TMR_IRQ_STA, bit);
+        fired
+    }
+}
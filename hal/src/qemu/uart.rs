@@ -1,29 +1,41 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use super::regs::{base::UART0_BASE, uart::*};
 use crate::util::{reg32_read, reg32_write};
 
+/// Where UART0's registers are, [`UART0_BASE`] unless a device tree moved it
+static BASE: AtomicU32 = AtomicU32::new(UART0_BASE);
+
+pub fn set_base(base: u32) {
+    BASE.store(base, Ordering::Relaxed);
+}
+
 pub fn init() {
+    let base = BASE.load(Ordering::Relaxed);
     unsafe {
-        reg32_write(UART0_BASE, IER_DLH, 0x0);
-        reg32_write(UART0_BASE, LCR, 0x80);
-        reg32_write(UART0_BASE, RBR_THR_DLL, 13);
-        reg32_write(UART0_BASE, IER_DLH, 0x0);
-        reg32_write(UART0_BASE, LCR, 0x3);
-        reg32_write(UART0_BASE, IIR_FCR, 0x1);
+        reg32_write(base, IER_DLH, 0x0);
+        reg32_write(base, LCR, 0x80);
+        reg32_write(base, RBR_THR_DLL, 13);
+        reg32_write(base, IER_DLH, 0x0);
+        reg32_write(base, LCR, 0x3);
+        reg32_write(base, IIR_FCR, 0x1);
     }
 }
 
 // On qemu, reads are buffered and we don't need to busywait for a signal to write next
 pub fn write_byte(byte: u8) {
+    let base = BASE.load(Ordering::Relaxed);
     unsafe {
-        reg32_write(UART0_BASE, RBR_THR_DLL, byte as u32);
+        reg32_write(base, RBR_THR_DLL, byte as u32);
     }
 }
 
 pub fn read_byte() -> Option<u8> {
+    let base = BASE.load(Ordering::Relaxed);
     unsafe {
-        let lsr = reg32_read(UART0_BASE, LSR);
+        let lsr = reg32_read(base, LSR);
         if lsr & 0x1 != 0 {
-            Some(reg32_read(UART0_BASE, RBR_THR_DLL) as u8)
+            Some(reg32_read(base, RBR_THR_DLL) as u8)
         } else {
             None
         }
@@ -0,0 +1,55 @@
+//! Allwinner A10 watchdog, part of the timer block.
+//!
+//! It only has 12 fixed intervals, from half a second to 16 seconds. QEMU's
+//! model of the timer block keeps the watchdog registers but never fires, so
+//! [`wait_for_reset`] restarts the bootloader instead.
+use core::time::Duration;
+
+use super::regs::{base::TIMER_BASE, timer::*};
+use crate::util::reg32_write;
+use crate::{asm, mmu, timer};
+
+/// Seconds each interval setting waits, 0 standing for half a second
+const INTERVALS: [u32; 12] = [0, 1, 2, 3, 4, 5, 6, 8, 10, 12, 14, 16];
+
+pub const MAX_TIMEOUT: u32 = 16;
+
+pub fn start(seconds: u32) {
+    let interval = INTERVALS
+        .iter()
+        .position(|&interval| interval >= seconds)
+        .unwrap_or(INTERVALS.len() - 1) as u32;
+    unsafe {
+        reg32_write(TIMER_BASE, WDOG_MODE, 0);
+        reg32_write(
+            TIMER_BASE,
+            WDOG_MODE,
+            interval << WDOG_MODE_INTV_SHIFT | WDOG_MODE_RST_EN | WDOG_MODE_EN,
+        );
+        reg32_write(TIMER_BASE, WDOG_CTRL, WDOG_CTRL_KEY | WDOG_CTRL_RESTART);
+    }
+}
+
+pub fn stop() {
+    unsafe { reg32_write(TIMER_BASE, WDOG_MODE, 0) };
+}
+
+/// Where `tools/run_qemu.sh` has QEMU load the bootloader, `ORIGIN(ROM)` in
+/// `linker_qemu.ld`
+const BOOTLOADER_ENTRY: u32 = 0x4001_0000;
+
+/// The watchdog won't fire, so wait as long as it would have and start the
+/// bootloader again ourselves. Unlike quitting QEMU this keeps DRAM, and the
+/// panic record in it, as a real watchdog reset would.
+pub fn wait_for_reset(seconds: u32) -> ! {
+    timer::delay(Duration::from_secs(seconds as u64));
+    println!("QEMU's watchdog never fires, restarting the bootloader");
+    unsafe {
+        asm::disable_interrupts();
+        asm::d_cache_disable();
+    }
+    mmu::clean_invalidate_d_cache();
+    // the MMU stays on, we may be running from the kernel's mappings; the
+    // bootloader is identity mapped and turns it off first thing
+    unsafe { asm::enter_reset_state(BOOTLOADER_ENTRY) }
+}
@@ -0,0 +1,70 @@
+//! SATA disk on the first port of the AHCI controller, polled.
+
+pub const SECTOR_SIZE: usize = 512;
+
+/// What IDENTIFY DEVICE told us about the disk
+#[derive(Debug, Clone, Copy)]
+pub struct DiskInfo {
+    /// Capacity in [`SECTOR_SIZE`] sectors
+    pub sectors: u64,
+    model: [u8; 40],
+}
+
+impl DiskInfo {
+    pub fn new(sectors: u64, model: [u8; 40]) -> Self {
+        Self { sectors, model }
+    }
+
+    /// Model string, without the padding
+    pub fn model(&self) -> &str {
+        core::str::from_utf8(&self.model)
+            .unwrap_or("?")
+            .trim_end_matches([' ', '\0'])
+    }
+}
+
+/// Reset the controller, bring up the disk and identify it
+pub fn init() -> Result<DiskInfo, SataError> {
+    platform::init()
+}
+
+pub fn read_sector(sector: u32, buffer: &mut [u8; SECTOR_SIZE]) -> Result<(), SataError> {
+    platform::read_sector(sector, buffer)
+}
+
+pub fn write_sector(sector: u32, buffer: &[u8; SECTOR_SIZE]) -> Result<(), SataError> {
+    platform::write_sector(sector, buffer)
+}
+
+#[derive(Debug)]
+pub enum SataError {
+    /// Controller doesn't implement the port we use
+    NoPort,
+    /// Nothing is plugged into the port
+    NoDisk,
+    /// Something other than an ATA disk is, holds its signature
+    NotAta(u32),
+    Timeout,
+    /// Disk failed a command, holds the port's task file data
+    DeviceError(u32),
+    Unimplemented,
+}
+
+#[cfg(feature = "qemu")]
+mod platform {
+    pub use crate::qemu::ahci::{init, read_sector, write_sector};
+}
+
+#[cfg(feature = "bbb")]
+mod platform {
+    use super::{DiskInfo, SECTOR_SIZE, SataError};
+    pub fn init() -> Result<DiskInfo, SataError> {
+        Err(SataError::Unimplemented)
+    }
+    pub fn read_sector(_sector: u32, _buffer: &mut [u8; SECTOR_SIZE]) -> Result<(), SataError> {
+        Err(SataError::Unimplemented)
+    }
+    pub fn write_sector(_sector: u32, _buffer: &[u8; SECTOR_SIZE]) -> Result<(), SataError> {
+        Err(SataError::Unimplemented)
+    }
+}
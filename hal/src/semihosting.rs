@@ -0,0 +1,157 @@
+//! ARM semihosting, I/O serviced by the host running us.
+//!
+//! QEMU answers these with `-semihosting`, which `tools/run_qemu.sh` always
+//! passes. Every call traps with [`asm::semihosting_call`], and without a
+//! host listening that is an ordinary supervisor call exception, so nothing
+//! here may be used unless semihosting is known to be on.
+use core::fmt::Write;
+
+use crate::asm;
+
+mod op {
+    pub const SYS_OPEN: u32 = 0x01;
+    pub const SYS_CLOSE: u32 = 0x02;
+    pub const SYS_WRITE0: u32 = 0x04;
+    pub const SYS_READ: u32 = 0x06;
+    pub const SYS_FLEN: u32 = 0x0C;
+    pub const SYS_CLOCK: u32 = 0x10;
+    pub const SYS_ERRNO: u32 = 0x13;
+    pub const SYS_EXIT: u32 = 0x18;
+    pub const SYS_EXIT_EXTENDED: u32 = 0x20;
+}
+
+// SYS_EXIT reasons
+const ADP_STOPPED_RUN_TIME_ERROR_UNKNOWN: usize = 0x2_0023;
+const ADP_STOPPED_APPLICATION_EXIT: usize = 0x2_0026;
+
+/// Longest path [`File::open`] takes
+pub const MAX_PATH: usize = 255;
+
+#[derive(Debug, Clone, Copy)]
+pub enum OpenMode {
+    Read,
+    /// Create or truncate
+    Write,
+    Append,
+}
+
+impl OpenMode {
+    /// The `fopen` mode SYS_OPEN takes, binary so the host leaves bytes alone
+    fn host_mode(self) -> usize {
+        match self {
+            Self::Read => 1,   // "rb"
+            Self::Write => 5,  // "wb"
+            Self::Append => 9, // "ab"
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SemihostingError {
+    PathTooLong,
+    /// Host call failed, holds the host's errno
+    Host(i32),
+}
+
+fn call(op: u32, param: usize) -> usize {
+    unsafe { asm::semihosting_call(op, param) }
+}
+
+/// The host's error for the last call that failed
+fn host_error() -> SemihostingError {
+    SemihostingError::Host(call(op::SYS_ERRNO, 0) as i32)
+}
+
+/// Print `s` on the host's console
+pub fn write_str(s: &str) {
+    // SYS_WRITE0 takes a NUL terminated string, send it a piece at a time
+    let mut buf = [0u8; 64];
+    for chunk in s.as_bytes().chunks(buf.len() - 1) {
+        buf[..chunk.len()].copy_from_slice(chunk);
+        buf[chunk.len()] = 0;
+        call(op::SYS_WRITE0, buf.as_ptr() as usize);
+    }
+}
+
+/// [`Write`] to the host's console, like [`crate::Writer`] is to the UART
+pub struct HostWriter;
+
+impl Write for HostWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        write_str(s);
+        Ok(())
+    }
+}
+
+/// Centiseconds since the program started, `None` if the host can't tell
+pub fn clock() -> Option<u32> {
+    let clock = call(op::SYS_CLOCK, 0);
+    (clock as isize != -1).then_some(clock as u32)
+}
+
+/// Stop the emulator with exit status `code`
+pub fn exit(code: u32) -> ! {
+    let args = [ADP_STOPPED_APPLICATION_EXIT, code as usize];
+    call(op::SYS_EXIT_EXTENDED, args.as_ptr() as usize);
+    // still here, the host has no SYS_EXIT_EXTENDED and plain SYS_EXIT can
+    // only tell success from failure
+    let reason = if code == 0 {
+        ADP_STOPPED_APPLICATION_EXIT
+    } else {
+        ADP_STOPPED_RUN_TIME_ERROR_UNKNOWN
+    };
+    call(op::SYS_EXIT, reason);
+    loop {
+        unsafe { asm::wfi() };
+    }
+}
+
+/// A file on the host, closed when dropped
+pub struct File {
+    handle: usize,
+}
+
+impl File {
+    /// Open `path`, relative to the host's working directory
+    pub fn open(path: &str, mode: OpenMode) -> Result<Self, SemihostingError> {
+        let mut name = [0u8; MAX_PATH + 1];
+        name.get_mut(..path.len())
+            .ok_or(SemihostingError::PathTooLong)?
+            .copy_from_slice(path.as_bytes());
+        let args = [name.as_ptr() as usize, mode.host_mode(), path.len()];
+        let handle = call(op::SYS_OPEN, args.as_ptr() as usize);
+        if handle as isize == -1 {
+            return Err(host_error());
+        }
+        Ok(Self { handle })
+    }
+
+    /// Size of the file in bytes
+    pub fn size(&self) -> Result<usize, SemihostingError> {
+        let args = [self.handle];
+        let size = call(op::SYS_FLEN, args.as_ptr() as usize);
+        if size as isize == -1 {
+            return Err(host_error());
+        }
+        Ok(size)
+    }
+
+    /// Read into `buf` from the current position, returning how many bytes
+    /// were read, 0 at the end of the file
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, SemihostingError> {
+        let args = [self.handle, buf.as_mut_ptr() as usize, buf.len()];
+        // the host returns how many bytes it did not read
+        let unread = call(op::SYS_READ, args.as_ptr() as usize);
+        if unread > buf.len() {
+            return Err(host_error());
+        }
+        Ok(buf.len() - unread)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let args = [self.handle];
+        call(op::SYS_CLOSE, args.as_ptr() as usize);
+    }
+}
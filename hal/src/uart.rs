@@ -1,4 +1,7 @@
 use core::fmt::Write;
+use core::time::Duration;
+
+use crate::timer::Instant;
 
 /// Initialize the UART device (UART0)
 pub fn init() {
@@ -10,6 +13,12 @@ pub fn read_byte() -> Option<u8> {
     platform::read_byte()
 }
 
+/// Drive a UART0 found at `base`, e.g. by [`crate::fdt::discover`]. Call it
+/// before [`init`].
+pub fn set_base(base: u32) {
+    platform::set_base(base);
+}
+
 pub struct Writer;
 impl Write for Writer {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
@@ -20,7 +29,11 @@ impl Write for Writer {
     }
 }
 
-/// UART wrapper that implements rmodem's expected Read and Write traits
+pub fn write_byte(byte: u8) {
+    platform::write_byte(byte);
+}
+
+/// Raw byte access to UART0 for binary protocols (XMODEM/YMODEM)
 #[derive(Debug, Default)]
 pub struct UartDevice;
 
@@ -29,6 +42,36 @@ impl UartDevice {
         // We're using the already initialized UART
         UartDevice
     }
+
+    pub fn read_byte(&mut self) -> Option<u8> {
+        platform::read_byte()
+    }
+
+    /// Poll for a byte, giving up once `timeout` passes without one
+    pub fn read_byte_timeout(&mut self, timeout: Duration) -> Result<u8, UartError> {
+        let end = Instant::now() + timeout;
+        while Instant::now() < end {
+            if let Some(byte) = platform::read_byte() {
+                return Ok(byte);
+            }
+        }
+        Err(UartError::Timeout)
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        platform::write_byte(byte);
+    }
+
+    pub fn write_all(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            platform::write_byte(byte);
+        }
+    }
+
+    /// Drop everything currently waiting in the receive FIFO
+    pub fn flush_input(&mut self) {
+        while platform::read_byte().is_some() {}
+    }
 }
 
 // Create custom error type for no_std
@@ -36,12 +79,13 @@ impl UartDevice {
 pub enum UartError {
     ReadError,
     WriteError,
+    Timeout,
 }
 
 // Platform-specific UART functions
 #[cfg(feature = "qemu")]
 mod platform {
-    pub use crate::qemu::uart::{init, read_byte, write_byte};
+    pub use crate::qemu::uart::{init, read_byte, set_base, write_byte};
 }
 
 #[cfg(feature = "bbb")]
@@ -50,4 +94,7 @@ mod platform {
     pub fn read_byte() -> Option<u8> {
         None
     }
+    /// The AM335x ROM starts the bootloader without a device tree, UART0
+    /// never moves
+    pub fn set_base(_base: u32) {}
 }
@@ -0,0 +1,49 @@
+//! Watchdog, used to reset the board.
+pub use platform::MAX_TIMEOUT;
+
+/// Seconds between a panic and the watchdog reset, set with
+/// `PANIC_RESET_DELAY` at build time
+pub const PANIC_RESET_DELAY: u32 = match option_env!("PANIC_RESET_DELAY") {
+    Some(delay) => match u32::from_str_radix(delay, 10) {
+        Ok(delay) => delay,
+        Err(_) => panic!("PANIC_RESET_DELAY must be a number of seconds"),
+    },
+    None => 10,
+};
+
+/// Reset the board in `seconds` unless [`stop`] is called first. The timeout
+/// is rounded up to one the hardware has, or cut to [`MAX_TIMEOUT`].
+pub fn start(seconds: u32) {
+    platform::start(seconds)
+}
+
+pub fn stop() {
+    platform::stop()
+}
+
+/// Reset the board as soon as the watchdog can
+pub fn reset() -> ! {
+    reset_in(0)
+}
+
+/// Reset the board in `seconds`, waiting for it
+pub fn reset_in(seconds: u32) -> ! {
+    start(seconds);
+    platform::wait_for_reset(seconds)
+}
+
+#[cfg(feature = "qemu")]
+mod platform {
+    pub use crate::qemu::wdog::{MAX_TIMEOUT, start, stop, wait_for_reset};
+}
+
+#[cfg(feature = "bbb")]
+mod platform {
+    pub use crate::bbb::wdt::{MAX_TIMEOUT, start, stop};
+
+    pub fn wait_for_reset(_seconds: u32) -> ! {
+        loop {
+            unsafe { crate::asm::wfi() };
+        }
+    }
+}
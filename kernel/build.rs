@@ -2,15 +2,35 @@ use std::env;
 
 const KERNEL_LDSCRIPT: &str = "kernel.ld";
 
+/// Physical address the kernel is loaded to, 16MB into DRAM
+fn kernel_phys_base() -> &'static str {
+    if env::var("CARGO_FEATURE_BBB").is_ok() {
+        "0x81000000"
+    } else {
+        "0x41000000"
+    }
+}
+
 fn set_ld_script() {
     let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     println!(
         "cargo:rustc-link-arg=-T{}/{}",
         manifest_dir, KERNEL_LDSCRIPT
     );
+    println!(
+        "cargo:rustc-link-arg=-Wl,--defsym=__kernel_phys_base={}",
+        kernel_phys_base()
+    );
 }
 
 fn main() {
+    println!("cargo:rerun-if-changed={}", KERNEL_LDSCRIPT);
+
+    // host builds only run the library's unit tests, there is nothing to link
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("none") {
+        return;
+    }
+
     set_ld_script();
     println!("cargo:rustc-link-arg=-nostartfiles");
 }
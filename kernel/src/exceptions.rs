@@ -0,0 +1,212 @@
+//! Exception vectors and trap handling.
+//!
+//! [`init`] points VBAR at `__vectors` and gives every exception mode its
+//! own stack. Each vector saves a [`TrapFrame`] of the interrupted code on
+//! that stack and calls a Rust handler with it, and whatever the handler
+//! leaves in the frame is restored on return. IRQs go to [`crate::irq`],
+//! aborts and undefined instructions print a crash report and panic, so they
+//! leave a panic record and reset like any other panic.
+//!
+//! The frame holds SP and LR of the user/system bank, which is the kernel's
+//! own as it runs in system mode. A FIQ saves its banked r8-r12 rather than
+//! the interrupted ones.
+use core::arch::global_asm;
+use core::cell::UnsafeCell;
+use core::fmt;
+
+use hal::backtrace::{self, Registers};
+use hal::{asm, println};
+use kernel_core::fault::Fault;
+
+/// Bytes of stack for each exception mode
+const STACK_SIZE: usize = 4096;
+
+mod mode {
+    pub const FIQ: u32 = 0x11;
+    pub const IRQ: u32 = 0x12;
+    pub const SVC: u32 = 0x13;
+    pub const ABT: u32 = 0x17;
+    pub const UND: u32 = 0x1B;
+}
+
+/// Modes that get a stack, in the order of [`STACKS`]
+const MODES: [u32; 5] = [mode::FIQ, mode::IRQ, mode::SVC, mode::ABT, mode::UND];
+
+/// SCTLR.V, exception vectors at 0xFFFF0000 instead of VBAR
+const SCTLR_HIGH_VECTORS: u32 = 1 << 13;
+/// CPSR.T, the interrupted code was in Thumb state
+const CPSR_THUMB: u32 = 1 << 5;
+
+/// Registers of the interrupted code, saved by the vector stubs
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TrapFrame {
+    pub r: [u32; 13],
+    pub sp: u32,
+    pub lr: u32,
+    /// Where execution resumes, the faulting instruction for aborts
+    pub pc: u32,
+    /// The SPSR, the interrupted code's CPSR
+    pub cpsr: u32,
+    /// Keeps the frame a multiple of 8 bytes, as the AAPCS wants the stack
+    _pad: u32,
+}
+
+impl fmt::Display for TrapFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, chunk) in self.r.chunks(4).enumerate() {
+            for (j, value) in chunk.iter().enumerate() {
+                write!(f, "r{:<2} 0x{:08x}  ", i * 4 + j, value)?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(
+            f,
+            "sp  0x{:08x}  lr  0x{:08x}  pc  0x{:08x}  cpsr 0x{:08x}",
+            self.sp, self.lr, self.pc, self.cpsr
+        )
+    }
+}
+
+#[repr(C, align(8))]
+struct Stacks([[u8; STACK_SIZE]; MODES.len()]);
+
+/// Only the exception modes use these, each its own
+struct ExceptionStacks(UnsafeCell<Stacks>);
+
+unsafe impl Sync for ExceptionStacks {}
+
+static STACKS: ExceptionStacks =
+    ExceptionStacks(UnsafeCell::new(Stacks([[0; STACK_SIZE]; MODES.len()])));
+
+// The return address in LR is off by a different amount for each exception,
+// `adjust` makes it the instruction to resume at. The frame is 18 words.
+global_asm!(
+    r#"
+    .macro trap_entry handler, adjust
+        sub lr, lr, #\adjust
+        sub sp, sp, #72
+        stmia sp, {{r0-r12}}
+        add r0, sp, #52
+        stmia r0, {{sp, lr}}^
+        nop
+        str lr, [sp, #60]
+        mrs r1, spsr
+        str r1, [sp, #64]
+        mov r0, sp
+        bl \handler
+        ldr r1, [sp, #64]
+        msr spsr_cxsf, r1
+        ldr lr, [sp, #60]
+        add r0, sp, #52
+        ldmia r0, {{sp, lr}}^
+        nop
+        ldmia sp, {{r0-r12}}
+        add sp, sp, #72
+        movs pc, lr
+    .endm
+
+    .section .text.vectors, "ax"
+    .arm
+    .balign 32
+    .global __vectors
+__vectors:
+    b .
+    b __undef_entry
+    b __svc_entry
+    b __prefetch_abort_entry
+    b __data_abort_entry
+    b .
+    b __irq_entry
+    b __fiq_entry
+
+__undef_entry:
+    trap_entry handle_undef, 4
+__svc_entry:
+    trap_entry handle_svc, 0
+__prefetch_abort_entry:
+    trap_entry handle_prefetch_abort, 4
+__data_abort_entry:
+    trap_entry handle_data_abort, 8
+__irq_entry:
+    trap_entry handle_irq, 4
+__fiq_entry:
+    trap_entry handle_fiq, 4
+"#
+);
+
+unsafe extern "C" {
+    static __vectors: u8;
+}
+
+/// Give every exception mode a stack and install the vectors
+pub fn init() {
+    let stacks = STACKS.0.get() as usize;
+    unsafe {
+        for (i, &mode) in MODES.iter().enumerate() {
+            asm::set_mode_stack(mode, (stacks + (i + 1) * STACK_SIZE) as u32);
+        }
+        asm::set_vbar(&raw const __vectors as u32);
+        asm::clear_scltr_flag(SCTLR_HIGH_VECTORS);
+        asm::isb();
+    }
+}
+
+/// Print what went wrong, the interrupted registers and their backtrace,
+/// then panic with `what`
+fn crash(what: &dyn fmt::Display, frame: &TrapFrame) -> ! {
+    println!("\n{} at 0x{:08x}", what, frame.pc);
+    println!("{}", frame);
+    backtrace::print(&Registers {
+        cpsr: frame.cpsr,
+        sp: frame.sp,
+        lr: frame.lr,
+        fp: frame.r[11],
+    });
+    panic!("{} at 0x{:08x}", what, frame.pc);
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn handle_undef(frame: &mut TrapFrame) {
+    // LR is 2 past the instruction in Thumb state, not 4, so the entry
+    // stub's adjustment left it short
+    if frame.cpsr & CPSR_THUMB != 0 {
+        frame.pc += 2;
+    }
+    crash(&"Undefined instruction", frame);
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn handle_svc(frame: &mut TrapFrame) {
+    // the immediate is in the SVC instruction, just before the return address
+    let number = unsafe {
+        if frame.cpsr & CPSR_THUMB != 0 {
+            ((frame.pc - 2) as *const u16).read_volatile() as u32 & 0xFF
+        } else {
+            ((frame.pc - 4) as *const u32).read_volatile() & 0x00FF_FFFF
+        }
+    };
+    println!("Supervisor call {} from 0x{:08x}", number, frame.pc);
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn handle_prefetch_abort(frame: &mut TrapFrame) {
+    let fault = unsafe { Fault::prefetch(asm::read_ifsr(), asm::read_ifar()) };
+    crash(&format_args!("Prefetch abort, {}", fault), frame);
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn handle_data_abort(frame: &mut TrapFrame) {
+    let fault = unsafe { Fault::data(asm::read_dfsr(), asm::read_dfar()) };
+    crash(&format_args!("Data abort, {}", fault), frame);
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn handle_irq(_frame: &mut TrapFrame) {
+    crate::irq::dispatch();
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn handle_fiq(frame: &mut TrapFrame) {
+    crash(&"Unexpected FIQ", frame);
+}
@@ -0,0 +1,253 @@
+//! Decoding of the DFSR and IFSR after an abort, short-descriptor format.
+use core::fmt;
+
+/// DFSR/IFSR fault status, FS[4] is bit 10 and FS[3:0] bits 3:0
+const FS_LOW_MASK: u32 = 0xF;
+const FS_HIGH_BIT: u32 = 1 << 10;
+const DFSR_DOMAIN_SHIFT: u32 = 4;
+const DFSR_DOMAIN_MASK: u32 = 0xF;
+/// Set when the faulting access was a write
+const DFSR_WNR: u32 = 1 << 11;
+
+/// Why an access faulted. Translation table faults say at which level of
+/// the walk, 1 for a section and 2 for a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    Alignment,
+    Debug,
+    AccessFlag {
+        level: u8,
+    },
+    Translation {
+        level: u8,
+    },
+    Domain {
+        level: u8,
+    },
+    Permission {
+        level: u8,
+    },
+    CacheMaintenance,
+    External,
+    /// External abort while reading the translation tables
+    ExternalOnWalk {
+        level: u8,
+    },
+    Parity,
+    ParityOnWalk {
+        level: u8,
+    },
+    /// Imprecise external abort, the address is unknown
+    AsyncExternal,
+    AsyncParity,
+    Unknown(u32),
+}
+
+impl FaultKind {
+    fn from_status(fsr: u32) -> Self {
+        let status = (fsr & FS_LOW_MASK) | ((fsr & FS_HIGH_BIT) >> 6);
+        match status {
+            0b00001 => Self::Alignment,
+            0b00010 => Self::Debug,
+            0b00011 => Self::AccessFlag { level: 1 },
+            0b00110 => Self::AccessFlag { level: 2 },
+            0b00100 => Self::CacheMaintenance,
+            0b00101 => Self::Translation { level: 1 },
+            0b00111 => Self::Translation { level: 2 },
+            0b01001 => Self::Domain { level: 1 },
+            0b01011 => Self::Domain { level: 2 },
+            0b01101 => Self::Permission { level: 1 },
+            0b01111 => Self::Permission { level: 2 },
+            0b01000 => Self::External,
+            0b01100 => Self::ExternalOnWalk { level: 1 },
+            0b01110 => Self::ExternalOnWalk { level: 2 },
+            0b11001 => Self::Parity,
+            0b11100 => Self::ParityOnWalk { level: 1 },
+            0b11110 => Self::ParityOnWalk { level: 2 },
+            0b10110 => Self::AsyncExternal,
+            0b11000 => Self::AsyncParity,
+            _ => Self::Unknown(status),
+        }
+    }
+
+    /// Whether the fault address register holds the faulting address
+    fn has_address(self) -> bool {
+        !matches!(
+            self,
+            Self::AsyncExternal | Self::AsyncParity | Self::Debug | Self::Unknown(_)
+        )
+    }
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = |level: u8| if level == 1 { "section" } else { "page" };
+        match *self {
+            Self::Alignment => write!(f, "alignment fault"),
+            Self::Debug => write!(f, "debug event"),
+            Self::AccessFlag { level: l } => write!(f, "access flag fault ({})", level(l)),
+            Self::Translation { level: l } => write!(f, "translation fault ({})", level(l)),
+            Self::Domain { level: l } => write!(f, "domain fault ({})", level(l)),
+            Self::Permission { level: l } => write!(f, "permission fault ({})", level(l)),
+            Self::CacheMaintenance => write!(f, "fault on cache maintenance"),
+            Self::External => write!(f, "external abort"),
+            Self::ExternalOnWalk { level: l } => {
+                write!(f, "external abort on table walk (level {})", l)
+            }
+            Self::Parity => write!(f, "parity error"),
+            Self::ParityOnWalk { level: l } => {
+                write!(f, "parity error on table walk (level {})", l)
+            }
+            Self::AsyncExternal => write!(f, "asynchronous external abort"),
+            Self::AsyncParity => write!(f, "asynchronous parity error"),
+            Self::Unknown(status) => write!(f, "unknown fault status 0b{:05b}", status),
+        }
+    }
+}
+
+/// A data or prefetch abort, decoded
+#[derive(Debug, Clone, Copy)]
+pub struct Fault {
+    pub kind: FaultKind,
+    /// Faulting address, `None` when the fault doesn't record one
+    pub address: Option<u32>,
+    /// Data aborts only, prefetch aborts are always reads
+    pub write: bool,
+    pub domain: u8,
+}
+
+impl Fault {
+    pub fn data(dfsr: u32, dfar: u32) -> Self {
+        let kind = FaultKind::from_status(dfsr);
+        Self {
+            kind,
+            address: kind.has_address().then_some(dfar),
+            write: dfsr & DFSR_WNR != 0,
+            domain: ((dfsr >> DFSR_DOMAIN_SHIFT) & DFSR_DOMAIN_MASK) as u8,
+        }
+    }
+
+    pub fn prefetch(ifsr: u32, ifar: u32) -> Self {
+        let kind = FaultKind::from_status(ifsr);
+        Self {
+            kind,
+            address: kind.has_address().then_some(ifar),
+            write: false,
+            // the IFSR has no domain field
+            domain: 0,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(address) = self.address {
+            let access = if self.write { "writing" } else { "reading" };
+            write!(f, " {} 0x{:08x}", access, address)?;
+        }
+        if matches!(
+            self.kind,
+            FaultKind::Domain { .. } | FaultKind::Permission { .. }
+        ) {
+            write!(f, ", domain {}", self.domain)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_every_status() {
+        let cases = [
+            (0b00001, FaultKind::Alignment),
+            (0b00010, FaultKind::Debug),
+            (0b00011, FaultKind::AccessFlag { level: 1 }),
+            (0b00110, FaultKind::AccessFlag { level: 2 }),
+            (0b00100, FaultKind::CacheMaintenance),
+            (0b00101, FaultKind::Translation { level: 1 }),
+            (0b00111, FaultKind::Translation { level: 2 }),
+            (0b01001, FaultKind::Domain { level: 1 }),
+            (0b01011, FaultKind::Domain { level: 2 }),
+            (0b01101, FaultKind::Permission { level: 1 }),
+            (0b01111, FaultKind::Permission { level: 2 }),
+            (0b01000, FaultKind::External),
+            (0b01100, FaultKind::ExternalOnWalk { level: 1 }),
+            (0b01110, FaultKind::ExternalOnWalk { level: 2 }),
+            (0b11001, FaultKind::Parity),
+            (0b11100, FaultKind::ParityOnWalk { level: 1 }),
+            (0b11110, FaultKind::ParityOnWalk { level: 2 }),
+            (0b10110, FaultKind::AsyncExternal),
+            (0b11000, FaultKind::AsyncParity),
+        ];
+        for (status, kind) in cases {
+            // FS[4] lives at bit 10 of the register, not bit 4
+            let fsr = (status & FS_LOW_MASK) | (status >> 4) << 10;
+            assert_eq!(FaultKind::from_status(fsr), kind, "status 0b{:05b}", status);
+        }
+    }
+
+    #[test]
+    fn fs4_is_bit_10() {
+        assert_eq!(FaultKind::from_status(0x406), FaultKind::AsyncExternal);
+        // bit 4 is the domain, it doesn't make 0b10110
+        assert_eq!(
+            FaultKind::from_status(0x016),
+            FaultKind::AccessFlag { level: 2 }
+        );
+        assert_eq!(FaultKind::from_status(0x408), FaultKind::AsyncParity);
+        assert_eq!(FaultKind::from_status(0x008), FaultKind::External);
+    }
+
+    #[test]
+    fn unknown_status_keeps_all_five_bits() {
+        assert_eq!(FaultKind::from_status(0x000), FaultKind::Unknown(0));
+        assert_eq!(FaultKind::from_status(0x40A), FaultKind::Unknown(0b11010));
+    }
+
+    #[test]
+    fn only_precise_faults_have_an_address() {
+        for kind in [
+            FaultKind::AsyncExternal,
+            FaultKind::AsyncParity,
+            FaultKind::Debug,
+            FaultKind::Unknown(0),
+        ] {
+            assert!(!kind.has_address(), "{:?}", kind);
+        }
+        for kind in [
+            FaultKind::Alignment,
+            FaultKind::Translation { level: 2 },
+            FaultKind::Permission { level: 1 },
+            FaultKind::External,
+            FaultKind::ParityOnWalk { level: 1 },
+        ] {
+            assert!(kind.has_address(), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn data_abort_fields() {
+        // page permission fault writing, domain 3
+        let fault = Fault::data(1 << 11 | 3 << 4 | 0b1111, 0x8000_1234);
+        assert_eq!(fault.kind, FaultKind::Permission { level: 2 });
+        assert_eq!(fault.address, Some(0x8000_1234));
+        assert!(fault.write);
+        assert_eq!(fault.domain, 3);
+
+        // imprecise aborts leave DFAR alone
+        let fault = Fault::data(0x406, 0x8000_1234);
+        assert_eq!(fault.address, None);
+    }
+
+    #[test]
+    fn prefetch_aborts_are_reads() {
+        let fault = Fault::prefetch(1 << 11 | 0b0101, 0x9000_0000);
+        assert_eq!(fault.kind, FaultKind::Translation { level: 1 });
+        assert!(!fault.write);
+        assert_eq!(fault.domain, 0);
+    }
+}
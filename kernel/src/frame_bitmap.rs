@@ -0,0 +1,332 @@
+//! Bitmap of physical page frames, set while a frame is in use.
+//!
+//! [`FrameBitmap::init`] starts out with every frame in use, frees what the
+//! [`MemoryKind::Ram`] entries of the bootloader's memory map cover and takes
+//! back everything any other entry covers, so the kernel image, the
+//! bootloader and its stack, its translation tables, the boot info and
+//! anything reserved are never handed out. The kernel keeps one for all of
+//! DRAM in `frames`.
+use core::fmt;
+use core::ops::Range;
+
+use bootloader_types::boot_info::{MemoryKind, MemoryRegion};
+
+pub const FRAME_SIZE: usize = 4096;
+
+/// How many frames there are to hand out, and how many are left
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} frames free ({} MiB)",
+            self.free,
+            self.total,
+            self.free * FRAME_SIZE / (1024 * 1024)
+        )
+    }
+}
+
+/// `frames` frames from `base`, in `WORDS` words of 32 bits
+pub struct FrameBitmap<const WORDS: usize> {
+    used: [u32; WORDS],
+    base: usize,
+    frames: usize,
+    stats: FrameStats,
+    /// Where single frame searches start, below it everything is in use
+    hint: usize,
+}
+
+impl<const WORDS: usize> FrameBitmap<WORDS> {
+    /// Every one of the `frames` frames from `base` in use. `base` has to be
+    /// aligned to the largest alignment ever asked for, frame numbers are
+    /// aligned like the addresses they stand for.
+    pub const fn new(base: usize, frames: usize) -> Self {
+        assert!(frames <= WORDS * 32, "too many frames for the bitmap");
+        Self {
+            used: [u32::MAX; WORDS],
+            base,
+            frames,
+            stats: FrameStats { total: 0, free: 0 },
+            hint: 0,
+        }
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        self.used[frame / 32] & 1 << (frame % 32) != 0
+    }
+
+    fn set(&mut self, frames: Range<usize>, used: bool) {
+        for frame in frames {
+            if self.is_used(frame) == used {
+                continue;
+            }
+            self.used[frame / 32] ^= 1 << (frame % 32);
+            if used {
+                self.stats.free -= 1;
+            } else {
+                self.stats.free += 1;
+            }
+        }
+    }
+
+    /// First run of `count` free frames starting at a multiple of `align`
+    /// frames
+    fn find(&self, count: usize, align: usize) -> Option<usize> {
+        let mut start = self.hint.next_multiple_of(align);
+        while start + count <= self.frames {
+            match (start..start + count).rfind(|&frame| self.is_used(frame)) {
+                // nothing can start before the frame after the used one
+                Some(used) => start = (used + 1).next_multiple_of(align),
+                None => return Some(start),
+            }
+        }
+        None
+    }
+
+    fn address(&self, frame: usize) -> usize {
+        self.base + frame * FRAME_SIZE
+    }
+
+    /// Frames `region` overlaps at all, or lies on completely if `whole`
+    fn frames_of(&self, region: &MemoryRegion, whole: bool) -> Range<usize> {
+        let limit = self.address(self.frames);
+        let base = (region.base as usize).clamp(self.base, limit) - self.base;
+        let end = (region.end() as usize).clamp(self.base, limit) - self.base;
+        if whole {
+            base.div_ceil(FRAME_SIZE)..end / FRAME_SIZE
+        } else {
+            base / FRAME_SIZE..end.div_ceil(FRAME_SIZE)
+        }
+    }
+
+    /// Free the frames nothing in `memory_map` claims
+    pub fn init(&mut self, memory_map: &[MemoryRegion]) {
+        self.used.fill(u32::MAX);
+        self.stats.free = 0;
+        for region in memory_map {
+            if region.kind() == Some(MemoryKind::Ram) {
+                self.set(self.frames_of(region, true), false);
+            }
+        }
+        self.stats.total = self.stats.free;
+        for region in memory_map {
+            if region.kind() != Some(MemoryKind::Ram) {
+                self.set(self.frames_of(region, false), true);
+            }
+        }
+        self.hint = 0;
+    }
+
+    /// Address of a free frame, which is now in use
+    pub fn alloc(&mut self) -> Option<usize> {
+        let frame = self.find(1, 1)?;
+        self.set(frame..frame + 1, true);
+        self.hint = frame + 1;
+        Some(self.address(frame))
+    }
+
+    /// Address of `count` consecutive free frames, aligned to `align` bytes,
+    /// a power of two
+    pub fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<usize> {
+        assert!(
+            align.is_power_of_two(),
+            "frame alignment must be a power of two"
+        );
+        let align = align.div_ceil(FRAME_SIZE).max(1);
+        let count = count.max(1);
+        let frame = self.find(count, align)?;
+        self.set(frame..frame + count, true);
+        Some(self.address(frame))
+    }
+
+    /// Give back the `count` frames at `addr`, which all have to be in use
+    pub fn free(&mut self, addr: usize, count: usize) {
+        let limit = self.address(self.frames);
+        assert!(
+            addr.is_multiple_of(FRAME_SIZE) && (self.base..limit).contains(&addr),
+            "freeing 0x{:08x}, which isn't a frame",
+            addr
+        );
+        let first = (addr - self.base) / FRAME_SIZE;
+        assert!(
+            count <= self.frames - first,
+            "freeing {} frames at 0x{:08x}, past the last frame",
+            count,
+            addr
+        );
+        let frames = first..first + count;
+        if let Some(frame) = frames.clone().find(|&frame| !self.is_used(frame)) {
+            panic!(
+                "freeing frame 0x{:08x}, which is already free",
+                self.address(frame)
+            );
+        }
+        self.set(frames, false);
+        self.hint = self.hint.min(first);
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    const BASE: usize = 0x4000_0000;
+    /// Three words, so runs can cross word boundaries
+    const FRAMES: usize = 96;
+
+    type Bitmap = FrameBitmap<3>;
+
+    fn frame(n: usize) -> usize {
+        BASE + n * FRAME_SIZE
+    }
+
+    fn ram(first: usize, count: usize) -> MemoryRegion {
+        region(first, count, MemoryKind::Ram)
+    }
+
+    fn region(first: usize, count: usize, kind: MemoryKind) -> MemoryRegion {
+        MemoryRegion::new(frame(first) as u32, (count * FRAME_SIZE) as u32, kind)
+    }
+
+    fn all_free() -> Bitmap {
+        let mut bitmap = Bitmap::new(BASE, FRAMES);
+        bitmap.init(&[ram(0, FRAMES)]);
+        bitmap
+    }
+
+    #[test]
+    fn init_keeps_claimed_regions() {
+        let mut bitmap = Bitmap::new(BASE, FRAMES);
+        bitmap.init(&[
+            // only whole frames of RAM count
+            MemoryRegion::new(
+                frame(0) as u32 + 100,
+                (64 * FRAME_SIZE - 200) as u32,
+                MemoryKind::Ram,
+            ),
+            // and any frame something else touches is taken
+            MemoryRegion::new(frame(10) as u32 + 8, 16, MemoryKind::Kernel),
+            region(20, 4, MemoryKind::Reserved),
+        ]);
+        assert_eq!(
+            bitmap.stats(),
+            FrameStats {
+                total: 62,
+                free: 57
+            }
+        );
+        for used in [0, 10, 20, 23, 63, 64, FRAMES - 1] {
+            assert!(bitmap.is_used(used), "frame {}", used);
+        }
+        for free in [1, 9, 11, 19, 24, 62] {
+            assert!(!bitmap.is_used(free), "frame {}", free);
+        }
+    }
+
+    #[test]
+    fn init_ignores_what_lies_outside() {
+        let mut bitmap = Bitmap::new(BASE, FRAMES);
+        bitmap.init(&[
+            MemoryRegion::new(
+                (BASE - 4 * FRAME_SIZE) as u32,
+                (8 * FRAME_SIZE) as u32,
+                MemoryKind::Ram,
+            ),
+            region(FRAMES - 2, 10, MemoryKind::Ram),
+            region(FRAMES + 10, 4, MemoryKind::Reserved),
+        ]);
+        assert_eq!(bitmap.stats(), FrameStats { total: 6, free: 6 });
+    }
+
+    #[test]
+    fn finds_aligned_runs() {
+        let mut bitmap = all_free();
+        assert_eq!(bitmap.alloc_contiguous(1, FRAME_SIZE), Some(frame(0)));
+        // 16KB is four frames, the next start after frame 0 is frame 4
+        assert_eq!(bitmap.alloc_contiguous(4, 4 * FRAME_SIZE), Some(frame(4)));
+        // smaller than a frame is any frame
+        assert_eq!(bitmap.alloc_contiguous(1, 64), Some(frame(1)));
+        assert_eq!(bitmap.alloc_contiguous(0, 1), Some(frame(2)));
+        assert_eq!(bitmap.alloc_contiguous(8, 8 * FRAME_SIZE), Some(frame(8)));
+        assert_eq!(bitmap.stats().free, FRAMES - 15);
+    }
+
+    #[test]
+    fn finds_runs_across_words() {
+        let mut bitmap = all_free();
+        assert_eq!(bitmap.alloc_contiguous(30, FRAME_SIZE), Some(frame(0)));
+        // frames 30 and 31 are too few, the run goes on into the next word
+        assert_eq!(bitmap.alloc_contiguous(40, FRAME_SIZE), Some(frame(30)));
+        assert_eq!(bitmap.alloc_contiguous(26, FRAME_SIZE), Some(frame(70)));
+        assert_eq!(bitmap.alloc_contiguous(1, FRAME_SIZE), None);
+        assert_eq!(bitmap.stats().free, 0);
+    }
+
+    #[test]
+    fn skips_past_used_frames() {
+        let mut bitmap = Bitmap::new(BASE, FRAMES);
+        bitmap.init(&[ram(0, FRAMES), region(33, 1, MemoryKind::Reserved)]);
+        assert_eq!(bitmap.alloc_contiguous(33, FRAME_SIZE), Some(frame(0)));
+        assert_eq!(bitmap.alloc_contiguous(4, FRAME_SIZE), Some(frame(34)));
+        assert_eq!(bitmap.alloc_contiguous(64, FRAME_SIZE), None);
+    }
+
+    #[test]
+    fn single_frames_follow_the_hint() {
+        let mut bitmap = all_free();
+        assert_eq!(bitmap.alloc(), Some(frame(0)));
+        assert_eq!(bitmap.alloc(), Some(frame(1)));
+        assert_eq!(bitmap.hint, 2);
+        assert_eq!(bitmap.alloc_contiguous(2, FRAME_SIZE), Some(frame(2)));
+        // the hint only moves back when something below it is freed
+        bitmap.free(frame(1), 1);
+        assert_eq!(bitmap.hint, 1);
+        assert_eq!(bitmap.alloc(), Some(frame(1)));
+        assert_eq!(bitmap.alloc(), Some(frame(4)));
+    }
+
+    #[test]
+    fn free_gives_frames_back() {
+        let mut bitmap = all_free();
+        let addr = bitmap.alloc_contiguous(40, FRAME_SIZE).unwrap();
+        bitmap.free(addr + 10 * FRAME_SIZE, 20);
+        assert_eq!(bitmap.stats().free, FRAMES - 20);
+        assert_eq!(bitmap.alloc_contiguous(20, FRAME_SIZE), Some(frame(10)));
+        bitmap.free(addr, 40);
+        assert_eq!(bitmap.stats().free, FRAMES);
+    }
+
+    #[test]
+    #[should_panic(expected = "already free")]
+    fn double_free_panics() {
+        let mut bitmap = all_free();
+        let addr = bitmap.alloc_contiguous(2, FRAME_SIZE).unwrap();
+        bitmap.free(addr, 2);
+        bitmap.free(addr + FRAME_SIZE, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "past the last frame")]
+    fn free_past_the_end_panics() {
+        let mut bitmap = all_free();
+        bitmap.alloc_contiguous(FRAMES, FRAME_SIZE).unwrap();
+        bitmap.free(frame(FRAMES - 2), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't a frame")]
+    fn free_of_a_non_frame_panics() {
+        let mut bitmap = all_free();
+        bitmap.free(frame(1) + 8, 1);
+    }
+}
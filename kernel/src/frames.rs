@@ -0,0 +1,45 @@
+//! Physical page frames, from the DRAM the bootloader's memory map leaves
+//! free.
+//!
+//! A [`FrameBitmap`] over all of DRAM, see there for what [`init`] hands
+//! out. DRAM is identity mapped, so a frame's physical address is also
+//! where the kernel reaches it.
+use core::cell::UnsafeCell;
+
+use bootloader_types::boot_info::MemoryRegion;
+use hal::dram::{DRAM_END, DRAM_START};
+use hal::interrupts;
+use kernel_core::frame_bitmap::FrameBitmap;
+pub use kernel_core::frame_bitmap::{FRAME_SIZE, FrameStats};
+
+const FRAMES: usize = (DRAM_END - DRAM_START + 1) / FRAME_SIZE;
+const WORDS: usize = FRAMES.div_ceil(32);
+
+/// Only touched with interrupts masked
+struct Frames(UnsafeCell<FrameBitmap<WORDS>>);
+
+unsafe impl Sync for Frames {}
+
+// DRAM starts on a boundary of its own size, so frame numbers align like
+// addresses do
+static BITMAP: Frames = Frames(UnsafeCell::new(FrameBitmap::new(DRAM_START, FRAMES)));
+
+fn with<R>(f: impl FnOnce(&mut FrameBitmap<WORDS>) -> R) -> R {
+    interrupts::free(|| f(unsafe { &mut *BITMAP.0.get() }))
+}
+
+/// Free the frames of DRAM nothing in `memory_map` claims
+pub fn init(memory_map: &[MemoryRegion]) {
+    with(|bitmap| bitmap.init(memory_map))
+}
+
+/// Physical address of `count` consecutive free frames, aligned to `align`
+/// bytes, a power of two. L1 translation tables need 16KB alignment, for
+/// one.
+pub fn alloc_contiguous(count: usize, align: usize) -> Option<usize> {
+    with(|bitmap| bitmap.alloc_contiguous(count, align))
+}
+
+pub fn stats() -> FrameStats {
+    with(|bitmap| bitmap.stats())
+}
@@ -0,0 +1,379 @@
+//! First-fit free list behind the kernel heap.
+//!
+//! Free blocks are kept in address order, so freed blocks merge with their
+//! neighbours. When nothing fits, [`FreeList::alloc`] grows the list by
+//! whole frames from the frame allocator. Every block is a multiple of
+//! [`BLOCK_ALIGN`] and at least big enough to hold a free list node once it
+//! is freed.
+use core::alloc::Layout;
+use core::fmt;
+use core::ptr::NonNull;
+
+use crate::frame_bitmap::FRAME_SIZE;
+
+/// Fewest frames the list grows by
+pub const GROW_FRAMES: usize = 16;
+
+pub const BLOCK_ALIGN: usize = size_of::<FreeBlock>();
+pub const MIN_BLOCK: usize = size_of::<FreeBlock>();
+
+/// Header of a free block, at its start
+struct FreeBlock {
+    size: usize,
+    next: Option<NonNull<FreeBlock>>,
+}
+
+/// How much of the heap is in use, and how much ever was
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Bytes the heap manages
+    pub size: usize,
+    pub used: usize,
+    /// Most bytes ever in use at once
+    pub peak: usize,
+    /// Live allocations
+    pub allocations: usize,
+    /// Allocations that found no space
+    pub failures: usize,
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} KiB used, peak {} KiB, {} allocations, {} failed",
+            self.used / 1024,
+            self.size / 1024,
+            self.peak / 1024,
+            self.allocations,
+            self.failures
+        )
+    }
+}
+
+/// Round `size` up for a block, so what's left over can always be freed
+fn block_size(layout: Layout) -> usize {
+    layout.size().max(MIN_BLOCK).next_multiple_of(BLOCK_ALIGN)
+}
+
+pub struct FreeList {
+    free: Option<NonNull<FreeBlock>>,
+    stats: HeapStats,
+}
+
+impl Default for FreeList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FreeList {
+    pub const fn new() -> Self {
+        Self {
+            free: None,
+            stats: HeapStats {
+                size: 0,
+                used: 0,
+                peak: 0,
+                allocations: 0,
+                failures: 0,
+            },
+        }
+    }
+
+    /// Hand `size` bytes at `addr` to the free list, merging them with
+    /// the free blocks either side
+    ///
+    /// # Safety
+    /// The memory has to be unused, aligned to [`BLOCK_ALIGN`] and a
+    /// multiple of it in size, at least [`MIN_BLOCK`].
+    unsafe fn free(&mut self, addr: usize, size: usize) {
+        let mut prev: Option<NonNull<FreeBlock>> = None;
+        let mut next = self.free;
+        while let Some(block) = next
+            && (block.as_ptr() as usize) < addr
+        {
+            prev = next;
+            next = unsafe { block.as_ref().next };
+        }
+
+        let mut size = size;
+        if let Some(block) = next
+            && block.as_ptr() as usize == addr + size
+        {
+            let block = unsafe { block.as_ref() };
+            size += block.size;
+            next = block.next;
+        }
+        if let Some(mut block) = prev {
+            let block = unsafe { block.as_mut() };
+            if block as *mut FreeBlock as usize + block.size == addr {
+                block.size += size;
+                block.next = next;
+                return;
+            }
+        }
+
+        let node = addr as *mut FreeBlock;
+        unsafe { node.write(FreeBlock { size, next }) };
+        let node = NonNull::new(node);
+        match prev {
+            Some(mut block) => unsafe { block.as_mut().next = node },
+            None => self.free = node,
+        }
+    }
+
+    /// Carve an allocation for `layout` out of the first free block it fits
+    fn allocate(&mut self, layout: Layout) -> Option<usize> {
+        let size = block_size(layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+
+        let mut prev: Option<NonNull<FreeBlock>> = None;
+        let mut next = self.free;
+        while let Some(block) = next {
+            let start = block.as_ptr() as usize;
+            let (end, after) = unsafe { (start + block.as_ref().size, block.as_ref().next) };
+            let mut addr = start.next_multiple_of(align);
+            // padding in front has to be able to stay on the list
+            if addr != start && addr - start < MIN_BLOCK {
+                addr = (start + MIN_BLOCK).next_multiple_of(align);
+            }
+            let tail = end.checked_sub(addr + size);
+            if let Some(tail) = tail
+                && (tail == 0 || tail >= MIN_BLOCK)
+            {
+                match prev {
+                    Some(mut block) => unsafe { block.as_mut().next = after },
+                    None => self.free = after,
+                }
+                unsafe {
+                    if addr != start {
+                        self.free(start, addr - start);
+                    }
+                    if tail != 0 {
+                        self.free(addr + size, tail);
+                    }
+                }
+                return Some(addr);
+            }
+            prev = next;
+            next = after;
+        }
+        None
+    }
+
+    /// Take enough frames for `layout` from `alloc_frames`, which is called
+    /// like [`crate::frame_bitmap::FrameBitmap::alloc_contiguous`]
+    fn grow(
+        &mut self,
+        layout: Layout,
+        alloc_frames: impl FnOnce(usize, usize) -> Option<usize>,
+    ) -> Option<()> {
+        // frames are already aligned enough for anything up to a frame
+        let count = block_size(layout).div_ceil(FRAME_SIZE).max(GROW_FRAMES);
+        let addr = alloc_frames(count, layout.align().max(FRAME_SIZE))?;
+        unsafe { self.free(addr, count * FRAME_SIZE) };
+        self.stats.size += count * FRAME_SIZE;
+        Some(())
+    }
+
+    /// Address of a block for `layout`, growing the list through
+    /// `alloc_frames` if nothing fits
+    pub fn alloc(
+        &mut self,
+        layout: Layout,
+        alloc_frames: impl FnOnce(usize, usize) -> Option<usize>,
+    ) -> Option<usize> {
+        let addr = self.allocate(layout).or_else(|| {
+            self.grow(layout, alloc_frames)?;
+            self.allocate(layout)
+        });
+        match addr {
+            Some(_) => {
+                let stats = &mut self.stats;
+                stats.used += block_size(layout);
+                stats.peak = stats.peak.max(stats.used);
+                stats.allocations += 1;
+            }
+            None => self.stats.failures += 1,
+        }
+        addr
+    }
+
+    /// Give back the block at `addr`
+    ///
+    /// # Safety
+    /// `addr` has to come from [`Self::alloc`] with the same `layout`, and
+    /// not have been given back already.
+    pub unsafe fn dealloc(&mut self, addr: usize, layout: Layout) {
+        let size = block_size(layout);
+        unsafe { self.free(addr, size) };
+        self.stats.used -= size;
+        self.stats.allocations -= 1;
+    }
+
+    /// Add `size` bytes at `base` to the list, trimmed to whole blocks
+    ///
+    /// # Safety
+    /// The memory has to be mapped, writable and not used for anything else,
+    /// for as long as the list is.
+    pub unsafe fn add_region(&mut self, base: usize, size: usize) {
+        let start = base.next_multiple_of(BLOCK_ALIGN);
+        let end = (base + size) & !(BLOCK_ALIGN - 1);
+        if end < start + MIN_BLOCK {
+            return;
+        }
+        unsafe { self.free(start, end - start) };
+        self.stats.size += end - start;
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.stats
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use crate::frame_bitmap::FrameBitmap;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    /// 32 frames of host memory, aligned like DRAM frames
+    #[repr(C, align(4096))]
+    struct Arena([u8; 32 * FRAME_SIZE]);
+
+    fn arena() -> Box<Arena> {
+        // straight on the heap, it's too big for a test thread's stack
+        let layout = Layout::new::<Arena>();
+        unsafe { Box::from_raw(std::alloc::alloc_zeroed(layout) as *mut Arena) }
+    }
+
+    fn no_frames(_count: usize, _align: usize) -> Option<usize> {
+        None
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    /// Free blocks as (offset from `base`, size), in list order
+    fn blocks(list: &FreeList, base: usize) -> Vec<(usize, usize)> {
+        let mut blocks = Vec::new();
+        let mut next = list.free;
+        while let Some(block) = next {
+            let block = unsafe { block.as_ref() };
+            blocks.push((block as *const FreeBlock as usize - base, block.size));
+            next = block.next;
+        }
+        blocks
+    }
+
+    /// A list over the first `size` bytes of `arena`
+    fn list_over(arena: &mut Arena, size: usize) -> (FreeList, usize) {
+        let base = arena.0.as_mut_ptr() as usize;
+        let mut list = FreeList::new();
+        unsafe { list.add_region(base, size) };
+        (list, base)
+    }
+
+    #[test]
+    fn splits_blocks() {
+        let mut arena = arena();
+        let (mut list, base) = list_over(&mut arena, 1024);
+        assert_eq!(list.alloc(layout(64, 1), no_frames), Some(base));
+        assert_eq!(blocks(&list, base), [(64, 960)]);
+        // rounded up to a whole block
+        assert_eq!(list.alloc(layout(1, 1), no_frames), Some(base + 64));
+        assert_eq!(blocks(&list, base), [(64 + MIN_BLOCK, 960 - MIN_BLOCK)]);
+        // an exact fit leaves nothing behind
+        let rest = 960 - MIN_BLOCK;
+        assert!(list.alloc(layout(rest, 1), no_frames).is_some());
+        assert_eq!(blocks(&list, base), []);
+        assert_eq!(
+            list.stats(),
+            HeapStats {
+                size: 1024,
+                used: 1024,
+                peak: 1024,
+                allocations: 3,
+                failures: 0,
+            }
+        );
+        assert_eq!(list.alloc(layout(1, 1), no_frames), None);
+        assert_eq!(list.stats().failures, 1);
+    }
+
+    #[test]
+    fn merges_neighbours() {
+        let mut arena = arena();
+        let (mut list, base) = list_over(&mut arena, 1024);
+        let block = layout(128, 1);
+        let addrs: Vec<usize> = (0..4)
+            .map(|_| list.alloc(block, no_frames).unwrap())
+            .collect();
+        unsafe {
+            list.dealloc(addrs[0], block);
+            list.dealloc(addrs[2], block);
+            assert_eq!(blocks(&list, base), [(0, 128), (256, 128), (512, 512)]);
+            // with the free blocks on both sides
+            list.dealloc(addrs[1], block);
+            assert_eq!(blocks(&list, base), [(0, 384), (512, 512)]);
+            list.dealloc(addrs[3], block);
+        }
+        assert_eq!(blocks(&list, base), [(0, 1024)]);
+        assert_eq!(list.stats().used, 0);
+        assert_eq!(list.stats().peak, 512);
+    }
+
+    #[test]
+    fn pads_for_alignment() {
+        let mut arena = arena();
+        let base = arena.0.as_mut_ptr() as usize;
+        // starts a block past a 256 byte boundary
+        let mut list = FreeList::new();
+        unsafe { list.add_region(base + BLOCK_ALIGN, 1024) };
+
+        assert_eq!(list.alloc(layout(32, 256), no_frames), Some(base + 256));
+        // the padding in front stays free
+        assert_eq!(
+            blocks(&list, base),
+            [
+                (BLOCK_ALIGN, 256 - BLOCK_ALIGN),
+                (256 + 32, 1024 - 256 - 32 + BLOCK_ALIGN)
+            ]
+        );
+        unsafe { list.dealloc(base + 256, layout(32, 256)) };
+        assert_eq!(blocks(&list, base), [(BLOCK_ALIGN, 1024)]);
+    }
+
+    #[test]
+    fn grows_by_frames() {
+        let mut arena = arena();
+        let base = arena.0.as_mut_ptr() as usize;
+        let mut frames = FrameBitmap::<1>::new(base, 32);
+        frames.free(base, 32);
+        let mut list = FreeList::new();
+
+        let small = list.alloc(layout(100, 8), |count, align| {
+            frames.alloc_contiguous(count, align)
+        });
+        assert_eq!(small, Some(base));
+        assert_eq!(list.stats().size, GROW_FRAMES * FRAME_SIZE);
+        assert_eq!(frames.stats().free, 32 - GROW_FRAMES);
+
+        // too big for what's left, the new frames merge with it
+        let big = layout(GROW_FRAMES * FRAME_SIZE, 1);
+        let addr = list.alloc(big, |count, align| frames.alloc_contiguous(count, align));
+        assert_eq!(addr, Some(base + block_size(layout(100, 8))));
+        assert_eq!(frames.stats().free, 32 - 2 * GROW_FRAMES);
+        assert_eq!(list.stats().size, 2 * GROW_FRAMES * FRAME_SIZE);
+
+        // the frames allocator is out
+        let huge = layout(20 * FRAME_SIZE, 1);
+        let addr = list.alloc(huge, |count, align| frames.alloc_contiguous(count, align));
+        assert_eq!(addr, None);
+        assert_eq!(list.stats().failures, 1);
+        assert_eq!(list.stats().allocations, 2);
+    }
+}
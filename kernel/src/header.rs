@@ -0,0 +1,45 @@
+//! The header the bootloader checks before jumping to the kernel, see
+//! `bootloader_types::kernel_abi`. `kernel.ld` places it first in `.text`.
+use bootloader_types::boot_info::{BOOT_INFO_VERSION, BootInfoHeader};
+use bootloader_types::kernel_abi::{KERNEL_ABI_HEADER_SIZE, KERNEL_ABI_MAGIC, KERNEL_ABI_VERSION};
+
+/// Least DRAM the kernel runs in
+const MIN_RAM: u32 = 64 << 20;
+
+/// The layout documented in `kernel_abi`. Addresses and sizes only exist once
+/// the kernel is linked, so they are pointers to linker symbols here.
+#[repr(C)]
+struct AbiHeader {
+    magic: [u8; 4],
+    abi_version: u32,
+    load_address: *const u8,
+    entry: unsafe extern "C" fn(*const BootInfoHeader) -> !,
+    image_size: *const u8,
+    bss_size: *const u8,
+    min_ram: u32,
+    boot_info_version: u32,
+}
+
+// never written, and only read by the bootloader
+unsafe impl Sync for AbiHeader {}
+
+const _: () = assert!(core::mem::size_of::<AbiHeader>() == KERNEL_ABI_HEADER_SIZE);
+
+unsafe extern "C" {
+    static __kernel_phys_base: u8;
+    static __kernel_image_size: u8;
+    static __kernel_bss_size: u8;
+}
+
+#[used]
+#[unsafe(link_section = ".kernel_header")]
+static HEADER: AbiHeader = AbiHeader {
+    magic: KERNEL_ABI_MAGIC,
+    abi_version: KERNEL_ABI_VERSION,
+    load_address: &raw const __kernel_phys_base,
+    entry: crate::_start,
+    image_size: &raw const __kernel_image_size,
+    bss_size: &raw const __kernel_bss_size,
+    min_ram: MIN_RAM,
+    boot_info_version: BOOT_INFO_VERSION,
+};
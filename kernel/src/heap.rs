@@ -0,0 +1,81 @@
+//! Kernel heap, the global allocator behind `alloc`.
+//!
+//! A [`FreeList`] that starts out with [`HEAP_SIZE`] bytes of `.bss`,
+//! enough to get by until [`crate::frames`] is up, and grows from there
+//! whenever it runs out. The list is only touched with interrupts masked,
+//! so IRQ handlers may allocate too.
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ptr;
+
+use hal::interrupts;
+use kernel_core::free_list::FreeList;
+pub use kernel_core::free_list::HeapStats;
+
+use crate::frames;
+
+/// Bytes of heap in `.bss`
+const HEAP_SIZE: usize = 512 * 1024;
+
+/// Only touched with interrupts masked
+pub struct KernelHeap(UnsafeCell<FreeList>);
+
+unsafe impl Sync for KernelHeap {}
+
+impl KernelHeap {
+    fn with<R>(&self, f: impl FnOnce(&mut FreeList) -> R) -> R {
+        interrupts::free(|| f(unsafe { &mut *self.0.get() }))
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.with(|heap| match heap.alloc(layout, frames::alloc_contiguous) {
+            Some(addr) => addr as *mut u8,
+            None => ptr::null_mut(),
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.with(|heap| unsafe { heap.dealloc(ptr as usize, layout) })
+    }
+}
+
+#[global_allocator]
+static HEAP: KernelHeap = KernelHeap(UnsafeCell::new(FreeList::new()));
+
+#[repr(C, align(8))]
+struct HeapSpace(UnsafeCell<[u8; HEAP_SIZE]>);
+
+unsafe impl Sync for HeapSpace {}
+
+static HEAP_SPACE: HeapSpace = HeapSpace(UnsafeCell::new([0; HEAP_SIZE]));
+
+/// Give the heap its `.bss` space
+pub fn init() {
+    unsafe { add_region(HEAP_SPACE.0.get() as usize, HEAP_SIZE) };
+}
+
+/// Grow the heap by `size` bytes at `base`, trimmed to whole blocks
+///
+/// # Safety
+/// The memory has to be mapped, writable and not used for anything else,
+/// for good.
+pub unsafe fn add_region(base: usize, size: usize) {
+    HEAP.with(|heap| unsafe { heap.add_region(base, size) })
+}
+
+pub fn stats() -> HeapStats {
+    HEAP.with(|heap| heap.stats())
+}
+
+#[cfg(not(test))]
+#[alloc_error_handler]
+fn out_of_memory(layout: Layout) -> ! {
+    panic!(
+        "out of memory allocating {} bytes aligned to {}, heap {}",
+        layout.size(),
+        layout.align(),
+        stats()
+    );
+}
@@ -0,0 +1,87 @@
+//! Device interrupts, dispatched from the IRQ vector to a handler per line.
+use core::cell::UnsafeCell;
+
+use hal::{intc, interrupts, println};
+
+/// Called in IRQ mode with the line that fired, and has to quiet the device
+/// before returning or the IRQ is taken again straight away
+pub type Handler = fn(line: u32);
+
+#[derive(Debug)]
+pub enum IrqError {
+    /// The interrupt controller has no such line
+    BadLine(u32),
+    /// Another handler has the line
+    InUse(u32),
+}
+
+impl core::fmt::Display for IrqError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::BadLine(line) => write!(f, "no IRQ line {}", line),
+            Self::InUse(line) => write!(f, "IRQ line {} already has a handler", line),
+        }
+    }
+}
+
+const LINES: usize = intc::LINES as usize;
+
+/// Only touched with interrupts masked, or from the IRQ handler
+struct Handlers(UnsafeCell<[Option<Handler>; LINES]>);
+
+unsafe impl Sync for Handlers {}
+
+static HANDLERS: Handlers = Handlers(UnsafeCell::new([None; LINES]));
+
+/// Start taking IRQs, with every line disabled until it gets a handler.
+/// The exception vectors have to be installed first.
+pub fn init() {
+    intc::init();
+    unsafe { interrupts::enable_irq() };
+}
+
+/// Call `handler` whenever `line` raises an interrupt, and enable it
+pub fn register_irq(line: u32, handler: Handler) -> Result<(), IrqError> {
+    let slot = line as usize;
+    if slot >= LINES {
+        return Err(IrqError::BadLine(line));
+    }
+    interrupts::free(|| {
+        let handlers = unsafe { &mut *HANDLERS.0.get() };
+        if handlers[slot].is_some() {
+            return Err(IrqError::InUse(line));
+        }
+        handlers[slot] = Some(handler);
+        intc::enable(line);
+        Ok(())
+    })
+}
+
+/// Disable `line` and drop its handler
+// for drivers that shut down, none do yet
+#[allow(dead_code)]
+pub fn unregister_irq(line: u32) {
+    let slot = line as usize;
+    if slot >= LINES {
+        return;
+    }
+    interrupts::free(|| {
+        intc::disable(line);
+        unsafe { (*HANDLERS.0.get())[slot] = None };
+    });
+}
+
+/// Hand the pending IRQ to its handler, from the IRQ vector
+pub fn dispatch() {
+    let Some(line) = intc::pending() else {
+        // the device let go of the line before we got here
+        return;
+    };
+    match unsafe { (*HANDLERS.0.get())[line as usize] } {
+        Some(handler) => handler(line),
+        None => {
+            println!("IRQ on line {} without a handler, disabling it", line);
+            intc::disable(line);
+        }
+    }
+}
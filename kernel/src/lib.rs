@@ -0,0 +1,30 @@
+//! The parts of the kernel that don't touch the hardware, in a library so
+//! `make test` can run their unit tests on the host.
+#![no_std]
+#![cfg_attr(all(test, target_os = "none"), feature(custom_test_frameworks))]
+#![cfg_attr(all(test, target_os = "none"), test_runner(crate::test_runner))]
+#![cfg_attr(
+    all(test, target_os = "none"),
+    reexport_test_harness_main = "test_main"
+)]
+
+// unit tests run on the host with std, `make test`
+#[cfg(all(test, not(target_os = "none")))]
+extern crate std;
+
+pub mod fault;
+pub mod frame_bitmap;
+pub mod free_list;
+
+#[cfg(all(test, target_os = "none"))]
+fn test_runner(tests: &[&dyn Fn()]) {
+    for test in tests {
+        test();
+    }
+}
+
+#[cfg(all(test, target_os = "none"))]
+#[panic_handler]
+fn test_panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -1,17 +1,81 @@
 #![no_std]
 #![no_main]
+#![cfg_attr(not(test), feature(alloc_error_handler))]
 #![cfg_attr(test, feature(custom_test_frameworks))]
 #![cfg_attr(test, test_runner(crate::test_runner))]
 #![cfg_attr(test, reexport_test_harness_main = "test_main")]
 
-use bootloader_types::BootInfoHeader;
-use hal::{dbg, println};
+extern crate alloc;
 
+use bootloader_types::boot_info::{BootInfo, BootInfoHeader, MemoryKind, Tag};
+use hal::println;
+
+mod exceptions;
+mod frames;
+mod header;
+mod heap;
+mod irq;
+mod panic;
+mod slot;
+mod time;
+
+/// # Safety
+/// Only called by the bootloader, `info` must point to the boot info it built.
 #[unsafe(no_mangle)]
-pub extern "C" fn _start(info: &mut BootInfoHeader) -> ! {
+pub unsafe extern "C" fn _start(info: *const BootInfoHeader) -> ! {
     println!("Hello, world!");
-    println!("This is aasdfasdf test, sizeasdfs: {:x}", info.boot_size);
-    dbg!(info);
+    exceptions::init();
+    heap::init();
+    irq::init();
+    time::init();
+    let info = match unsafe { BootInfo::from_ptr(info) } {
+        Ok(info) => info,
+        Err(e) => panic!("Invalid boot info at {:p}: {:?}", info, e),
+    };
+
+    frames::init(info.memory_map());
+    println!("Physical memory: {}", frames::stats());
+
+    for tag in info.tags() {
+        match tag {
+            Tag::MemoryMap(regions) => {
+                for region in regions {
+                    let kind = region.kind().unwrap_or(MemoryKind::Reserved);
+                    if kind == MemoryKind::PanicRecord {
+                        panic::set_record_area(region.base);
+                    }
+                    println!("  0x{:08x}-0x{:08x} {:?}", region.base, region.end(), kind);
+                }
+            }
+            Tag::BoardName(name) => println!("Board: {}", name),
+            Tag::CommandLine(cmdline) => println!("Command line: {}", cmdline),
+            Tag::BootSlot(state) if state.confirmed => {
+                println!("Booted from slot {}", state.active.name())
+            }
+            Tag::BootSlot(state) => {
+                println!("Confirming slot {}", state.active.name());
+                if let Err(e) = slot::confirm(&state) {
+                    println!("Failed to confirm slot: {:?}", e);
+                }
+            }
+            Tag::BootTimes(stages) => {
+                for stage in stages.iter() {
+                    println!("  {:<16} {:>8} us", stage.name(), stage.time_us);
+                }
+            }
+            Tag::Unknown { tag, data } => {
+                println!(
+                    "Skipping unknown boot info tag {} ({} bytes)",
+                    tag,
+                    data.len()
+                )
+            }
+        }
+    }
+    if let Some(initrd) = info.initrd() {
+        println!("Initrd at 0x{:08x}, {} bytes", initrd.base, initrd.size);
+    }
+    println!("Heap: {}", heap::stats());
     todo!("End of kernel main");
 }
 
@@ -31,13 +95,3 @@ use core::panic::PanicInfo;
 fn test_panic(_info: &PanicInfo) -> ! {
     loop {} // Halt the system on panic
 }
-
-#[cfg(not(test))]
-mod panic_handler {
-    use core::panic::PanicInfo;
-    /// Panic handler (required for `no_std`)
-    #[panic_handler]
-    fn panic(_info: &PanicInfo) -> ! {
-        loop {}
-    }
-}
@@ -0,0 +1,53 @@
+//! Panic handling.
+//!
+//! A panic prints its message, the registers and a backtrace, leaves a
+//! [`PanicRecord`] where the bootloader's memory map said to, and resets the
+//! board with the watchdog after [`hal::watchdog::PANIC_RESET_DELAY`] seconds
+//! so the next boot reports it.
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Address of the panic record area, 0 until the boot info named one
+static RECORD_AREA: AtomicUsize = AtomicUsize::new(0);
+
+/// Leave panic records at `base` from now on
+pub fn set_record_area(base: u32) {
+    RECORD_AREA.store(base as usize, Ordering::Relaxed);
+}
+
+#[cfg(not(test))]
+mod panic_handler {
+    use super::RECORD_AREA;
+    use bootloader_types::panic_record::{PanicRecord, RECORD_SIZE};
+    use core::fmt::Write;
+    use core::panic::PanicInfo;
+    use core::sync::atomic::{AtomicBool, Ordering, fence};
+    use hal::backtrace::{self, Registers};
+    use hal::{mmu, println, watchdog};
+
+    static PANICKING: AtomicBool = AtomicBool::new(false);
+
+    /// Panic handler (required for `no_std`)
+    #[panic_handler]
+    fn panic(info: &PanicInfo) -> ! {
+        let registers = Registers::capture();
+        if PANICKING.swap(true, Ordering::Relaxed) {
+            // reporting the first panic panicked, don't try again
+            watchdog::reset();
+        }
+        println!("\nKernel panic: {}", info);
+        backtrace::print(&registers);
+
+        let area = RECORD_AREA.load(Ordering::Relaxed);
+        if area != 0 {
+            let mut record = PanicRecord::new("kernel", registers.cpsr, registers.sp, registers.lr);
+            let _ = write!(record, "{}", info.message());
+            record.store(unsafe { core::slice::from_raw_parts_mut(area as *mut u8, RECORD_SIZE) });
+            // the record has to be in DRAM before the watchdog fires
+            fence(Ordering::SeqCst);
+            mmu::clean_d_cache_range(area, RECORD_SIZE);
+        }
+
+        println!("Resetting in {}s", watchdog::PANIC_RESET_DELAY);
+        watchdog::reset_in(watchdog::PANIC_RESET_DELAY)
+    }
+}
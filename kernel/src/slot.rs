@@ -0,0 +1,45 @@
+//! Confirming an A/B slot boot.
+//!
+//! The bootloader gives a freshly activated slot a few tries to come up. Once
+//! the kernel is far enough along it marks the slot good in the state file so
+//! it isn't rolled back on the next reset.
+use bootloader_types::slot::{STATE_PATH, STATE_SIZE, SlotState};
+use fat32::{Fat32Error, Fat32FileSystem};
+
+unsafe extern "C" fn read_sector(sector: u32, buffer: *mut u8) -> i32 {
+    if buffer.is_null() {
+        return -1;
+    }
+
+    let buffer_slice: &mut [u8; 512] = unsafe { &mut *(buffer as *mut [u8; 512]) };
+    match hal::mmc::read_sector(sector, buffer_slice) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+unsafe extern "C" fn write_sector(sector: u32, buffer: *const u8) -> i32 {
+    if buffer.is_null() {
+        return -1;
+    }
+
+    let buffer_slice: &[u8; 512] = unsafe { &*(buffer as *const [u8; 512]) };
+    match hal::mmc::write_sector(sector, buffer_slice) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Mark the slot we booted from as good. The bootloader only hands over a
+/// slot after booting from MMC, so the controller is already initialized.
+pub fn confirm(state: &SlotState) -> Result<(), Fat32Error> {
+    let mut state = *state;
+    state.confirm();
+
+    let mut fs = Fat32FileSystem::from_fns(read_sector, write_sector)?;
+    let mut file = fs.open_file(STATE_PATH)?;
+    if file.write(&state.to_bytes())? != STATE_SIZE {
+        return Err(Fat32Error::NoSpace);
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+//! The scheduling tick and sleeping on it.
+//!
+//! The alarm fires every [`TICK`], which also keeps the clock from missing
+//! a wrap. Without a tick, on boards whose alarm can't interrupt yet,
+//! [`sleep`] spins instead of waiting for interrupts.
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::time::Duration;
+
+use hal::timer::{self, AlarmMode, Instant};
+use hal::{asm, println};
+
+use crate::irq;
+
+pub const TICK: Duration = Duration::from_millis(10);
+
+static TICKS: AtomicU32 = AtomicU32::new(0);
+static TICKING: AtomicBool = AtomicBool::new(false);
+
+/// Restart the clock and start the tick, IRQs have to be up
+pub fn init() {
+    timer::init();
+    if let Err(e) = irq::register_irq(timer::ALARM_IRQ, on_tick) {
+        println!("No timer tick, alarm interrupt unavailable: {}", e);
+        return;
+    }
+    TICKING.store(true, Ordering::Relaxed);
+    timer::start_alarm(TICK, AlarmMode::Periodic);
+}
+
+fn on_tick(_line: u32) {
+    timer::clear_alarm();
+    TICKS.fetch_add(1, Ordering::Relaxed);
+    Instant::now();
+}
+
+/// Ticks since [`init`]
+// for the scheduler, which isn't written yet
+#[allow(dead_code)]
+pub fn ticks() -> u32 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Wait at least `duration`, sleeping between ticks
+pub fn sleep(duration: Duration) {
+    let end = Instant::now() + duration;
+    while Instant::now() < end {
+        if TICKING.load(Ordering::Relaxed) {
+            unsafe { asm::wfi() };
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+// nothing in the kernel waits yet
+#[allow(dead_code)]
+pub fn sleep_ms(ms: u64) {
+    sleep(Duration::from_millis(ms))
+}
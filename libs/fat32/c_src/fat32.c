@@ -325,6 +325,89 @@ int fat32_read(fat32_file_t* file, void* buffer, int size)
     return bytes_read;
 }
 
+int fat32_write(fat32_file_t* file, const void* buffer, int size)
+{
+    fat32_fs_t* fs;
+    int32_t cluster_result;
+    uint32_t cluster_size;
+    uint32_t bytes_written = 0;
+    const uint8_t* buf_ptr = (const uint8_t*) buffer;
+    uint32_t remaining     = size;
+    uint32_t max_writable;
+
+    if (!file || !buffer || size < 0)
+    {
+        return FAT32_ERROR_BAD_PARAMETER;
+    }
+
+    fs = file->fs;
+    if (!fs->disk.write_sector)
+    {
+        return FAT32_ERROR_READ_ONLY;
+    }
+
+    if (file->file_offset >= file->file_size || size == 0)
+    {
+        return 0;
+    }
+
+    cluster_size = fs->bytes_per_sector * fs->sectors_per_cluster;
+
+    /* In place only, never past the end of the file */
+    max_writable = file->file_size - file->file_offset;
+    if (remaining > max_writable)
+    {
+        remaining = max_writable;
+    }
+
+    while (remaining > 0)
+    {
+        uint32_t cluster_offset = file->file_offset % cluster_size;
+        uint32_t cluster_index  = file->file_offset / cluster_size;
+        uint32_t sector_offset  = cluster_offset % fs->bytes_per_sector;
+        uint32_t bytes_in_sector, bytes_to_sector, current_sector;
+        uint32_t target_cluster;
+        size_t i;
+        uint8_t sector_buffer[FAT32_SECTOR_SIZE] __attribute__((aligned(8))); /* 8byte alignment for arm */
+
+        int result = get_cluster_at_index(fs, file->start_cluster, cluster_index, &target_cluster);
+        if (result != FAT32_SUCCESS)
+        {
+            return bytes_written > 0 ? (int) bytes_written : result;
+        }
+
+        cluster_result = cluster_to_sector(fs, target_cluster);
+        if (cluster_result < 0)
+            return cluster_result;
+        current_sector = (uint32_t) cluster_result + cluster_offset / fs->bytes_per_sector;
+
+        bytes_in_sector = fs->bytes_per_sector - sector_offset;
+        bytes_to_sector = (remaining < bytes_in_sector) ? remaining : bytes_in_sector;
+
+        /* Keep the rest of a partially written sector */
+        if (bytes_to_sector < fs->bytes_per_sector && fs->disk.read_sector(current_sector, sector_buffer) != 0)
+        {
+            return bytes_written > 0 ? (int) bytes_written : FAT32_ERROR_IO;
+        }
+        for (i = 0; i < bytes_to_sector; i++)
+        {
+            sector_buffer[sector_offset + i] = buf_ptr[i];
+        }
+        if (fs->disk.write_sector(current_sector, sector_buffer) != 0)
+        {
+            return bytes_written > 0 ? (int) bytes_written : FAT32_ERROR_IO;
+        }
+
+        buf_ptr += bytes_to_sector;
+        bytes_written += bytes_to_sector;
+        remaining -= bytes_to_sector;
+        file->file_offset += bytes_to_sector;
+        file->current_cluster = target_cluster;
+    }
+
+    return bytes_written;
+}
+
 /* Function to read the volume label from the root directory */
 int fat32_readlabel(fat32_fs_t* fs, char* label_out)
 {
@@ -441,6 +524,135 @@ int fat32_close(fat32_file_t* file)
     return FAT32_SUCCESS;
 }
 
+int fat32_opendir(fat32_fs_t* fs, const char* path, fat32_dir_t* dir)
+{
+    int i;
+    fat32_dir_entry_t current_dir;
+    fat32_path_t path_struct;
+
+    if (!fs || !fat32_is_initialized(fs) || !path || !dir)
+    {
+        return FAT32_ERROR_BAD_PARAMETER;
+    }
+
+    fat32_path_init(&path_struct);
+    parse_fat32_path(path, &path_struct);
+
+    current_dir.is_initialized = false;
+    current_dir.start_cluster  = fs->root_cluster;
+    for (i = 0; i < path_struct.num_components; i++)
+    {
+        if (read_dir_entry(fs, &current_dir, path_struct.components[i]) != 0)
+        {
+            return FAT32_ERROR_NO_DIR;
+        }
+        if ((current_dir.attributes & FAT32_ATTR_DIRECTORY) != FAT32_ATTR_DIRECTORY)
+        {
+            return FAT32_ERROR_NOT_DIR;
+        }
+        /* ".." entries of first level directories point at cluster 0 */
+        if (current_dir.start_cluster == 0)
+        {
+            current_dir.start_cluster = fs->root_cluster;
+        }
+    }
+
+    dir->fs              = fs;
+    dir->start_cluster   = current_dir.start_cluster;
+    dir->current_cluster = current_dir.start_cluster;
+    dir->current_sector  = 0;
+    dir->current_entry   = 0;
+    return FAT32_SUCCESS;
+}
+
+/* Turn a space padded 8.3 name into "NAME.EXT" */
+static void format_name_from_fat32(const uint8_t* input, char* output)
+{
+    size_t i, len = 0;
+
+    for (i = 0; i < 8 && input[i] != ' '; i++)
+    {
+        output[len++] = input[i];
+    }
+    if (input[8] != ' ')
+    {
+        output[len++] = '.';
+        for (i = 8; i < 11 && input[i] != ' '; i++)
+        {
+            output[len++] = input[i];
+        }
+    }
+    output[len] = '\0';
+}
+
+int fat32_readdir(fat32_dir_t* dir, fat32_dir_entry_t* entry)
+{
+    uint8_t sector_buffer[FAT32_SECTOR_SIZE];
+    fat32_fs_t* fs;
+    const uint32_t entries_per_sector = FAT32_SECTOR_SIZE / sizeof(Fat32DirectoryEntry);
+
+    if (!dir || !entry || !dir->fs)
+    {
+        return FAT32_ERROR_BAD_PARAMETER;
+    }
+    fs = dir->fs;
+
+    while (dir->current_cluster != FAT32_EOC_MARKER)
+    {
+        int32_t cluster_sector = cluster_to_sector(fs, dir->current_cluster);
+        if (cluster_sector < 0)
+        {
+            return cluster_sector;
+        }
+        if (fs->disk.read_sector((uint32_t) cluster_sector + dir->current_sector, sector_buffer) != 0)
+        {
+            return FAT32_ERROR_IO;
+        }
+
+        while (dir->current_entry < entries_per_sector)
+        {
+            const Fat32DirectoryEntry* current_entry =
+                &((const Fat32DirectoryEntry*) sector_buffer)[dir->current_entry];
+
+            if (current_entry->filename[0] == FAT32_ENTRY_EMPTY)
+            {
+                return FAT32_ERROR_END_OF_DIR; /* position stays here, later calls end too */
+            }
+            dir->current_entry++;
+
+            if (current_entry->filename[0] == FAT32_ENTRY_DELETED ||
+                (current_entry->attr & FAT32_ATTR_LFN) == FAT32_ATTR_LFN ||
+                (current_entry->attr & FAT32_ATTR_VOLLABEL))
+            {
+                continue;
+            }
+
+            format_name_from_fat32(current_entry->filename, entry->name);
+            entry->attributes     = current_entry->attr;
+            entry->file_size      = current_entry->fileSize;
+            entry->start_cluster  = (current_entry->firstClusterHigh << 16) | current_entry->firstClusterLow;
+            entry->is_initialized = 1;
+            return FAT32_SUCCESS;
+        }
+
+        /* Move on to the next sector, then the next cluster */
+        dir->current_entry = 0;
+        dir->current_sector++;
+        if (dir->current_sector >= fs->sectors_per_cluster)
+        {
+            uint32_t next_cluster = fat32_get_next_cluster(fs, dir->current_cluster);
+            if ((int32_t) next_cluster < 0)
+            {
+                return (int32_t) next_cluster;
+            }
+            dir->current_sector  = 0;
+            dir->current_cluster = next_cluster;
+        }
+    }
+
+    return FAT32_ERROR_END_OF_DIR;
+}
+
 static uint32_t fat32_get_next_cluster(fat32_fs_t* fs, uint32_t curr)
 {
     uint8_t buffer[FAT32_SECTOR_SIZE];
@@ -19,6 +19,7 @@
 #define FAT32_ERROR_END_OF_DIR          (-11)
 #define FAT32_ERROR_NO_LABEL            (-12)
 #define FAT32_ERROR_INVALID_CLUSTER     (-13)
+#define FAT32_ERROR_READ_ONLY           (-14)
 
 #define FAT32_SUCCESS 0
 #define FAT32_EOC     1
@@ -75,6 +76,13 @@ typedef struct
      * Returns 0 on success.
      */
     int (*read_sector)(uint32_t sector, uint8_t* buffer);
+    /*
+     * write one sector, NULL if the medium is read only.
+     *   sector:   The absolute sector number to write.
+     *   buffer:   A pointer to 'bytes_per_sector' bytes to write.
+     * Returns 0 on success.
+     */
+    int (*write_sector)(uint32_t sector, const uint8_t* buffer);
 } fat32_diskio_t;
 
 /*
@@ -197,6 +205,20 @@ int fat32_open(fat32_fs_t* fs, const char* path, fat32_file_t* file);
  */
 int fat32_read(fat32_file_t* file, void* buffer, int size);
 
+/**
+ * @brief Overwrites data in an open file in place.
+ *
+ * Writes up to 'size' bytes at the file offset, updating it. The file is
+ * never extended and no clusters are allocated, writing stops at the end of
+ * the file. Sectors are read, modified and written back one at a time.
+ *
+ * @param file         Pointer to an open fat32_file_t.
+ * @param buffer       Pointer to the data to write.
+ * @param size         Number of bytes to write.
+ * @return             Real bytes written on success, or an error code.
+ */
+int fat32_write(fat32_file_t* file, const void* buffer, int size);
+
 int fat32_tell(fat32_file_t* file);
 int fat32_seek(fat32_file_t* file, int offset);
 int fat32_eof(fat32_file_t* file);
@@ -212,6 +234,29 @@ int fat32_size(fat32_file_t* file);
  */
 int fat32_close(fat32_file_t* file);
 
+/* Directory ops */
+/**
+ * @brief Opens a directory given its path.
+ *
+ * @param fs       Mounted FAT32 filesystem pointer.
+ * @param path     Null-terminated path to the directory, "/" for the root.
+ * @param dir      Pointer to a fat32_dir_t structure (allocated by the caller).
+ * @return         FAT32_SUCCESS on success, or an error code.
+ */
+int fat32_opendir(fat32_fs_t* fs, const char* path, fat32_dir_t* dir);
+
+/**
+ * @brief Reads the next entry of an open directory.
+ *
+ * Deleted entries, long filename entries and the volume label are skipped,
+ * entry->name holds the 8.3 name as "NAME.EXT".
+ *
+ * @param dir      Pointer to an open fat32_dir_t.
+ * @param entry    Pointer to the fat32_dir_entry_t to fill in.
+ * @return         FAT32_SUCCESS, FAT32_ERROR_END_OF_DIR after the last entry, or an error code.
+ */
+int fat32_readdir(fat32_dir_t* dir, fat32_dir_entry_t* entry);
+
 /*                                */
 /* parsing structs, do not modify */
 /*                                */
@@ -17,13 +17,15 @@ pub mod raw {
 
 // type Fat32DiskIOReadSector = extern "C" fn(u32, &mut [u8; 512]) -> i32;
 type Fat32DiskIOReadSectorPtr = unsafe extern "C" fn(u32, *mut u8) -> i32;
-type Fat32DiskIOWriteSector = fn(u32, &[u8; raw::FAT32_SECTOR_SIZE as usize]) -> i32;
+type Fat32DiskIOWriteSectorPtr = unsafe extern "C" fn(u32, *const u8) -> i32;
 
 pub type Fat32DiskIO = raw::fat32_diskio_t;
 impl Fat32DiskIO {
-    pub fn new(read: Fat32DiskIOReadSectorPtr, _write: Option<Fat32DiskIOWriteSector>) -> Self {
+    /// Without `write` the volume is read only
+    pub fn new(read: Fat32DiskIOReadSectorPtr, write: Option<Fat32DiskIOWriteSectorPtr>) -> Self {
         Self {
             read_sector: Some(read),
+            write_sector: write,
         }
     }
 
@@ -65,6 +67,24 @@ impl Fat32File {
         }
     }
 
+    /// Overwrite file contents at the current offset. Files never grow, the
+    /// write stops at the end of the file and the written length is returned.
+    pub fn write(&mut self, buffer: &[u8]) -> Result<usize, Fat32Error> {
+        unsafe {
+            let res = raw::fat32_write(
+                &mut self.file,
+                buffer.as_ptr() as *const core::ffi::c_void,
+                buffer.len() as i32,
+            );
+
+            if res < raw::FAT32_SUCCESS as i32 {
+                return Err(Fat32Error::from(res));
+            }
+
+            Ok(res as usize)
+        }
+    }
+
     pub fn size(&self) -> u32 {
         self.file.file_size
     }
@@ -109,6 +129,7 @@ pub enum Fat32Error {
     EndOfDir = raw::FAT32_ERROR_END_OF_DIR as isize,
     NoLabel = raw::FAT32_ERROR_NO_LABEL as isize,
     InvalidCluster = raw::FAT32_ERROR_INVALID_CLUSTER as isize,
+    ReadOnly = raw::FAT32_ERROR_READ_ONLY as isize,
 }
 
 // implement fat32 error from i32
@@ -128,6 +149,7 @@ impl From<i32> for Fat32Error {
             raw::FAT32_ERROR_END_OF_DIR => Self::EndOfDir,
             raw::FAT32_ERROR_NO_LABEL => Self::NoLabel,
             raw::FAT32_ERROR_INVALID_CLUSTER => Self::InvalidCluster,
+            raw::FAT32_ERROR_READ_ONLY => Self::ReadOnly,
             _ => panic!("Unknown error code: {}", err),
         }
     }
@@ -155,16 +177,22 @@ impl Fat32FileSystem {
         Self::mount(diskio)
     }
 
+    /// Mount a writable volume
+    pub fn from_fns(
+        read: Fat32DiskIOReadSectorPtr,
+        write: Fat32DiskIOWriteSectorPtr,
+    ) -> Result<Self, Fat32Error> {
+        Self::mount(Fat32DiskIO::new(read, Some(write)))
+    }
+
+    /// Open `filename`, which may or may not be NUL terminated
     pub fn open_file(&mut self, filename: &str) -> Result<Fat32File, Fat32Error> {
-        unsafe {
-            // let mut buffer = [0u8; 256];
-            // let bytes = filename.as_bytes();
-            // buffer[..bytes.len()].copy_from_slice(bytes);
-            // buffer[bytes.len()] = 0;
+        let mut buffer = [0u8; raw::FAT32_MAX_PATH as usize];
+        let path = nul_terminated(filename, &mut buffer)?;
 
+        unsafe {
             let mut file: MaybeUninit<raw::fat32_file_t> = MaybeUninit::uninit();
-            let filename_ptr = filename.as_ptr();
-            let res = raw::fat32_open(&mut self.fs, filename_ptr, file.as_mut_ptr());
+            let res = raw::fat32_open(&mut self.fs, path.as_ptr(), file.as_mut_ptr());
 
             if res != raw::FAT32_SUCCESS as i32 {
                 return Err(Fat32Error::from(res));
@@ -175,4 +203,95 @@ impl Fat32FileSystem {
             })
         }
     }
+
+    /// Open the directory at `path` ("/" for the root) for listing
+    pub fn open_dir(&mut self, path: &str) -> Result<Fat32Dir, Fat32Error> {
+        let mut buffer = [0u8; raw::FAT32_MAX_PATH as usize];
+        let path = nul_terminated(path, &mut buffer)?;
+
+        unsafe {
+            let mut dir: MaybeUninit<raw::fat32_dir_t> = MaybeUninit::uninit();
+            let res = raw::fat32_opendir(&mut self.fs, path.as_ptr(), dir.as_mut_ptr());
+
+            if res != raw::FAT32_SUCCESS as i32 {
+                return Err(Fat32Error::from(res));
+            }
+
+            Ok(Fat32Dir {
+                dir: dir.assume_init(),
+            })
+        }
+    }
+}
+
+/// `path` as a C string, copied into `buffer` if it isn't NUL terminated already
+fn nul_terminated<'a>(path: &'a str, buffer: &'a mut [u8]) -> Result<&'a [u8], Fat32Error> {
+    let bytes = path.as_bytes();
+    if bytes.last() == Some(&0) {
+        Ok(bytes)
+    } else if bytes.len() < buffer.len() {
+        buffer[..bytes.len()].copy_from_slice(bytes);
+        buffer[bytes.len()] = 0;
+        Ok(&buffer[..=bytes.len()])
+    } else {
+        Err(Fat32Error::BadParam)
+    }
+}
+
+#[derive(Debug)]
+pub struct Fat32Dir {
+    dir: raw::fat32_dir_t,
+}
+
+/// A directory entry with its 8.3 name
+#[derive(Clone, Copy)]
+pub struct Fat32DirEntry {
+    name: [u8; 13],
+    name_len: usize,
+    attributes: u8,
+    size: u32,
+}
+
+impl Fat32DirEntry {
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("?")
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attributes as u32 & raw::FAT32_ATTR_DIRECTORY != 0
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+impl Iterator for Fat32Dir {
+    type Item = Result<Fat32DirEntry, Fat32Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            let mut entry: MaybeUninit<raw::fat32_dir_entry_t> = MaybeUninit::uninit();
+            let res = raw::fat32_readdir(&mut self.dir, entry.as_mut_ptr());
+
+            if res == raw::FAT32_ERROR_END_OF_DIR {
+                return None;
+            }
+            if res != raw::FAT32_SUCCESS as i32 {
+                return Some(Err(Fat32Error::from(res)));
+            }
+
+            let entry = entry.assume_init();
+            let bytes = core::ffi::CStr::from_ptr(entry.name.as_ptr()).to_bytes();
+            let mut name = [0u8; 13];
+            let name_len = bytes.len().min(name.len());
+            name[..name_len].copy_from_slice(&bytes[..name_len]);
+            Some(Ok(Fat32DirEntry {
+                name,
+                name_len,
+                attributes: entry.attributes,
+                size: entry.file_size,
+            }))
+        }
+    }
 }
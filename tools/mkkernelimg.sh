@@ -0,0 +1,33 @@
+#!/usr/bin/env bash
+# Prepend the kernel image header (see bootloader/src/image.rs) to a kernel ELF
+set -euo pipefail
+
+KERNEL=${1:-}
+IMG=${2:-}
+
+if [ -z "$KERNEL" ] || [ -z "$IMG" ]; then
+    echo "Usage: $0 <kernel_elf> <output_file>"
+    exit 1
+fi
+
+HEADER_VERSION=1
+HEADER_SIZE=64
+
+# little endian u32 as printf escapes
+le32() {
+    printf '\\x%02x\\x%02x\\x%02x\\x%02x' \
+        $(( $1 & 0xff )) $(( ($1 >> 8) & 0xff )) $(( ($1 >> 16) & 0xff )) $(( ($1 >> 24) & 0xff ))
+}
+
+SIZE=$(stat -c %s "$KERNEL")
+DIGEST=$(sha256sum "$KERNEL" | cut -d' ' -f1)
+
+{
+    printf 'KIMG'
+    printf "$(le32 $HEADER_VERSION)$(le32 $HEADER_SIZE)$(le32 $SIZE)"
+    printf "$(echo "$DIGEST" | sed 's/../\\x&/g')"
+    head -c 16 /dev/zero
+    cat "$KERNEL"
+} > "$IMG"
+
+echo "Stamped $IMG: $SIZE byte kernel, sha256 $DIGEST"
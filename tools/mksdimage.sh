@@ -4,10 +4,19 @@ set -euo pipefail
 MLO=$1
 IMG=$2
 KERNEL=$3
+BOOT_CFG=${4:-}
+# set to install the kernel into both A/B slots as well
+AB_SLOTS=${AB_SLOTS:-}
+# optional initrd, installed as /boot/initrd.img
+INITRD=${INITRD:-}
+# optional stamped Linux zImage and device tree, installed as /boot/zImage.bin
+# and under their own name in /boot
+ZIMAGE=${ZIMAGE:-}
+DTB=${DTB:-}
 
 # verify all parameters are provided
 if [ -z $MLO ] || [ -z $IMG ] || [ -z $KERNEL ]; then
-    echo "Usage: $0 <mlo_file> <output_file> <kernel_file>"
+    echo "Usage: [AB_SLOTS=1] [INITRD=<file>] [ZIMAGE=<file>] [DTB=<file>] $0 <mlo_file> <output_file> <kernel_file> [boot_cfg]"
     exit 1
 fi
 
@@ -64,6 +73,39 @@ mmd -i $BOOT_IMG ::/boot
 echo "Copying kernel image to boot partition..."
 mcopy -o $KERNEL c:/boot/kernel.bin
 
+if [ -n "$AB_SLOTS" ]; then
+    echo "Copying kernel image to slots a and b..."
+    mmd -i $BOOT_IMG ::/boot/a ::/boot/b
+    mcopy -o $KERNEL c:/boot/a/kernel.bin
+    mcopy -o $KERNEL c:/boot/b/kernel.bin
+
+    # magic, version 1, slot a, no tries left, confirmed, reserved
+    SLOT_STATE="$IMG.slot.state"
+    printf 'ABST\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00' > $SLOT_STATE
+    mcopy -o $SLOT_STATE c:/boot/slot.state
+    rm $SLOT_STATE
+fi
+
+if [ -n "$INITRD" ]; then
+    echo "Copying initrd to boot partition..."
+    mcopy -o $INITRD c:/boot/initrd.img
+fi
+
+if [ -n "$ZIMAGE" ]; then
+    echo "Copying Linux zImage to boot partition..."
+    mcopy -o $ZIMAGE c:/boot/zImage.bin
+fi
+
+if [ -n "$DTB" ]; then
+    echo "Copying device tree to boot partition..."
+    mcopy -o $DTB c:/boot/$(basename $DTB)
+fi
+
+if [ -n "$BOOT_CFG" ]; then
+    echo "Copying boot config to boot partition..."
+    mcopy -o $BOOT_CFG c:/boot/boot.cfg
+fi
+
 # DD the partition image into the full image
 echo "Writing boot partition to disk image..."
 dd if=$BOOT_IMG of=$IMG seek=$START_SECTOR bs=512 conv=notrunc status=none
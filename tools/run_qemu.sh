@@ -87,6 +87,8 @@ fi
 SYSTEM_ARGS="-m 512M -M cubieboard -cpu cortex-a8"
 OUTPUT_ARGS="-serial mon:stdio -nographic"
 LOG_ARGS="-d guest_errors,unimp,int -D qemu.log"
+# host I/O for hal::semihosting and boot_semihost, paths are relative to here
+SEMIHOSTING_ARGS="-semihosting-config enable=on,target=native"
 
 
 # Log a message if no sd card image is found
@@ -96,8 +98,27 @@ else
     SDCARD_FLAGS="-drive if=sd,format=raw,file=$SDCARD_IMG"
 fi
 
+# SATA_IMG attaches a disk image to the AHCI controller for boot_sata, it
+# takes the same layout as the SD card image
+if [ -n "${SATA_IMG:-}" ]; then
+    SATA_FLAGS="-drive if=none,id=sata0,format=raw,file=$SATA_IMG -device ide-hd,drive=sata0,bus=ide.0"
+else
+    SATA_FLAGS=""
+fi
+
 BOOTLOADER_FLAGS="-kernel $BOOTBIN_FILE"
 
+# BOOT_DTB hands the bootloader a device tree in r2, it takes the UART, MMC
+# and DRAM layout from there, e.g. hal/testdata/cubieboard.dtb
+if [ -n "${BOOT_DTB:-}" ]; then
+    BOOTLOADER_FLAGS="$BOOTLOADER_FLAGS -dtb $BOOT_DTB"
+fi
+
+# User mode network on the EMAC, its DHCP server hands out kernel.bin from the
+# TFTP directory for boot_net
+TFTP_DIR="${TFTP_DIR:-$DEFAULT_DEPLOY_DIR}"
+NET_FLAGS="-nic user,tftp=$TFTP_DIR,bootfile=kernel.bin"
+
 # Check if second argument is "--gdb"
 if [[ -n "${2:-}" && "$2" == "--gdb" ]]; then
     GDB_ARGS="-S -gdb tcp::$GDB_PORT"
@@ -108,7 +129,7 @@ else
     GDB_ARGS=""  # Default to an empty string if not using GDB
 fi
 
-QEMU_CMD="qemu-system-arm $SYSTEM_ARGS $OUTPUT_ARGS $LOG_ARGS $SDCARD_FLAGS $BOOTLOADER_FLAGS $GDB_ARGS"
+QEMU_CMD="qemu-system-arm $SYSTEM_ARGS $OUTPUT_ARGS $LOG_ARGS $SEMIHOSTING_ARGS $SDCARD_FLAGS $SATA_FLAGS $NET_FLAGS $BOOTLOADER_FLAGS $GDB_ARGS"
 
 echo $QEMU_CMD
 $QEMU_CMD
@@ -0,0 +1,60 @@
+#!/usr/bin/env bash
+# Sign a stamped kernel image (see bootloader/src/image.rs) with an Ed25519 key
+#
+#   signkernel.sh <key.pem> <kernel.bin> <output_file>   sign an image
+#   signkernel.sh --pubkey <key.pem> <output_file>       export the raw public key
+#
+# Create a key with `openssl genpkey -algorithm ed25519 -out key.pem`.
+set -euo pipefail
+
+usage() {
+    echo "Usage: $0 <key.pem> <kernel.bin> <output_file>"
+    echo "       $0 --pubkey <key.pem> <output_file>"
+    exit 1
+}
+
+if [ "${1:-}" = "--pubkey" ]; then
+    [ -n "${2:-}" ] && [ -n "${3:-}" ] || usage
+    # the DER encoding of an Ed25519 public key ends with the raw 32 byte key
+    openssl pkey -in "$2" -pubout -outform DER | tail -c 32 > "$3"
+    exit 0
+fi
+
+KEY=${1:-}
+IMG=${2:-}
+OUT=${3:-}
+[ -n "$KEY" ] && [ -n "$IMG" ] && [ -n "$OUT" ] || usage
+
+FLAG_SIGNED=1
+SIGNATURE_SIZE=64
+
+# little endian u32 at a byte offset of the image header
+read_le32() {
+    od -An -tu4 -j "$1" -N4 --endian=little "$IMG" | tr -d ' '
+}
+
+if [ "$(head -c 4 "$IMG")" != "KIMG" ]; then
+    echo "$IMG is not a stamped kernel image, run mkkernelimg.sh first"
+    exit 1
+fi
+HEADER_SIZE=$(read_le32 8)
+IMAGE_SIZE=$(read_le32 12)
+FLAGS=$(( $(read_le32 48) | FLAG_SIGNED ))
+
+TMP=$(mktemp)
+trap 'rm -f "$TMP" "$TMP.sig"' EXIT
+
+# header with the signed flag set and the payload, dropping any old signature
+head -c $(( HEADER_SIZE + IMAGE_SIZE )) "$IMG" > "$TMP"
+printf "$(printf '\\x%02x\\x%02x\\x%02x\\x%02x' \
+    $(( FLAGS & 0xff )) $(( (FLAGS >> 8) & 0xff )) $(( (FLAGS >> 16) & 0xff )) $(( (FLAGS >> 24) & 0xff )))" |
+    dd of="$TMP" bs=1 seek=48 conv=notrunc status=none
+
+openssl pkeyutl -sign -rawin -inkey "$KEY" -in "$TMP" -out "$TMP.sig"
+if [ "$(stat -c %s "$TMP.sig")" -ne $SIGNATURE_SIZE ]; then
+    echo "$KEY is not an Ed25519 key"
+    exit 1
+fi
+cat "$TMP" "$TMP.sig" > "$OUT"
+
+echo "Signed $OUT with $KEY"